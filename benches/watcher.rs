@@ -0,0 +1,135 @@
+//! Cost of turning a batch of inotify events into tracker events,
+//! including the file metadata lookups. Run with `cargo bench`.
+//! Only the metadata lookups are measured outside of Linux.
+
+use blkchn_file_tracker::dir_watcher::read_file_metadata;
+#[cfg(target_os = "linux")]
+use blkchn_file_tracker::dir_watcher::{extract_events, GlobFilter, Renames};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+#[cfg(target_os = "linux")]
+use criterion::{BatchSize, Throughput};
+#[cfg(target_os = "linux")]
+use inotify::{EventMask, Inotify, WatchMask};
+#[cfg(target_os = "linux")]
+use std::collections::HashMap;
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+use std::fs;
+use std::hint::black_box;
+
+// Same as the watcher's upper limit of events read at once
+#[cfg(target_os = "linux")]
+const BATCH_SIZES: [usize; 3] = [1, 16, 64];
+
+#[cfg(target_os = "linux")]
+fn bench_extract_events(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    // The watch is only needed for a valid descriptor, its events are never read
+    let inotify = Inotify::init().unwrap();
+    let wd = inotify
+        .watches()
+        .add(dir.path(), WatchMask::CLOSE_WRITE)
+        .unwrap();
+    let dirs = HashMap::from([(
+        wd.clone(),
+        dir.path()
+            .to_str()
+            .unwrap()
+            .to_string(),
+    )]);
+
+    let file_names: Vec<String> = (0..BATCH_SIZES[2])
+        .map(|i| format!("file-{}.txt", i))
+        .collect();
+    for name in file_names.iter() {
+        fs::write(dir.path().join(name), b"content").unwrap();
+    }
+
+    let inotify_event = |name: &str, mask: EventMask| inotify::Event {
+        wd: wd.clone(),
+        mask,
+        cookie: 0,
+        name: Some(OsString::from(name)),
+    };
+
+    let mut group = c.benchmark_group("extract_events");
+    for batch_size in BATCH_SIZES {
+        group.throughput(Throughput::Elements(batch_size as u64));
+
+        // Every event of a different file, each needs its own metadata lookup
+        let distinct: Vec<_> = file_names[..batch_size]
+            .iter()
+            .map(|name| inotify_event(name, EventMask::CLOSE_WRITE))
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::new("distinct_files", batch_size),
+            &distinct,
+            |b, events| {
+                b.iter_batched(
+                    || events.clone(),
+                    |events| {
+                        extract_events(
+                            &dirs,
+                            events,
+                            &GlobFilter::default(),
+                            &mut Renames::default(),
+                        )
+                        .unwrap()
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+
+        // All events of one file, the metadata is looked up once per batch
+        let same: Vec<_> = (0..batch_size)
+            .map(|_| inotify_event(&file_names[0], EventMask::CLOSE_WRITE | EventMask::ATTRIB))
+            .collect();
+        group.bench_with_input(
+            BenchmarkId::new("same_file", batch_size),
+            &same,
+            |b, events| {
+                b.iter_batched(
+                    || events.clone(),
+                    |events| {
+                        extract_events(
+                            &dirs,
+                            events,
+                            &GlobFilter::default(),
+                            &mut Renames::default(),
+                        )
+                        .unwrap()
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_read_file_metadata(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("read_file_metadata");
+    // Only the metadata is read, the size of the file shouldn't matter
+    for size in [0, 1024 * 1024] {
+        let path = dir
+            .path()
+            .join(format!("file-{}.bin", size));
+        fs::write(&path, vec![0u8; size]).unwrap();
+        let path = path
+            .to_str()
+            .unwrap()
+            .to_string();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| read_file_metadata(black_box(path)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+#[cfg(target_os = "linux")]
+criterion_group!(benches, bench_extract_events, bench_read_file_metadata);
+#[cfg(not(target_os = "linux"))]
+criterion_group!(benches, bench_read_file_metadata);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// Receives the hashes of the file events sent by blkchn-file-tracker.
+/// Only the log is kept, the hash of an event is looked up in the Anchored events.
+contract EventAnchor {
+    event Anchored(address indexed sender, bytes32 indexed eventHash);
+
+    function anchor(bytes32 eventHash) external {
+        emit Anchored(msg.sender, eventHash);
+    }
+}
@@ -0,0 +1,657 @@
+//! File event types shared by the on-chain program and the client,
+//! both serialize them with borsh.
+//!
+//! `no_std` compatible when built without the default `std` feature.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// ToString is used by the BorshSchema derive
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use core::fmt::{Debug, Display};
+use core::str::FromStr;
+
+mod v2;
+mod v3;
+
+pub use v2::{CompressedEventV2, EventV2, FileInfoV2, Timestamp, TimestampOutOfRange};
+pub use v3::{CompressedEventV3, EventV3, FileInfoV3};
+
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub enum EventType {
+    AttributeChanged,
+    Created,
+    Deleted,
+    MovedFrom,
+    MovedTo,
+    Opened,
+    Written,
+    /// Both halves of a rename within the watched directories, reported under `to`.
+    Renamed {
+        from: String,
+        to: String,
+    },
+    /// Closed after being read only, reported by the auditing setups, not by default.
+    ReadClosed,
+    /// Read, reported for every read, not by default.
+    Accessed,
+}
+
+impl Display for EventType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::AttributeChanged => write!(f, "AttributeChanged"),
+            Self::Created => write!(f, "Created"),
+            Self::Deleted => write!(f, "Deleted"),
+            Self::MovedFrom => write!(f, "MovedFrom"),
+            Self::MovedTo => write!(f, "MovedTo"),
+            Self::Opened => write!(f, "Opened"),
+            Self::Written => write!(f, "Written"),
+            Self::Renamed { .. } => write!(f, "Renamed"),
+            Self::ReadClosed => write!(f, "ReadClosed"),
+            Self::Accessed => write!(f, "Accessed"),
+        }
+    }
+}
+
+impl EventType {
+    pub const COUNT: usize = 10;
+
+    /// The types by their names, [Self::Renamed] without its paths.
+    pub const ALL: [EventType; Self::COUNT] = [
+        EventType::AttributeChanged,
+        EventType::Created,
+        EventType::Deleted,
+        EventType::MovedFrom,
+        EventType::MovedTo,
+        EventType::Opened,
+        EventType::Written,
+        EventType::Renamed {
+            from: String::new(),
+            to: String::new(),
+        },
+        EventType::ReadClosed,
+        EventType::Accessed,
+    ];
+
+    /// The name used in config files and on the command line, e.g. `moved_from`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::AttributeChanged => "attribute_changed",
+            Self::Created => "created",
+            Self::Deleted => "deleted",
+            Self::MovedFrom => "moved_from",
+            Self::MovedTo => "moved_to",
+            Self::Opened => "opened",
+            Self::Written => "written",
+            Self::Renamed { .. } => "renamed",
+            Self::ReadClosed => "read_closed",
+            Self::Accessed => "accessed",
+        }
+    }
+
+    /// Whether both are of the same type, the paths of [Self::Renamed] aside.
+    pub fn same_type(&self, other: &EventType) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseEventTypeError(String);
+
+impl Display for ParseEventTypeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Unknown event type {}, expected one of: ", self.0)?;
+        for (i, event_type) in EventType::ALL
+            .iter()
+            .enumerate()
+        {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", event_type.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseEventTypeError {}
+
+/// Accepts the snake case names (`moved_from`) as well as the displayed ones (`MovedFrom`),
+/// case insensitive.
+impl FromStr for EventType {
+    type Err = ParseEventTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s
+            .trim()
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<String>();
+        EventType::ALL
+            .into_iter()
+            .find(|event_type| {
+                event_type
+                    .as_str()
+                    .replace('_', "")
+                    == normalized
+            })
+            .ok_or_else(|| ParseEventTypeError(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for EventType {
+    type Error = ParseEventTypeError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for EventType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EventType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+        name.parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct Event {
+    pub file_path: String,
+    pub event_type: EventType,
+
+    // Unix timestamp, marks the time when this even has been
+    // received by the dir watcher and its processing started.
+    // Assigned according to SOLANA CLOCK, not the system clock.
+    pub solana_ts_received_at: i128,
+    pub file_info: Option<FileInfo>,
+
+    // Not sent to the program, like the owner in the file info
+    #[borsh(skip)]
+    pub kind: FileKind,
+    // Events of the file left out in favor of this one by the rate limit
+    #[borsh(skip)]
+    pub suppressed_count: u32,
+    // Who made the change, known only to the audit source
+    #[borsh(skip)]
+    pub process: Option<Process>,
+    // Number of the event among the ones its program records, from 1 in the order the
+    // tracker passed them on to the sinks, 0 if not numbered
+    #[borsh(skip)]
+    pub sequence: u64,
+    // Unix timestamp in milliseconds, marks the time when the watcher read the event.
+    // Assigned according to the system clock, 0 if not known
+    #[borsh(skip)]
+    pub captured_at: i64,
+    // Other names of the file known to the watcher, its hard links
+    #[borsh(skip)]
+    pub hard_links: Vec<String>,
+    // Unified diff of the written text file's content from its previous version
+    #[borsh(skip)]
+    pub diff: Option<String>,
+    // Hex SHA-256 of the file's content, hashed by the pipeline ahead of the submitter
+    #[borsh(skip)]
+    pub sha256: Option<String>,
+}
+
+impl Event {
+    /// The event of the path with nothing else known yet, the other fields are set
+    /// by the struct update syntax, e.g. `Event { file_info, ..Event::new(path, event_type) }`.
+    pub fn new(file_path: impl Into<String>, event_type: EventType) -> Event {
+        Event {
+            file_path: file_path.into(),
+            event_type,
+            solana_ts_received_at: 0,
+            file_info: None,
+            kind: FileKind::File,
+            suppressed_count: 0,
+            process: None,
+            sequence: 0,
+            captured_at: 0,
+            hard_links: Vec::new(),
+            diff: None,
+            sha256: None,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}: {}", self.file_path, self.event_type)
+    }
+}
+
+/// The process which made the change.
+#[derive(Clone, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct Process {
+    pub pid: u32,
+    /// Path of the executable, e.g. `/usr/bin/vim`.
+    pub exe: String,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} (pid {})", self.exe, self.pid)
+    }
+}
+
+/// What the event's path is, known also when it's gone, e.g. a deleted directory.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize, BorshSchema,
+)]
+pub enum FileKind {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+    /// A FIFO, a socket or a device.
+    Other,
+}
+
+impl FileKind {
+    /// The kind in the type bits of the mode, a file if it has none, e.g. outside of unix.
+    pub fn from_mode(mode: u32) -> FileKind {
+        match mode & 0o170000 {
+            0 | 0o100000 => FileKind::File,
+            0o040000 => FileKind::Dir,
+            0o120000 => FileKind::Symlink,
+            _ => FileKind::Other,
+        }
+    }
+
+    /// The kind of the path with the file info, a file if it has none, e.g. when it's gone.
+    pub fn of(file_info: Option<&FileInfo>) -> FileKind {
+        file_info.map_or(FileKind::File, |info| FileKind::from_mode(info.mode))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Dir => "dir",
+            Self::Symlink => "symlink",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct FileInfo {
+    pub access_ts: Option<i128>,  // unix timestamp
+    pub modify_ts: Option<i128>,  // unix timestamp
+    pub created_ts: Option<i128>, // unix timestamp
+
+    pub size: u64,
+    pub mode: u32, // libc::mode_t;
+
+    // The owner, not sent to the program: the events' layout and the vaults stay as they are
+    #[borsh(skip)]
+    pub uid: Option<u32>,
+    #[borsh(skip)]
+    pub gid: Option<u32>,
+    #[borsh(skip)]
+    pub user: Option<String>, // resolved from the uid if the host knows it
+    #[borsh(skip)]
+    pub group: Option<String>,
+    // Extended attributes captured with the attribute changes, e.g. the SELinux label,
+    // as text or 0x-prefixed hex
+    #[borsh(skip)]
+    pub xattrs: Vec<(String, String)>,
+    // The info is the link's own, not its target's
+    #[borsh(skip)]
+    pub is_symlink: bool,
+    #[borsh(skip)]
+    pub link_target: Option<String>,
+    // Identify the file apart from its names, its hard links share them
+    #[borsh(skip)]
+    pub inode: Option<u64>,
+    #[borsh(skip)]
+    pub device: Option<u64>,
+    #[borsh(skip)]
+    pub nlink: Option<u64>,
+    // MIME type sniffed from the content of the created and written files, e.g. image/png
+    #[borsh(skip)]
+    pub file_type: Option<String>,
+}
+
+/// [Event] with the beginning of its path replaced by the index
+/// of a prefix from the vault's [PathDictionary].
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct CompressedEvent {
+    pub prefix: u8,
+    pub path_suffix: String,
+    pub event_type: EventType,
+    pub solana_ts_received_at: i128,
+    pub file_info: Option<FileInfo>,
+}
+
+/// Events the watcher dropped when its queue was full, recorded in their place so the
+/// trail of the vault discloses the gap.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct EventsDropped {
+    pub count: u64,
+    // Unix timestamps of the first and the last dropped event, by the system clock
+    pub since: i64,
+    pub until: i64,
+}
+
+impl Display for EventsDropped {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} events dropped between {} and {}",
+            self.count, self.since, self.until
+        )
+    }
+}
+
+/// Common path prefixes, e.g. the watched directories, stored once in the vault
+/// when it's initialized, so that the events don't repeat them.
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct PathDictionary {
+    prefixes: Vec<String>,
+}
+
+impl PathDictionary {
+    /// Limited by the size of [CompressedEvent::prefix] and the vault's size.
+    pub const MAX_PREFIXES: usize = 64;
+
+    pub fn new(prefixes: Vec<String>) -> PathDictionary {
+        PathDictionary { prefixes }
+    }
+
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Replaces the longest matching prefix of the event's path with its index,
+    /// `None` if no prefix matches.
+    pub fn compress(&self, event: &Event) -> Option<CompressedEvent> {
+        let (prefix, path_suffix) = self.split(&event.file_path)?;
+
+        Some(CompressedEvent {
+            prefix,
+            path_suffix: path_suffix.to_string(),
+            event_type: event.event_type.clone(),
+            solana_ts_received_at: event.solana_ts_received_at,
+            file_info: event.file_info.clone(),
+        })
+    }
+
+    /// Restores the full path, `None` if the prefix is not in the dictionary.
+    pub fn expand(&self, event: CompressedEvent) -> Option<Event> {
+        let file_path = self.join(event.prefix, &event.path_suffix)?;
+
+        Some(Event {
+            solana_ts_received_at: event.solana_ts_received_at,
+            kind: FileKind::of(event.file_info.as_ref()),
+            file_info: event.file_info,
+            ..Event::new(file_path, event.event_type)
+        })
+    }
+
+    // The index of the longest prefix of the path and the rest of the path
+    fn split<'a>(&self, path: &'a str) -> Option<(u8, &'a str)> {
+        let (prefix, path_suffix) = self
+            .prefixes
+            .iter()
+            .take(Self::MAX_PREFIXES)
+            .enumerate()
+            .filter(|(_, prefix)| !prefix.is_empty())
+            .filter_map(|(i, prefix)| {
+                path.strip_prefix(prefix.as_str())
+                    .map(|suffix| (i, suffix))
+            })
+            .min_by_key(|(_, suffix)| suffix.len())?;
+        Some((prefix as u8, path_suffix))
+    }
+
+    // The full path of the suffix of the prefix at the index
+    fn join(&self, prefix: u8, path_suffix: &str) -> Option<String> {
+        let prefix = self
+            .prefixes
+            .get(usize::from(prefix))?;
+        let mut file_path = String::with_capacity(prefix.len() + path_suffix.len());
+        file_path.push_str(prefix);
+        file_path.push_str(path_suffix);
+        Some(file_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_event_serialization() {
+        let event = Event {
+            solana_ts_received_at: 55543119,
+            file_info: Some(FileInfo {
+                access_ts: Some(34242),
+                created_ts: None,
+                modify_ts: Some(2221212),
+                mode: 433,
+                size: 100000000,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            }),
+            ..Event::new("name.txt", EventType::MovedTo)
+        };
+
+        let mut buf: Vec<u8> = Vec::new();
+        event
+            .serialize(&mut buf)
+            .unwrap();
+
+        println!("Size of an event: {}", buf.len());
+
+        let deserialized = Event::deserialize(&mut buf.as_slice()).unwrap();
+        assert_eq!(event, deserialized);
+
+        // The owner isn't serialized
+        let mut owned = event.clone();
+        if let Some(info) = owned.file_info.as_mut() {
+            info.uid = Some(1000);
+            info.user = Some("alice".to_string());
+        }
+        assert_eq!(borsh::to_vec(&owned).unwrap(), buf);
+    }
+
+    #[test]
+    fn test_event_type_from_str() {
+        assert_eq!("moved_from".parse(), Ok(EventType::MovedFrom));
+        assert_eq!("MovedFrom".parse(), Ok(EventType::MovedFrom));
+        assert_eq!(" WRITTEN ".parse(), Ok(EventType::Written));
+        assert_eq!("read_closed".parse(), Ok(EventType::ReadClosed));
+        assert_eq!(
+            EventType::try_from("attribute-changed"),
+            Ok(EventType::AttributeChanged)
+        );
+        assert!("modified"
+            .parse::<EventType>()
+            .is_err());
+
+        for event_type in EventType::ALL {
+            assert_eq!(event_type.as_str().parse(), Ok(event_type.clone()));
+            assert_eq!(event_type.to_string().parse(), Ok(event_type));
+        }
+        let renamed = EventType::Renamed {
+            from: "/srv/a.txt".to_string(),
+            to: "/srv/b.txt".to_string(),
+        };
+        assert!(renamed.same_type(&"renamed".parse().unwrap()));
+        assert!(!renamed.same_type(&EventType::MovedTo));
+    }
+
+    #[test]
+    fn test_path_dictionary() {
+        let dictionary = PathDictionary::new(vec![
+            "/srv/".to_string(),
+            "/srv/data/".to_string(),
+            "/home/user/".to_string(),
+        ]);
+        let event = Event {
+            solana_ts_received_at: 55543119,
+            ..Event::new("/srv/data/reports/2024.csv", EventType::Written)
+        };
+
+        // The longest prefix is used
+        let compressed = dictionary
+            .compress(&event)
+            .unwrap();
+        assert_eq!(compressed.prefix, 1);
+        assert_eq!(compressed.path_suffix, "reports/2024.csv");
+        assert!(
+            borsh::to_vec(&compressed)
+                .unwrap()
+                .len()
+                < borsh::to_vec(&event)
+                    .unwrap()
+                    .len()
+        );
+        assert_eq!(dictionary.expand(compressed.clone()), Some(event.clone()));
+
+        // Unknown prefix
+        assert_eq!(PathDictionary::default().expand(compressed), None);
+        // No matching prefix
+        let event = Event {
+            file_path: "/tmp/file.txt".to_string(),
+            ..event
+        };
+        assert_eq!(dictionary.compress(&event), None);
+    }
+
+    #[test]
+    fn test_event_serialization_no_file_info() {
+        let event = Event {
+            solana_ts_received_at: 55543119,
+            ..Event::new("name.txt", EventType::Written)
+        };
+
+        let mut buf: Vec<u8> = Vec::new();
+        event
+            .serialize(&mut buf)
+            .unwrap();
+
+        println!("Size of an event: {}", buf.len());
+
+        let deserialized = Event::deserialize(&mut buf.as_slice()).unwrap();
+        assert_eq!(event, deserialized);
+    }
+
+    #[test]
+    fn test_file_kind_from_mode() {
+        assert_eq!(FileKind::from_mode(0o100644), FileKind::File);
+        assert_eq!(FileKind::from_mode(0o040755), FileKind::Dir);
+        assert_eq!(FileKind::from_mode(0o120777), FileKind::Symlink);
+        assert_eq!(FileKind::from_mode(0o010644), FileKind::Other);
+        // Without the type bits
+        assert_eq!(FileKind::from_mode(0o644), FileKind::File);
+        assert_eq!(FileKind::of(None), FileKind::File);
+    }
+}
+
+/*
+BORSH SERIALIZATION for OffsetDateTime
+//////////////////////////////////////
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetDateTime(time::OffsetDateTime);
+
+impl BorshSerialize for OffsetDateTime {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let unix_timestamp = self.0.unix_timestamp_nanos();
+        borsh::to_writer(writer, &unix_timestamp)
+    }
+}
+
+impl BorshDeserialize for OffsetDateTime {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        // find the length of the serialized value
+        let schema_container = borsh::schema_container_of::<i128>();
+        let serialized_size = schema_container
+            .max_serialized_size()
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "Failed to check serialized size of OffsetDateTime",
+                )
+            })?;
+        let mut buf: Vec<u8> = Vec::with_capacity(serialized_size);
+        reader.read_exact(&mut buf)?;
+
+        let unix_timestamp = borsh::from_reader::<R, i128>(reader)?;
+
+        time::OffsetDateTime::from_unix_timestamp_nanos(unix_timestamp)
+            .map(|u| OffsetDateTime(u))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
+    }
+}
+
+impl BorshSchema for OffsetDateTime {
+    fn add_definitions_recursively(
+        _definitions: &mut std::collections::BTreeMap<
+            borsh::schema::Declaration,
+            borsh::schema::Definition,
+        >,
+    ) {
+        // do nothing
+    }
+
+    fn declaration() -> borsh::schema::Declaration {
+        let schema_container = borsh::schema_container_of::<i64>();
+        schema_container
+            .declaration()
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_offset_date_time() {
+        let time = super::OffsetDateTime(time::OffsetDateTime::now_utc());
+        let mut buf: Vec<u8> = Vec::new();
+        time.serialize(&mut buf)
+            .expect("Serialization failed");
+        let res = OffsetDateTime::deserialize(&mut buf.as_slice()).expect("Deserialization failed");
+        debug_assert_eq!(res, time);
+    }
+}
+*/
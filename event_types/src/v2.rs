@@ -0,0 +1,248 @@
+//! The events of the schema 2, with the timestamps as i64 seconds and optional
+//! nanoseconds instead of i128 seconds: 7 bytes less for each timestamp, in the
+//! instructions and in the vault.
+//!
+//! The client and the program still work with the events of the schema 1,
+//! converted to and from these ones on the wire.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use core::fmt::Display;
+
+use crate::{CompressedEvent, Event, EventType, FileInfo, FileKind};
+
+/// Unix timestamp: the seconds, and the nanoseconds within the second if known.
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct Timestamp {
+    pub secs: i64,
+    pub nanos: Option<u32>,
+}
+
+impl Timestamp {
+    /// `None` if the seconds don't fit in an i64.
+    pub fn from_secs(secs: i128) -> Option<Timestamp> {
+        Some(Timestamp {
+            secs: i64::try_from(secs).ok()?,
+            nanos: None,
+        })
+    }
+
+    /// The seconds as in the schema 1, the nanoseconds are dropped.
+    pub fn secs(&self) -> i128 {
+        self.secs.into()
+    }
+}
+
+/// A timestamp of the schema 1 event doesn't fit in the schema 2.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampOutOfRange(pub i128);
+
+impl Display for TimestampOutOfRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Timestamp {} doesn't fit in i64 seconds", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TimestampOutOfRange {}
+
+fn timestamp(secs: i128) -> Result<Timestamp, TimestampOutOfRange> {
+    Timestamp::from_secs(secs).ok_or(TimestampOutOfRange(secs))
+}
+
+fn optional(secs: Option<i128>) -> Result<Option<Timestamp>, TimestampOutOfRange> {
+    secs.map(timestamp)
+        .transpose()
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct FileInfoV2 {
+    pub access_ts: Option<Timestamp>,
+    pub modify_ts: Option<Timestamp>,
+    pub created_ts: Option<Timestamp>,
+
+    pub size: u64,
+    pub mode: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct EventV2 {
+    pub file_path: String,
+    pub event_type: EventType,
+    pub solana_ts_received_at: Timestamp,
+    pub file_info: Option<FileInfoV2>,
+}
+
+/// [CompressedEvent] of the schema 2.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct CompressedEventV2 {
+    pub prefix: u8,
+    pub path_suffix: String,
+    pub event_type: EventType,
+    pub solana_ts_received_at: Timestamp,
+    pub file_info: Option<FileInfoV2>,
+}
+
+impl TryFrom<&FileInfo> for FileInfoV2 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(info: &FileInfo) -> Result<Self, Self::Error> {
+        Ok(FileInfoV2 {
+            access_ts: optional(info.access_ts)?,
+            modify_ts: optional(info.modify_ts)?,
+            created_ts: optional(info.created_ts)?,
+            size: info.size,
+            mode: info.mode,
+        })
+    }
+}
+
+impl From<FileInfoV2> for FileInfo {
+    fn from(info: FileInfoV2) -> Self {
+        FileInfo {
+            access_ts: info
+                .access_ts
+                .map(|t| t.secs()),
+            modify_ts: info
+                .modify_ts
+                .map(|t| t.secs()),
+            created_ts: info
+                .created_ts
+                .map(|t| t.secs()),
+            size: info.size,
+            mode: info.mode,
+            uid: None,
+            gid: None,
+            user: None,
+            group: None,
+            xattrs: Vec::new(),
+            is_symlink: false,
+            link_target: None,
+            inode: None,
+            device: None,
+            nlink: None,
+            file_type: None,
+        }
+    }
+}
+
+impl TryFrom<&Event> for EventV2 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(event: &Event) -> Result<Self, Self::Error> {
+        Ok(EventV2 {
+            file_path: event.file_path.clone(),
+            event_type: event.event_type.clone(),
+            solana_ts_received_at: timestamp(event.solana_ts_received_at)?,
+            file_info: event
+                .file_info
+                .as_ref()
+                .map(FileInfoV2::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl From<EventV2> for Event {
+    fn from(event: EventV2) -> Self {
+        let file_info: Option<FileInfo> = event
+            .file_info
+            .map(Into::into);
+        Event {
+            solana_ts_received_at: event
+                .solana_ts_received_at
+                .secs(),
+            kind: FileKind::of(file_info.as_ref()),
+            file_info,
+            ..Event::new(event.file_path, event.event_type)
+        }
+    }
+}
+
+impl TryFrom<&CompressedEvent> for CompressedEventV2 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(event: &CompressedEvent) -> Result<Self, Self::Error> {
+        Ok(CompressedEventV2 {
+            prefix: event.prefix,
+            path_suffix: event.path_suffix.clone(),
+            event_type: event.event_type.clone(),
+            solana_ts_received_at: timestamp(event.solana_ts_received_at)?,
+            file_info: event
+                .file_info
+                .as_ref()
+                .map(FileInfoV2::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl From<CompressedEventV2> for CompressedEvent {
+    fn from(event: CompressedEventV2) -> Self {
+        CompressedEvent {
+            prefix: event.prefix,
+            path_suffix: event.path_suffix,
+            event_type: event.event_type,
+            solana_ts_received_at: event
+                .solana_ts_received_at
+                .secs(),
+            file_info: event
+                .file_info
+                .map(Into::into),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_2_round_trip() {
+        let event = Event {
+            solana_ts_received_at: 1729000000,
+            file_info: Some(FileInfo {
+                access_ts: Some(1729000000),
+                modify_ts: Some(-1),
+                created_ts: None,
+                size: 100,
+                mode: 0o644,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            }),
+            ..Event::new("/srv/data/a.txt", EventType::Written)
+        };
+        let v2 = EventV2::try_from(&event).unwrap();
+        // 7 bytes less for each of the 3 timestamps set
+        assert_eq!(
+            borsh::to_vec(&event)
+                .unwrap()
+                .len()
+                - borsh::to_vec(&v2)
+                    .unwrap()
+                    .len(),
+            3 * 7
+        );
+        let deserialized = EventV2::try_from_slice(&borsh::to_vec(&v2).unwrap()).unwrap();
+        assert_eq!(Event::from(deserialized), event);
+
+        let too_late = Event {
+            solana_ts_received_at: i128::from(i64::MAX) + 1,
+            ..event
+        };
+        assert_eq!(
+            EventV2::try_from(&too_late),
+            Err(TimestampOutOfRange(i128::from(i64::MAX) + 1))
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! The events of the schema 3: the schema 2 with what the watcher knows about the event
+//! besides the file, e.g. the process which made the change, and the file's kind, owner,
+//! link target and type, stored in the vault too.
+//!
+//! Like the schema 2, converted to and from the events of the schema 1 on the wire.
+
+use alloc::string::{String, ToString};
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+
+use crate::{
+    Event, EventType, EventV2, FileInfo, FileInfoV2, FileKind, PathDictionary, Process, Timestamp,
+    TimestampOutOfRange,
+};
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct FileInfoV3 {
+    pub access_ts: Option<Timestamp>,
+    pub modify_ts: Option<Timestamp>,
+    pub created_ts: Option<Timestamp>,
+
+    pub size: u64,
+    pub mode: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    // The info is the link's own, not its target's
+    pub is_symlink: bool,
+    pub link_target: Option<String>,
+    // MIME type sniffed from the content of the created and written files, e.g. image/png
+    pub file_type: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct EventV3 {
+    pub file_path: String,
+    pub event_type: EventType,
+    pub solana_ts_received_at: Timestamp,
+    pub file_info: Option<FileInfoV3>,
+    // Who made the change, known only to the audit source
+    pub process: Option<Process>,
+    // Number of the event among the ones its program records, from 1 in the order the
+    // tracker passed them on to the sinks, 0 if not numbered
+    pub sequence: u64,
+    // Unix timestamp in milliseconds, marks the time when the watcher read the event.
+    // Assigned according to the system clock, 0 if not known
+    pub captured_at: i64,
+    // What the path is, known also when it's gone, e.g. a deleted directory
+    pub kind: FileKind,
+    // Events of the file left out in favor of this one by the rate limit
+    pub suppressed_count: u32,
+}
+
+/// [crate::CompressedEvent] of the schema 3.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct CompressedEventV3 {
+    pub prefix: u8,
+    pub path_suffix: String,
+    pub event_type: EventType,
+    pub solana_ts_received_at: Timestamp,
+    pub file_info: Option<FileInfoV3>,
+    pub process: Option<Process>,
+    pub sequence: u64,
+    pub captured_at: i64,
+    pub kind: FileKind,
+    pub suppressed_count: u32,
+}
+
+impl TryFrom<&FileInfo> for FileInfoV3 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(info: &FileInfo) -> Result<Self, Self::Error> {
+        let info_v2 = FileInfoV2::try_from(info)?;
+        Ok(FileInfoV3 {
+            access_ts: info_v2.access_ts,
+            modify_ts: info_v2.modify_ts,
+            created_ts: info_v2.created_ts,
+            size: info_v2.size,
+            mode: info_v2.mode,
+            uid: info.uid,
+            gid: info.gid,
+            is_symlink: info.is_symlink,
+            link_target: info.link_target.clone(),
+            file_type: info.file_type.clone(),
+        })
+    }
+}
+
+impl From<FileInfoV3> for FileInfo {
+    fn from(info: FileInfoV3) -> Self {
+        let info_v2 = FileInfoV2 {
+            access_ts: info.access_ts,
+            modify_ts: info.modify_ts,
+            created_ts: info.created_ts,
+            size: info.size,
+            mode: info.mode,
+        };
+        FileInfo {
+            uid: info.uid,
+            gid: info.gid,
+            is_symlink: info.is_symlink,
+            link_target: info.link_target,
+            file_type: info.file_type,
+            ..info_v2.into()
+        }
+    }
+}
+
+impl TryFrom<&Event> for EventV3 {
+    type Error = TimestampOutOfRange;
+
+    fn try_from(event: &Event) -> Result<Self, Self::Error> {
+        let event_v2 = EventV2::try_from(event)?;
+        Ok(EventV3 {
+            file_path: event_v2.file_path,
+            event_type: event_v2.event_type,
+            solana_ts_received_at: event_v2.solana_ts_received_at,
+            file_info: event
+                .file_info
+                .as_ref()
+                .map(FileInfoV3::try_from)
+                .transpose()?,
+            process: event.process.clone(),
+            sequence: event.sequence,
+            captured_at: event.captured_at,
+            kind: event.kind,
+            suppressed_count: event.suppressed_count,
+        })
+    }
+}
+
+impl From<EventV3> for Event {
+    fn from(event: EventV3) -> Self {
+        let event_v2 = EventV2 {
+            file_path: event.file_path,
+            event_type: event.event_type,
+            solana_ts_received_at: event.solana_ts_received_at,
+            file_info: None,
+        };
+        Event {
+            file_info: event
+                .file_info
+                .map(Into::into),
+            kind: event.kind,
+            suppressed_count: event.suppressed_count,
+            process: event.process,
+            sequence: event.sequence,
+            captured_at: event.captured_at,
+            ..event_v2.into()
+        }
+    }
+}
+
+impl PathDictionary {
+    /// [Self::compress] of the schema 3.
+    pub fn compress_v3(&self, event: &EventV3) -> Option<CompressedEventV3> {
+        let (prefix, path_suffix) = self.split(&event.file_path)?;
+
+        Some(CompressedEventV3 {
+            prefix,
+            path_suffix: path_suffix.to_string(),
+            event_type: event.event_type.clone(),
+            solana_ts_received_at: event.solana_ts_received_at,
+            file_info: event.file_info.clone(),
+            process: event.process.clone(),
+            sequence: event.sequence,
+            captured_at: event.captured_at,
+            kind: event.kind,
+            suppressed_count: event.suppressed_count,
+        })
+    }
+
+    /// [Self::expand] of the schema 3.
+    pub fn expand_v3(&self, event: CompressedEventV3) -> Option<EventV3> {
+        let file_path = self.join(event.prefix, &event.path_suffix)?;
+
+        Some(EventV3 {
+            file_path,
+            event_type: event.event_type,
+            solana_ts_received_at: event.solana_ts_received_at,
+            file_info: event.file_info,
+            process: event.process,
+            sequence: event.sequence,
+            captured_at: event.captured_at,
+            kind: event.kind,
+            suppressed_count: event.suppressed_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_schema_3_round_trip() {
+        let event = Event {
+            solana_ts_received_at: 1729000000,
+            process: Some(Process {
+                pid: 4242,
+                exe: "/usr/bin/vim".to_string(),
+            }),
+            sequence: 1042,
+            captured_at: 1729000000123,
+            kind: FileKind::Symlink,
+            suppressed_count: 3,
+            file_info: Some(FileInfo {
+                access_ts: Some(1729000000),
+                modify_ts: Some(1729000000),
+                created_ts: None,
+                size: 100,
+                mode: 0o120777,
+                uid: Some(1000),
+                gid: Some(1000),
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: true,
+                link_target: Some("a.txt".to_string()),
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: Some("text/plain".to_string()),
+            }),
+            ..Event::new("/srv/data/a.txt", EventType::Written)
+        };
+        let v3 = EventV3::try_from(&event).unwrap();
+        let deserialized = EventV3::try_from_slice(&borsh::to_vec(&v3).unwrap()).unwrap();
+        assert_eq!(Event::from(deserialized), event);
+
+        // Stored with its path compressed, with all of its fields
+        let dictionary = PathDictionary::new(alloc::vec!["/srv/data/".to_string()]);
+        let compressed = dictionary
+            .compress_v3(&v3)
+            .unwrap();
+        assert_eq!(compressed.path_suffix, "a.txt");
+        assert_eq!(dictionary.expand_v3(compressed), Some(v3));
+    }
+}
@@ -0,0 +1,326 @@
+//! History of the files recorded by the file event tracker, for the auditors building their
+//! own verification tools without the tracker's binary.
+//!
+//! A wallet's vault holds the last event of every file, the earlier events are in the
+//! transactions which recorded them. Fetch the vault's account and its successful
+//! transactions with any RPC client, then:
+//!
+//! ```ignore
+//! let address = file_event_history::vault_address(&program, &wallet, 0);
+//! let vault = file_event_history::decode_vault(&account.data)?;
+//! let history = file_event_history::file_history(
+//!     &program,
+//!     &address,
+//!     &vault.path_dictionary,
+//!     "/srv/data/a.txt",
+//!     transactions,
+//! )?;
+//! assert!(history.matches(&vault));
+//! ```
+//!
+//! Once a vault is full its [AccountData::next_vault] links the overflow vault,
+//! of the next epoch, getting the new files.
+
+use file_event_tracker::instruction::EventTrackerInstruction;
+use solana_program::message::VersionedMessage;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+
+pub use file_event_tracker::processor::{AccountData, WatcherIdentity};
+pub use file_event_types::{Event, EventType, FileInfo, FileKind, PathDictionary};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to decode the vault: {0}")]
+    Vault(borsh::io::Error),
+    #[error("Invalid instruction of the program in the transaction {0}: {1}")]
+    Instruction(String, ProgramError),
+    #[error("Compressed event with an unknown path prefix {1} in the transaction {0}")]
+    UnknownPathPrefix(String, u8),
+}
+
+/// A successful transaction, as fetched from the RPC.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub message: VersionedMessage,
+}
+
+/// An event with the transaction which recorded it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recorded {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub event: Event,
+}
+
+/// The events of a file, oldest first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct History {
+    pub path: String,
+    pub records: Vec<Recorded>,
+}
+
+impl History {
+    pub fn last(&self) -> Option<&Event> {
+        self.records
+            .last()
+            .map(|r| &r.event)
+    }
+
+    /// Whether the last event of the history is the one the vault holds for the file,
+    /// the transactions are missing a later event otherwise.
+    pub fn matches(&self, vault: &AccountData) -> bool {
+        self.last()
+            == vault
+                .last_file_events
+                .get(&self.path)
+    }
+}
+
+/// Address of the wallet's vault of the `epoch`, the first vault is of the epoch 0.
+pub fn vault_address(program: &Pubkey, wallet: &Pubkey, epoch: u32) -> Pubkey {
+    file_event_tracker::processor::find_vault_address(program, wallet, epoch).0
+}
+
+/// Decodes the vault's account data.
+pub fn decode_vault(data: &[u8]) -> Result<AccountData, Error> {
+    AccountData::unpack(data).map_err(Error::Vault)
+}
+
+/// The events the transaction recorded in the vault, the compressed ones expanded
+/// with the vault's path dictionary.
+pub fn recorded_events(
+    program: &Pubkey,
+    vault: &Pubkey,
+    dictionary: &PathDictionary,
+    transaction: &Transaction,
+) -> Result<Vec<Event>, Error> {
+    let keys = transaction
+        .message
+        .static_account_keys();
+    let key = |index: u8| keys.get(usize::from(index));
+    let mut events = Vec::new();
+    for instruction in transaction
+        .message
+        .instructions()
+    {
+        // The vault is the second account of the events' instructions
+        if key(instruction.program_id_index) != Some(program)
+            || instruction
+                .accounts
+                .get(1)
+                .and_then(|&index| key(index))
+                != Some(vault)
+        {
+            continue;
+        }
+        let unpacked = EventTrackerInstruction::unpack(&instruction.data)
+            .map_err(|e| Error::Instruction(transaction.signature.clone(), e))?;
+        match unpacked {
+            EventTrackerInstruction::AddEvent(data) => events.push(data.event),
+            EventTrackerInstruction::AddCompressedEvent(data) => {
+                let prefix = data.event.prefix;
+                let event = dictionary
+                    .expand(data.event)
+                    .ok_or_else(|| {
+                        Error::UnknownPathPrefix(transaction.signature.clone(), prefix)
+                    })?;
+                events.push(event);
+            }
+            EventTrackerInstruction::AddEventV2(data) => events.push(data.event.into()),
+            EventTrackerInstruction::AddCompressedEventV2(data) => {
+                let prefix = data.event.prefix;
+                let event = dictionary
+                    .expand(data.event.into())
+                    .ok_or_else(|| {
+                        Error::UnknownPathPrefix(transaction.signature.clone(), prefix)
+                    })?;
+                events.push(event);
+            }
+            EventTrackerInstruction::AddEventV3(data) => events.push(data.event.into()),
+            EventTrackerInstruction::AddCompressedEventV3(data) => {
+                let prefix = data.event.prefix;
+                let event = dictionary
+                    .expand_v3(data.event)
+                    .ok_or_else(|| {
+                        Error::UnknownPathPrefix(transaction.signature.clone(), prefix)
+                    })?;
+                events.push(event.into());
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+/// Reconstructs the history of the file in the vault from the vault's transactions,
+/// in any order. The order of the transactions of the same slot is kept.
+pub fn file_history(
+    program: &Pubkey,
+    vault: &Pubkey,
+    dictionary: &PathDictionary,
+    path: &str,
+    transactions: impl IntoIterator<Item = Transaction>,
+) -> Result<History, Error> {
+    let mut records = Vec::new();
+    for transaction in transactions {
+        for event in recorded_events(program, vault, dictionary, &transaction)? {
+            if event.file_path != path {
+                continue;
+            }
+            records.push(Recorded {
+                signature: transaction.signature.clone(),
+                slot: transaction.slot,
+                block_time: transaction.block_time,
+                event,
+            });
+        }
+    }
+    records.sort_by_key(|r| r.slot);
+    Ok(History {
+        path: path.to_string(),
+        records,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_event_tracker::instruction::{
+        AddCompressedEventInstructionData, AddEventInstructionData, AddEventV2InstructionData,
+    };
+    use solana_program::instruction::{AccountMeta, Instruction};
+    use solana_program::message::Message;
+
+    fn event(path: &str, event_type: EventType, ts: i128) -> Event {
+        Event {
+            solana_ts_received_at: ts,
+            ..Event::new(path, event_type)
+        }
+    }
+
+    fn transaction(
+        signature: &str,
+        slot: u64,
+        instructions: Vec<(Pubkey, Pubkey, EventTrackerInstruction)>,
+        payer: &Pubkey,
+    ) -> Transaction {
+        let instructions: Vec<Instruction> = instructions
+            .into_iter()
+            .map(|(program, vault, instruction)| Instruction {
+                program_id: program,
+                accounts: vec![
+                    AccountMeta::new(*payer, true),
+                    AccountMeta::new(vault, false),
+                ],
+                data: instruction.pack().unwrap(),
+            })
+            .collect();
+        Transaction {
+            signature: signature.to_string(),
+            slot,
+            block_time: None,
+            message: VersionedMessage::Legacy(Message::new(&instructions, Some(payer))),
+        }
+    }
+
+    #[test]
+    fn test_history_reconstructed() {
+        let (program, wallet) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let vault = vault_address(&program, &wallet, 0);
+        let other_vault = vault_address(&program, &wallet, 1);
+        let dictionary = PathDictionary::new(vec!["/srv/data/".to_string()]);
+        let add =
+            |event: Event| EventTrackerInstruction::AddEvent(AddEventInstructionData { event });
+        let created = event("/srv/data/a.txt", EventType::Created, 10);
+        let written = event("/srv/data/a.txt", EventType::Written, 20);
+        // Of the schema 2
+        let first_write = event("/srv/data/a.txt", EventType::Written, 15);
+        let add_v2 = EventTrackerInstruction::AddEventV2(AddEventV2InstructionData {
+            event: (&first_write)
+                .try_into()
+                .unwrap(),
+        });
+        let compressed =
+            EventTrackerInstruction::AddCompressedEvent(AddCompressedEventInstructionData {
+                event: dictionary
+                    .compress(&written)
+                    .unwrap(),
+            });
+
+        // Newest first, as listed by the RPC
+        let transactions = vec![
+            transaction("3", 30, vec![(program, vault, compressed)], &wallet),
+            transaction(
+                "2",
+                20,
+                vec![
+                    (
+                        program,
+                        vault,
+                        add(event("/srv/data/b.txt", EventType::Created, 15)),
+                    ),
+                    (program, vault, add_v2),
+                    (
+                        program,
+                        other_vault,
+                        add(event("/srv/data/a.txt", EventType::Deleted, 15)),
+                    ),
+                    (
+                        Pubkey::new_unique(),
+                        vault,
+                        add(event("/srv/data/a.txt", EventType::Deleted, 15)),
+                    ),
+                ],
+                &wallet,
+            ),
+            transaction(
+                "1",
+                10,
+                vec![(program, vault, add(created.clone()))],
+                &wallet,
+            ),
+        ];
+        let history = file_history(
+            &program,
+            &vault,
+            &dictionary,
+            "/srv/data/a.txt",
+            transactions.clone(),
+        )
+        .unwrap();
+        let recorded: Vec<(&str, &Event)> = history
+            .records
+            .iter()
+            .map(|r| (r.signature.as_str(), &r.event))
+            .collect();
+        assert_eq!(
+            recorded,
+            vec![("1", &created), ("2", &first_write), ("3", &written)]
+        );
+
+        let mut account = AccountData::default();
+        account
+            .last_file_events
+            .insert(written.file_path.clone(), written.clone());
+        let mut data = Vec::new();
+        borsh::BorshSerialize::serialize(&account, &mut data).unwrap();
+        assert!(history.matches(&decode_vault(&data).unwrap()));
+
+        // Without the dictionary the compressed event can't be expanded
+        assert!(matches!(
+            file_history(
+                &program,
+                &vault,
+                &PathDictionary::default(),
+                "/srv/data/a.txt",
+                transactions
+            ),
+            Err(Error::UnknownPathPrefix(signature, 0)) if signature == "3"
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+//! Borsh costs of the events, the vault's content and the instruction data.
+//! Run with `cargo bench`, the numbers justify changes of the data layout.
+
+use borsh::BorshDeserialize;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use file_event_tracker::event::{Event, EventType, FileInfo};
+use file_event_tracker::instruction::{AddEventInstructionData, EventTrackerInstruction};
+use file_event_tracker::processor::AccountData;
+use std::collections::HashMap;
+use std::hint::black_box;
+
+// From a short relative path up to the usual PATH_MAX
+const PATH_LENGTHS: [usize; 3] = [16, 256, 4096];
+// Files tracked in one vault
+const VAULT_ENTRIES: [usize; 3] = [1, 16, 256];
+
+fn event(file_path: String) -> Event {
+    Event {
+        solana_ts_received_at: 1_729_000_000,
+        file_info: Some(FileInfo {
+            access_ts: Some(1_729_000_000),
+            modify_ts: Some(1_729_000_000),
+            created_ts: None,
+            size: 4096,
+            mode: 0o100644,
+            uid: None,
+            gid: None,
+            user: None,
+            group: None,
+            xattrs: Vec::new(),
+            is_symlink: false,
+            link_target: None,
+            inode: None,
+            device: None,
+            nlink: None,
+            file_type: None,
+        }),
+        ..Event::new(file_path, EventType::Written)
+    }
+}
+
+fn path_of_len(len: usize) -> String {
+    format!("/{}", "a".repeat(len - 1))
+}
+
+fn bench_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("event");
+    for path_len in PATH_LENGTHS {
+        let event = event(path_of_len(path_len));
+        let bytes = borsh::to_vec(&event).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("serialize", path_len),
+            &event,
+            |b, event| b.iter(|| borsh::to_vec(black_box(event)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("deserialize", path_len),
+            &bytes,
+            |b, bytes| b.iter(|| Event::try_from_slice(black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_account_data(c: &mut Criterion) {
+    let mut group = c.benchmark_group("account_data");
+    for entries in VAULT_ENTRIES {
+        let account_data = AccountData {
+            last_file_events: (0..entries)
+                .map(|i| {
+                    let path = format!("/var/log/tracked/file-{}.log", i);
+                    (path.clone(), event(path))
+                })
+                .collect::<HashMap<_, _>>(),
+            ..AccountData::default()
+        };
+        let bytes = borsh::to_vec(&account_data).unwrap();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("serialize", entries),
+            &account_data,
+            |b, account_data| b.iter(|| borsh::to_vec(black_box(account_data)).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("deserialize", entries),
+            &bytes,
+            |b, bytes| b.iter(|| AccountData::try_from_slice(black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_instruction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_event_instruction");
+    for path_len in PATH_LENGTHS {
+        let instruction = EventTrackerInstruction::AddEvent(AddEventInstructionData {
+            event: event(path_of_len(path_len)),
+        });
+        let packed = instruction.pack().unwrap();
+        group.throughput(Throughput::Bytes(packed.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("pack", path_len),
+            &instruction,
+            |b, instruction| {
+                b.iter(|| {
+                    black_box(instruction)
+                        .pack()
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("unpack", path_len),
+            &packed,
+            |b, packed| b.iter(|| EventTrackerInstruction::unpack(black_box(packed)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_event, bench_account_data, bench_instruction);
+criterion_main!(benches);
@@ -8,3 +8,5 @@ cargo build-sbf
 # When using different program keys: https://docs.solanalabs.com/cli/examples/deploy-a-program
 solana program deploy ./target/deploy/file_event_tracker.so
 solana program show --programs
+# IDL for the explorers, of the deployed address
+cargo run --example idl -- "$(solana address -k ./target/deploy/file_event_tracker-keypair.json)" > ./target/deploy/file_event_tracker.json
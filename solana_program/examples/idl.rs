@@ -0,0 +1,16 @@
+//! Prints the program's IDL, e.g. of the default deployment:
+//! ```sh
+//! cargo run --example idl -- BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd > idl/file_event_tracker.json
+//! ```
+
+use solana_program::pubkey::Pubkey;
+use std::str::FromStr;
+
+fn main() {
+    let program_id = std::env::args()
+        .nth(1)
+        .expect("Usage: idl <PROGRAM_ID>");
+    let program_id = Pubkey::from_str(&program_id).expect("Invalid program id");
+    let idl = file_event_tracker::idl::idl(&program_id);
+    println!("{}", serde_json::to_string_pretty(&idl).unwrap());
+}
@@ -0,0 +1,22 @@
+//! Arbitrary vault content. The vault is owned by the program, but its data
+//! is read back on every AddEvent, whatever an older version left there.
+
+#![no_main]
+
+use borsh::BorshDeserialize;
+use file_event_tracker::processor::AccountData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // What the program actually uses to read the vault, it must not panic either
+    let _ = AccountData::unpack(data);
+
+    let Ok(account_data) = AccountData::try_from_slice(data) else {
+        return;
+    };
+
+    let serialized = borsh::to_vec(&account_data).expect("failed to serialize account data");
+    let deserialized = AccountData::try_from_slice(&serialized)
+        .expect("failed to read back serialized account data");
+    assert_eq!(account_data, deserialized);
+});
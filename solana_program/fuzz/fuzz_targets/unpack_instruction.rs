@@ -0,0 +1,22 @@
+//! Arbitrary instruction data, as anyone can send it to the program.
+
+#![no_main]
+
+use file_event_tracker::instruction::EventTrackerInstruction;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Malformed data has to be rejected with an error, never with a panic
+    let Ok(instruction) = EventTrackerInstruction::unpack(data) else {
+        return;
+    };
+
+    // Whatever got accepted has to survive a round trip,
+    // the trailing bytes ignored by unpack are dropped on the way
+    let packed = instruction
+        .pack()
+        .expect("failed to pack an unpacked instruction");
+    let unpacked =
+        EventTrackerInstruction::unpack(&packed).expect("failed to unpack a packed instruction");
+    assert_eq!(instruction, unpacked);
+});
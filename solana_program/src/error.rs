@@ -13,6 +13,22 @@ use {
 pub enum TrackerError {
     #[error("This program is absolutely deaf to your requests")]
     Deaf,
+    #[error("Too many path prefixes")]
+    TooManyPathPrefixes,
+    #[error("Path prefix not found in the vault")]
+    UnknownPathPrefix,
+    #[error("Not the last vault of the user")]
+    NotLastVault,
+    #[error("Not the signing key of the watcher registered in the vault")]
+    UnregisteredWatcher,
+    #[error("Unsupported schema version")]
+    UnsupportedSchema,
+    #[error("Timestamp out of the range of the registered schema")]
+    TimestampOutOfRange,
+    #[error("Instruction not supported by the program")]
+    UnsupportedInstruction,
+    #[error("Not the next vault linked from the previous one")]
+    NotNextVault,
 }
 
 impl From<TrackerError> for ProgramError {
@@ -39,6 +55,22 @@ impl PrintProgramError for TrackerError {
             TrackerError::Deaf => {
                 msg!("Error: This program is absolutely deaf to your requests")
             }
+            TrackerError::TooManyPathPrefixes => msg!("Error: Too many path prefixes"),
+            TrackerError::UnknownPathPrefix => msg!("Error: Path prefix not found in the vault"),
+            TrackerError::NotLastVault => msg!("Error: Not the last vault of the user"),
+            TrackerError::UnregisteredWatcher => {
+                msg!("Error: Not the signing key of the watcher registered in the vault")
+            }
+            TrackerError::UnsupportedSchema => msg!("Error: Unsupported schema version"),
+            TrackerError::TimestampOutOfRange => {
+                msg!("Error: Timestamp out of the range of the registered schema")
+            }
+            TrackerError::UnsupportedInstruction => {
+                msg!("Error: Instruction not supported by the program")
+            }
+            TrackerError::NotNextVault => {
+                msg!("Error: Not the next vault linked from the previous one")
+            }
         }
     }
 }
@@ -0,0 +1,356 @@
+//! IDL of the program in the Anchor format (spec 0.1.0), for explorers and indexers.
+//!
+//! The program is not an Anchor program: the instructions are tagged with a single byte,
+//! given as their discriminator, and the vault has no discriminator at all.
+//! The types are generated from the Borsh schemas, so the IDL follows the layout changes.
+//! The vault is described in the layout of the schema 1, the vaults of the schemas 2 and 3
+//! start with a marker and the version, and hold the events of their schema,
+//! see `AccountData::pack`.
+
+use borsh::schema::{BorshSchemaContainer, Declaration, Definition, Fields};
+use borsh::BorshSchema;
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+use solana_program::{pubkey::Pubkey, system_program};
+use std::collections::BTreeMap;
+
+use crate::{
+    error::TrackerError,
+    instruction::{
+        AddCompressedEventInstructionData, AddCompressedEventV2InstructionData,
+        AddCompressedEventV3InstructionData, AddEventInstructionData, AddEventV2InstructionData,
+        AddEventV3InstructionData, AddEventsDroppedInstructionData, InitializeInstructionData,
+        InitializeOverflowInstructionData, RegisterWatcherInstructionData,
+    },
+    processor::{AccountData, PDA_SEED_PREFIX},
+};
+
+/// Builds the IDL of the program deployed at `program_id`.
+pub fn idl(program_id: &Pubkey) -> Value {
+    let mut types = Types::default();
+
+    let instructions = vec![
+        instruction(
+            "initialize",
+            "Create the user's vault, storing the path prefixes of the compressed events.",
+            0,
+            true,
+            types.args::<InitializeInstructionData>(),
+        ),
+        instruction(
+            "add_event",
+            "Add new event to the user's vault.",
+            1,
+            false,
+            types.args::<AddEventInstructionData>(),
+        ),
+        instruction(
+            "close_account",
+            "Close the user's vault, draining its lamports to the user.",
+            2,
+            false,
+            Vec::new(),
+        ),
+        instruction(
+            "add_compressed_event",
+            "Add new event to the user's vault, its path starts with a stored prefix.",
+            3,
+            false,
+            types.args::<AddCompressedEventInstructionData>(),
+        ),
+        overflow_instruction(
+            "initialize_overflow",
+            "Create the user's next vault once the last one is full, linked from the last one.",
+            4,
+            types.args::<InitializeOverflowInstructionData>(),
+        ),
+        instruction(
+            "register_watcher",
+            "Store the identity of the watcher feeding the user's vault, only its key signs the events.",
+            5,
+            false,
+            types.args::<RegisterWatcherInstructionData>(),
+        ),
+        instruction(
+            "add_event_v2",
+            "Add new event to the user's vault, its timestamps as i64 seconds.",
+            6,
+            false,
+            types.args::<AddEventV2InstructionData>(),
+        ),
+        instruction(
+            "add_compressed_event_v2",
+            "Add new event to the user's vault, its path compressed and its timestamps as i64 seconds.",
+            7,
+            false,
+            types.args::<AddCompressedEventV2InstructionData>(),
+        ),
+        instruction(
+            "add_events_dropped",
+            "Record that the watcher dropped events, with their count and time range.",
+            8,
+            false,
+            types.args::<AddEventsDroppedInstructionData>(),
+        ),
+        instruction(
+            "add_event_v3",
+            "Add new event to the user's vault, with the watcher's details of the event, e.g. its process and sequence number.",
+            9,
+            false,
+            types.args::<AddEventV3InstructionData>(),
+        ),
+        instruction(
+            "add_compressed_event_v3",
+            "Add new event to the user's vault, its path compressed, with the watcher's details of the event.",
+            10,
+            false,
+            types.args::<AddCompressedEventV3InstructionData>(),
+        ),
+    ];
+    types.add::<AccountData>();
+
+    let errors: Vec<Value> = (0..)
+        .map_while(TrackerError::from_u32)
+        .map(|error| {
+            json!({
+                "code": error.clone() as u32,
+                "name": format!("{:?}", error),
+                "msg": error.to_string(),
+            })
+        })
+        .collect();
+
+    json!({
+        "address": program_id.to_string(),
+        "metadata": {
+            "name": env!("CARGO_PKG_NAME").replace('-', "_"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "spec": "0.1.0",
+            "description": "Tracks the file events, the vault keeps the last event of each file",
+        },
+        "instructions": instructions,
+        "accounts": [{ "name": "AccountData", "discriminator": [] }],
+        "errors": errors,
+        "types": types.defined.into_values().collect::<Vec<_>>(),
+    })
+}
+
+fn instruction(name: &str, doc: &str, tag: u8, creates_vault: bool, args: Vec<Value>) -> Value {
+    let mut accounts = vec![
+        json!({ "name": "user", "writable": true, "signer": true }),
+        json!({
+            "name": "vault",
+            "writable": true,
+            "pda": {
+                "seeds": [
+                    { "kind": "const", "value": PDA_SEED_PREFIX },
+                    { "kind": "account", "path": "user" },
+                ],
+            },
+        }),
+    ];
+    if creates_vault {
+        accounts
+            .push(json!({ "name": "system_program", "address": system_program::id().to_string() }));
+    }
+    json!({
+        "name": name,
+        "docs": [doc],
+        "discriminator": [tag],
+        "accounts": accounts,
+        "args": args,
+    })
+}
+
+// The overflow vault's seeds end with the epoch, the last vault is of the previous one
+fn overflow_instruction(name: &str, doc: &str, tag: u8, args: Vec<Value>) -> Value {
+    json!({
+        "name": name,
+        "docs": [doc],
+        "discriminator": [tag],
+        "accounts": [
+            { "name": "user", "writable": true, "signer": true },
+            { "name": "last_vault", "writable": true },
+            {
+                "name": "vault",
+                "writable": true,
+                "pda": {
+                    "seeds": [
+                        { "kind": "const", "value": PDA_SEED_PREFIX },
+                        { "kind": "account", "path": "user" },
+                        { "kind": "arg", "path": "epoch" },
+                    ],
+                },
+            },
+            { "name": "system_program", "address": system_program::id().to_string() },
+        ],
+        "args": args,
+    })
+}
+
+// The defined types, by name
+#[derive(Default)]
+struct Types {
+    defined: BTreeMap<String, Value>,
+}
+
+impl Types {
+    // The fields of the instruction's data, as its arguments
+    fn args<T: BorshSchema>(&mut self) -> Vec<Value> {
+        let schema = borsh::schema_container_of::<T>();
+        match schema.get_definition(schema.declaration()) {
+            Some(Definition::Struct {
+                fields: Fields::NamedFields(fields),
+            }) => fields
+                .iter()
+                .map(|(name, declaration)| {
+                    let idl_type = self.idl_type(&schema, declaration);
+                    json!({ "name": name, "type": idl_type })
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn add<T: BorshSchema>(&mut self) {
+        let schema = borsh::schema_container_of::<T>();
+        self.idl_type(&schema, schema.declaration());
+    }
+
+    // The IDL type of the declaration, the structs and enums are added to the defined types
+    fn idl_type(&mut self, schema: &BorshSchemaContainer, declaration: &Declaration) -> Value {
+        match (declaration.as_str(), schema.get_definition(declaration)) {
+            ("String", _) => json!("string"),
+            ("Pubkey", _) => json!("pubkey"),
+            (primitive, Some(Definition::Primitive(_))) => json!(primitive),
+            (
+                _,
+                Some(Definition::Sequence {
+                    length_width: 0,
+                    length_range,
+                    elements,
+                }),
+            ) => {
+                json!({ "array": [self.idl_type(schema, elements), length_range.start()] })
+            }
+            (_, Some(Definition::Sequence { elements, .. })) => {
+                json!({ "vec": self.idl_type(schema, elements) })
+            }
+            (_, Some(Definition::Enum { variants, .. }))
+                if variants.len() == 2 && variants[0].1 == "None" && variants[1].1 == "Some" =>
+            {
+                json!({ "option": self.idl_type(schema, &variants[1].2) })
+            }
+            (_, Some(Definition::Tuple { elements })) => {
+                // A map's entry, e.g. (String, Event) of a HashMap<String, Event>
+                let fields: Vec<Value> = elements
+                    .iter()
+                    .map(|e| self.idl_type(schema, e))
+                    .collect();
+                let name = format!(
+                    "{}Entry",
+                    elements
+                        .iter()
+                        .map(|e| type_name(e))
+                        .collect::<String>()
+                );
+                self.define(name, json!({ "kind": "struct", "fields": fields }))
+            }
+            (name, Some(Definition::Struct { fields })) => {
+                let fields = self.fields(schema, fields);
+                self.define(
+                    name.to_string(),
+                    json!({ "kind": "struct", "fields": fields }),
+                )
+            }
+            (name, Some(Definition::Enum { variants, .. })) => {
+                let variants: Vec<Value> = variants
+                    .iter()
+                    .map(|(_, variant, declaration)| {
+                        let fields = match schema.get_definition(declaration) {
+                            Some(Definition::Struct { fields }) => self.fields(schema, fields),
+                            _ => vec![self.idl_type(schema, declaration)],
+                        };
+                        match fields.is_empty() {
+                            true => json!({ "name": variant }),
+                            false => json!({ "name": variant, "fields": fields }),
+                        }
+                    })
+                    .collect();
+                self.define(
+                    name.to_string(),
+                    json!({ "kind": "enum", "variants": variants }),
+                )
+            }
+            (name, None) => json!(name),
+        }
+    }
+
+    fn fields(&mut self, schema: &BorshSchemaContainer, fields: &Fields) -> Vec<Value> {
+        match fields {
+            Fields::NamedFields(fields) => fields
+                .iter()
+                .map(|(name, declaration)| {
+                    let idl_type = self.idl_type(schema, declaration);
+                    json!({ "name": name, "type": idl_type })
+                })
+                .collect(),
+            Fields::UnnamedFields(fields) => fields
+                .iter()
+                .map(|declaration| self.idl_type(schema, declaration))
+                .collect(),
+            Fields::Empty => Vec::new(),
+        }
+    }
+
+    fn define(&mut self, name: String, ty: Value) -> Value {
+        let defined = json!({ "defined": { "name": name } });
+        self.defined
+            .insert(name.clone(), json!({ "name": name, "type": ty }));
+        defined
+    }
+}
+
+// Name of the declared type usable in a type name, e.g. `Vec<String>` -> `VecString`
+fn type_name(declaration: &str) -> String {
+    declaration
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_idl_up_to_date() {
+        // Regenerate with `cargo run --example idl -- BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd`
+        let program_id = Pubkey::from_str("BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd").unwrap();
+        let committed: Value =
+            serde_json::from_str(include_str!("../idl/file_event_tracker.json")).unwrap();
+        let idl = idl(&program_id);
+        assert_eq!(idl, committed);
+
+        // The vault's map of the last events is a vector of its entries
+        let types = idl["types"]
+            .as_array()
+            .unwrap();
+        let account = types
+            .iter()
+            .find(|t| t["name"] == "AccountData")
+            .unwrap();
+        assert_eq!(
+            account["type"]["fields"][0]["type"],
+            json!({ "vec": { "defined": { "name": "StringEventEntry" } } })
+        );
+        assert_eq!(
+            idl["errors"]
+                .as_array()
+                .unwrap()
+                .len(),
+            9
+        );
+    }
+}
@@ -14,10 +14,59 @@ pub struct AddEventInstructionData {
     pub event: crate::event::Event,
 }
 
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddCompressedEventInstructionData {
+    pub event: crate::event::CompressedEvent,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddEventV2InstructionData {
+    pub event: crate::event::EventV2,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddCompressedEventV2InstructionData {
+    pub event: crate::event::CompressedEventV2,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddEventV3InstructionData {
+    pub event: crate::event::EventV3,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddCompressedEventV3InstructionData {
+    pub event: crate::event::CompressedEventV3,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AddEventsDroppedInstructionData {
+    pub dropped: crate::event::EventsDropped,
+}
+
 #[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
 pub struct InitializeInstructionData {
     pub lamports: u64, // to pay for rent of the PDA
     pub pda_bump_seed: u8,
+    // stored in the vault, referenced by the compressed events
+    pub path_prefixes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct InitializeOverflowInstructionData {
+    pub lamports: u64, // to pay for rent of the PDA
+    pub pda_bump_seed: u8,
+    // the overflow vaults of a user are numbered from 1, the first vault is 0
+    pub epoch: u32,
+    pub path_prefixes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct RegisterWatcherInstructionData {
+    pub hostname: String,
+    // the key signing the watcher's events, only its events are accepted
+    pub signing_key: Pubkey,
+    pub schema_version: u16,
 }
 
 // #[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
@@ -32,6 +81,10 @@ pub enum EventTrackerInstruction {
     ///
     /// Accounts expected by this instruction:
     ///
+    /// Stores the path prefixes used by [Self::AddCompressedEvent] in the PDA.
+    ///
+    /// Accounts expected by this instruction:
+    ///
     /// 0. `[writable, signer]` User account, PDA owner.
     /// 1. `[writable]` PDA found with Pubkey::find_program_address for this user.
     /// 2. `[]` System program used to create a new account.
@@ -53,6 +106,81 @@ pub enum EventTrackerInstruction {
     /// 0. `[writable, signer]` User account, PDA owner.
     /// 1. `[writable]` User's PDA
     CloseAccount,
+
+    /// Add new event to the user's PDA, its path starts with
+    /// one of the prefixes stored at initialization
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddCompressedEvent(AddCompressedEventInstructionData),
+
+    /// Create the next vault of the user, once the last one is full,
+    /// and link it from the last one.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's last PDA, of the previous epoch.
+    /// 2. `[writable]` PDA of the epoch found with Pubkey::find_program_address for this user.
+    /// 3. `[]` System program used to create a new account.
+    InitializeOverflow(InitializeOverflowInstructionData),
+
+    /// Store the identity of the watcher feeding the user's vault, sent together
+    /// with [Self::Initialize]. The events are accepted only from its signing key then,
+    /// the overflow vaults inherit it. Registered again, e.g. with a new key, it's stored
+    /// in all the vaults of the user.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's first PDA
+    /// 2. `[writable]` The overflow PDAs linked from it, in the order of their epochs.
+    RegisterWatcher(RegisterWatcherInstructionData),
+
+    /// [Self::AddEvent] of the schema 2, the timestamps as i64 seconds.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddEventV2(AddEventV2InstructionData),
+
+    /// [Self::AddCompressedEvent] of the schema 2, the timestamps as i64 seconds.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddCompressedEventV2(AddCompressedEventV2InstructionData),
+
+    /// Record that the watcher dropped events instead of sending them, e.g. when
+    /// the RPC node was too slow. Only logged, the vault is left as it is.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddEventsDropped(AddEventsDroppedInstructionData),
+
+    /// [Self::AddEventV2] of the schema 3, with what the watcher knows about the event
+    /// besides the file, e.g. the process which made the change and the event's sequence number.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddEventV3(AddEventV3InstructionData),
+
+    /// [Self::AddCompressedEventV2] of the schema 3, with what the watcher knows about the event
+    /// besides the file, see [Self::AddEventV3].
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    /// 0. `[writable, signer]` User account, PDA owner.
+    /// 1. `[writable]` User's PDA
+    AddCompressedEventV3(AddCompressedEventV3InstructionData),
     // /// Reallocate additional space in the user's PDA
     // ///
     // /// If the record account already has enough space to hold the specified
@@ -68,13 +196,25 @@ pub enum EventTrackerInstruction {
 impl EventTrackerInstruction {
     /// Unpacks a byte buffer into a [EventTrackerInstruction].
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        const U32_BYTES: usize = 4;
         const U64_BYTES: usize = 8;
 
         let (&tag, mut data) = input
             .split_first()
             .ok_or(ProgramError::InvalidInstructionData)?;
         Ok(match tag {
+            // Sent by the clients which don't know the path prefixes yet
+            0 if data.len() == U64_BYTES + 1 => {
+                let (lamports, pda_bump_seed) =
+                    <(u64, u8)>::deserialize(&mut data).map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::Initialize(InitializeInstructionData {
+                    lamports,
+                    pda_bump_seed,
+                    path_prefixes: Vec::new(),
+                })
+            }
             0 => {
                 let instruction_data =
                     InitializeInstructionData::deserialize(&mut data).map_err(|e| {
@@ -92,6 +232,70 @@ impl EventTrackerInstruction {
                 Self::AddEvent(instruction_data)
             }
             2 => Self::CloseAccount,
+            3 => {
+                let instruction_data = AddCompressedEventInstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddCompressedEvent(instruction_data)
+            }
+            4 => {
+                let instruction_data = InitializeOverflowInstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::InitializeOverflow(instruction_data)
+            }
+            5 => {
+                let instruction_data = RegisterWatcherInstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::RegisterWatcher(instruction_data)
+            }
+            6 => {
+                let instruction_data =
+                    AddEventV2InstructionData::deserialize(&mut data).map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddEventV2(instruction_data)
+            }
+            7 => {
+                let instruction_data = AddCompressedEventV2InstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddCompressedEventV2(instruction_data)
+            }
+            8 => {
+                let instruction_data = AddEventsDroppedInstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddEventsDropped(instruction_data)
+            }
+            9 => {
+                let instruction_data =
+                    AddEventV3InstructionData::deserialize(&mut data).map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddEventV3(instruction_data)
+            }
+            10 => {
+                let instruction_data = AddCompressedEventV3InstructionData::deserialize(&mut data)
+                    .map_err(|e| {
+                        msg!("Failed to deserialize instruction body: {}", e);
+                        return ProgramError::InvalidInstructionData;
+                    })?;
+                Self::AddCompressedEventV3(instruction_data)
+            }
 
             _ => return Err(ProgramError::InvalidInstructionData),
         })
@@ -110,6 +314,38 @@ impl EventTrackerInstruction {
                 data.serialize(&mut buf)?;
             }
             Self::CloseAccount => buf.push(2),
+            Self::AddCompressedEvent(data) => {
+                buf.push(3);
+                data.serialize(&mut buf)?;
+            }
+            Self::InitializeOverflow(data) => {
+                buf.push(4);
+                data.serialize(&mut buf)?;
+            }
+            Self::RegisterWatcher(data) => {
+                buf.push(5);
+                data.serialize(&mut buf)?;
+            }
+            Self::AddEventV2(data) => {
+                buf.push(6);
+                data.serialize(&mut buf)?;
+            }
+            Self::AddCompressedEventV2(data) => {
+                buf.push(7);
+                data.serialize(&mut buf)?;
+            }
+            Self::AddEventsDropped(data) => {
+                buf.push(8);
+                data.serialize(&mut buf)?;
+            }
+            Self::AddEventV3(data) => {
+                buf.push(9);
+                data.serialize(&mut buf)?;
+            }
+            Self::AddCompressedEventV3(data) => {
+                buf.push(10);
+                data.serialize(&mut buf)?;
+            }
         };
         Ok(buf)
     }
@@ -118,12 +354,343 @@ impl EventTrackerInstruction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event::{
+        CompressedEvent, Event, EventType, FileInfo, FileKind, PathDictionary, Process,
+    };
+    use proptest::prelude::*;
+
+    fn timestamp() -> impl Strategy<Value = i128> {
+        prop_oneof![
+            Just(i128::MIN),
+            Just(i128::MAX),
+            Just(0),
+            // Fits in the schema 2
+            any::<i64>().prop_map(i128::from),
+            any::<i128>()
+        ]
+    }
+
+    fn event_type() -> impl Strategy<Value = EventType> {
+        prop_oneof![
+            Just(EventType::AttributeChanged),
+            Just(EventType::Created),
+            Just(EventType::Deleted),
+            Just(EventType::MovedFrom),
+            Just(EventType::MovedTo),
+            Just(EventType::Opened),
+            Just(EventType::Written),
+            (any::<String>(), any::<String>())
+                .prop_map(|(from, to)| EventType::Renamed { from, to }),
+            Just(EventType::ReadClosed),
+            Just(EventType::Accessed),
+        ]
+    }
+
+    fn file_info() -> impl Strategy<Value = FileInfo> {
+        (
+            proptest::option::of(timestamp()),
+            proptest::option::of(timestamp()),
+            proptest::option::of(timestamp()),
+            any::<u64>(),
+            any::<u32>(),
+        )
+            .prop_map(|(access_ts, modify_ts, created_ts, size, mode)| FileInfo {
+                access_ts,
+                modify_ts,
+                created_ts,
+                size,
+                mode,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            })
+    }
+
+    fn process() -> impl Strategy<Value = Process> {
+        (any::<u32>(), any::<String>()).prop_map(|(pid, exe)| Process { pid, exe })
+    }
+
+    fn event() -> impl Strategy<Value = Event> {
+        (
+            // Any unicode, not only valid file names
+            any::<String>(),
+            event_type(),
+            timestamp(),
+            proptest::option::of(file_info()),
+            proptest::option::of(process()),
+            any::<u64>(),
+            any::<i64>(),
+        )
+            .prop_map(
+                |(
+                    file_path,
+                    event_type,
+                    solana_ts_received_at,
+                    file_info,
+                    process,
+                    sequence,
+                    captured_at,
+                )| {
+                    Event {
+                        solana_ts_received_at,
+                        process,
+                        sequence,
+                        captured_at,
+                        file_info,
+                        ..Event::new(file_path, event_type)
+                    }
+                },
+            )
+    }
+
+    fn compressed_event() -> impl Strategy<Value = CompressedEvent> {
+        (any::<u8>(), event()).prop_map(|(prefix, event)| CompressedEvent {
+            prefix,
+            path_suffix: event.file_path,
+            event_type: event.event_type,
+            solana_ts_received_at: event.solana_ts_received_at,
+            file_info: event.file_info,
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn event_round_trip(event in event()) {
+            let serialized = borsh::to_vec(&event).unwrap();
+            prop_assert_eq!(Event::try_from_slice(&serialized).unwrap(), event);
+        }
+
+        #[test]
+        fn add_event_round_trip(event in event()) {
+            let instruction = EventTrackerInstruction::AddEvent(AddEventInstructionData {
+                event: event.clone(),
+            });
+
+            let packed = instruction.pack().unwrap();
+            // The tag followed by the event exactly as borsh serializes it
+            prop_assert_eq!(packed[0], 1);
+            let serialized = borsh::to_vec(&event).unwrap();
+            prop_assert_eq!(&packed[1..], serialized.as_slice());
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_compressed_event_round_trip(event in compressed_event()) {
+            let instruction =
+                EventTrackerInstruction::AddCompressedEvent(AddCompressedEventInstructionData {
+                    event,
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 3);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_event_v2_round_trip(event in event(), nanos in proptest::option::of(any::<u32>())) {
+            let instruction = match crate::event::EventV2::try_from(&event) {
+                Ok(mut v2) => {
+                    v2.solana_ts_received_at.nanos = nanos;
+                    EventTrackerInstruction::AddEventV2(AddEventV2InstructionData { event: v2 })
+                }
+                // Sent with the schema 1 then
+                Err(_) => return Ok(()),
+            };
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 6);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_compressed_event_v2_round_trip(event in compressed_event()) {
+            let event = match crate::event::CompressedEventV2::try_from(&event) {
+                Ok(event) => event,
+                Err(_) => return Ok(()),
+            };
+            let instruction =
+                EventTrackerInstruction::AddCompressedEventV2(AddCompressedEventV2InstructionData {
+                    event,
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 7);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_event_v3_round_trip(
+            event in event(),
+            file_type in proptest::option::of(any::<String>()),
+        ) {
+            let instruction = match crate::event::EventV3::try_from(&event) {
+                Ok(mut event) => {
+                    if let Some(info) = event.file_info.as_mut() {
+                        info.file_type = file_type;
+                    }
+                    EventTrackerInstruction::AddEventV3(AddEventV3InstructionData { event })
+                }
+                Err(_) => return Ok(()),
+            };
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 9);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_compressed_event_v3_round_trip(prefix in any::<u8>(), event in event()) {
+            let event = match crate::event::EventV3::try_from(&event) {
+                Ok(event) => crate::event::CompressedEventV3 {
+                    prefix,
+                    path_suffix: event.file_path,
+                    event_type: event.event_type,
+                    solana_ts_received_at: event.solana_ts_received_at,
+                    file_info: event.file_info,
+                    process: event.process,
+                    sequence: event.sequence,
+                    captured_at: event.captured_at,
+                    kind: event.kind,
+                    suppressed_count: event.suppressed_count,
+                },
+                Err(_) => return Ok(()),
+            };
+            let instruction =
+                EventTrackerInstruction::AddCompressedEventV3(AddCompressedEventV3InstructionData {
+                    event,
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 10);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn add_events_dropped_round_trip(
+            count in any::<u64>(),
+            since in any::<i64>(),
+            until in any::<i64>(),
+        ) {
+            let instruction =
+                EventTrackerInstruction::AddEventsDropped(AddEventsDroppedInstructionData {
+                    dropped: crate::event::EventsDropped { count, since, until },
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 8);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn initialize_round_trip(
+            lamports in any::<u64>(),
+            pda_bump_seed in any::<u8>(),
+            path_prefixes in proptest::collection::vec(any::<String>(), 0..4),
+        ) {
+            let instruction = EventTrackerInstruction::Initialize(InitializeInstructionData {
+                lamports,
+                pda_bump_seed,
+                path_prefixes,
+            });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn initialize_overflow_round_trip(
+            lamports in any::<u64>(),
+            pda_bump_seed in any::<u8>(),
+            epoch in any::<u32>(),
+            path_prefixes in proptest::collection::vec(any::<String>(), 0..4),
+        ) {
+            let instruction =
+                EventTrackerInstruction::InitializeOverflow(InitializeOverflowInstructionData {
+                    lamports,
+                    pda_bump_seed,
+                    epoch,
+                    path_prefixes,
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 4);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn register_watcher_round_trip(
+            hostname in any::<String>(),
+            signing_key in any::<[u8; 32]>(),
+            schema_version in any::<u16>(),
+        ) {
+            let instruction =
+                EventTrackerInstruction::RegisterWatcher(RegisterWatcherInstructionData {
+                    hostname,
+                    signing_key: Pubkey::new_from_array(signing_key),
+                    schema_version,
+                });
+
+            let packed = instruction.pack().unwrap();
+            prop_assert_eq!(packed[0], 5);
+            prop_assert_eq!(EventTrackerInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        #[test]
+        fn initialize_without_path_prefixes(lamports in any::<u64>(), pda_bump_seed in any::<u8>()) {
+            // As packed before the path prefixes were added
+            let mut packed = vec![0];
+            packed.extend(lamports.to_le_bytes());
+            packed.push(pda_bump_seed);
+
+            prop_assert_eq!(
+                EventTrackerInstruction::unpack(&packed).unwrap(),
+                EventTrackerInstruction::Initialize(InitializeInstructionData {
+                    lamports,
+                    pda_bump_seed,
+                    path_prefixes: Vec::new(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_event_is_smaller() {
+        let dir = "/home/user/projects/blkchn-file-tracker/data/".to_string();
+        let event = Event {
+            solana_ts_received_at: 1729000000,
+            ..Event::new(format!("{}report.csv", dir), EventType::Written)
+        };
+        let compressed = PathDictionary::new(vec![dir.clone()])
+            .compress(&event)
+            .unwrap();
+
+        let full = EventTrackerInstruction::AddEvent(AddEventInstructionData { event })
+            .pack()
+            .unwrap();
+        let compressed =
+            EventTrackerInstruction::AddCompressedEvent(AddCompressedEventInstructionData {
+                event: compressed,
+            })
+            .pack()
+            .unwrap();
+        // The prefix is replaced by its one byte index
+        assert_eq!(full.len() - compressed.len(), dir.len() - 1);
+    }
 
     #[test]
     fn serialize_initialize() {
         let instruction = EventTrackerInstruction::Initialize(InitializeInstructionData {
             lamports: 3213,
             pda_bump_seed: 255,
+            path_prefixes: vec!["/srv/data/".to_string()],
         });
 
         let packed = instruction.pack().unwrap();
@@ -1,5 +1,7 @@
 pub mod error;
-pub mod event;
+pub use file_event_types as event;
+#[cfg(not(target_os = "solana"))]
+pub mod idl;
 pub mod instruction;
 pub mod processor;
 
@@ -15,23 +15,182 @@ use solana_program::{
 };
 
 use crate::{
-    event,
+    error::TrackerError,
+    event::{self, PathDictionary},
     instruction::{self, EventTrackerInstruction},
 };
 
 pub const VAULT_ACCOUNT_SIZE: u64 = 1024;
 pub const PDA_SEED_PREFIX: &[u8] = b"vault";
+/// Version of the vault's layout and of the events' schema, registered by the watchers.
+/// The schema 2 stores the timestamps as i64 seconds, the schema 3 also the process
+/// which made the change, the sequence number, the capture time and the file's type,
+/// see [AccountData::pack].
+pub const SCHEMA_VERSION: u16 = 3;
+/// Starts the vaults of the schema 2 and later in place of the length of the events' map,
+/// which never has that many entries.
+const VERSIONED_MARKER: u32 = u32::MAX;
+/// The longest hostname, as in DNS.
+pub const MAX_HOSTNAME_LEN: usize = 253;
 
+/// Content of the user's vault: the last event of each file.
 #[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
-struct AccountData {
-    last_file_events: std::collections::HashMap<String, event::Event>,
+pub struct AccountData {
+    pub last_file_events: std::collections::HashMap<String, event::Event>,
+    /// Path prefixes of the compressed events, set at initialization.
+    pub path_dictionary: PathDictionary,
+    /// Set once the vault is full, the user's next vault getting the new files.
+    pub next_vault: Option<Pubkey>,
+    /// Set by RegisterWatcher, the vault accepts the events of any signer without it.
+    pub watcher: Option<WatcherIdentity>,
+    /// Sequence number of the watcher's last event, 0 before its first numbered one.
+    pub last_sequence: u64,
+}
+
+/// The watcher feeding the vault, documented in the vault itself.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct WatcherIdentity {
+    pub hostname: String,
+    /// The only key accepted as the signer of the events.
+    pub signing_key: Pubkey,
+    pub schema_version: u16,
 }
 
 impl Default for AccountData {
     fn default() -> Self {
         AccountData {
             last_file_events: std::collections::HashMap::<String, event::Event>::new(),
+            path_dictionary: PathDictionary::default(),
+            next_vault: None,
+            watcher: None,
+            last_sequence: 0,
+        }
+    }
+}
+
+impl AccountData {
+    /// Reads the vault's data. Unlike `try_from_slice` it accepts the unused space
+    /// of a new vault, the vaults written before the path dictionary
+    /// the link to the next vault, the watcher's identity or the last sequence number
+    /// were added, and the vaults of the schema 2 and 3.
+    pub fn unpack(data: &[u8]) -> Result<AccountData, borsh::io::Error> {
+        let mut data = data;
+        let last_file_events = match data.starts_with(&VERSIONED_MARKER.to_le_bytes()) {
+            true => {
+                data = &data[4..];
+                match u16::deserialize(&mut data)? {
+                    2 => {
+                        std::collections::HashMap::<String, event::EventV2>::deserialize(&mut data)?
+                            .into_iter()
+                            .map(|(path, event)| (path, event.into()))
+                            .collect()
+                    }
+                    3 => {
+                        std::collections::HashMap::<String, event::EventV3>::deserialize(&mut data)?
+                            .into_iter()
+                            .map(|(path, event)| (path, event.into()))
+                            .collect()
+                    }
+                    version => {
+                        return Err(borsh::io::Error::new(
+                            borsh::io::ErrorKind::InvalidData,
+                            format!("Unsupported vault schema version {}", version),
+                        ))
+                    }
+                }
+            }
+            false => BorshDeserialize::deserialize(&mut data)?,
+        };
+        let path_dictionary = match data.is_empty() {
+            true => PathDictionary::default(),
+            false => PathDictionary::deserialize(&mut data)?,
+        };
+        let next_vault = match data.is_empty() {
+            true => None,
+            false => Option::<Pubkey>::deserialize(&mut data)?,
+        };
+        let watcher = match data.is_empty() {
+            true => None,
+            false => Option::<WatcherIdentity>::deserialize(&mut data)?,
+        };
+        // The unused space left of a new vault may be shorter than the number
+        let last_sequence = match data.len() < std::mem::size_of::<u64>() {
+            true => 0,
+            false => u64::deserialize(&mut data)?,
+        };
+        Ok(AccountData {
+            last_file_events,
+            path_dictionary,
+            next_vault,
+            watcher,
+            last_sequence,
+        })
+    }
+
+    /// Serializes the vault in the layout of its watcher's schema: the schema 1 is the
+    /// derived borsh layout, the schemas 2 and 3 are the marker, the version and the same
+    /// fields with the events of that schema.
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut serialized = Vec::<u8>::new();
+        let version = self
+            .watcher
+            .as_ref()
+            .map_or(1, |watcher| watcher.schema_version);
+        if version < 2 {
+            self.serialize(&mut serialized)?;
+            return Ok(serialized);
+        }
+
+        VERSIONED_MARKER.serialize(&mut serialized)?;
+        match version {
+            2 => {
+                2u16.serialize(&mut serialized)?;
+                versioned::<event::EventV2>(&self.last_file_events)?.serialize(&mut serialized)?;
+            }
+            _ => {
+                3u16.serialize(&mut serialized)?;
+                versioned::<event::EventV3>(&self.last_file_events)?.serialize(&mut serialized)?;
+            }
         }
+        self.path_dictionary
+            .serialize(&mut serialized)?;
+        self.next_vault
+            .serialize(&mut serialized)?;
+        self.watcher
+            .serialize(&mut serialized)?;
+        self.last_sequence
+            .serialize(&mut serialized)?;
+        Ok(serialized)
+    }
+}
+
+// The last events in the layout of a schema from the 2 on
+fn versioned<'a, E>(
+    last_file_events: &'a std::collections::HashMap<String, event::Event>,
+) -> Result<std::collections::HashMap<&'a String, E>, ProgramError>
+where
+    E: for<'e> TryFrom<&'e event::Event, Error = event::TimestampOutOfRange>,
+{
+    let mut versioned = std::collections::HashMap::new();
+    for (path, event) in last_file_events.iter() {
+        let event = E::try_from(event).map_err(|e| {
+            msg!("{}: {}", path, e);
+            TrackerError::TimestampOutOfRange
+        })?;
+        versioned.insert(path, event);
+    }
+    Ok(versioned)
+}
+
+/// Finds the address of the user's vault of the `epoch`, the first vault is of the epoch 0
+/// and the overflow vaults are numbered from 1.
+pub fn find_vault_address(program_id: &Pubkey, user: &Pubkey, epoch: u32) -> (Pubkey, u8) {
+    match epoch {
+        0 => Pubkey::find_program_address(&[PDA_SEED_PREFIX, user.as_ref()], program_id),
+        _ => Pubkey::find_program_address(
+            &[PDA_SEED_PREFIX, user.as_ref(), &epoch.to_le_bytes()],
+            program_id,
+        ),
     }
 }
 
@@ -57,7 +216,46 @@ pub fn process_instruction(
         EventTrackerInstruction::AddEvent(add_event_instruction_data) => {
             process_add_event(program_id, accounts, add_event_instruction_data)
         }
-        EventTrackerInstruction::CloseAccount => todo!(),
+        EventTrackerInstruction::CloseAccount => {
+            msg!("Closing the vault is not implemented yet");
+            Err(TrackerError::UnsupportedInstruction.into())
+        }
+        EventTrackerInstruction::AddCompressedEvent(add_compressed_event_instruction_data) => {
+            process_add_compressed_event(
+                program_id,
+                accounts,
+                add_compressed_event_instruction_data,
+            )
+        }
+        EventTrackerInstruction::InitializeOverflow(initialize_overflow_instruction_data) => {
+            process_initialize_overflow(program_id, accounts, initialize_overflow_instruction_data)
+        }
+        EventTrackerInstruction::RegisterWatcher(register_watcher_instruction_data) => {
+            process_register_watcher(program_id, accounts, register_watcher_instruction_data)
+        }
+        EventTrackerInstruction::AddEventV2(add_event_instruction_data) => {
+            process_add_event_v2(program_id, accounts, add_event_instruction_data)
+        }
+        EventTrackerInstruction::AddCompressedEventV2(add_compressed_event_instruction_data) => {
+            process_add_compressed_event_v2(
+                program_id,
+                accounts,
+                add_compressed_event_instruction_data,
+            )
+        }
+        EventTrackerInstruction::AddEventsDropped(add_events_dropped_instruction_data) => {
+            process_add_events_dropped(program_id, accounts, add_events_dropped_instruction_data)
+        }
+        EventTrackerInstruction::AddEventV3(add_event_instruction_data) => {
+            process_add_event_v3(program_id, accounts, add_event_instruction_data)
+        }
+        EventTrackerInstruction::AddCompressedEventV3(add_compressed_event_instruction_data) => {
+            process_add_compressed_event_v3(
+                program_id,
+                accounts,
+                add_compressed_event_instruction_data,
+            )
+        }
     }
 }
 
@@ -78,6 +276,9 @@ pub fn process_initialize(
     if !pda.is_writable {
         return Err(ProgramError::Immutable);
     }
+    if input.path_prefixes.len() > PathDictionary::MAX_PREFIXES {
+        return Err(TrackerError::TooManyPathPrefixes.into());
+    }
     // System program needs to come from the outside
     let system_program = solana_program::account_info::next_account_info(account_info_iter)?;
 
@@ -90,23 +291,209 @@ pub fn process_initialize(
         &[input.pda_bump_seed],
     ];
 
+    create_vault(
+        program_id,
+        payer,
+        pda,
+        system_program,
+        input.lamports,
+        pda_seed,
+        AccountData {
+            path_dictionary: PathDictionary::new(input.path_prefixes),
+            ..AccountData::default()
+        },
+    )
+}
+
+pub fn process_initialize_overflow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::InitializeOverflowInstructionData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !payer.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let last_vault = solana_program::account_info::next_account_info(account_info_iter)?;
+    let pda = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !last_vault.is_writable || !pda.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    if input.epoch == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    if input.path_prefixes.len() > PathDictionary::MAX_PREFIXES {
+        return Err(TrackerError::TooManyPathPrefixes.into());
+    }
+    let system_program = solana_program::account_info::next_account_info(account_info_iter)?;
+
+    // Only the user's vault of the previous epoch, not linked yet, is followed by the new one
+    let (previous, _) = find_vault_address(program_id, payer.key, input.epoch - 1);
+    if *last_vault.key != previous || last_vault.owner != program_id {
+        return Err(TrackerError::NotLastVault.into());
+    }
+    let mut last_vault_data = AccountData::unpack(&last_vault.try_borrow_data()?)?;
+    if last_vault_data
+        .next_vault
+        .is_some()
+    {
+        return Err(TrackerError::NotLastVault.into());
+    }
+
+    let epoch = input.epoch.to_le_bytes();
+    let pda_seed = &[
+        PDA_SEED_PREFIX,
+        payer.key.as_ref(),
+        &epoch,
+        &[input.pda_bump_seed],
+    ];
+    create_vault(
+        program_id,
+        payer,
+        pda,
+        system_program,
+        input.lamports,
+        pda_seed,
+        AccountData {
+            path_dictionary: PathDictionary::new(input.path_prefixes),
+            // The events go on coming from the same watcher, numbered on
+            watcher: last_vault_data
+                .watcher
+                .clone(),
+            last_sequence: last_vault_data.last_sequence,
+            ..AccountData::default()
+        },
+    )?;
+
+    // The queries follow the link from the full vault to the new one
+    last_vault_data.next_vault = Some(*pda.key);
+    write_vault(last_vault, &last_vault_data.pack()?)?;
+    msg!(
+        "Vault {} is full, the new files go to {} of epoch {}",
+        last_vault.key,
+        pda.key,
+        input.epoch
+    );
+    Ok(())
+}
+
+// Creates the vault at the PDA of the `pda_seed`, with its initial content:
+// the path prefixes and the watcher's identity
+fn create_vault<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    pda: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    lamports: u64,
+    pda_seed: &[&[u8]],
+    vault_data: AccountData,
+) -> ProgramResult {
     // Invoke the system program to create an account while virtually
     // signing with the vault PDA, which is owned by this caller program.
     solana_program::program::invoke_signed(
         &system_instruction::create_account(
             payer.key,
             pda.key,
-            input.lamports,
+            lamports,
             VAULT_ACCOUNT_SIZE,
             program_id,
         ),
         &[payer.clone(), pda.clone(), system_program.clone()],
         &[pda_seed],
-    )
+    )?;
+
+    if vault_data == AccountData::default() {
+        return Ok(());
+    }
+    // The prefixes are stored once, the rest of the vault stays unused until the first event
+    let serialized = vault_data.pack()?;
+    if serialized.len() > pda.data_len() {
+        pda.realloc(serialized.len(), false)?;
+    }
+    pda.try_borrow_mut_data()?
+        .get_mut(..serialized.len())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(&serialized);
+    Ok(())
+}
+
+// Resizes the vault to the `serialized` data and writes it. Growing by more than
+// the runtime allows in one instruction fails with InvalidRealloc.
+fn write_vault(vault: &AccountInfo, serialized: &[u8]) -> ProgramResult {
+    vault.realloc(serialized.len(), false)?;
+    vault
+        .try_borrow_mut_data()?
+        .get_mut(..serialized.len())
+        .ok_or(ProgramError::AccountDataTooSmall)?
+        .copy_from_slice(serialized);
+    Ok(())
+}
+
+pub fn process_register_watcher(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::RegisterWatcherInstructionData,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let payer = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !payer.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let vault = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !vault.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    // Only the owner of the vault registers its watcher, e.g. again with a new key
+    let (first, _) = find_vault_address(program_id, payer.key, 0);
+    if *vault.key != first || vault.owner != program_id {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if input.schema_version == 0 || input.schema_version > SCHEMA_VERSION {
+        return Err(TrackerError::UnsupportedSchema.into());
+    }
+    if input.hostname.len() > MAX_HOSTNAME_LEN {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    msg!(
+        "Watcher {} registered with the key {}",
+        input.hostname,
+        input.signing_key
+    );
+    let watcher = WatcherIdentity {
+        hostname: input.hostname,
+        signing_key: input.signing_key,
+        schema_version: input.schema_version,
+    };
+    // Each vault checks the events' key itself, the whole chain gets the new identity
+    let mut vault = vault;
+    loop {
+        let mut vault_data = AccountData::unpack(&vault.try_borrow_data()?)?;
+        vault_data.watcher = Some(watcher.clone());
+        // Rewritten in the layout of the registered schema
+        write_vault(vault, &vault_data.pack()?)?;
+        let Some(next) = vault_data.next_vault else {
+            return Ok(());
+        };
+        vault = solana_program::account_info::next_account_info(account_info_iter)?;
+        if *vault.key != next || vault.owner != program_id {
+            return Err(TrackerError::NotNextVault.into());
+        }
+        if !vault.is_writable {
+            return Err(ProgramError::Immutable);
+        }
+    }
 }
 
 pub fn process_add_event(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     input: instruction::AddEventInstructionData,
 ) -> ProgramResult {
@@ -114,7 +501,81 @@ pub fn process_add_event(
     //     .ok()
     //     .unwrap()
     //     .unix_timestamp as i128;
-    let event = input.event;
+    store_event(program_id, accounts, |_| Ok(input.event))
+}
+
+pub fn process_add_compressed_event(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddCompressedEventInstructionData,
+) -> ProgramResult {
+    store_event(program_id, accounts, |vault_data| {
+        vault_data
+            .path_dictionary
+            .expand(input.event)
+            .ok_or(TrackerError::UnknownPathPrefix.into())
+    })
+}
+
+pub fn process_add_event_v2(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddEventV2InstructionData,
+) -> ProgramResult {
+    store_event(program_id, accounts, |_| Ok(input.event.into()))
+}
+
+pub fn process_add_compressed_event_v2(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddCompressedEventV2InstructionData,
+) -> ProgramResult {
+    store_event(program_id, accounts, |vault_data| {
+        vault_data
+            .path_dictionary
+            .expand(input.event.into())
+            .ok_or(TrackerError::UnknownPathPrefix.into())
+    })
+}
+
+pub fn process_add_event_v3(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddEventV3InstructionData,
+) -> ProgramResult {
+    store_event(program_id, accounts, |_| Ok(input.event.into()))
+}
+
+pub fn process_add_compressed_event_v3(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddCompressedEventV3InstructionData,
+) -> ProgramResult {
+    store_event(program_id, accounts, |vault_data| {
+        vault_data
+            .path_dictionary
+            .expand_v3(input.event)
+            .map(Into::into)
+            .ok_or(TrackerError::UnknownPathPrefix.into())
+    })
+}
+
+pub fn process_add_events_dropped(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    input: instruction::AddEventsDroppedInstructionData,
+) -> ProgramResult {
+    // Checked like the events, the transaction itself is the record
+    let (vault, _) = fed_vault(program_id, accounts)?;
+    msg!("Vault {}: {}", vault.key, input.dropped);
+    Ok(())
+}
+
+// Stores the event returned by `get_event` from the vault's content in the vault.
+fn store_event<F>(program_id: &Pubkey, accounts: &[AccountInfo], get_event: F) -> ProgramResult
+where
+    F: FnOnce(&AccountData) -> Result<event::Event, ProgramError>,
+{
     // msg!(
     //     "Event generated: {:?} | Received by this program in: {} s",
     //     event.solana_ts_received_at,
@@ -123,19 +584,9 @@ pub fn process_add_event(
 
     // log_accounts(accounts);
 
-    let account_info_iter = &mut accounts.iter();
-    let payer = solana_program::account_info::next_account_info(account_info_iter)?;
-    if !payer.is_writable {
-        return Err(ProgramError::Immutable);
-    }
-    // Vault is the user's PDA created with Initialize.
-    let vault = solana_program::account_info::next_account_info(account_info_iter)?;
-    if !vault.is_writable {
-        return Err(ProgramError::Immutable);
-    }
-
-    let mut vault_data =
-        AccountData::try_from_slice(&vault.data.borrow()).unwrap_or(AccountData::default());
+    let (vault, mut vault_data) = fed_vault(program_id, accounts)?;
+    let event = get_event(&vault_data)?;
+    check_sequence(&mut vault_data, event.sequence);
 
     // track only the latest event in the account data,
     // all events are available from the transactions payload
@@ -157,13 +608,10 @@ pub fn process_add_event(
     vault_data
         .last_file_events
         .insert(event.file_path.clone(), event.clone());
-    let mut serialized = Vec::<u8>::new();
-    vault_data.serialize(&mut serialized)?;
+    let serialized = vault_data.pack()?;
 
-    // check how much space is needed and increase it
-    vault.realloc(serialized.len(), false)?;
-    // store new account data
-    vault.data.borrow_mut()[..].copy_from_slice(&serialized);
+    // check how much space is needed and increase it, then store new account data
+    write_vault(vault, &serialized)?;
     // println!("Value updated, data size: {}", vault.data_len());
 
     msg!(
@@ -179,6 +627,67 @@ pub fn process_add_event(
     Ok(())
 }
 
+// Logs the watcher's events missing or out of order before the one numbered `sequence`,
+// the events are stored anyway: their transactions may land in another order than they
+// were sent in. The numbers start from 1 again when the tracker restarts.
+fn check_sequence(vault_data: &mut AccountData, sequence: u64) {
+    let last = vault_data.last_sequence;
+    match sequence {
+        // Not numbered, e.g. sent in an older schema
+        0 => return,
+        1 if last > 1 => msg!("Sequence restarted after {}", last),
+        _ if sequence <= last => msg!("Sequence {} out of order, after {}", sequence, last),
+        _ if last > 0 && sequence - last > 1 => {
+            msg!(
+                "Sequence gap: events {} to {} missing",
+                last + 1,
+                sequence - 1
+            )
+        }
+        _ => (),
+    }
+    vault_data.last_sequence = match sequence {
+        1 => 1,
+        _ => std::cmp::max(sequence, last),
+    };
+}
+
+// The vault of the events' instructions and its content, checked to be fed by the payer.
+fn fed_vault<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<(&'b AccountInfo<'a>, AccountData), ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+    let payer = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !payer.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+    // Vault is the user's PDA created with Initialize.
+    let vault = solana_program::account_info::next_account_info(account_info_iter)?;
+    if !vault.is_writable {
+        return Err(ProgramError::Immutable);
+    }
+
+    if vault.owner != program_id {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    // A corrupted vault is left as it is, not overwritten with a new one
+    let vault_data = AccountData::unpack(&vault.try_borrow_data()?).map_err(|e| {
+        msg!("Failed to read the vault {}: {}", vault.key, e);
+        ProgramError::InvalidAccountData
+    })?;
+    // Only the registered watcher feeds the vault
+    if vault_data
+        .watcher
+        .as_ref()
+        .is_some_and(|watcher| !payer.is_signer || *payer.key != watcher.signing_key)
+    {
+        return Err(TrackerError::UnregisteredWatcher.into());
+    }
+    Ok((vault, vault_data))
+}
+
 fn log_accounts(accounts: &[AccountInfo]) {
     msg!("Accounts num: {}", accounts.len());
     for account_info in accounts.iter() {
@@ -219,6 +728,366 @@ mod tests {
         std::io::Write,
     };
 
+    #[test]
+    fn test_unpack_account_data() {
+        // A new vault, not written yet
+        let empty = vec![0; VAULT_ACCOUNT_SIZE as usize];
+        assert_eq!(AccountData::unpack(&empty).unwrap(), AccountData::default());
+
+        let mut account_data = AccountData::default();
+        account_data
+            .last_file_events
+            .insert(
+                "/srv/path".to_string(),
+                event::Event {
+                    solana_ts_received_at: 123,
+                    ..event::Event::new("/srv/path", EventType::Created)
+                },
+            );
+        // Written before the path dictionary was added
+        let legacy = borsh::to_vec(&account_data.last_file_events).unwrap();
+        assert_eq!(AccountData::unpack(&legacy).unwrap(), account_data);
+
+        // Initialized with the path prefixes
+        account_data.path_dictionary = PathDictionary::new(vec!["/srv/".to_string()]);
+        let mut serialized = borsh::to_vec(&account_data).unwrap();
+        serialized.resize(VAULT_ACCOUNT_SIZE as usize, 0);
+        assert_eq!(AccountData::unpack(&serialized).unwrap(), account_data);
+
+        // Written before the link to the next vault was added
+        let without_link = borsh::to_vec(&(
+            &account_data.last_file_events,
+            &account_data.path_dictionary,
+        ))
+        .unwrap();
+        assert_eq!(AccountData::unpack(&without_link).unwrap(), account_data);
+
+        // Full, linked to the overflow vault
+        account_data.next_vault = Some(Pubkey::new_unique());
+        let serialized = borsh::to_vec(&account_data).unwrap();
+        assert_eq!(AccountData::unpack(&serialized).unwrap(), account_data);
+
+        // Written before the watcher's identity was added
+        let without_watcher = borsh::to_vec(&(
+            &account_data.last_file_events,
+            &account_data.path_dictionary,
+            &account_data.next_vault,
+        ))
+        .unwrap();
+        assert_eq!(AccountData::unpack(&without_watcher).unwrap(), account_data);
+
+        // With the registered watcher
+        account_data.watcher = Some(WatcherIdentity {
+            hostname: "host-1".to_string(),
+            signing_key: Pubkey::new_unique(),
+            schema_version: SCHEMA_VERSION,
+        });
+        let serialized = borsh::to_vec(&account_data).unwrap();
+        assert_eq!(AccountData::unpack(&serialized).unwrap(), account_data);
+
+        // Written before the last sequence number was added
+        let without_sequence = borsh::to_vec(&(
+            &account_data.last_file_events,
+            &account_data.path_dictionary,
+            &account_data.next_vault,
+            &account_data.watcher,
+        ))
+        .unwrap();
+        assert_eq!(
+            AccountData::unpack(&without_sequence).unwrap(),
+            account_data
+        );
+        account_data.last_sequence = 1042;
+        let serialized = borsh::to_vec(&account_data).unwrap();
+        assert_eq!(AccountData::unpack(&serialized).unwrap(), account_data);
+
+        // Of the schema 2, the timestamps as i64 seconds
+        account_data
+            .watcher
+            .as_mut()
+            .unwrap()
+            .schema_version = 2;
+        let packed = account_data.pack().unwrap();
+        assert_eq!(packed[..4], VERSIONED_MARKER.to_le_bytes());
+        assert_eq!(serialized.len() - packed.len(), 7 - 4 - 2);
+        assert_eq!(AccountData::unpack(&packed).unwrap(), account_data);
+        let mut too_late = account_data.clone();
+        too_late
+            .last_file_events
+            .get_mut("/srv/path")
+            .unwrap()
+            .solana_ts_received_at = i128::MAX;
+        assert_eq!(
+            too_late.pack(),
+            Err(TrackerError::TimestampOutOfRange.into())
+        );
+
+        // Of the schema 3, with the process
+        account_data
+            .watcher
+            .as_mut()
+            .unwrap()
+            .schema_version = 3;
+        account_data
+            .last_file_events
+            .get_mut("/srv/path")
+            .unwrap()
+            .process = Some(event::Process {
+            pid: 4242,
+            exe: "/usr/bin/vim".to_string(),
+        });
+        let packed = account_data.pack().unwrap();
+        assert_eq!(packed[4..6], 3u16.to_le_bytes());
+        assert_eq!(AccountData::unpack(&packed).unwrap(), account_data);
+        // Dropped by the schema 2
+        account_data
+            .watcher
+            .as_mut()
+            .unwrap()
+            .schema_version = 2;
+        let without_process = AccountData::unpack(&account_data.pack().unwrap()).unwrap();
+        assert_eq!(without_process.last_file_events["/srv/path"].process, None);
+        account_data
+            .last_file_events
+            .get_mut("/srv/path")
+            .unwrap()
+            .process = None;
+
+        // Registered with the schema 1
+        account_data
+            .watcher
+            .as_mut()
+            .unwrap()
+            .schema_version = 1;
+        assert_eq!(
+            account_data.pack().unwrap(),
+            borsh::to_vec(&account_data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_sequence() {
+        let mut vault_data = AccountData::default();
+        // The number after each event: not numbered, in order, a gap, out of order,
+        // and the watcher restarted
+        for (sequence, last) in [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (5, 5),
+            (4, 5),
+            (0, 5),
+            (6, 6),
+            (1, 1),
+            (2, 2),
+        ] {
+            check_sequence(&mut vault_data, sequence);
+            assert_eq!(vault_data.last_sequence, last, "after {}", sequence);
+        }
+    }
+
+    // The payer and the vault as the runtime serializes the program's input, which
+    // `realloc` relies on: it tracks the original length and the spare space there
+    fn serialized_input(payer: &Pubkey, vault: &Pubkey, owner: &Pubkey, data: &[u8]) -> Vec<u64> {
+        serialized_vaults(payer, &[(vault, data)], owner)
+    }
+
+    // The payer and the `vaults` of the `owner`, with their data
+    fn serialized_vaults(payer: &Pubkey, vaults: &[(&Pubkey, &[u8])], owner: &Pubkey) -> Vec<u64> {
+        use solana_program::entrypoint::{
+            BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER,
+        };
+        let mut input = Vec::<u8>::new();
+        input.extend_from_slice(&(1 + vaults.len() as u64).to_le_bytes());
+        let accounts = std::iter::once((payer, &Pubkey::default(), &[][..])).chain(
+            vaults
+                .iter()
+                .map(|(key, data)| (*key, owner, *data)),
+        );
+        for (key, owner, data) in accounts {
+            // duplicate marker, signer, writable, executable, original data length
+            input.extend_from_slice(&[NON_DUP_MARKER, 1, 1, 0, 0, 0, 0, 0]);
+            input.extend_from_slice(key.as_ref());
+            input.extend_from_slice(owner.as_ref());
+            input.extend_from_slice(&1_000_000_000u64.to_le_bytes());
+            input.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            input.extend_from_slice(data);
+            input.resize(input.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+            input.resize(
+                input
+                    .len()
+                    .next_multiple_of(BPF_ALIGN_OF_U128),
+                0,
+            );
+            // rent epoch
+            input.extend_from_slice(&0u64.to_le_bytes());
+        }
+        // The instruction data is passed on its own
+        input.extend_from_slice(&0u64.to_le_bytes());
+        input.extend_from_slice(owner.as_ref());
+
+        // Aligned as the runtime's buffer
+        let mut aligned = vec![0u64; input.len().div_ceil(8)];
+        for (word, bytes) in aligned
+            .iter_mut()
+            .zip(input.chunks(8))
+        {
+            let mut le = [0; 8];
+            le[..bytes.len()].copy_from_slice(bytes);
+            *word = u64::from_le_bytes(le);
+        }
+        aligned
+    }
+
+    #[test]
+    fn test_watcher_registered_in_all_vaults() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (first, _) = find_vault_address(&program_id, &payer, 0);
+        let (second, _) = find_vault_address(&program_id, &payer, 1);
+        let watcher = |hostname: &str, signing_key: Pubkey| WatcherIdentity {
+            hostname: hostname.to_string(),
+            signing_key,
+            schema_version: 2,
+        };
+        let rotated = Pubkey::new_unique();
+        let old = Some(watcher("old", Pubkey::new_unique()));
+        let head = AccountData {
+            next_vault: Some(second),
+            watcher: old.clone(),
+            ..AccountData::default()
+        }
+        .pack()
+        .unwrap();
+        let overflow = AccountData {
+            watcher: old,
+            ..AccountData::default()
+        }
+        .pack()
+        .unwrap();
+        let register =
+            EventTrackerInstruction::RegisterWatcher(instruction::RegisterWatcherInstructionData {
+                hostname: "new".to_string(),
+                signing_key: rotated,
+                schema_version: 2,
+            })
+            .pack()
+            .unwrap();
+        let process = |vaults: &[(&Pubkey, &[u8])]| {
+            let mut input = serialized_vaults(&payer, vaults, &program_id);
+            let (_, accounts, _) =
+                unsafe { solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+            let result = process_instruction(&program_id, &accounts, &register);
+            let watchers: Vec<_> = accounts[1..]
+                .iter()
+                .map(|vault| {
+                    AccountData::unpack(
+                        &vault
+                            .try_borrow_data()
+                            .unwrap(),
+                    )
+                    .unwrap()
+                    .watcher
+                })
+                .collect();
+            (result, watchers)
+        };
+
+        // The overflow vault accepts the rotated key too
+        let (result, watchers) = process(&[(&first, &head), (&second, &overflow)]);
+        assert_eq!(result, Ok(()));
+        assert_eq!(watchers, vec![Some(watcher("new", rotated)); 2]);
+
+        // Without the overflow vault, or with another one
+        let (result, _) = process(&[(&first, &head)]);
+        assert_eq!(result, Err(ProgramError::NotEnoughAccountKeys));
+        let (result, _) = process(&[(&first, &head), (&Pubkey::new_unique(), &overflow)]);
+        assert_eq!(result, Err(TrackerError::NotNextVault.into()));
+    }
+
+    #[test]
+    fn test_failures_return_errors() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (vault, _) = find_vault_address(&program_id, &payer, 0);
+        let process = |input: &mut Vec<u64>, instruction: &EventTrackerInstruction| {
+            let (_, accounts, _) =
+                unsafe { solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+            let result = process_instruction(&program_id, &accounts, &instruction.pack().unwrap());
+            let data = accounts[1]
+                .try_borrow_data()
+                .unwrap()
+                .to_vec();
+            (result, data)
+        };
+        let add_event = |file_path: String| {
+            EventTrackerInstruction::AddEvent(instruction::AddEventInstructionData {
+                event: event::Event {
+                    solana_ts_received_at: 123,
+                    ..event::Event::new(file_path, EventType::Created)
+                },
+            })
+        };
+
+        // Stored in a new vault
+        let new_vault = vec![0; VAULT_ACCOUNT_SIZE as usize];
+        let mut input = serialized_input(&payer, &vault, &program_id, &new_vault);
+        let (result, data) = process(&mut input, &add_event("/srv/a.txt".to_string()));
+        assert_eq!(result, Ok(()));
+        assert!(AccountData::unpack(&data)
+            .unwrap()
+            .last_file_events
+            .contains_key("/srv/a.txt"));
+
+        // Only logged, the vault is left as it is
+        let dropped = EventTrackerInstruction::AddEventsDropped(
+            instruction::AddEventsDroppedInstructionData {
+                dropped: event::EventsDropped {
+                    count: 3,
+                    since: 1729000000,
+                    until: 1729000060,
+                },
+            },
+        );
+        let (result, dropped_data) = process(&mut input, &dropped);
+        assert_eq!(result, Ok(()));
+        assert_eq!(dropped_data, data);
+
+        // A corrupted vault is left as it is
+        let corrupted = vec![0x07; 64];
+        let mut input = serialized_input(&payer, &vault, &program_id, &corrupted);
+        let (result, data) = process(&mut input, &add_event("/srv/a.txt".to_string()));
+        assert_eq!(result, Err(ProgramError::InvalidAccountData));
+        assert_eq!(data, corrupted);
+
+        // The event doesn't fit in what the vault may grow by at once
+        let mut input = serialized_input(&payer, &vault, &program_id, &new_vault);
+        let (result, data) = process(&mut input, &add_event("a".repeat(11 * 1024)));
+        assert_eq!(result, Err(ProgramError::InvalidRealloc));
+        assert_eq!(data, new_vault);
+
+        // Not the program's vault
+        let mut input = serialized_input(&payer, &vault, &Pubkey::new_unique(), &new_vault);
+        let (result, _) = process(&mut input, &add_event("/srv/a.txt".to_string()));
+        assert_eq!(result, Err(ProgramError::IllegalOwner));
+
+        let mut input = serialized_input(&payer, &vault, &program_id, &new_vault);
+        let (result, _) = process(&mut input, &EventTrackerInstruction::CloseAccount);
+        assert_eq!(result, Err(TrackerError::UnsupportedInstruction.into()));
+
+        // Without the vault
+        let mut input = serialized_input(&payer, &vault, &program_id, &new_vault);
+        let (_, accounts, _) =
+            unsafe { solana_program::entrypoint::deserialize(input.as_mut_ptr() as *mut u8) };
+        let packed = add_event("/srv/a.txt".to_string())
+            .pack()
+            .unwrap();
+        assert_eq!(
+            process_instruction(&program_id, &accounts[..1], &packed),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
     #[test]
     fn test_serialize_account_data() {
         let mut account_data = AccountData::default();
@@ -227,10 +1096,8 @@ mod tests {
             .insert(
                 "path".to_string(),
                 event::Event {
-                    file_path: "path".to_string(),
-                    event_type: EventType::AttributeChanged,
                     solana_ts_received_at: 123,
-                    file_info: None,
+                    ..event::Event::new("path", EventType::AttributeChanged)
                 },
             );
 
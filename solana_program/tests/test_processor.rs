@@ -37,6 +37,7 @@ fn test_create_pda() {
         et::instruction::InitializeInstructionData {
             lamports,
             pda_bump_seed,
+            path_prefixes: vec!["/home/user/".to_string()],
         },
     )
     .pack()
@@ -103,10 +104,8 @@ fn test_add_event() {
 
     // create event data
     let event = et::event::Event {
-        event_type: et::event::EventType::Written,
-        file_path: "/home/user/file2.txt".to_string(),
         solana_ts_received_at: solana_current_time as i128,
-        file_info: None,
+        ..et::event::Event::new("/home/user/file2.txt", et::event::EventType::Written)
     };
     let instr_data = et::instruction::EventTrackerInstruction::AddEvent(
         et::instruction::AddEventInstructionData { event },
@@ -272,10 +271,8 @@ fn test_add_multiple_events_mutli_transactions() {
 
 fn get_event_for_testing() -> et::event::Event {
     et::event::Event {
-        event_type: et::event::EventType::Deleted,
-        file_path: "/home/user/file1.txt".to_string(),
         solana_ts_received_at: OffsetDateTime::now_utc().unix_timestamp() as i128,
-        file_info: None,
+        ..et::event::Event::new("/home/user/file1.txt", et::event::EventType::Deleted)
     }
 }
 
@@ -0,0 +1,132 @@
+//! Anchors the events on an EVM chain: the keccak256 hash of each borsh serialized
+//! event is passed to the `anchor(bytes32)` function of a contract, see
+//! `ethereum/EventAnchor.sol`.
+//!
+//! The transactions are sent with `eth_sendTransaction`, signed by the node's signer
+//! (e.g. Clef or an unlocked development account), the tracker holds no Ethereum key.
+
+use super::ChainAnchor;
+use crate::config::EthereumConfig;
+use crate::error::Error;
+use crate::event::Event;
+use async_trait::async_trait;
+use sha3::{Digest, Keccak256};
+
+const ANCHOR_FUNCTION: &str = "anchor(bytes32)";
+
+pub struct EthereumAnchor {
+    config: EthereumConfig,
+    // JSON-RPC request id
+    next_id: u64,
+}
+
+impl EthereumAnchor {
+    pub fn from_config(config: &EthereumConfig) -> EthereumAnchor {
+        EthereumAnchor {
+            config: config.clone(),
+            next_id: 1,
+        }
+    }
+
+    async fn call(
+        &mut self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, Error> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        });
+        self.next_id += 1;
+
+        // ureq is blocking, keep it off the async workers
+        let url = self.config.rpc_url.clone();
+        let mut response: serde_json::Value = tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(request)?
+                .into_json()
+                .map_err(Error::Io)
+        })
+        .await??;
+
+        if let Some(error) = response.get("error") {
+            return Err(Error::Rpc {
+                source: format!("{} failed: {}", method, error["message"]).into(),
+                retryable: false,
+            });
+        }
+        Ok(response["result"].take())
+    }
+}
+
+#[async_trait]
+impl ChainAnchor for EthereumAnchor {
+    fn chain(&self) -> &str {
+        "ethereum"
+    }
+
+    async fn anchor(&mut self, event: &Event) -> Result<String, Error> {
+        let data = anchor_call_data(&event_hash(event)?);
+        let transaction = serde_json::json!({
+            "from": self.config.from,
+            "to": self.config.contract,
+            "data": format!("0x{}", to_hex(&data)),
+        });
+        let result = self
+            .call("eth_sendTransaction", serde_json::json!([transaction]))
+            .await?;
+        result
+            .as_str()
+            .map(|hash| hash.to_string())
+            .ok_or(Error::serialization(
+                "eth_sendTransaction returned no transaction hash",
+            ))
+    }
+}
+
+/// Hash of the event anchored on the chain, keccak256 of its borsh serialization.
+pub fn event_hash(event: &Event) -> Result<[u8; 32], Error> {
+    let serialized = borsh::to_vec(event).map_err(Error::Serialization)?;
+    Ok(Keccak256::digest(serialized).into())
+}
+
+/// ABI encoded call of the contract's `anchor(bytes32)`.
+pub fn anchor_call_data(hash: &[u8; 32]) -> Vec<u8> {
+    let mut data = selector(ANCHOR_FUNCTION).to_vec();
+    data.extend_from_slice(hash);
+    data
+}
+
+// First 4 bytes of the keccak256 of the function's signature
+fn selector(signature: &str) -> [u8; 4] {
+    let hash = Keccak256::digest(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    #[test]
+    fn test_anchor_call_data() {
+        // ERC-20 transfer, a well known selector
+        assert_eq!(to_hex(&selector("transfer(address,uint256)")), "a9059cbb");
+
+        let event = Event::new("/srv/data/a.txt", EventType::Written);
+        let hash = event_hash(&event).unwrap();
+        let data = anchor_call_data(&hash);
+        assert_eq!(data.len(), 4 + 32);
+        assert_eq!(data[..4], selector(ANCHOR_FUNCTION));
+        assert_eq!(data[4..], hash);
+    }
+}
@@ -0,0 +1,316 @@
+//! Blockchains the events are anchored on.
+//!
+//! Every target (the Solana program, an EVM contract, ...) implements [ChainAnchor]
+//! and is plugged into the sinks with [AnchorSink], so the same watcher pipeline
+//! feeds any of them.
+
+#[cfg(feature = "ethereum")]
+pub mod ethereum;
+
+use crate::error::Error;
+use crate::event::{Event, EventsDropped};
+use crate::sink::latency::SharedLatencies;
+use crate::sink::{EventSink, IDLE_FLUSH_INTERVAL};
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[async_trait]
+pub trait ChainAnchor: Send {
+    /// Name of the chain, used in the logs.
+    fn chain(&self) -> &str;
+
+    /// Records the event on the chain, returns the transaction id.
+    /// The transaction may still be unconfirmed.
+    async fn anchor(&mut self, event: &Event) -> Result<String, Error>;
+
+    /// Records the events in as few transactions as the chain allows, in their order.
+    /// By default every event is anchored in a transaction of its own.
+    async fn anchor_batch(&mut self, events: Vec<Event>) -> Vec<Anchored> {
+        let mut anchored = Vec::with_capacity(events.len());
+        for event in events {
+            let transaction = self.anchor(&event).await;
+            anchored.push(Anchored {
+                events: vec![event],
+                transaction,
+            });
+        }
+        anchored
+    }
+
+    /// Records the events dropped instead of anchored, returns the transaction id.
+    /// Not supported by default.
+    async fn anchor_dropped(&mut self, _dropped: &EventsDropped) -> Result<String, Error> {
+        Err(Error::Config(format!(
+            "Dropped events aren't recorded on {}",
+            self.chain()
+        )))
+    }
+
+    /// Called once, after the last event. Waits for the pending transactions.
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+// The idle batches are checked at most this often
+const MIN_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Outcome of a transaction of [ChainAnchor::anchor_batch].
+pub struct Anchored {
+    pub events: Vec<Event>,
+    /// The transaction id, or why the events were not anchored.
+    pub transaction: Result<String, Error>,
+}
+
+/// Anchors the events it receives, in batches if set [AnchorSink::with_batching].
+pub struct AnchorSink<A: ChainAnchor> {
+    anchor: A,
+    batch_size: usize,
+    max_latency: Duration,
+    batch: Vec<Event>,
+    // When the oldest event of the batch was received
+    batch_started: Option<Instant>,
+    // Capture times of the batched events of each file, in their order
+    captured: HashMap<String, VecDeque<Instant>>,
+    latencies: Option<SharedLatencies>,
+}
+
+impl<A: ChainAnchor> AnchorSink<A> {
+    pub fn new(anchor: A) -> AnchorSink<A> {
+        AnchorSink {
+            anchor,
+            batch_size: 1,
+            max_latency: Duration::ZERO,
+            batch: Vec::new(),
+            batch_started: None,
+            captured: HashMap::new(),
+            latencies: None,
+        }
+    }
+
+    /// Records the latency from the capture of the events to their submission.
+    pub fn with_latencies(mut self, latencies: SharedLatencies) -> AnchorSink<A> {
+        self.latencies = Some(latencies);
+        self
+    }
+
+    /// Anchors the events once `size` of them are received, or once the oldest one
+    /// waited for `max_latency`, so that a quiet directory is still recorded in time.
+    /// The latency is checked with every event and whenever the stream is idle.
+    pub fn with_batching(mut self, size: usize, max_latency: Duration) -> AnchorSink<A> {
+        self.batch_size = size.max(1);
+        self.max_latency = max_latency;
+        self
+    }
+
+    fn batch_due(&self, now: Instant) -> bool {
+        self.batch.len() >= self.batch_size
+            || self
+                .batch_started
+                .is_some_and(|started| now.duration_since(started) >= self.max_latency)
+    }
+
+    // The failures are logged here, with all the events of the failed transaction
+    async fn anchor_pending(&mut self) {
+        self.batch_started = None;
+        let events = std::mem::take(&mut self.batch);
+        if events.is_empty() {
+            return;
+        }
+        for anchored in self
+            .anchor
+            .anchor_batch(events)
+            .await
+        {
+            let submitted = Instant::now();
+            for event in anchored.events.iter() {
+                let captured = self
+                    .captured
+                    .get_mut(&event.file_path)
+                    .and_then(|times| times.pop_front());
+                if let (Some(captured), Some(latencies), true) = (
+                    captured,
+                    self.latencies.as_ref(),
+                    anchored.transaction.is_ok(),
+                ) {
+                    latencies
+                        .lock()
+                        .unwrap()
+                        .capture_to_submit
+                        .record(submitted.duration_since(captured));
+                }
+            }
+            match anchored.transaction {
+                Ok(transaction) => println!(
+                    "[{}] {} event(s) anchored: {}",
+                    self.anchor.chain(),
+                    anchored.events.len(),
+                    transaction
+                ),
+                Err(err) => {
+                    for event in anchored.events.iter() {
+                        println!(
+                            "[{}] Failed to anchor the event {}: {}",
+                            self.anchor.chain(),
+                            event,
+                            err
+                        );
+                    }
+                }
+            }
+        }
+        self.captured
+            .retain(|_, times| !times.is_empty());
+    }
+}
+
+#[async_trait]
+impl<A: ChainAnchor> EventSink for AnchorSink<A> {
+    fn name(&self) -> &str {
+        self.anchor.chain()
+    }
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+        self.on_captured(event, Instant::now())
+            .await
+    }
+
+    async fn on_captured(&mut self, event: &Event, captured: Instant) -> Result<(), Error> {
+        let now = Instant::now();
+        self.batch_started
+            .get_or_insert(now);
+        self.batch.push(event.clone());
+        self.captured
+            .entry(event.file_path.clone())
+            .or_default()
+            .push_back(captured);
+        if self.batch_due(now) {
+            self.anchor_pending().await;
+        }
+        Ok(())
+    }
+
+    /// Anchors the events received before the drops, then records the drops.
+    async fn on_dropped(&mut self, dropped: &EventsDropped) -> Result<(), Error> {
+        self.anchor_pending().await;
+        let transaction = self
+            .anchor
+            .anchor_dropped(dropped)
+            .await?;
+        println!(
+            "[{}] Dropped events recorded: {}",
+            self.anchor.chain(),
+            transaction
+        );
+        Ok(())
+    }
+
+    /// Anchors the batch if its oldest event waited long enough.
+    async fn flush(&mut self) -> Result<(), Error> {
+        if self.batch_due(Instant::now()) {
+            self.anchor_pending().await;
+        }
+        Ok(())
+    }
+
+    /// The batches waiting for less than the idle interval are checked that often.
+    fn flush_interval(&self) -> Duration {
+        match self.batch_size {
+            1 => IDLE_FLUSH_INTERVAL,
+            _ => self
+                .max_latency
+                .clamp(MIN_FLUSH_INTERVAL, IDLE_FLUSH_INTERVAL),
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        self.anchor_pending().await;
+        self.anchor.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+    use std::sync::{Arc, Mutex};
+
+    // Records the size of every batch
+    struct RecordingAnchor {
+        batches: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl ChainAnchor for RecordingAnchor {
+        fn chain(&self) -> &str {
+            "recording"
+        }
+
+        async fn anchor(&mut self, _event: &Event) -> Result<String, Error> {
+            unreachable!("The events are anchored in batches")
+        }
+
+        async fn anchor_batch(&mut self, events: Vec<Event>) -> Vec<Anchored> {
+            self.batches
+                .lock()
+                .unwrap()
+                .push(events.len());
+            vec![Anchored {
+                events,
+                transaction: Ok("tx".to_string()),
+            }]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_flushed_when_full_or_late() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = AnchorSink::new(RecordingAnchor {
+            batches: batches.clone(),
+        })
+        .with_batching(3, Duration::from_secs(60));
+        let event = Event::new("a.txt", EventType::Written);
+
+        for _ in 0..4 {
+            sink.on_event(&event)
+                .await
+                .unwrap();
+        }
+        // A young partial batch waits for more events
+        sink.flush().await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![3]);
+
+        let late = sink.batch_started.unwrap() + Duration::from_secs(60);
+        assert!(sink.batch_due(late));
+        sink.max_latency = Duration::ZERO;
+        sink.flush().await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![3, 1]);
+
+        // Nothing is left behind on shutdown
+        sink.max_latency = Duration::from_secs(60);
+        sink.on_event(&event)
+            .await
+            .unwrap();
+        sink.shutdown().await.unwrap();
+        assert_eq!(*batches.lock().unwrap(), vec![3, 1, 1]);
+    }
+
+    #[test]
+    fn test_short_latency_checked_sooner() {
+        let sink = |size, max_latency| {
+            AnchorSink::new(RecordingAnchor {
+                batches: Arc::new(Mutex::new(Vec::new())),
+            })
+            .with_batching(size, max_latency)
+        };
+        let interval = |size, max_latency| sink(size, max_latency).flush_interval();
+        assert_eq!(
+            interval(20, Duration::from_millis(200)),
+            Duration::from_millis(200)
+        );
+        assert_eq!(interval(20, Duration::ZERO), MIN_FLUSH_INTERVAL);
+        assert_eq!(interval(20, Duration::from_secs(10)), IDLE_FLUSH_INTERVAL);
+        assert_eq!(interval(1, Duration::ZERO), IDLE_FLUSH_INTERVAL);
+    }
+}
@@ -0,0 +1,151 @@
+//! Event source reading the Linux audit events, an alternative to inotify
+//! which also tells who changed the file and with which syscall.
+//!
+//! The audit rules are not installed by the tracker, the watched directories
+//! need a file watch, e.g. `auditctl -w /srv/data -p wa -k blkchn-file-tracker`.
+
+pub mod parser;
+
+use crate::config::WatcherConfig;
+use crate::dir_watcher::{capture_time, read_file_info, WatchBackend};
+use crate::error::Error;
+use crate::event::{Event, EventType, FileKind, Process};
+use async_trait::async_trait;
+use parser::{AuditChange, Parser};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// How often the log is checked for new lines once all of them are read
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct AuditSource {
+    // The audit log, or the socket of the audispd af_unix plugin
+    path: String,
+    dirs: Vec<String>,
+    events: Vec<EventType>,
+}
+
+impl AuditSource {
+    pub fn from_config(path: &str, config: &WatcherConfig) -> AuditSource {
+        AuditSource {
+            path: path.to_string(),
+            dirs: config.dirs.clone(),
+            events: config.events.clone(),
+        }
+    }
+
+    /// Sends the changes in the watched directories, never returns unless it fails.
+    pub async fn run(self, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        WatchBackend::run(Box::new(self), tx).await
+    }
+
+    fn read_socket(&self, tx: &mpsc::Sender<Event>) -> Result<(), Error> {
+        let stream = UnixStream::connect(&self.path)?;
+        println!("Reading audit events from {}", self.path);
+
+        let mut parser = Parser::default();
+        for line in BufReader::new(stream).lines() {
+            self.send(parser.push_line(&line?), tx)?;
+        }
+        Err(Error::Watcher(format!(
+            "Audit socket {} closed by audispd",
+            self.path
+        )))
+    }
+
+    // Reads the lines appended to the log, starting from its current end,
+    // and reopens it when it's rotated
+    fn follow_log(&self, tx: &mpsc::Sender<Event>) -> Result<(), Error> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::End(0))?;
+        let mut inode = file.metadata()?.ino();
+        let mut reader = BufReader::new(file);
+        println!("Following the audit log {}", self.path);
+
+        let mut parser = Parser::default();
+        let mut line = String::new();
+        loop {
+            // A partially written line is kept until it's complete
+            reader.read_line(&mut line)?;
+            if line.ends_with('\n') {
+                self.send(parser.push_line(line.trim_end()), tx)?;
+                line.clear();
+                continue;
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+
+            let rotated = std::fs::metadata(&self.path)
+                .map(|m| m.ino() != inode)
+                .unwrap_or(false);
+            if rotated {
+                let file = File::open(&self.path)?;
+                inode = file.metadata()?.ino();
+                reader = BufReader::new(file);
+                println!("Audit log {} rotated, reopened", self.path);
+            }
+        }
+    }
+
+    fn send(&self, changes: Vec<AuditChange>, tx: &mpsc::Sender<Event>) -> Result<(), Error> {
+        for change in changes {
+            if !self.is_watched(&change) {
+                continue;
+            }
+            println!("Audit: {}", change);
+            let file_info = match change.event_type {
+                EventType::Deleted | EventType::MovedFrom => None,
+                _ => read_file_info(&change.path),
+            };
+            // On the blocking thread, waits there while the queue is full. Timestamped
+            // by the listener
+            tx.blocking_send(Event {
+                kind: FileKind::of(file_info.as_ref()),
+                process: Some(Process {
+                    pid: change.actor.pid,
+                    exe: change.actor.exe,
+                }),
+                captured_at: capture_time(),
+                file_info,
+                ..Event::new(change.path, change.event_type)
+            })
+            .map_err(|_| Error::Disconnected)?;
+        }
+        Ok(())
+    }
+
+    fn is_watched(&self, change: &AuditChange) -> bool {
+        self.events
+            .contains(&change.event_type)
+            && self
+                .dirs
+                .iter()
+                .any(|dir| std::path::Path::new(&change.path).starts_with(dir))
+    }
+}
+
+#[async_trait]
+impl WatchBackend for AuditSource {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    async fn run(self: Box<Self>, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        // The reads block, the audit log is followed by polling
+        tokio::task::spawn_blocking(move || {
+            let metadata = std::fs::metadata(&self.path)?;
+            match metadata
+                .file_type()
+                .is_socket()
+            {
+                true => self.read_socket(&tx),
+                false => self.follow_log(&tx),
+            }
+        })
+        .await?
+    }
+}
@@ -0,0 +1,385 @@
+//! Audit log lines, as written to audit.log and sent by the audispd af_unix plugin:
+//! `type=SYSCALL msg=audit(1700000000.123:42): arch=c000003e syscall=257 ...`.
+//! The records of one event share the serial number (42) and end with an `EOE` record.
+
+use crate::event::EventType;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+// Audit's identifiers of the architectures, the syscall numbers depend on them
+const ARCH_X86_64: &str = "c000003e";
+const ARCH_AARCH64: &str = "c00000b7";
+// Fields which are hex encoded if they contain special characters
+const ENCODED_FIELDS: &[&str] = &["name", "cwd", "comm", "exe", "key", "proctitle"];
+
+/// Who made the syscall.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Actor {
+    pub pid: u32,
+    pub uid: u32,
+    /// Login user, kept across su and sudo, unset for the system services
+    pub auid: Option<u32>,
+    pub comm: String,
+    pub exe: String,
+}
+
+/// A file change reported by the audit subsystem, with the syscall which made it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditChange {
+    pub event_type: EventType,
+    pub path: String,
+    pub syscall: String,
+    pub actor: Actor,
+}
+
+impl Display for AuditChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} by {} (pid {}, uid {}",
+            self.path, self.event_type, self.actor.exe, self.actor.pid, self.actor.uid
+        )?;
+        if let Some(auid) = self.actor.auid {
+            write!(f, ", login uid {}", auid)?;
+        }
+        write!(f, ") with {}", self.syscall)
+    }
+}
+
+/// Collects the records of each event and turns the complete events into changes.
+#[derive(Default)]
+pub struct Parser {
+    serial: Option<String>,
+    records: Vec<(String, HashMap<String, String>)>,
+}
+
+impl Parser {
+    /// Returns the changes of the event completed by this line, if any.
+    pub fn push_line(&mut self, line: &str) -> Vec<AuditChange> {
+        let (record_type, serial, fields) = match parse_line(line) {
+            Some(record) => record,
+            None => return Vec::new(),
+        };
+
+        // Records of an event are written together, a new serial
+        // means that the previous event is complete even without EOE
+        let mut changes = Vec::new();
+        if self.serial.as_ref() != Some(&serial) {
+            changes = self.finish();
+            self.serial = Some(serial);
+        }
+        if record_type == "EOE" {
+            changes.extend(self.finish());
+        } else {
+            self.records
+                .push((record_type, fields));
+        }
+        changes
+    }
+
+    /// Ends the pending event, e.g. when the input has ended.
+    pub fn finish(&mut self) -> Vec<AuditChange> {
+        self.serial = None;
+        let records = std::mem::take(&mut self.records);
+        to_changes(&records).unwrap_or_default()
+    }
+}
+
+fn to_changes(records: &[(String, HashMap<String, String>)]) -> Option<Vec<AuditChange>> {
+    let record = |record_type: &'static str| {
+        records
+            .iter()
+            .filter(move |(t, _)| t == record_type)
+            .map(|(_, fields)| fields)
+    };
+    let syscall = record("SYSCALL").next()?;
+    if syscall.get("success")? != "yes" {
+        return None;
+    }
+    let syscall_name = syscall_name(syscall)?;
+    let cwd = record("CWD")
+        .next()
+        .and_then(|fields| fields.get("cwd"));
+    let actor = Actor {
+        pid: syscall
+            .get("pid")?
+            .parse()
+            .ok()?,
+        uid: syscall
+            .get("uid")?
+            .parse()
+            .ok()?,
+        // 4294967295, (u32)-1, if unset
+        auid: syscall
+            .get("auid")
+            .and_then(|a| a.parse().ok())
+            .filter(|a| *a != u32::MAX),
+        comm: syscall
+            .get("comm")
+            .cloned()
+            .unwrap_or_default(),
+        exe: syscall
+            .get("exe")
+            .cloned()
+            .unwrap_or_default(),
+    };
+
+    let renamed = syscall_name.starts_with("rename");
+    let changes = record("PATH")
+        .filter_map(|item| {
+            let event_type = match (item.get("nametype")?.as_str(), renamed) {
+                ("CREATE", false) => EventType::Created,
+                ("CREATE", true) => EventType::MovedTo,
+                ("DELETE", false) => EventType::Deleted,
+                ("DELETE", true) => EventType::MovedFrom,
+                ("NORMAL", _) => normal_event_type(&syscall_name)?,
+                // Parent directories
+                _ => return None,
+            };
+            Some(AuditChange {
+                event_type,
+                path: absolute_path(cwd, item.get("name")?),
+                syscall: syscall_name.clone(),
+                actor: actor.clone(),
+            })
+        })
+        .collect();
+    Some(changes)
+}
+
+// A file watch (`auditctl -w <path> -p wa`) reports the opens for writing,
+// not the writes themselves, and the attribute changes
+fn normal_event_type(syscall: &str) -> Option<EventType> {
+    match syscall {
+        "open" | "openat" | "openat2" | "creat" | "truncate" | "ftruncate" => {
+            Some(EventType::Written)
+        }
+        "chmod" | "fchmod" | "fchmodat" | "fchmodat2" | "chown" | "fchown" | "lchown"
+        | "fchownat" | "setxattr" | "lsetxattr" | "fsetxattr" | "removexattr" | "lremovexattr"
+        | "fremovexattr" | "utime" | "utimes" | "futimesat" | "utimensat" => {
+            Some(EventType::AttributeChanged)
+        }
+        _ => None,
+    }
+}
+
+// The relative names are relative to the working directory of the process. The ones
+// relative to a directory descriptor (`*at` syscalls) can't be resolved, these end up
+// outside of the watched directories.
+fn absolute_path(cwd: Option<&String>, name: &str) -> String {
+    match cwd {
+        Some(cwd) if !name.starts_with('/') => Path::new(cwd)
+            .join(name)
+            .to_string_lossy()
+            .into_owned(),
+        _ => name.to_string(),
+    }
+}
+
+// Name of the syscall, given by the enriched log format or looked up by its number
+fn syscall_name(fields: &HashMap<String, String>) -> Option<String> {
+    if let Some(name) = fields.get("SYSCALL") {
+        return Some(name.clone());
+    }
+    let number: u32 = fields
+        .get("syscall")?
+        .parse()
+        .ok()?;
+    let table = match fields.get("arch")?.as_str() {
+        ARCH_X86_64 => SYSCALLS_X86_64,
+        ARCH_AARCH64 => SYSCALLS_AARCH64,
+        _ => return Some(number.to_string()),
+    };
+    let name = table
+        .iter()
+        .find(|(n, _)| *n == number)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| number.to_string());
+    Some(name)
+}
+
+// Returns the record type, the event's serial number and the fields
+fn parse_line(line: &str) -> Option<(String, String, HashMap<String, String>)> {
+    let record_type = line
+        .strip_prefix("type=")?
+        .split(' ')
+        .next()?
+        .to_string();
+    let (_, rest) = line.split_once("msg=audit(")?;
+    let (id, rest) = rest.split_once("):")?;
+    let (_, serial) = id.split_once(':')?;
+
+    // The enriched format appends the translated fields after a group separator
+    let (raw, enriched) = rest
+        .split_once('\x1d')
+        .unwrap_or((rest, ""));
+    let mut fields = HashMap::new();
+    for field in raw.split_whitespace() {
+        if let Some((key, value)) = field.split_once('=') {
+            fields.insert(key.to_string(), decode_value(key, value));
+        }
+    }
+    for field in enriched.split_whitespace() {
+        if let Some(("SYSCALL", value)) = field.split_once('=') {
+            fields.insert("SYSCALL".to_string(), value.to_string());
+        }
+    }
+    Some((record_type, serial.to_string(), fields))
+}
+
+// Strings are either quoted or, if they contain special characters, hex encoded
+fn decode_value(key: &str, value: &str) -> String {
+    if let Some(quoted) = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+    {
+        return quoted.to_string();
+    }
+    let is_hex = value.len().is_multiple_of(2)
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit());
+    if !ENCODED_FIELDS.contains(&key) || !is_hex {
+        return value.to_string();
+    }
+    let bytes: Option<Vec<u8>> = (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect();
+    match bytes {
+        Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        None => value.to_string(),
+    }
+}
+
+// Only the syscalls changing files, the others are reported by their numbers
+const SYSCALLS_X86_64: &[(u32, &str)] = &[
+    (2, "open"),
+    (76, "truncate"),
+    (77, "ftruncate"),
+    (82, "rename"),
+    (83, "mkdir"),
+    (84, "rmdir"),
+    (85, "creat"),
+    (86, "link"),
+    (87, "unlink"),
+    (88, "symlink"),
+    (90, "chmod"),
+    (91, "fchmod"),
+    (92, "chown"),
+    (93, "fchown"),
+    (94, "lchown"),
+    (132, "utime"),
+    (133, "mknod"),
+    (188, "setxattr"),
+    (189, "lsetxattr"),
+    (190, "fsetxattr"),
+    (197, "removexattr"),
+    (198, "lremovexattr"),
+    (199, "fremovexattr"),
+    (235, "utimes"),
+    (257, "openat"),
+    (258, "mkdirat"),
+    (259, "mknodat"),
+    (260, "fchownat"),
+    (261, "futimesat"),
+    (263, "unlinkat"),
+    (264, "renameat"),
+    (265, "linkat"),
+    (266, "symlinkat"),
+    (268, "fchmodat"),
+    (280, "utimensat"),
+    (316, "renameat2"),
+    (437, "openat2"),
+    (452, "fchmodat2"),
+];
+
+const SYSCALLS_AARCH64: &[(u32, &str)] = &[
+    (5, "setxattr"),
+    (6, "lsetxattr"),
+    (7, "fsetxattr"),
+    (14, "removexattr"),
+    (15, "lremovexattr"),
+    (16, "fremovexattr"),
+    (33, "mknodat"),
+    (34, "mkdirat"),
+    (35, "unlinkat"),
+    (36, "symlinkat"),
+    (37, "linkat"),
+    (38, "renameat"),
+    (45, "truncate"),
+    (46, "ftruncate"),
+    (52, "fchmod"),
+    (53, "fchmodat"),
+    (54, "fchownat"),
+    (55, "fchown"),
+    (56, "openat"),
+    (88, "utimensat"),
+    (276, "renameat2"),
+    (437, "openat2"),
+    (452, "fchmodat2"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_events() {
+        let log = r#"type=SYSCALL msg=audit(1729000000.100:41): arch=c000003e syscall=257 success=yes exit=3 a0=ffffff9c items=2 ppid=1 pid=1234 auid=1000 uid=1000 gid=1000 comm="vim" exe="/usr/bin/vim" key="tracker"
+type=CWD msg=audit(1729000000.100:41): cwd="/srv/data"
+type=PATH msg=audit(1729000000.100:41): item=0 name="/srv/data/" inode=2 nametype=PARENT
+type=PATH msg=audit(1729000000.100:41): item=1 name=6E6577206E6F7465732E747874 inode=3 nametype=CREATE
+type=EOE msg=audit(1729000000.100:41):
+type=SYSCALL msg=audit(1729000000.200:42): arch=c000003e syscall=316 success=yes exit=0 items=4 ppid=1 pid=99 auid=4294967295 uid=0 gid=0 comm="mv" exe="/usr/bin/mv" key="tracker"
+type=CWD msg=audit(1729000000.200:42): cwd="/root"
+type=PATH msg=audit(1729000000.200:42): item=0 name="/srv/data/" nametype=PARENT
+type=PATH msg=audit(1729000000.200:42): item=1 name="/srv/data/" nametype=PARENT
+type=PATH msg=audit(1729000000.200:42): item=2 name="/srv/data/a.txt" nametype=DELETE
+type=PATH msg=audit(1729000000.200:42): item=3 name="/srv/data/b.txt" nametype=CREATE
+type=SYSCALL msg=audit(1729000000.300:43): arch=c000003e syscall=90 success=no exit=-1 items=1 ppid=1 pid=5 auid=1000 uid=1000 gid=1000 comm="chmod" exe="/usr/bin/chmod"
+type=PATH msg=audit(1729000000.300:43): item=0 name="/srv/data/b.txt" nametype=NORMAL
+type=SYSCALL msg=audit(1729000000.400:44): arch=c00000b7 syscall=53 success=yes exit=0 items=1 ppid=1 pid=6 auid=1000 uid=1000 gid=1000 comm="chmod" exe="/usr/bin/chmod"
+type=PATH msg=audit(1729000000.400:44): item=0 name="/srv/data/b.txt" nametype=NORMAL
+"#;
+
+        let mut parser = Parser::default();
+        let mut changes: Vec<AuditChange> = log
+            .lines()
+            .flat_map(|line| parser.push_line(line))
+            .collect();
+        changes.extend(parser.finish());
+
+        let summary: Vec<(EventType, &str, &str)> = changes
+            .iter()
+            .map(|c| (c.event_type.clone(), c.path.as_str(), c.syscall.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                // Hex encoded name, relative to the working directory
+                (EventType::Created, "/srv/data/new notes.txt", "openat"),
+                (EventType::MovedFrom, "/srv/data/a.txt", "renameat2"),
+                (EventType::MovedTo, "/srv/data/b.txt", "renameat2"),
+                // The failed chmod is skipped
+                (EventType::AttributeChanged, "/srv/data/b.txt", "fchmodat"),
+            ]
+        );
+        assert_eq!(
+            changes[0].actor,
+            Actor {
+                pid: 1234,
+                uid: 1000,
+                auid: Some(1000),
+                comm: "vim".to_string(),
+                exe: "/usr/bin/vim".to_string(),
+            }
+        );
+        assert_eq!(changes[1].actor.auid, None);
+        assert_eq!(
+            changes[0].to_string(),
+            "/srv/data/new notes.txt: Created by /usr/bin/vim (pid 1234, uid 1000, login uid 1000) with openat"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Attests the files which existed before the tracker was started.
+//!
+//! Adopting the tracker on an existing server, the watched directories are walked
+//! once and every regular file is recorded with its file info and the SHA-256 hash
+//! of its content, so the vault starts with a complete inventory.
+
+use crate::dir_watcher::{existing_files, read_file_info};
+use crate::event::{Event, EventType};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// An existing file, recorded as created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Baseline {
+    pub event: Event,
+    pub sha256: [u8; 32],
+}
+
+impl Baseline {
+    /// Sent with the event, e.g. `baseline sha256 9f86d081884c7d65...`.
+    pub fn memo(&self) -> String {
+        format!("baseline sha256 {}", crate::integrity::hex(&self.sha256))
+    }
+}
+
+/// Walks the directories and hashes all the regular files in them, the symlinks
+/// are not followed. The files which can't be read are logged and skipped.
+pub fn scan(dirs: &[String]) -> Vec<Baseline> {
+    dirs.iter()
+        .flat_map(|dir| existing_files(Path::new(dir), true))
+        .filter_map(|path| baseline(&path))
+        .collect()
+}
+
+fn baseline(path: &Path) -> Option<Baseline> {
+    let file_path = path
+        .to_string_lossy()
+        .into_owned();
+    let sha256 = hash_file(path)
+        .map_err(|e| println!("Failed to hash {}: {}", file_path, e))
+        .ok()?;
+    let file_info = read_file_info(&file_path)?;
+    Some(Baseline {
+        // Timestamped by the submitter
+        event: Event {
+            file_info: Some(file_info),
+            ..Event::new(file_path, EventType::Created)
+        },
+        sha256,
+    })
+}
+
+/// SHA-256 of the file's content, streamed, the files may be larger than the memory.
+pub fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_scan_records_regular_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "test").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a.txt"), dir.path().join("link")).unwrap();
+
+        let records = scan(&[dir
+            .path()
+            .to_string_lossy()
+            .into_owned()]);
+        let paths: Vec<_> = records
+            .iter()
+            .map(|r| r.event.file_path.clone())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path()
+                    .join("a.txt")
+                    .to_string_lossy(),
+                dir.path()
+                    .join("sub/b.txt")
+                    .to_string_lossy(),
+            ]
+        );
+        assert_eq!(records[0].event.event_type, EventType::Created);
+        assert_eq!(
+            records[0]
+                .event
+                .file_info
+                .as_ref()
+                .map(|fi| fi.size),
+            Some(4)
+        );
+        assert_eq!(
+            records[0].memo(),
+            "baseline sha256 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+}
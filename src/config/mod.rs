@@ -0,0 +1,1067 @@
+//! Tracker configuration.
+//!
+//! Values are taken from (highest precedence first):
+//! 1. command line flags, e.g. `--events created,written`,
+//! 2. `TRACKER_*` environment variables, e.g. `TRACKER_RPC_URL`,
+//! 3. the TOML config file pointed to by `TRACKER_CONFIG` (or `./tracker.toml` if present),
+//! 4. built-in defaults.
+//!
+//! This way the tracker drops into Docker/Kubernetes without mounting any config file.
+
+mod reload;
+mod tracker;
+
+pub use reload::{Reloader, Reloads};
+#[cfg(feature = "ethereum")]
+pub use tracker::EthereumConfig;
+pub use tracker::{
+    EventSource, JournalConfig, LeaderConfig, Mode, Problem, SnapshotConfig, TrackerConfig,
+    WatcherConfig,
+};
+#[cfg(feature = "solana")]
+pub use tracker::{ProgramRoute, SolanaConfig};
+
+use crate::error::Error;
+use crate::event::EventType;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+pub const ENV_PREFIX: &str = "TRACKER_";
+const DEFAULT_CONFIG_FILE: &str = "tracker.toml";
+const DEFAULT_PROGRAM_ID: &str = "BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd";
+const DEFAULT_ARWEAVE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_CLOCK_SKEW_THRESHOLD_SECS: u64 = 30;
+const DEFAULT_BUDGET_PERIOD_HOURS: u64 = 24;
+const DEFAULT_BREAKER_FAILURE_RATE: u8 = 50;
+const DEFAULT_BREAKER_COOL_DOWN_SECS: u64 = 30;
+const DEFAULT_RETRY_BUDGET_SECS: u64 = 30;
+const DEFAULT_BATCH_SIZE: usize = 1;
+const DEFAULT_AUDIT_LOG: &str = "/var/log/audit/audit.log";
+// inotify is Linux only, the other platforms watch with their native API
+#[cfg(target_os = "linux")]
+const DEFAULT_EVENT_SOURCE: &str = "inotify";
+#[cfg(not(target_os = "linux"))]
+const DEFAULT_EVENT_SOURCE: &str = "notify";
+const DEFAULT_SCHEMA_VERSION: u16 = 3;
+const DEFAULT_BATCH_FLUSH_SECS: u64 = 10;
+const DEFAULT_METRICS_FILE: &str = "./tracker-metrics.json";
+const DEFAULT_SPILL_DIR: &str = "./tracker-spill";
+const DEFAULT_HASHES_FILE: &str = "./tracker-hashes.json";
+const DEFAULT_HASH_WORKERS: usize = 2;
+const DEFAULT_JOURNAL_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_JOURNAL_MAX_FILES: usize = 8;
+const DEFAULT_WALLET_POOL_MIN_BALANCE: u64 = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Watched directories
+    pub watch_dirs: Vec<String>,
+    // Files watched on their own, e.g. /etc/passwd, followed when replaced
+    pub watch_files: Vec<String>,
+    // Watch the subdirectories too, including those created while running
+    pub recursive: bool,
+    // Levels of subdirectories watched if recursive, e.g. 2 for /srv/a/b, all if not set
+    pub max_depth: Option<usize>,
+    // Glob patterns of the reported files, e.g. ["*.conf"], empty means all of them
+    pub include_globs: Vec<String>,
+    // Glob patterns of the ignored files, e.g. ["*.tmp"]
+    pub exclude_globs: Vec<String>,
+    // Built-in sets of the ignored files: hidden, editor_temp
+    pub exclude_presets: Vec<String>,
+    // Bytes, the files out of the range are not reported
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    // Name of the ignore file in the watched directories, in the gitignore syntax
+    pub ignore_file: Option<String>,
+    // Reported event types, e.g. ["created", "written"]
+    pub events: Vec<EventType>,
+    // Where the events come from: inotify, notify, poll or audit
+    pub event_source: String,
+    // How often the poll source lists the watched directories
+    pub poll_interval_secs: u64,
+    // Bytes the inotify source reads the events into at once, the long names take more
+    pub inotify_buffer_size: usize,
+    // Poll the directories the inotify watches ran out for, instead of failing
+    pub watch_limit_fallback: bool,
+    // Print the inotify watcher's statistics every so often
+    pub watcher_stats_secs: Option<u64>,
+    // Report the files existing at the start as created, so the vault holds the whole tree
+    pub baseline_scan: bool,
+    // Where the watched files' state is kept, to report the changes made while stopped
+    pub state_file: Option<String>,
+    pub state_interval_secs: u64, // how often the state is saved while running
+    // Apply the changes of the config file to the watched paths, the filters and the pipeline
+    pub reload_config: bool,
+    // The audit log, or the audispd af_unix plugin's socket
+    pub audit_log: String,
+    // Drop the .git/ changes and submit the repository's state with the events
+    pub git_aware: bool,
+    // Watch the directories inside containers in their overlayfs upper directories
+    pub overlay_aware: bool,
+    // Capture of the raw inotify events, written by the watcher, read by the replay mode
+    pub record_file: Option<String>,
+    // Write the confirmed transactions to the files' user.blkchn.sig attribute
+    pub xattr_receipts: bool,
+    // Extended attributes read with the attribute changes, e.g. ["security.selinux", "user.*"]
+    pub capture_xattrs: Vec<String>,
+    // Sniff the MIME type of the created and written files, for the filters and the memos
+    pub detect_file_types: bool,
+    // Diff the written text files of at most this many bytes with their previous content
+    pub diff_max_size: Option<u64>,
+    // Hash the opened files and compare them with the hashes recorded on chain
+    pub verify_on_open: bool,
+    pub hashes_file: String, // the recorded hashes, saved by the submitter
+    pub hash_workers: usize, // threads hashing the written files ahead of the submitter
+
+    // Solana
+    pub rpc_url: String,
+    pub commitment: String, // processed, confirmed or finalized
+    pub rpc_debug: bool,    // log every RPC request and response
+    pub program_id: String,
+    pub wallet_keypair: Option<String>, // defaults to ~/.config/solana/id.json
+    // Keypairs of the funded wallets paying the events' transactions in turn
+    pub wallet_pool: Vec<String>,
+    pub wallet_pool_min_balance: u64, // lamports, a poorer pool wallet leaves the rotation
+    // Schema of the sent events, 2 stores the timestamps as i64 seconds, 1 as i128,
+    // 3 also the process, the sequence number, the capture time and the file's type
+    pub schema_version: u16,
+    // The watcher's wallet, read-only, instead of the keypair in the verify mode
+    pub wallet_pubkey: Option<String>,
+    // Where the verify mode writes its JSON report
+    pub report_file: Option<String>,
+    // Unix time range of the events proven in the report, none proven if not set
+    pub report_since: Option<i64>,
+    pub report_until: Option<i64>,
+    pub clock_skew_threshold_secs: u64, // alert when the local clock is off by more
+    pub budget_lamports: Option<u64>,   // spent on transactions per period, then journaled
+    pub budget_period_hours: u64,
+    pub breaker_failure_rate: u8, // percent of failed RPC calls stopping the sending
+    pub breaker_cool_down_secs: u64, // then the RPC node is probed
+    pub retry_budget_secs: u64,   // spent retrying a failed transaction, then it's journaled
+    pub batch_size: usize,        // events packed in the transactions at once
+    pub batch_flush_secs: u64,
+    pub batch_flush_ms: Option<u64>, // instead of batch_flush_secs, for the shorter waits
+    // Address lookup tables of the batched transactions, sent as v0 transactions
+    pub lookup_tables: Vec<String>,
+    // Compute-unit prices in micro-lamports by event type, e.g. { deleted = 50000 }
+    pub priority_fees: BTreeMap<String, u64>, // a partial batch is sent after waiting for this long
+    // Directories whose events go to another program than program_id, e.g. a staging one
+    pub program_routes: BTreeMap<String, String>,
+    // Report the captured events not confirmed on chain every so often
+    pub completeness_report_secs: Option<u64>,
+    pub completeness_on_chain: bool, // record the reported gaps in a memo too
+
+    // Permanent copies of the changed files
+    pub arweave_bundler: Option<String>, // bundlr node URL
+    pub arweave_max_size: u64,           // larger files are not uploaded
+
+    // Ethereum/EVM anchoring, with the ethereum feature
+    pub ethereum_rpc_url: Option<String>,
+    pub ethereum_contract: Option<String>, // address of the EventAnchor contract
+    pub ethereum_from: Option<String>,     // account signing the transactions on the node
+
+    // Between the watcher and the sinks in one process: tokio, std, crossbeam or unix
+    pub transport: String,
+    // Split process mode
+    pub socket_path: String,
+
+    // Forwarding from the watcher or agent process
+    pub max_event_age_hours: Option<u64>, // older queued events are dead-lettered
+    pub dead_letter_path: String,
+
+    // Local binary journal of the events, written before they're passed to the sinks
+    pub journal_dir: Option<String>,
+    pub journal_max_file_size: u64, // bytes, the file is rotated once it's full
+    pub journal_max_files: usize,   // the oldest files are deleted
+    pub journal_retention_days: Option<u64>, // the gc mode prunes the older files
+    pub journal_retention_size: Option<u64>, // bytes, the gc mode prunes the oldest files over it
+
+    // Hot standby: the instance holding the lease in this file submits, the other one waits
+    pub leader_lease_file: Option<String>,
+    pub leader_lease_secs: u64, // the standby takes over once it's not renewed for this long
+
+    // Remote agent/collector mode
+    pub collector_addr: Option<String>,
+    pub collector_listen: String,
+    pub tls_ca_cert: Option<String>,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+
+    // Middleware stages between the watcher and the sinks, in order
+    pub pipeline: Vec<crate::pipeline::StageConfig>,
+    // Overrides of the default priorities, e.g. { deleted = 3, written = 1 }
+    pub event_priorities: BTreeMap<String, u8>,
+    // Events waiting for each sink, the next ones are dropped and the drops recorded
+    pub channel_capacity: Option<usize>,
+    // What happens once a sink's queue is full: block, drop_oldest, drop_newest or spill_to_disk
+    pub backpressure: String,
+    pub spill_dir: String, // overflow of the sinks' queues with spill_to_disk
+
+    // Additional event sinks
+    pub webhook_url: Option<String>,
+    pub metrics: bool,
+    pub metrics_file: String, // latencies saved by the metrics sink, read by the status mode
+    pub anomaly_detection: bool, // alert when a file changes much more often than usual
+
+    // Snapshot diff mode
+    pub snapshot_backend: Option<String>, // zfs or btrfs
+    pub snapshot_from: Option<String>,
+    pub snapshot_to: Option<String>,
+    pub snapshot_root: Option<String>, // btrfs: where the subvolume is mounted
+
+    // Where each of the values set explicitly comes from
+    #[serde(skip)]
+    sources: BTreeMap<String, Source>,
+}
+
+/// Where a config value comes from, reported together with invalid values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(String),
+    Arg(String),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "the default"),
+            Source::File(path) => write!(f, "the config file {}", path),
+            Source::Env(name) => write!(f, "the environment variable {}", name),
+            Source::Arg(flag) => write!(f, "the command line flag {}", flag),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            watch_dirs: vec!["./tmp".to_string()], // path relative to Cargo.toml
+            watch_files: Vec::new(),
+            recursive: false,
+            max_depth: None,
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            exclude_presets: Vec::new(),
+            min_file_size: None,
+            max_file_size: None,
+            ignore_file: None,
+            events: vec![
+                EventType::AttributeChanged,
+                EventType::Created,
+                EventType::Deleted,
+                EventType::MovedFrom,
+                EventType::MovedTo,
+                EventType::Written,
+            ],
+            event_source: DEFAULT_EVENT_SOURCE.to_string(),
+            poll_interval_secs: 5,
+            inotify_buffer_size: crate::dir_watcher::DEFAULT_BUFFER_SIZE,
+            watch_limit_fallback: false,
+            watcher_stats_secs: None,
+            baseline_scan: false,
+            state_file: None,
+            state_interval_secs: 60,
+            reload_config: false,
+            audit_log: DEFAULT_AUDIT_LOG.to_string(),
+            git_aware: false,
+            overlay_aware: false,
+            record_file: None,
+            xattr_receipts: false,
+            capture_xattrs: Vec::new(),
+            detect_file_types: false,
+            diff_max_size: None,
+            verify_on_open: false,
+            hashes_file: DEFAULT_HASHES_FILE.to_string(),
+            hash_workers: DEFAULT_HASH_WORKERS,
+            rpc_url: "http://127.0.0.1:8899".to_string(),
+            commitment: "confirmed".to_string(),
+            rpc_debug: false,
+            program_id: DEFAULT_PROGRAM_ID.to_string(),
+            wallet_keypair: None,
+            wallet_pool: Vec::new(),
+            wallet_pool_min_balance: DEFAULT_WALLET_POOL_MIN_BALANCE,
+            schema_version: DEFAULT_SCHEMA_VERSION,
+            wallet_pubkey: None,
+            report_file: None,
+            report_since: None,
+            report_until: None,
+            clock_skew_threshold_secs: DEFAULT_CLOCK_SKEW_THRESHOLD_SECS,
+            budget_lamports: None,
+            budget_period_hours: DEFAULT_BUDGET_PERIOD_HOURS,
+            breaker_failure_rate: DEFAULT_BREAKER_FAILURE_RATE,
+            breaker_cool_down_secs: DEFAULT_BREAKER_COOL_DOWN_SECS,
+            retry_budget_secs: DEFAULT_RETRY_BUDGET_SECS,
+            batch_size: DEFAULT_BATCH_SIZE,
+            batch_flush_secs: DEFAULT_BATCH_FLUSH_SECS,
+            batch_flush_ms: None,
+            lookup_tables: Vec::new(),
+            priority_fees: BTreeMap::new(),
+            program_routes: BTreeMap::new(),
+            completeness_report_secs: None,
+            completeness_on_chain: false,
+            arweave_bundler: None,
+            arweave_max_size: DEFAULT_ARWEAVE_MAX_SIZE,
+            ethereum_rpc_url: None,
+            ethereum_contract: None,
+            ethereum_from: None,
+            transport: "tokio".to_string(),
+            socket_path: crate::ipc::DEFAULT_SOCKET_PATH.to_string(),
+            max_event_age_hours: None,
+            dead_letter_path: crate::outbox::dead_letter::DEFAULT_DEAD_LETTER_PATH.to_string(),
+            journal_dir: None,
+            journal_max_file_size: DEFAULT_JOURNAL_MAX_FILE_SIZE,
+            journal_max_files: DEFAULT_JOURNAL_MAX_FILES,
+            journal_retention_days: None,
+            journal_retention_size: None,
+            leader_lease_file: None,
+            leader_lease_secs: crate::leader::DEFAULT_LEASE.as_secs(),
+            collector_addr: None,
+            collector_listen: crate::remote::DEFAULT_COLLECTOR_LISTEN.to_string(),
+            tls_ca_cert: None,
+            tls_cert: None,
+            tls_key: None,
+            pipeline: Vec::new(),
+            event_priorities: BTreeMap::new(),
+            channel_capacity: None,
+            backpressure: "drop_newest".to_string(),
+            spill_dir: DEFAULT_SPILL_DIR.to_string(),
+            webhook_url: None,
+            metrics: false,
+            metrics_file: DEFAULT_METRICS_FILE.to_string(),
+            anomaly_detection: false,
+            snapshot_backend: None,
+            snapshot_from: None,
+            snapshot_to: None,
+            snapshot_root: None,
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file (if any) and applies the environment on top of it.
+    pub fn load() -> Result<Config, Error> {
+        let mut config = match Config::file() {
+            Some(path) => Config::from_file(&path)?,
+            None => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// The config file [Self::load] reads, none if there's none.
+    pub fn file() -> Option<String> {
+        match std::env::var(ENV_PREFIX.to_string() + "CONFIG") {
+            Ok(path) => Some(path),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Some(DEFAULT_CONFIG_FILE.to_string())
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, Error> {
+        let content = std::fs::read_to_string(path)?;
+        let invalid =
+            |e: toml::de::Error| Error::Config(format!("Invalid config file {}: {}", path, e));
+        let table: toml::Table = toml::from_str(&content).map_err(invalid)?;
+        let keys: Vec<String> = table
+            .keys()
+            .cloned()
+            .collect();
+
+        let mut config: Config = table
+            .try_into()
+            .map_err(invalid)?;
+        for key in keys {
+            config
+                .sources
+                .insert(key, Source::File(path.to_string()));
+        }
+        Ok(config)
+    }
+
+    /// Where the value of the `field` comes from.
+    pub fn source(&self, field: &str) -> Source {
+        self.sources
+            .get(field)
+            .cloned()
+            .unwrap_or(Source::Default)
+    }
+
+    /// Overrides the values with `TRACKER_*` variables returned by `lookup`.
+    /// Lists are comma separated.
+    pub fn apply_env<F>(&mut self, lookup: F) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        // The variables found, the field names are their lowercase names without the prefix
+        let found = RefCell::new(Vec::new());
+        let lookup = |name: &str| {
+            let value = lookup(name);
+            if value.is_some() {
+                found
+                    .borrow_mut()
+                    .push(name.to_string());
+            }
+            value
+        };
+        let var = |name: &str| lookup(&(ENV_PREFIX.to_string() + name));
+
+        if let Some(v) = var("WATCH_DIRS") {
+            self.watch_dirs = split_list(&v);
+        }
+        if let Some(v) = var("WATCH_FILES") {
+            self.watch_files = split_list(&v);
+        }
+        if let Some(v) = var("RECURSIVE") {
+            self.recursive = parse_bool(&v)?;
+        }
+        if let Some(v) = var("MAX_DEPTH") {
+            self.max_depth = Some(
+                v.parse()
+                    .map_err(|e| Error::Config(format!("Invalid maximum depth {}: {}", v, e)))?,
+            );
+        }
+        if let Some(v) = var("INCLUDE_GLOBS") {
+            self.include_globs = split_list(&v);
+        }
+        if let Some(v) = var("EXCLUDE_GLOBS") {
+            self.exclude_globs = split_list(&v);
+        }
+        if let Some(v) = var("EXCLUDE_PRESETS") {
+            self.exclude_presets = split_list(&v);
+        }
+        for (name, size) in [
+            ("MIN_FILE_SIZE", &mut self.min_file_size),
+            ("MAX_FILE_SIZE", &mut self.max_file_size),
+        ] {
+            if let Some(v) = var(name) {
+                *size =
+                    Some(v.parse().map_err(|e| {
+                        Error::Config(format!("Invalid number of bytes {}: {}", v, e))
+                    })?);
+            }
+        }
+        if let Some(v) = var("IGNORE_FILE") {
+            self.ignore_file = Some(v);
+        }
+        if let Some(v) = var("EVENTS") {
+            self.events = parse_events(&v)?;
+        }
+        if let Some(v) = var("EVENT_SOURCE") {
+            self.event_source = v;
+        }
+        if let Some(v) = var("POLL_INTERVAL_SECS") {
+            self.poll_interval_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("WATCH_LIMIT_FALLBACK") {
+            self.watch_limit_fallback = parse_bool(&v)?;
+        }
+        if let Some(v) = var("WATCHER_STATS_SECS") {
+            self.watcher_stats_secs =
+                Some(v.parse().map_err(|e| {
+                    Error::Config(format!("Invalid number of seconds {}: {}", v, e))
+                })?);
+        }
+        if let Some(v) = var("INOTIFY_BUFFER_SIZE") {
+            self.inotify_buffer_size = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of bytes {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BASELINE_SCAN") {
+            self.baseline_scan = parse_bool(&v)?;
+        }
+        if let Some(v) = var("STATE_FILE") {
+            self.state_file = Some(v);
+        }
+        if let Some(v) = var("STATE_INTERVAL_SECS") {
+            self.state_interval_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("RELOAD_CONFIG") {
+            self.reload_config = parse_bool(&v)?;
+        }
+        if let Some(v) = var("AUDIT_LOG") {
+            self.audit_log = v;
+        }
+        if let Some(v) = var("GIT_AWARE") {
+            self.git_aware = parse_bool(&v)?;
+        }
+        if let Some(v) = var("OVERLAY_AWARE") {
+            self.overlay_aware = parse_bool(&v)?;
+        }
+        if let Some(v) = var("RECORD_FILE") {
+            self.record_file = Some(v);
+        }
+        if let Some(v) = var("XATTR_RECEIPTS") {
+            self.xattr_receipts = parse_bool(&v)?;
+        }
+        if let Some(v) = var("CAPTURE_XATTRS") {
+            self.capture_xattrs = split_list(&v);
+        }
+        if let Some(v) = var("DETECT_FILE_TYPES") {
+            self.detect_file_types = parse_bool(&v)?;
+        }
+        if let Some(v) = var("DIFF_MAX_SIZE") {
+            self.diff_max_size = Some(
+                v.parse()
+                    .map_err(|e| Error::Config(format!("Invalid number of bytes {}: {}", v, e)))?,
+            );
+        }
+        if let Some(v) = var("VERIFY_ON_OPEN") {
+            self.verify_on_open = parse_bool(&v)?;
+        }
+        if let Some(v) = var("HASHES_FILE") {
+            self.hashes_file = v;
+        }
+        if let Some(v) = var("HASH_WORKERS") {
+            self.hash_workers = v.parse().map_err(|e| {
+                Error::Config(format!("Invalid number of hash workers {}: {}", v, e))
+            })?;
+        }
+        if let Some(v) = var("RPC_URL") {
+            self.rpc_url = v;
+        }
+        if let Some(v) = var("COMMITMENT") {
+            self.commitment = v;
+        }
+        if let Some(v) = var("RPC_DEBUG") {
+            self.rpc_debug = parse_bool(&v)?;
+        }
+        // PROGRAM_ID and WALLET_KEYPAIR are still accepted for backward compatibility
+        if let Some(v) = var("PROGRAM_ID").or_else(|| lookup("PROGRAM_ID")) {
+            self.program_id = v;
+        }
+        if let Some(v) = var("WALLET_KEYPAIR").or_else(|| lookup("WALLET_KEYPAIR")) {
+            self.wallet_keypair = Some(v);
+        }
+        if let Some(v) = var("WALLET_POOL") {
+            self.wallet_pool = split_list(&v);
+        }
+        if let Some(v) = var("WALLET_POOL_MIN_BALANCE") {
+            self.wallet_pool_min_balance = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid lamports {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("SCHEMA_VERSION") {
+            self.schema_version = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid schema version {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("WALLET_PUBKEY") {
+            self.wallet_pubkey = Some(v);
+        }
+        if let Some(v) = var("REPORT_FILE") {
+            self.report_file = Some(v);
+        }
+        if let Some(v) = var("REPORT_SINCE") {
+            self.report_since = Some(parse_unix_time(&v)?);
+        }
+        if let Some(v) = var("REPORT_UNTIL") {
+            self.report_until = Some(parse_unix_time(&v)?);
+        }
+        if let Some(v) = var("CLOCK_SKEW_THRESHOLD_SECS") {
+            self.clock_skew_threshold_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BUDGET_LAMPORTS") {
+            self.budget_lamports = Some(
+                v.parse()
+                    .map_err(|e| Error::Config(format!("Invalid lamports {}: {}", v, e)))?,
+            );
+        }
+        if let Some(v) = var("BUDGET_PERIOD_HOURS") {
+            self.budget_period_hours = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of hours {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BREAKER_FAILURE_RATE") {
+            self.breaker_failure_rate = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid failure rate {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BREAKER_COOL_DOWN_SECS") {
+            self.breaker_cool_down_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("RETRY_BUDGET_SECS") {
+            self.retry_budget_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BATCH_SIZE") {
+            self.batch_size = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid batch size {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BATCH_FLUSH_SECS") {
+            self.batch_flush_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("BATCH_FLUSH_MS") {
+            self.batch_flush_ms = Some(v.parse().map_err(|e| {
+                Error::Config(format!("Invalid number of milliseconds {}: {}", v, e))
+            })?);
+        }
+        if let Some(v) = var("LOOKUP_TABLES") {
+            self.lookup_tables = split_list(&v);
+        }
+        if let Some(v) = var("PRIORITY_FEES") {
+            self.priority_fees = parse_fees(&v)?;
+        }
+        if let Some(v) = var("PROGRAM_ROUTES") {
+            self.program_routes = parse_routes(&v)?;
+        }
+        if let Some(v) = var("COMPLETENESS_REPORT_SECS") {
+            self.completeness_report_secs =
+                Some(v.parse().map_err(|e| {
+                    Error::Config(format!("Invalid number of seconds {}: {}", v, e))
+                })?);
+        }
+        if let Some(v) = var("COMPLETENESS_ON_CHAIN") {
+            self.completeness_on_chain = parse_bool(&v)?;
+        }
+        if let Some(v) = var("ARWEAVE_BUNDLER") {
+            self.arweave_bundler = Some(v);
+        }
+        if let Some(v) = var("ARWEAVE_MAX_SIZE") {
+            self.arweave_max_size = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid size {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("ETHEREUM_RPC_URL") {
+            self.ethereum_rpc_url = Some(v);
+        }
+        if let Some(v) = var("ETHEREUM_CONTRACT") {
+            self.ethereum_contract = Some(v);
+        }
+        if let Some(v) = var("ETHEREUM_FROM") {
+            self.ethereum_from = Some(v);
+        }
+        if let Some(v) = var("TRANSPORT") {
+            self.transport = v;
+        }
+        if let Some(v) = var("SOCKET_PATH") {
+            self.socket_path = v;
+        }
+        if let Some(v) = var("MAX_EVENT_AGE_HOURS") {
+            self.max_event_age_hours = Some(
+                v.parse()
+                    .map_err(|e| Error::Config(format!("Invalid number of hours {}: {}", v, e)))?,
+            );
+        }
+        if let Some(v) = var("DEAD_LETTER_PATH") {
+            self.dead_letter_path = v;
+        }
+        if let Some(v) = var("JOURNAL_DIR") {
+            self.journal_dir = Some(v);
+        }
+        if let Some(v) = var("JOURNAL_MAX_FILE_SIZE") {
+            self.journal_max_file_size = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid journal file size {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("JOURNAL_MAX_FILES") {
+            self.journal_max_files = v.parse().map_err(|e| {
+                Error::Config(format!("Invalid number of journal files {}: {}", v, e))
+            })?;
+        }
+        if let Some(v) = var("JOURNAL_RETENTION_DAYS") {
+            self.journal_retention_days = Some(
+                v.parse()
+                    .map_err(|e| Error::Config(format!("Invalid number of days {}: {}", v, e)))?,
+            );
+        }
+        if let Some(v) = var("JOURNAL_RETENTION_SIZE") {
+            self.journal_retention_size = Some(v.parse().map_err(|e| {
+                Error::Config(format!("Invalid journal retention size {}: {}", v, e))
+            })?);
+        }
+        if let Some(v) = var("LEADER_LEASE_FILE") {
+            self.leader_lease_file = Some(v);
+        }
+        if let Some(v) = var("LEADER_LEASE_SECS") {
+            self.leader_lease_secs = v
+                .parse()
+                .map_err(|e| Error::Config(format!("Invalid number of seconds {}: {}", v, e)))?;
+        }
+        if let Some(v) = var("COLLECTOR_ADDR") {
+            self.collector_addr = Some(v);
+        }
+        if let Some(v) = var("COLLECTOR_LISTEN") {
+            self.collector_listen = v;
+        }
+        if let Some(v) = var("TLS_CA_CERT") {
+            self.tls_ca_cert = Some(v);
+        }
+        if let Some(v) = var("TLS_CERT") {
+            self.tls_cert = Some(v);
+        }
+        if let Some(v) = var("TLS_KEY") {
+            self.tls_key = Some(v);
+        }
+        if let Some(v) = var("PIPELINE") {
+            self.pipeline = parse_pipeline(&v)?;
+        }
+        if let Some(v) = var("EVENT_PRIORITIES") {
+            self.event_priorities = parse_priorities(&v)?;
+        }
+        if let Some(v) = var("CHANNEL_CAPACITY") {
+            self.channel_capacity =
+                Some(v.parse().map_err(|e| {
+                    Error::Config(format!("Invalid channel capacity {}: {}", v, e))
+                })?);
+        }
+        if let Some(v) = var("BACKPRESSURE") {
+            self.backpressure = v;
+        }
+        if let Some(v) = var("SPILL_DIR") {
+            self.spill_dir = v;
+        }
+        if let Some(v) = var("WEBHOOK_URL") {
+            self.webhook_url = Some(v);
+        }
+        if let Some(v) = var("METRICS") {
+            self.metrics = parse_bool(&v)?;
+        }
+        if let Some(v) = var("METRICS_FILE") {
+            self.metrics_file = v;
+        }
+        if let Some(v) = var("ANOMALY_DETECTION") {
+            self.anomaly_detection = parse_bool(&v)?;
+        }
+        if let Some(v) = var("SNAPSHOT_BACKEND") {
+            self.snapshot_backend = Some(v);
+        }
+        if let Some(v) = var("SNAPSHOT_FROM") {
+            self.snapshot_from = Some(v);
+        }
+        if let Some(v) = var("SNAPSHOT_TO") {
+            self.snapshot_to = Some(v);
+        }
+        if let Some(v) = var("SNAPSHOT_ROOT") {
+            self.snapshot_root = Some(v);
+        }
+
+        for name in found.into_inner() {
+            let field = name
+                .trim_start_matches(ENV_PREFIX)
+                .to_lowercase();
+            self.sources
+                .insert(field, Source::Env(name));
+        }
+        Ok(())
+    }
+
+    /// Applies the command line flags and returns the remaining arguments.
+    pub fn apply_args<I>(&mut self, args: I) -> Result<Vec<String>, Error>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        const FLAGS: [(&str, &str); 5] = [
+            ("--events", "events"),
+            ("--wallet-pubkey", "wallet_pubkey"),
+            ("--report", "report_file"),
+            ("--since", "report_since"),
+            ("--until", "report_until"),
+        ];
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some((flag, field, inline)) = FLAGS
+                .iter()
+                .find_map(|&(flag, field)| match arg.strip_prefix(flag) {
+                    Some("") => Some((flag, field, None)),
+                    Some(value) => value
+                        .strip_prefix('=')
+                        .map(|value| (flag, field, Some(value.to_string()))),
+                    None => None,
+                })
+            else {
+                rest.push(arg);
+                continue;
+            };
+            let value = match inline {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .ok_or(Error::Config(format!("{} needs a value", flag)))?,
+            };
+            match field {
+                "events" => self.events = parse_events(&value)?,
+                "wallet_pubkey" => self.wallet_pubkey = Some(value),
+                "report_file" => self.report_file = Some(value),
+                "report_since" => self.report_since = Some(parse_unix_time(&value)?),
+                _ => self.report_until = Some(parse_unix_time(&value)?),
+            }
+            self.sources
+                .insert(field.to_string(), Source::Arg(flag.to_string()));
+        }
+        Ok(rest)
+    }
+}
+
+/// The pipeline is given inline, in the TOML array syntax:
+/// `[{ stage = "filter", exclude = ["./tmp/cache/"] }]`
+fn parse_pipeline(value: &str) -> Result<Vec<crate::pipeline::StageConfig>, Error> {
+    #[derive(Deserialize)]
+    struct Wrapper {
+        pipeline: Vec<crate::pipeline::StageConfig>,
+    }
+    toml::from_str::<Wrapper>(&format!("pipeline = {}", value))
+        .map(|w| w.pipeline)
+        .map_err(|e| Error::Config(format!("Invalid pipeline {}: {}", value, e)))
+}
+
+// dir=program pairs, e.g. /srv/staging=Stag1ng...,/srv/prod=Pr0d...
+fn parse_routes(value: &str) -> Result<BTreeMap<String, String>, Error> {
+    split_list(value)
+        .iter()
+        .map(|route| {
+            route
+                .split_once('=')
+                .map(|(dir, program)| (dir.trim().to_string(), program.trim().to_string()))
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "Invalid program route {}, expected dir=program",
+                        route
+                    ))
+                })
+        })
+        .collect()
+}
+
+// type=priority pairs, e.g. deleted=3,written=1
+fn parse_priorities(value: &str) -> Result<BTreeMap<String, u8>, Error> {
+    split_list(value)
+        .iter()
+        .map(|pair| {
+            pair.split_once('=')
+                .and_then(|(event_type, priority)| {
+                    let priority = priority.trim().parse().ok()?;
+                    Some((event_type.trim().to_string(), priority))
+                })
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "Invalid event priority {}, expected type=0..255",
+                        pair
+                    ))
+                })
+        })
+        .collect()
+}
+
+// type=price pairs, e.g. deleted=50000,attribute_changed=50000
+fn parse_fees(value: &str) -> Result<BTreeMap<String, u64>, Error> {
+    split_list(value)
+        .iter()
+        .map(|pair| {
+            pair.split_once('=')
+                .and_then(|(event_type, price)| {
+                    let price = price.trim().parse().ok()?;
+                    Some((event_type.trim().to_string(), price))
+                })
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "Invalid priority fee {}, expected type=micro-lamports",
+                        pair
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn parse_unix_time(value: &str) -> Result<i64, Error> {
+    value
+        .parse()
+        .map_err(|e| Error::Config(format!("Invalid unix time {}: {}", value, e)))
+}
+
+fn parse_events(value: &str) -> Result<Vec<EventType>, Error> {
+    let events = split_list(value)
+        .iter()
+        .map(|name| {
+            name.parse()
+                .map_err(|e| Error::Config(format!("{}", e)))
+        })
+        .collect::<Result<Vec<EventType>, Error>>()?;
+    if events.is_empty() {
+        return Err(Error::Config("No event types given".to_string()));
+    }
+    Ok(events)
+}
+
+fn parse_bool(value: &str) -> Result<bool, Error> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(Error::Config(format!("Invalid boolean value: {}", value))),
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_env_overrides_file() {
+        let mut config: Config = toml::from_str(
+            r#"
+            watch_dirs = ["/srv/a"]
+            rpc_url = "http://file:8899"
+            commitment = "finalized"
+            "#,
+        )
+        .unwrap();
+
+        let env = HashMap::from([
+            ("TRACKER_WATCH_DIRS", "/srv/b, /srv/c"),
+            ("TRACKER_RPC_URL", "http://env:8899"),
+        ]);
+        config
+            .apply_env(|name| {
+                env.get(name)
+                    .map(|v| v.to_string())
+            })
+            .unwrap();
+
+        assert_eq!(config.watch_dirs, vec!["/srv/b", "/srv/c"]);
+        assert_eq!(config.rpc_url, "http://env:8899");
+        // not overridden
+        assert_eq!(config.commitment, "finalized");
+        assert_eq!(config.socket_path, crate::ipc::DEFAULT_SOCKET_PATH);
+    }
+
+    #[test]
+    fn test_value_sources() {
+        let path = std::env::temp_dir().join("tracker-test-value-sources.toml");
+        std::fs::write(&path, "commitment = \"finalized\"").unwrap();
+        let mut config = Config::from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let env = HashMap::from([("PROGRAM_ID", "legacy")]);
+        config
+            .apply_env(|name| {
+                env.get(name)
+                    .map(|v| v.to_string())
+            })
+            .unwrap();
+        config
+            .apply_args(["--events=created".to_string()])
+            .unwrap();
+
+        assert_eq!(
+            config.source("commitment"),
+            Source::File(
+                path.to_str()
+                    .unwrap()
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            config.source("program_id"),
+            Source::Env("PROGRAM_ID".to_string())
+        );
+        assert_eq!(config.source("events"), Source::Arg("--events".to_string()));
+        assert_eq!(config.source("rpc_url"), Source::Default);
+    }
+
+    #[test]
+    fn test_namespaced_env_wins_over_legacy() {
+        let mut config = Config::default();
+        let env = HashMap::from([
+            ("PROGRAM_ID", "legacy"),
+            ("TRACKER_PROGRAM_ID", "namespaced"),
+        ]);
+        config
+            .apply_env(|name| {
+                env.get(name)
+                    .map(|v| v.to_string())
+            })
+            .unwrap();
+        assert_eq!(config.program_id, "namespaced");
+    }
+
+    #[test]
+    fn test_pipeline_from_env() {
+        let pipeline = parse_pipeline(r#"[{ stage = "filter", include = ["/etc/"] }]"#).unwrap();
+        assert_eq!(
+            pipeline,
+            vec![crate::pipeline::StageConfig::Filter {
+                include: vec!["/etc/".to_string()],
+                exclude: vec![],
+                include_types: vec![],
+                exclude_types: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_events_from_file_and_args() {
+        let mut config: Config = toml::from_str(r#"events = ["created", "MovedTo"]"#).unwrap();
+        assert_eq!(config.events, vec![EventType::Created, EventType::MovedTo]);
+
+        let rest = config
+            .apply_args(["watch", "--events", "written,deleted"].map(String::from))
+            .unwrap();
+        assert_eq!(rest, vec!["watch"]);
+        assert_eq!(config.events, vec![EventType::Written, EventType::Deleted]);
+
+        assert!(config
+            .apply_args(["--events=written,modified".to_string()])
+            .is_err());
+
+        let rest = config
+            .apply_args(
+                ["verify", "--wallet-pubkey", "W4ll3t", "--report=r.json"].map(String::from),
+            )
+            .unwrap();
+        assert_eq!(rest, vec!["verify"]);
+        assert_eq!(
+            config
+                .wallet_pubkey
+                .as_deref(),
+            Some("W4ll3t")
+        );
+        assert_eq!(config.report_file.as_deref(), Some("r.json"));
+        assert!(config
+            .apply_args(["--report".to_string()])
+            .is_err());
+        config
+            .apply_args(["--since=1700000000".to_string()])
+            .unwrap();
+        assert_eq!(config.report_since, Some(1_700_000_000));
+        assert!(config
+            .apply_args(["--until", "yesterday"].map(String::from))
+            .is_err());
+        assert!(toml::from_str::<Config>(r#"events = ["modified"]"#).is_err());
+    }
+
+    #[test]
+    fn test_unknown_field_rejected() {
+        assert!(toml::from_str::<Config>("rpc = \"typo\"").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Hot reload of the config file, with `reload_config`: the file is checked for changes
+//! every few seconds, and once it's changed and still valid, the watched paths, the
+//! filters and the pipeline stages are applied to the running tracker. The events on
+//! the way are kept, the sinks and the Solana client aren't touched.
+//!
+//! The other settings need a restart, their changes are reported and left out.
+//! An invalid config file is reported and the running configuration kept.
+
+use super::{Config, Mode, TrackerConfig, WatcherConfig};
+use crate::error::Error;
+use crate::pipeline::StageConfig;
+use crate::supervisor::Shutdown;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+// How often the config file is checked for changes
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The latest reloaded watcher and pipeline configurations, each one applied by its
+/// component, also when it's restarted.
+#[derive(Clone)]
+pub struct Reloads {
+    pub watcher: watch::Receiver<WatcherConfig>,
+    pub pipeline: watch::Receiver<Vec<StageConfig>>,
+}
+
+impl Reloads {
+    /// The `config` with the reloaded watcher and pipeline configurations.
+    pub fn current(&self, config: &TrackerConfig) -> TrackerConfig {
+        let mut config = config.clone();
+        config.watcher = self.watcher.borrow().clone();
+        config.pipeline = self.pipeline.borrow().clone();
+        config
+    }
+}
+
+/// Checks the config file for changes and publishes them as [Reloads].
+pub struct Reloader {
+    path: String,
+    mode: Mode,
+    // As last loaded, with the flags
+    loaded: Config,
+    modified: Option<SystemTime>,
+    watcher: watch::Sender<WatcherConfig>,
+    pipeline: watch::Sender<Vec<StageConfig>>,
+}
+
+impl Reloader {
+    /// Reloads the config file at `path`, the `config` is the running one.
+    pub fn new(path: &str, config: &TrackerConfig) -> Result<(Reloader, Reloads), Error> {
+        let (watcher, watcher_rx) = watch::channel(config.watcher.clone());
+        let (pipeline, pipeline_rx) = watch::channel(config.pipeline.clone());
+        let reloader = Reloader {
+            path: path.to_string(),
+            mode: config.mode,
+            loaded: load()?,
+            modified: modified(path),
+            watcher,
+            pipeline,
+        };
+        let reloads = Reloads {
+            watcher: watcher_rx,
+            pipeline: pipeline_rx,
+        };
+        Ok((reloader, reloads))
+    }
+
+    /// Checks the file until the `shutdown`.
+    pub async fn run(mut self, shutdown: Shutdown) {
+        println!("Reloading {} once it changes", self.path);
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(CHECK_INTERVAL) => (),
+                _ = shutdown.requested() => return,
+            }
+            let modified = modified(&self.path);
+            if modified != self.modified {
+                self.modified = modified;
+                self.reload();
+            }
+        }
+    }
+
+    fn reload(&mut self) {
+        let (loaded, config) = match load().and_then(|loaded| {
+            let config = loaded
+                .clone()
+                .validate(self.mode)?;
+            Ok((loaded, config))
+        }) {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                println!(
+                    "Failed to reload {}, keeping the running configuration: {}",
+                    self.path, e
+                );
+                return;
+            }
+        };
+        if restart_needed(&self.loaded, &loaded) {
+            println!(
+                "{} changed beyond the watched paths, the filters and the pipeline, \
+                 restart the tracker to apply the rest",
+                self.path
+            );
+        }
+        self.loaded = loaded;
+
+        let watcher = config.watcher;
+        self.watcher
+            .send_if_modified(|running| {
+                let reloaded = WatcherConfig {
+                    dirs: watcher.dirs,
+                    files: watcher.files,
+                    filter: watcher.filter,
+                    ignore_file: watcher.ignore_file,
+                    ..running.clone()
+                };
+                let modified = *running != reloaded;
+                *running = reloaded;
+                modified
+            });
+        self.pipeline
+            .send_if_modified(|running| {
+                let modified = *running != config.pipeline;
+                *running = config.pipeline;
+                modified
+            });
+        println!("Reloaded {}", self.path);
+    }
+}
+
+// The config file, the environment and the flags
+fn load() -> Result<Config, Error> {
+    let mut config = Config::load()?;
+    config.apply_args(std::env::args().skip(1))?;
+    Ok(config)
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Whether the configurations differ in more than what's applied while running
+fn restart_needed(running: &Config, reloaded: &Config) -> bool {
+    let rest = |config: &Config| Config {
+        watch_dirs: Vec::new(),
+        watch_files: Vec::new(),
+        include_globs: Vec::new(),
+        exclude_globs: Vec::new(),
+        exclude_presets: Vec::new(),
+        min_file_size: None,
+        max_file_size: None,
+        ignore_file: None,
+        pipeline: Vec::new(),
+        sources: BTreeMap::new(),
+        ..config.clone()
+    };
+    rest(running) != rest(reloaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_needed() {
+        let running = Config::default();
+        let reloaded = Config {
+            watch_dirs: vec!["/srv/b".to_string()],
+            exclude_globs: vec!["*.tmp".to_string()],
+            pipeline: vec![StageConfig::Debounce {
+                window_ms: 200,
+                max_wait_ms: None,
+            }],
+            ..Config::default()
+        };
+        assert!(!restart_needed(&running, &reloaded));
+        let reloaded = Config {
+            recursive: true,
+            ..reloaded
+        };
+        assert!(restart_needed(&running, &reloaded));
+    }
+}
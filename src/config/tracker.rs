@@ -0,0 +1,1606 @@
+//! Validated configuration the modules are built from.
+//!
+//! [Config] holds the values as given in the file and the environment,
+//! [Config::validate] checks them for the selected [Mode] and reports
+//! all the problems at once, not only the first one.
+
+use super::{Config, Source, ENV_PREFIX};
+use crate::container::Containers;
+use crate::dir_watcher::{self, GlobFilter};
+use crate::error::Error;
+use crate::event::EventType;
+use crate::git::Repositories;
+#[cfg(feature = "solana")]
+use crate::integrity::ContentHashes;
+use crate::pipeline::StageConfig;
+use crate::receipt::Receipts;
+use crate::remote::TlsFiles;
+use crate::sink::backpressure::Backpressure;
+use crate::sink::priority::Priorities;
+use crate::snapshot;
+#[cfg(feature = "solana")]
+use crate::solana_client::fees::PriorityFees;
+use crate::transport::Transport;
+#[cfg(feature = "solana")]
+use file_event_tracker::processor::SCHEMA_VERSION;
+#[cfg(feature = "solana")]
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// What the process runs, given as the first argument.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Watcher and submitter in one process.
+    All,
+    /// Only watch, stream events to the submitter's socket (run as root).
+    Watch,
+    /// Only submit, receive events from the socket (holds the keypair).
+    Submit,
+    /// Only watch, forward events over TLS to a remote collector.
+    Agent,
+    /// Only submit, receive events over TLS from remote agents.
+    Collector,
+    /// Diff two filesystem snapshots instead of watching, submit the changes and exit.
+    Snapshot,
+    /// Record the existing files with their hashes, submit them and exit.
+    Backfill,
+    /// Print the vault's usage and exit.
+    Status,
+    /// Print the journaled events and exit.
+    Journal,
+    /// Replay the recorded inotify events through the pipeline, print the events and exit.
+    Replay,
+    /// Prune the journal's files confirmed on chain and exit.
+    Gc,
+    /// Verify the watched files against the vault without a keypair, report and exit.
+    Verify,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::All => "",
+            Mode::Watch => "watch",
+            Mode::Submit => "submit",
+            Mode::Agent => "agent",
+            Mode::Collector => "collector",
+            Mode::Snapshot => "snapshot",
+            Mode::Backfill => "backfill",
+            Mode::Status => "status",
+            Mode::Journal => "journal",
+            Mode::Replay => "replay",
+            Mode::Gc => "gc",
+            Mode::Verify => "verify",
+        }
+    }
+
+    /// Whether the sinks, the Solana submitter among them, run in this mode.
+    pub fn submits(&self) -> bool {
+        matches!(
+            self,
+            Mode::All | Mode::Submit | Mode::Collector | Mode::Snapshot | Mode::Backfill
+        )
+    }
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Ok(Mode::All),
+            "watch" => Ok(Mode::Watch),
+            "submit" => Ok(Mode::Submit),
+            "agent" => Ok(Mode::Agent),
+            "collector" => Ok(Mode::Collector),
+            "snapshot" => Ok(Mode::Snapshot),
+            "backfill" => Ok(Mode::Backfill),
+            "status" => Ok(Mode::Status),
+            "journal" => Ok(Mode::Journal),
+            "replay" => Ok(Mode::Replay),
+            "gc" => Ok(Mode::Gc),
+            "verify" => Ok(Mode::Verify),
+            _ => Err(Error::Config(format!(
+                "Unknown mode: {}, expected `watch`, `submit`, `agent`, `collector`, `snapshot`, `backfill`, `status`, `journal`, `replay`, `gc` or `verify`",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatcherConfig {
+    pub dirs: Vec<String>,
+    /// The files watched on their own, inotify only.
+    pub files: Vec<String>,
+    /// Set to watch the subdirectories too.
+    pub recursive: bool,
+    /// Levels of subdirectories watched if recursive, all of them if not set.
+    pub max_depth: Option<usize>,
+    /// The inotify source polls this often the directories it's out of watches for,
+    /// fails without it.
+    pub poll_fallback: Option<Duration>,
+    pub filter: GlobFilter,
+    /// Name of the ignore file read from each watched directory.
+    pub ignore_file: Option<String>,
+    pub events: Vec<EventType>,
+    pub source: EventSource,
+    /// Set in the overlay-aware mode, the watched directories inside containers.
+    pub containers: Option<Containers>,
+    /// Where the raw inotify events are recorded, or replayed from in the replay mode.
+    pub record: Option<String>,
+    /// Set to report the existing files as created once the watches are set up.
+    pub baseline_scan: bool,
+    /// Where the watched files' state is saved, to report the changes made while stopped.
+    pub state_file: Option<String>,
+    /// How often the state is saved while running.
+    pub state_interval: Duration,
+    /// How often the inotify watcher's statistics are printed, if at all.
+    pub stats_report: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventSource {
+    /// Reading the events into a buffer of the size in bytes.
+    Inotify(usize),
+    /// The native API of the platform through the notify crate, FSEvents on macOS.
+    Notify,
+    /// Listing the watched directories every interval, where inotify doesn't work.
+    Poll(Duration),
+    /// Linux audit events, read from the audit log or the audispd socket.
+    Audit(String),
+}
+
+#[cfg(feature = "solana")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SolanaConfig {
+    pub rpc_url: String,
+    pub commitment: CommitmentConfig,
+    /// Set to log the RPC requests and responses, redacted.
+    pub rpc_debug: bool,
+    pub program: Pubkey,
+    pub wallet_keypair: String,
+    /// Keypair files of the wallets paying the events' transactions in turn, if any.
+    pub wallet_pool: Vec<String>,
+    /// Lamports, a pool wallet with less leaves the rotation until it's funded.
+    pub wallet_pool_min_balance: u64,
+    /// Schema of the sent events, registered with the watcher in the new vaults.
+    pub schema_version: u16,
+    /// The watcher's wallet read by the verify mode, which has no keypair.
+    pub wallet_pubkey: Option<Pubkey>,
+    /// Stored in a new vault, the watched directories.
+    pub path_prefixes: Vec<String>,
+    /// Set to upload the changed files to Arweave.
+    pub arweave_bundler: Option<String>,
+    pub arweave_max_size: u64,
+    /// Seconds the local clock may be off the Solana clock without an alert.
+    pub clock_skew_threshold: u64,
+    /// Lamports which may be spent on the events' transactions in a period.
+    pub budget_lamports: Option<u64>,
+    pub budget_period: Duration,
+    /// Percent of the failed RPC calls opening the circuit breaker.
+    pub breaker_failure_rate: u8,
+    /// How long the open breaker holds the events back before probing the RPC node.
+    pub breaker_cool_down: Duration,
+    /// Longest time spent retrying a transaction failing for a transient reason.
+    pub retry_budget: Duration,
+    /// Events anchored at once, packed in as few transactions as fit.
+    pub batch_size: usize,
+    /// How long a partial batch waits for more events.
+    pub batch_max_latency: Duration,
+    /// Address lookup tables of the events' transactions.
+    pub lookup_tables: Vec<Pubkey>,
+    /// Compute-unit price of the transactions by their events' type.
+    pub priority_fees: PriorityFees,
+    /// Where the events over the budget are journaled.
+    pub journal_path: String,
+    /// Directories whose events go to another program, one route per program.
+    pub routes: Vec<ProgramRoute>,
+    /// Where the confirmed transactions are POSTed, with their explorer links.
+    pub webhook_url: Option<String>,
+    /// How often the captured events not confirmed on chain are reported, if set.
+    pub completeness_report: Option<Duration>,
+    /// Set to record the reported gaps on chain, in a memo.
+    pub completeness_on_chain: bool,
+}
+
+#[cfg(feature = "solana")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgramRoute {
+    pub program: Pubkey,
+    pub dirs: Vec<String>,
+}
+
+#[cfg(feature = "solana")]
+impl SolanaConfig {
+    /// The route of the file, the one of the innermost routed directory it's in.
+    /// `None` if it goes to the default program.
+    pub fn route(&self, path: &str) -> Option<&ProgramRoute> {
+        self.routes
+            .iter()
+            .flat_map(|route| {
+                route
+                    .dirs
+                    .iter()
+                    .map(move |dir| (dir, route))
+            })
+            .filter(|(dir, _)| snapshot::is_under(path, dir))
+            .max_by_key(|(dir, _)| {
+                dir.trim_end_matches('/')
+                    .len()
+            })
+            .map(|(_, route)| route)
+    }
+
+    /// Configuration of the route's own client, its vault stores the routed directories.
+    pub fn for_route(&self, route: &ProgramRoute) -> SolanaConfig {
+        SolanaConfig {
+            program: route.program,
+            path_prefixes: route
+                .dirs
+                .iter()
+                .map(|dir| format!("{}/", dir.trim_end_matches('/')))
+                .collect(),
+            routes: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(feature = "ethereum")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EthereumConfig {
+    pub rpc_url: String,
+    /// Address of the contract receiving the event hashes.
+    pub contract: String,
+    /// Account the node signs the transactions with.
+    pub from: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotConfig {
+    pub backend: snapshot::Backend,
+    pub from: String,
+    pub to: String,
+    /// Set for the btrfs backend.
+    pub root: Option<String>,
+}
+
+/// Set if the events are journaled, see [crate::journal].
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalConfig {
+    pub dir: String,
+    pub max_file_size: u64,
+    pub max_files: usize,
+    /// The rotated files older than this are pruned by the `gc` mode.
+    pub retention_age: Option<Duration>,
+    /// The oldest files are pruned by the `gc` mode while all of them are larger.
+    pub retention_size: Option<u64>,
+}
+
+/// Set if the instance runs as one of a hot-standby pair, see [crate::leader].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeaderConfig {
+    pub lease_file: String,
+    pub lease: Duration,
+}
+
+/// A single invalid or missing value found by [Config::validate].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    pub message: String,
+    /// The option the problem is about, and where its value comes from
+    pub option: Option<(String, Source)>,
+    /// How to fix it
+    pub help: Option<String>,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some((option, source)) = self.option.as_ref() {
+            write!(f, " ({} from {})", option, source)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackerConfig {
+    pub mode: Mode,
+    pub watcher: WatcherConfig,
+    pub pipeline: Vec<StageConfig>,
+    /// Order of the events waiting for a sink.
+    pub priorities: Priorities,
+    /// Most events waiting for a sink, unlimited if not set.
+    pub channel_capacity: Option<usize>,
+    /// What happens to the events once a sink's queue is full.
+    pub backpressure: Backpressure,
+    /// Between the watcher and the sinks in the all-in-one mode.
+    pub transport: Transport,
+    /// Set in the modes which submit the events.
+    #[cfg(feature = "solana")]
+    pub solana: Option<SolanaConfig>,
+    /// Set in the modes which submit the events, if an Ethereum node is configured.
+    #[cfg(feature = "ethereum")]
+    pub ethereum: Option<EthereumConfig>,
+    pub socket_path: String,
+    /// How long the watcher and the agent keep the queued events, kept forever if not set.
+    pub max_event_age: Option<Duration>,
+    pub dead_letter_path: String,
+    pub journal: Option<JournalConfig>,
+    /// Set if the sinks run only in the elected leader.
+    pub leader: Option<LeaderConfig>,
+    /// Always set in the agent mode.
+    pub collector_addr: Option<String>,
+    pub collector_listen: String,
+    /// Always set in the agent and collector modes.
+    pub tls: Option<TlsFiles>,
+    pub webhook_url: Option<String>,
+    pub metrics: bool,
+    pub metrics_file: String,
+    pub anomaly_detection: bool,
+    /// Set in the git-aware mode, the watched repositories.
+    pub git: Option<Repositories>,
+    /// Set if the receipts are written to the files, shared by the submitter
+    /// writing them and the pipeline dropping the changes they make.
+    pub receipts: Option<Receipts>,
+    /// The extended attributes read with the attribute changes, none if empty.
+    pub capture_xattrs: Vec<String>,
+    /// Set to sniff the MIME type of the created and written files.
+    pub detect_file_types: bool,
+    /// Set to diff the written text files of at most this many bytes.
+    pub diff_max_size: Option<u64>,
+    /// Set if the opened files are verified, shared by the submitter recording and
+    /// verifying the hashes and the pipeline dropping the opens made by hashing.
+    #[cfg(feature = "solana")]
+    pub integrity: Option<ContentHashes>,
+    /// Threads hashing the written files in the pipeline, 0 leaves it to the submitter.
+    #[cfg(feature = "solana")]
+    pub hash_workers: usize,
+    /// Set in the snapshot mode.
+    pub snapshot: Option<SnapshotConfig>,
+    /// The config file reloaded while running, if set.
+    pub reload: Option<String>,
+    /// Where the verify mode writes its report, printed if not set.
+    pub report_file: Option<String>,
+    /// Unix time range of the events proven in the verify mode's report, if set.
+    pub report_range: Option<(i64, Option<i64>)>,
+    sources: BTreeMap<String, Source>,
+}
+
+impl TrackerConfig {
+    /// Where the value of the `field` comes from.
+    pub fn source(&self, field: &str) -> Source {
+        self.sources
+            .get(field)
+            .cloned()
+            .unwrap_or(Source::Default)
+    }
+}
+
+impl Config {
+    /// Checks the values needed by the `mode`, fails with all the problems found.
+    pub fn validate(self, mode: Mode) -> Result<TrackerConfig, Error> {
+        let mut problems = Vec::new();
+
+        if self.watch_dirs.is_empty() && self.watch_files.is_empty() {
+            problems.push(self.problem(
+                "watch_dirs",
+                "No directories to watch".to_string(),
+                &format!("Set {}WATCH_DIRS, e.g. to /srv/data", ENV_PREFIX),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for dir in self.watch_dirs.iter() {
+            if !seen.insert(dir.trim_end_matches('/')) {
+                problems.push(self.problem(
+                    "watch_dirs",
+                    format!("Directory {} is listed more than once", dir),
+                    "Remove the duplicate",
+                ));
+            }
+        }
+        for file in self.watch_files.iter() {
+            if !seen.insert(file.as_str()) {
+                problems.push(self.problem(
+                    "watch_files",
+                    format!("File {} is listed more than once", file),
+                    "Remove the duplicate",
+                ));
+            }
+        }
+        if !self.watch_files.is_empty() && self.event_source != "inotify" {
+            problems.push(self.problem(
+                "watch_files",
+                format!(
+                    "Watching single files is not supported by the {} event source",
+                    self.event_source
+                ),
+                "Use the inotify event source, or watch the files' directories instead",
+            ));
+        }
+        if self.events.is_empty() {
+            problems.push(self.problem(
+                "events",
+                "No event types".to_string(),
+                "Set the reported event types, e.g. --events created,written,deleted",
+            ));
+        }
+
+        for (field, patterns) in [
+            ("include_globs", &self.include_globs),
+            ("exclude_globs", &self.exclude_globs),
+        ] {
+            if let Err(e) = GlobFilter::new(patterns, &[]) {
+                problems.push(self.problem(
+                    field,
+                    e.to_string(),
+                    "Use *, **, ? and [...] in the patterns, e.g. *.tmp or /srv/**/*.conf",
+                ));
+            }
+        }
+        let mut exclude = self.exclude_globs.clone();
+        for name in self.exclude_presets.iter() {
+            match dir_watcher::preset(name) {
+                Some(patterns) => exclude.extend(
+                    patterns
+                        .iter()
+                        .map(|p| p.to_string()),
+                ),
+                None => problems.push(self.problem(
+                    "exclude_presets",
+                    format!("Unknown exclude preset {}", name),
+                    &format!("Use {}", dir_watcher::PRESETS.join(" or ")),
+                )),
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_file_size, self.max_file_size) {
+            if min > max {
+                problems.push(self.problem(
+                    "min_file_size",
+                    format!(
+                        "Minimum file size of {} bytes is above the maximum of {}",
+                        min, max
+                    ),
+                    "Set min_file_size at most max_file_size",
+                ));
+            }
+        }
+        let filter = GlobFilter::new(&self.include_globs, &exclude)
+            .unwrap_or_default()
+            .with_size_range(self.min_file_size, self.max_file_size);
+        if self.baseline_scan
+            && !self
+                .events
+                .contains(&EventType::Created)
+        {
+            problems.push(self.problem(
+                "baseline_scan",
+                "The existing files are reported as created".to_string(),
+                &format!("Add created to {}EVENTS", ENV_PREFIX),
+            ));
+        }
+        if let Some(path) = self.state_file.as_ref() {
+            if self.state_interval_secs == 0 {
+                problems.push(
+                    self.problem(
+                        "state_interval_secs",
+                        "State saving interval of 0 seconds would scan the files continuously"
+                            .to_string(),
+                        "Set at least 1 second",
+                    ),
+                );
+            }
+            // Each save would be reported as a change
+            if self
+                .watch_dirs
+                .iter()
+                .any(|dir| Path::new(path).starts_with(dir))
+            {
+                problems.push(self.problem(
+                    "state_file",
+                    format!("State file {} is in a watched directory", path),
+                    "Keep the state file outside the watched directories",
+                ));
+            }
+        }
+        let reload = match self.reload_config {
+            true => Config::file().or_else(|| {
+                problems.push(self.problem(
+                    "reload_config",
+                    "No config file to reload".to_string(),
+                    &format!(
+                        "Point {}CONFIG to the config file, or unset reload_config",
+                        ENV_PREFIX
+                    ),
+                ));
+                None
+            }),
+            false => None,
+        };
+        if let Some(name) = self.ignore_file.as_ref() {
+            if name.is_empty() || name.contains('/') {
+                problems.push(self.problem(
+                    "ignore_file",
+                    format!("Invalid ignore file name {:?}", name),
+                    "Set the name of the file in the watched directories, e.g. .trackerignore",
+                ));
+            }
+        }
+
+        let source = match self.event_source.as_str() {
+            "inotify" if self.inotify_buffer_size < dir_watcher::MIN_BUFFER_SIZE => {
+                problems.push(self.problem(
+                    "inotify_buffer_size",
+                    format!(
+                        "Buffer of {} bytes can't hold an event with a long file name",
+                        self.inotify_buffer_size
+                    ),
+                    &format!("Set at least {} bytes", dir_watcher::MIN_BUFFER_SIZE),
+                ));
+                None
+            }
+            "inotify" => Some(EventSource::Inotify(self.inotify_buffer_size)),
+            "notify" => Some(EventSource::Notify),
+            "poll" if self.poll_interval_secs == 0 => {
+                problems.push(
+                    self.problem(
+                        "poll_interval_secs",
+                        "Polling interval of 0 seconds would list the directories continuously"
+                            .to_string(),
+                        "Set at least 1 second",
+                    ),
+                );
+                None
+            }
+            "poll" => Some(EventSource::Poll(Duration::from_secs(
+                self.poll_interval_secs,
+            ))),
+            "audit" => Some(EventSource::Audit(self.audit_log.clone())),
+            other => {
+                problems.push(self.problem(
+                    "event_source",
+                    format!("Unknown event source {}", other),
+                    "Use inotify, notify, poll or audit",
+                ));
+                None
+            }
+        };
+
+        // inotify, the audit log and the extended attributes are Linux APIs
+        #[cfg(not(target_os = "linux"))]
+        {
+            if matches!(self.event_source.as_str(), "inotify" | "audit") {
+                problems.push(self.problem(
+                    "event_source",
+                    format!(
+                        "The {} event source needs Linux, not supported on this platform",
+                        self.event_source
+                    ),
+                    "Use the notify or the poll event source",
+                ));
+            }
+            if self.xattr_receipts || !self.capture_xattrs.is_empty() {
+                problems.push(self.problem(
+                    match self.xattr_receipts {
+                        true => "xattr_receipts",
+                        false => "capture_xattrs",
+                    },
+                    "Extended attributes are only read and written on Linux".to_string(),
+                    "Unset xattr_receipts and capture_xattrs",
+                ));
+            }
+            if self.record_file.is_some() || mode == Mode::Replay {
+                problems.push(self.problem(
+                    "record_file",
+                    "Recording and replaying the inotify events needs Linux".to_string(),
+                    "Unset record_file",
+                ));
+            }
+        }
+        // The separate processes talk over a Unix domain socket
+        #[cfg(not(unix))]
+        if matches!(mode, Mode::Watch | Mode::Submit) {
+            problems.push(Problem {
+                message: format!(
+                    "The {} mode needs Unix domain sockets, not supported on this platform",
+                    mode.as_str()
+                ),
+                option: None,
+                help: Some("Run the watcher and the submitter in one process, or as an agent and a collector".to_string()),
+            });
+        }
+
+        if self.watch_limit_fallback && self.poll_interval_secs == 0 {
+            problems.push(self.problem(
+                "poll_interval_secs",
+                "Polling interval of 0 seconds would list the unwatched directories continuously"
+                    .to_string(),
+                "Set at least 1 second",
+            ));
+        }
+        if self.watcher_stats_secs == Some(0) {
+            problems.push(self.problem(
+                "watcher_stats_secs",
+                "Watcher statistics every 0 seconds".to_string(),
+                "Set how often they are printed, e.g. 60 seconds",
+            ));
+        }
+        if self
+            .watcher_stats_secs
+            .is_some()
+            && self.event_source != "inotify"
+        {
+            problems.push(self.problem(
+                "watcher_stats_secs",
+                format!(
+                    "Watcher statistics are not kept by the {} event source",
+                    self.event_source
+                ),
+                "Use the inotify event source, or unset watcher_stats_secs",
+            ));
+        }
+        if self.max_depth.is_some() && !self.recursive {
+            problems.push(self.problem(
+                "max_depth",
+                "Maximum depth is set, but the subdirectories aren't watched".to_string(),
+                "Set recursive as well, or unset max_depth",
+            ));
+        }
+        if self.max_depth.is_some() && matches!(self.event_source.as_str(), "notify" | "audit") {
+            problems.push(self.problem(
+                "max_depth",
+                format!(
+                    "Maximum depth is not supported by the {} event source",
+                    self.event_source
+                ),
+                "Use the inotify or the poll event source, or unset max_depth",
+            ));
+        }
+
+        let transport = Transport::parse(&self.transport, &self.socket_path).map_err(|e| {
+            problems.push(self.problem(
+                "transport",
+                e.to_string(),
+                "Use tokio, std, crossbeam or unix",
+            ))
+        });
+
+        let mut priorities = Priorities::default();
+        for (name, priority) in self.event_priorities.iter() {
+            match name.parse::<EventType>() {
+                Ok(event_type) => priorities = priorities.with(event_type, *priority),
+                Err(e) => problems.push(self.problem(
+                    "event_priorities",
+                    e.to_string(),
+                    "Set the priorities of the event types, e.g. { deleted = 3, written = 1 }",
+                )),
+            }
+        }
+        for stage in self.pipeline.iter() {
+            if let StageConfig::Filter {
+                include_types,
+                exclude_types,
+                ..
+            } = stage
+            {
+                if !self.detect_file_types
+                    && !(include_types.is_empty() && exclude_types.is_empty())
+                {
+                    problems.push(self.problem(
+                        "pipeline",
+                        "The filter stage's file types are not detected".to_string(),
+                        "Set detect_file_types as well",
+                    ));
+                }
+            }
+            if let StageConfig::RateLimit { per_second, .. } = stage {
+                if per_second.is_nan() || *per_second <= 0.0 {
+                    problems.push(self.problem(
+                        "pipeline",
+                        format!(
+                            "Rate limit of {} events a second would hold all the events",
+                            per_second
+                        ),
+                        "Set a positive per_second for the rate_limit stage",
+                    ));
+                }
+            }
+        }
+        if self.channel_capacity == Some(0) {
+            problems.push(self.problem(
+                "channel_capacity",
+                "Channel capacity of 0 would drop all the events".to_string(),
+                "Set at least 1, or unset it to queue the events without a limit",
+            ));
+        }
+        let backpressure = match Backpressure::parse(&self.backpressure, &self.spill_dir) {
+            Ok(backpressure) => backpressure,
+            Err(e) => {
+                problems.push(self.problem(
+                    "backpressure",
+                    e.to_string(),
+                    "Set one of block, drop_oldest, drop_newest or spill_to_disk",
+                ));
+                Backpressure::default()
+            }
+        };
+        if backpressure != Backpressure::DropNewest
+            && self
+                .channel_capacity
+                .is_none()
+        {
+            problems.push(self.problem(
+                "backpressure",
+                format!(
+                    "Backpressure policy {} applies only to a full queue, but the queues are unlimited",
+                    self.backpressure
+                ),
+                "Set channel_capacity as well",
+            ));
+        }
+
+        // The collector's watched directories are on the agents' hosts
+        let git = match self.git_aware {
+            true => Some(Repositories::discover(&self.watch_dirs)),
+            false => None,
+        };
+        if mode != Mode::Collector
+            && git
+                .as_ref()
+                .is_some_and(|g| g.is_empty())
+        {
+            problems.push(self.problem(
+                "git_aware",
+                "None of the watched directories is a git repository".to_string(),
+                "Watch the root of a repository, the directory containing .git",
+            ));
+        }
+
+        let containers = match self.overlay_aware {
+            true => Some(Containers::discover(&self.watch_dirs)),
+            false => None,
+        };
+        if mode != Mode::Collector
+            && containers
+                .as_ref()
+                .is_some_and(|c| c.is_empty())
+        {
+            problems.push(self.problem(
+                "overlay_aware",
+                "None of the watched directories is in an overlayfs mount".to_string(),
+                "Watch a directory in a container's merged root filesystem, e.g. /var/lib/docker/overlay2/<id>/merged/app",
+            ));
+        }
+
+        let all_tls_files = format!("{0}TLS_CA_CERT, {0}TLS_CERT and {0}TLS_KEY", ENV_PREFIX);
+        let tls = match (&self.tls_ca_cert, &self.tls_cert, &self.tls_key) {
+            (Some(ca_cert), Some(cert), Some(key)) => Some(TlsFiles {
+                ca_cert: ca_cert.clone(),
+                cert: cert.clone(),
+                key: key.clone(),
+            }),
+            (None, None, None) => {
+                if matches!(mode, Mode::Agent | Mode::Collector) {
+                    problems.push(Problem {
+                        message: format!("The {} mode needs TLS", mode.as_str()),
+                        option: None,
+                        help: Some(format!("Set {}", all_tls_files)),
+                    });
+                }
+                None
+            }
+            _ => {
+                problems.push(Problem {
+                    message: "Only some of the TLS files are set".to_string(),
+                    option: None,
+                    help: Some(format!("Set all of {}, or none", all_tls_files)),
+                });
+                None
+            }
+        };
+
+        if self.max_event_age_hours == Some(0) {
+            problems.push(self.problem(
+                "max_event_age_hours",
+                "Maximum event age of 0 hours would drop all the queued events".to_string(),
+                "Set at least 1 hour, or unset it to keep the events until they're forwarded",
+            ));
+        }
+
+        match (self.report_since, self.report_until) {
+            (None, Some(_)) => problems.push(self.problem(
+                "report_until",
+                "The end of the report's time range is set without its start".to_string(),
+                &format!("Set {}REPORT_SINCE or --since too", ENV_PREFIX),
+            )),
+            (Some(since), Some(until)) if until < since => problems.push(self.problem(
+                "report_until",
+                format!(
+                    "The report's time range ends at {}, before its start {}",
+                    until, since
+                ),
+                "Set the end after the start, or unset it to prove the events until now",
+            )),
+            _ => {}
+        }
+
+        if mode == Mode::Replay && self.record_file.is_none() {
+            problems.push(self.problem(
+                "record_file",
+                "No capture of inotify events to replay".to_string(),
+                &format!(
+                    "Set {}RECORD_FILE to a file recorded by the watcher",
+                    ENV_PREFIX
+                ),
+            ));
+        }
+        if matches!(mode, Mode::Journal | Mode::Gc) && self.journal_dir.is_none() {
+            problems.push(self.problem(
+                "journal_dir",
+                "Journal directory not set".to_string(),
+                &format!(
+                    "Set {}JOURNAL_DIR to the directory of the journal",
+                    ENV_PREFIX
+                ),
+            ));
+        }
+        if mode == Mode::Gc
+            && self
+                .journal_retention_days
+                .is_none()
+            && self
+                .journal_retention_size
+                .is_none()
+        {
+            problems.push(self.problem(
+                "journal_retention_days",
+                "No retention policy, nothing would be pruned".to_string(),
+                &format!(
+                    "Set {0}JOURNAL_RETENTION_DAYS or {0}JOURNAL_RETENTION_SIZE",
+                    ENV_PREFIX
+                ),
+            ));
+        }
+        if self.journal_dir.is_some() && self.journal_max_files == 0 {
+            problems.push(self.problem(
+                "journal_max_files",
+                "The journal needs at least 1 file".to_string(),
+                "Set at least 1, or unset journal_dir to disable the journal",
+            ));
+        }
+
+        if self
+            .leader_lease_file
+            .is_some()
+            && self.leader_lease_secs == 0
+        {
+            problems.push(self.problem(
+                "leader_lease_secs",
+                "Leader's lease of 0 seconds".to_string(),
+                "Set how long the standby waits for the leader, e.g. 5 seconds",
+            ));
+        }
+
+        if mode == Mode::Agent && self.collector_addr.is_none() {
+            problems.push(self.problem(
+                "collector_addr",
+                "Collector address not set".to_string(),
+                &format!("Set {}COLLECTOR_ADDR to host:port", ENV_PREFIX),
+            ));
+        }
+        if let Some(url) = self.webhook_url.as_ref() {
+            if !is_http_url(url) {
+                problems.push(self.problem(
+                    "webhook_url",
+                    format!("Invalid webhook URL {}", url),
+                    "Use an http:// or https:// URL",
+                ));
+            }
+        }
+
+        let snapshot = match mode {
+            Mode::Snapshot => self.snapshot_config(&mut problems),
+            _ => None,
+        };
+
+        #[cfg(feature = "solana")]
+        let integrity = match self.verify_on_open {
+            true => self.content_hashes(mode, &mut problems),
+            false => None,
+        };
+        // The hashes are recorded only in the vault
+        #[cfg(not(feature = "solana"))]
+        if self.verify_on_open {
+            problems.push(self.problem(
+                "verify_on_open",
+                "Verifying the opened files needs Solana, not supported by this build".to_string(),
+                "Build with the default solana feature",
+            ));
+        }
+
+        #[cfg(feature = "solana")]
+        let solana = match mode.submits() || matches!(mode, Mode::Status | Mode::Gc | Mode::Verify)
+        {
+            true => self.solana_config(&mut problems),
+            false => None,
+        };
+        #[cfg(feature = "solana")]
+        if mode == Mode::Verify && self.wallet_pubkey.is_none() {
+            problems.push(self.problem(
+                "wallet_pubkey",
+                "The verify mode reads the vault of a wallet, none set".to_string(),
+                &format!(
+                    "Set {}WALLET_PUBKEY or --wallet-pubkey to the watcher wallet's address",
+                    ENV_PREFIX
+                ),
+            ));
+        }
+
+        // The inventory is recorded only in the vault
+        #[cfg(not(feature = "solana"))]
+        if matches!(
+            mode,
+            Mode::Backfill | Mode::Status | Mode::Gc | Mode::Verify
+        ) {
+            problems.push(Problem {
+                message: format!(
+                    "The {} mode needs Solana, not supported by this build",
+                    mode.as_str()
+                ),
+                option: None,
+                help: Some("Build with the default solana feature".to_string()),
+            });
+        }
+
+        #[cfg(feature = "ethereum")]
+        let ethereum = match mode.submits()
+            && self
+                .ethereum_rpc_url
+                .is_some()
+        {
+            true => self.ethereum_config(&mut problems),
+            false => None,
+        };
+        #[cfg(not(feature = "ethereum"))]
+        if self
+            .ethereum_rpc_url
+            .is_some()
+        {
+            problems.push(self.problem(
+                "ethereum_rpc_url",
+                "Ethereum anchoring is not supported by this build".to_string(),
+                "Build with --features ethereum",
+            ));
+        }
+
+        if !problems.is_empty() {
+            return Err(Error::InvalidConfig(problems));
+        }
+        Ok(TrackerConfig {
+            mode,
+            watcher: WatcherConfig {
+                dirs: self.watch_dirs,
+                files: self.watch_files,
+                recursive: self.recursive,
+                max_depth: self.max_depth,
+                poll_fallback: self
+                    .watch_limit_fallback
+                    .then(|| Duration::from_secs(self.poll_interval_secs)),
+                filter,
+                ignore_file: self.ignore_file,
+                events: self.events,
+                source: source.unwrap_or(EventSource::Inotify(dir_watcher::DEFAULT_BUFFER_SIZE)),
+                containers,
+                record: self.record_file,
+                baseline_scan: self.baseline_scan,
+                state_file: self.state_file,
+                state_interval: Duration::from_secs(self.state_interval_secs),
+                stats_report: self
+                    .watcher_stats_secs
+                    .map(Duration::from_secs),
+            },
+            pipeline: self.pipeline,
+            priorities,
+            channel_capacity: self.channel_capacity,
+            backpressure,
+            transport: transport.unwrap_or(Transport::Tokio),
+            #[cfg(feature = "solana")]
+            solana,
+            #[cfg(feature = "ethereum")]
+            ethereum,
+            socket_path: self.socket_path,
+            max_event_age: self
+                .max_event_age_hours
+                .map(|hours| Duration::from_secs(hours * 3600)),
+            dead_letter_path: self.dead_letter_path,
+            journal: self
+                .journal_dir
+                .map(|dir| JournalConfig {
+                    dir,
+                    max_file_size: self.journal_max_file_size,
+                    max_files: self.journal_max_files,
+                    retention_age: self
+                        .journal_retention_days
+                        .map(|days| Duration::from_secs(days * 24 * 3600)),
+                    retention_size: self.journal_retention_size,
+                }),
+            leader: self
+                .leader_lease_file
+                .map(|lease_file| LeaderConfig {
+                    lease_file,
+                    lease: Duration::from_secs(self.leader_lease_secs),
+                }),
+            collector_addr: self.collector_addr,
+            collector_listen: self.collector_listen,
+            tls,
+            webhook_url: self.webhook_url,
+            metrics: self.metrics,
+            metrics_file: self.metrics_file,
+            anomaly_detection: self.anomaly_detection,
+            git,
+            receipts: self
+                .xattr_receipts
+                .then(Receipts::default),
+            capture_xattrs: self.capture_xattrs,
+            detect_file_types: self.detect_file_types,
+            diff_max_size: self.diff_max_size,
+            #[cfg(feature = "solana")]
+            integrity,
+            #[cfg(feature = "solana")]
+            hash_workers: self.hash_workers,
+            snapshot,
+            reload,
+            report_file: self.report_file,
+            report_range: self
+                .report_since
+                .map(|since| (since, self.report_until)),
+            sources: self.sources,
+        })
+    }
+
+    #[cfg(feature = "solana")]
+    fn solana_config(&self, problems: &mut Vec<Problem>) -> Option<SolanaConfig> {
+        if !is_http_url(&self.rpc_url) {
+            problems.push(self.problem(
+                "rpc_url",
+                format!("Invalid RPC URL {}", self.rpc_url),
+                "Use an http:// or https:// URL, e.g. http://127.0.0.1:8899",
+            ));
+        }
+        let commitment = self
+            .commitment
+            .parse::<CommitmentConfig>()
+            .map_err(|e| {
+                problems.push(self.problem(
+                    "commitment",
+                    format!("Invalid commitment {}: {}", self.commitment, e),
+                    "Use one of: processed, confirmed, finalized",
+                ))
+            });
+        let program = self
+            .program_id
+            .parse::<Pubkey>()
+            .map_err(|e| {
+                problems.push(self.problem(
+                    "program_id",
+                    format!("Invalid program id {}: {}", self.program_id, e),
+                    "Use the base58 program id printed by `solana program deploy`",
+                ))
+            });
+
+        if let Some(url) = self.arweave_bundler.as_ref() {
+            if !is_http_url(url) {
+                problems.push(self.problem(
+                    "arweave_bundler",
+                    format!("Invalid bundlr node URL {}", url),
+                    "Use an http:// or https:// URL, e.g. https://node1.bundlr.network",
+                ));
+            }
+        }
+
+        if self.budget_lamports.is_some() && self.budget_period_hours == 0 {
+            problems.push(self.problem(
+                "budget_period_hours",
+                "Budget period of 0 hours".to_string(),
+                "Set the period the budget is renewed after, e.g. 24 hours",
+            ));
+        }
+
+        if !(1..=100).contains(&self.breaker_failure_rate) {
+            problems.push(self.problem(
+                "breaker_failure_rate",
+                format!("Invalid failure rate {}%", self.breaker_failure_rate),
+                "Set the percent of the failed RPC calls opening the breaker, 1 to 100",
+            ));
+        }
+
+        if self.batch_size == 0 {
+            problems.push(self.problem(
+                "batch_size",
+                "Batch size of 0 events".to_string(),
+                "Set 1 to send every event right away, or more to batch them",
+            ));
+        }
+        if self.batch_flush_ms.is_some() && self.batch_size == 1 {
+            problems.push(self.problem(
+                "batch_flush_ms",
+                "Every event is sent right away without batching".to_string(),
+                "Set batch_size over 1 too, e.g. 20 events",
+            ));
+        }
+
+        if self.completeness_report_secs == Some(0) {
+            problems.push(self.problem(
+                "completeness_report_secs",
+                "Completeness report every 0 seconds".to_string(),
+                "Set how often the gaps are reported, e.g. 600 seconds",
+            ));
+        }
+        if self.completeness_on_chain
+            && self
+                .completeness_report_secs
+                .is_none()
+        {
+            problems.push(self.problem(
+                "completeness_on_chain",
+                "The gaps are recorded on chain once reported".to_string(),
+                "Set completeness_report_secs too, e.g. 600 seconds",
+            ));
+        }
+
+        let mut priority_fees = PriorityFees::default();
+        for (name, price) in self.priority_fees.iter() {
+            match name.parse::<EventType>() {
+                Ok(event_type) => priority_fees = priority_fees.with(event_type, *price),
+                Err(e) => problems.push(self.problem(
+                    "priority_fees",
+                    e.to_string(),
+                    "Set the compute-unit prices of the event types, e.g. { deleted = 50000 }",
+                )),
+            }
+        }
+
+        let mut lookup_tables = Vec::with_capacity(self.lookup_tables.len());
+        for table in self.lookup_tables.iter() {
+            match table.parse::<Pubkey>() {
+                Ok(table) => lookup_tables.push(table),
+                Err(e) => problems.push(self.problem(
+                    "lookup_tables",
+                    format!("Invalid address lookup table {}: {}", table, e),
+                    "Use the base58 address printed by `solana address-lookup-table create`",
+                )),
+            }
+        }
+
+        if self.schema_version == 0 || self.schema_version > SCHEMA_VERSION {
+            problems.push(self.problem(
+                "schema_version",
+                format!("Unsupported schema version {}", self.schema_version),
+                &format!(
+                    "Set {}, or the schema of the deployed program, e.g. 1 before the schema 2",
+                    SCHEMA_VERSION
+                ),
+            ));
+        }
+
+        let wallet_pubkey = self
+            .wallet_pubkey
+            .as_ref()
+            .and_then(|wallet| match wallet.parse::<Pubkey>() {
+                Ok(wallet) => Some(wallet),
+                Err(e) => {
+                    problems.push(self.problem(
+                        "wallet_pubkey",
+                        format!("Invalid wallet address {}: {}", wallet, e),
+                        "Use the base58 address printed by `solana address`",
+                    ));
+                    None
+                }
+            });
+
+        let mut routes: Vec<ProgramRoute> = Vec::new();
+        for (dir, program_id) in self.program_routes.iter() {
+            let program = match program_id.parse::<Pubkey>() {
+                Ok(program) => program,
+                Err(e) => {
+                    problems.push(self.problem(
+                        "program_routes",
+                        format!("Invalid program id {} of {}: {}", program_id, dir, e),
+                        "Use the base58 program id printed by `solana program deploy`",
+                    ));
+                    continue;
+                }
+            };
+            if !self
+                .watch_dirs
+                .iter()
+                .any(|watched| snapshot::is_under(dir, watched))
+            {
+                problems.push(self.problem(
+                    "program_routes",
+                    format!("Routed directory {} is not watched", dir),
+                    "Route a watched directory or a directory in one",
+                ));
+            }
+            match routes
+                .iter_mut()
+                .find(|r| r.program == program)
+            {
+                Some(route) => route.dirs.push(dir.clone()),
+                None => routes.push(ProgramRoute {
+                    program,
+                    dirs: vec![dir.clone()],
+                }),
+            }
+        }
+
+        let wallet_keypair = self
+            .wallet_keypair
+            .clone()
+            .unwrap_or_else(|| {
+                std::env::var("HOME")
+                    .map(|mut s| {
+                        s.push_str("/.config/solana/id.json");
+                        s
+                    })
+                    .unwrap_or_default()
+            });
+        Some(SolanaConfig {
+            rpc_url: self.rpc_url.clone(),
+            commitment: commitment.ok()?,
+            rpc_debug: self.rpc_debug,
+            program: program.ok()?,
+            wallet_keypair,
+            wallet_pool: self.wallet_pool.clone(),
+            wallet_pool_min_balance: self.wallet_pool_min_balance,
+            schema_version: self.schema_version,
+            wallet_pubkey,
+            path_prefixes: self
+                .watch_dirs
+                .iter()
+                .map(|dir| format!("{}/", dir.trim_end_matches('/')))
+                .collect(),
+            arweave_bundler: self.arweave_bundler.clone(),
+            arweave_max_size: self.arweave_max_size,
+            clock_skew_threshold: self.clock_skew_threshold_secs,
+            budget_lamports: self.budget_lamports,
+            budget_period: Duration::from_secs(self.budget_period_hours * 3600),
+            breaker_failure_rate: self.breaker_failure_rate,
+            breaker_cool_down: Duration::from_secs(self.breaker_cool_down_secs),
+            retry_budget: Duration::from_secs(self.retry_budget_secs),
+            batch_size: self.batch_size,
+            batch_max_latency: match self.batch_flush_ms {
+                Some(ms) => Duration::from_millis(ms),
+                None => Duration::from_secs(self.batch_flush_secs),
+            },
+            lookup_tables,
+            priority_fees,
+            journal_path: self.dead_letter_path.clone(),
+            routes,
+            webhook_url: self.webhook_url.clone(),
+            completeness_report: self
+                .completeness_report_secs
+                .map(Duration::from_secs),
+            completeness_on_chain: self.completeness_on_chain,
+        })
+    }
+
+    #[cfg(feature = "solana")]
+    fn content_hashes(&self, mode: Mode, problems: &mut Vec<Problem>) -> Option<ContentHashes> {
+        if matches!(mode, Mode::Agent | Mode::Collector) {
+            problems.push(
+                self.problem(
+                    "verify_on_open",
+                    "The opened files are verified on the submitter's host, not on the agents'"
+                        .to_string(),
+                    "Run the watcher and the submitter on the host of the files",
+                ),
+            );
+            return None;
+        }
+        if matches!(mode, Mode::All | Mode::Watch | Mode::Submit)
+            && !self
+                .events
+                .contains(&EventType::Opened)
+        {
+            problems.push(self.problem(
+                "events",
+                "Verifying the opened files needs the opened events".to_string(),
+                "Add opened to the event types, e.g. --events created,written,deleted,opened",
+            ));
+        }
+        match ContentHashes::load(&self.hashes_file) {
+            Ok(hashes) => Some(hashes),
+            Err(e) => {
+                problems.push(self.problem(
+                    "hashes_file",
+                    format!(
+                        "Failed to read the content hashes {}: {}",
+                        self.hashes_file, e
+                    ),
+                    "Remove the file, the backfill records the hashes again",
+                ));
+                None
+            }
+        }
+    }
+
+    #[cfg(feature = "ethereum")]
+    fn ethereum_config(&self, problems: &mut Vec<Problem>) -> Option<EthereumConfig> {
+        let rpc_url = self
+            .ethereum_rpc_url
+            .clone()?;
+        if !is_http_url(&rpc_url) {
+            problems.push(self.problem(
+                "ethereum_rpc_url",
+                format!("Invalid Ethereum RPC URL {}", rpc_url),
+                "Use an http:// or https:// URL, e.g. http://127.0.0.1:8545",
+            ));
+        }
+        let mut address = |field: &str, value: &Option<String>| match value {
+            Some(address) if is_evm_address(address) => Some(address.clone()),
+            Some(address) => {
+                problems.push(self.problem(
+                    field,
+                    format!("Invalid address {}", address),
+                    "Use a 0x prefixed address of 40 hex digits",
+                ));
+                None
+            }
+            None => {
+                problems.push(self.problem(
+                    field,
+                    "Address not set".to_string(),
+                    &format!("Set {}{}", ENV_PREFIX, field.to_uppercase()),
+                ));
+                None
+            }
+        };
+        let contract = address("ethereum_contract", &self.ethereum_contract);
+        let from = address("ethereum_from", &self.ethereum_from);
+        Some(EthereumConfig {
+            rpc_url,
+            contract: contract?,
+            from: from?,
+        })
+    }
+
+    fn snapshot_config(&self, problems: &mut Vec<Problem>) -> Option<SnapshotConfig> {
+        let backend = match self
+            .snapshot_backend
+            .as_deref()
+        {
+            None => {
+                problems.push(self.problem(
+                    "snapshot_backend",
+                    "Snapshot backend not set".to_string(),
+                    &format!("Set {}SNAPSHOT_BACKEND to zfs or btrfs", ENV_PREFIX),
+                ));
+                None
+            }
+            Some(backend) => backend
+                .parse::<snapshot::Backend>()
+                .map_err(|e| {
+                    problems.push(self.problem(
+                        "snapshot_backend",
+                        e.to_string(),
+                        "Use zfs or btrfs",
+                    ))
+                })
+                .ok(),
+        };
+        let mut snapshot = |field: &str, value: &Option<String>, example: &str| {
+            if value.is_none() {
+                problems.push(self.problem(
+                    field,
+                    format!("Snapshot not set in {}", field),
+                    &format!(
+                        "Set {}{}, e.g. to {}",
+                        ENV_PREFIX,
+                        field.to_uppercase(),
+                        example
+                    ),
+                ));
+            }
+            value.clone()
+        };
+        let from = snapshot(
+            "snapshot_from",
+            &self.snapshot_from,
+            "tank/home@mon (zfs) or /mnt/snapshots/mon (btrfs)",
+        );
+        let to = snapshot(
+            "snapshot_to",
+            &self.snapshot_to,
+            "tank/home@tue (zfs) or /mnt/snapshots/tue (btrfs)",
+        );
+        if backend == Some(snapshot::Backend::Btrfs) && self.snapshot_root.is_none() {
+            problems.push(self.problem(
+                "snapshot_root",
+                "The btrfs snapshot's paths need a root".to_string(),
+                &format!(
+                    "Set {}SNAPSHOT_ROOT to where the snapshotted subvolume is mounted",
+                    ENV_PREFIX
+                ),
+            ));
+        }
+
+        Some(SnapshotConfig {
+            backend: backend?,
+            from: from?,
+            to: to?,
+            root: self.snapshot_root.clone(),
+        })
+    }
+
+    fn problem(&self, field: &str, message: String, help: &str) -> Problem {
+        Problem {
+            message,
+            option: Some((field.to_string(), self.source(field))),
+            help: Some(help.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "ethereum")]
+fn is_evm_address(address: &str) -> bool {
+    address
+        .strip_prefix("0x")
+        .is_some_and(|hex| {
+            hex.len() == 40
+                && hex
+                    .chars()
+                    .all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_problems_reported() {
+        let config = Config {
+            watch_dirs: vec!["/srv/a".to_string(), "/srv/a/".to_string()],
+            tls_cert: Some("cert.pem".to_string()),
+            webhook_url: Some("hooks.example.com".to_string()),
+            ..Config::default()
+        };
+
+        let problems = match config.validate(Mode::Agent) {
+            Err(Error::InvalidConfig(problems)) => problems,
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        };
+        let messages: Vec<String> = problems
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Directory /srv/a/ is listed more than once (watch_dirs from the default)",
+                "Only some of the TLS files are set",
+                "Collector address not set (collector_addr from the default)",
+                "Invalid webhook URL hooks.example.com (webhook_url from the default)",
+            ]
+        );
+        assert!(problems
+            .iter()
+            .all(|p| p.help.is_some()));
+    }
+
+    #[test]
+    fn test_mode_requirements() {
+        // Nothing else is needed to watch
+        let config = Config::default()
+            .validate(Mode::Watch)
+            .unwrap();
+        assert!(config.tls.is_none());
+        #[cfg(feature = "solana")]
+        assert!(config.solana.is_none());
+        assert_eq!(
+            config.watcher.source,
+            EventSource::Inotify(dir_watcher::DEFAULT_BUFFER_SIZE)
+        );
+
+        // A collector needs TLS
+        assert!(Config::default()
+            .validate(Mode::Collector)
+            .is_err());
+
+        // The snapshot mode needs both snapshots
+        let config = Config {
+            snapshot_backend: Some("zfs".to_string()),
+            snapshot_from: Some("tank/home@mon".to_string()),
+            ..Config::default()
+        };
+        match config.validate(Mode::Snapshot) {
+            Err(Error::InvalidConfig(problems)) => assert_eq!(
+                problems[0].to_string(),
+                "Snapshot not set in snapshot_to (snapshot_to from the default)"
+            ),
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        // An event with a long name doesn't fit
+        let config = Config {
+            inotify_buffer_size: 256,
+            ..Config::default()
+        };
+        match config.validate(Mode::Watch) {
+            Err(Error::InvalidConfig(problems)) => assert_eq!(
+                problems[0].to_string(),
+                "Buffer of 256 bytes can't hold an event with a long file name (inotify_buffer_size from the default)"
+            ),
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[cfg(feature = "solana")]
+    #[test]
+    fn test_solana_checked_only_when_submitting() {
+        let config = Config {
+            program_id: "not-a-pubkey".to_string(),
+            ..Config::default()
+        };
+        assert!(config
+            .clone()
+            .validate(Mode::Watch)
+            .is_ok());
+        assert!(config
+            .validate(Mode::Submit)
+            .is_err());
+
+        let solana = Config::default()
+            .validate(Mode::All)
+            .unwrap()
+            .solana
+            .unwrap();
+        assert_eq!(solana.commitment, CommitmentConfig::confirmed());
+    }
+
+    #[cfg(feature = "solana")]
+    #[test]
+    fn test_program_routes() {
+        let staging = Pubkey::new_unique();
+        let config = Config {
+            watch_dirs: vec!["/srv".to_string()],
+            program_routes: BTreeMap::from([
+                ("/srv/staging".to_string(), staging.to_string()),
+                (
+                    "/srv/staging/prod".to_string(),
+                    Config::default().program_id,
+                ),
+                ("/srv/qa/".to_string(), staging.to_string()),
+            ]),
+            ..Config::default()
+        };
+        let solana = config
+            .validate(Mode::All)
+            .unwrap()
+            .solana
+            .unwrap();
+
+        // One route per program
+        assert_eq!(solana.routes.len(), 2);
+        let route = |path| {
+            solana
+                .route(path)
+                .map(|r| r.program)
+        };
+        assert_eq!(route("/srv/staging/a.txt"), Some(staging));
+        assert_eq!(route("/srv/qa/a.txt"), Some(staging));
+        assert_eq!(route("/srv/staging/prod/a.txt"), Some(solana.program));
+        assert_eq!(route("/srv/staging2/a.txt"), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Watching inside the root filesystems of containers, mounted with overlayfs.
+//!
+//! A container's merged view is an overlay of its image layers and its own upper
+//! directory, where all the changes land: made inside the container or directly on
+//! the host. The watched directories inside a merged view are resolved to the upper
+//! directory and watched there, the events keep the merged paths. The events carry
+//! the id of the container, looked up in the Docker or Podman storage.
+//!
+//! The volumes need none of it, they are plain host directories.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Watched directory inside a container's merged view.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overlay {
+    /// As configured, in the merged view.
+    pub dir: String,
+    /// The same directory in the upper directory of the overlay.
+    pub upper: String,
+    pub container_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Containers {
+    overlays: Vec<Overlay>,
+}
+
+impl Containers {
+    /// Finds the directories in the overlayfs mounts, from the mount table.
+    pub fn discover(dirs: &[String]) -> Containers {
+        match fs::read_to_string(MOUNTINFO) {
+            Ok(mountinfo) => Containers::from_mountinfo(&mountinfo, dirs, container_id),
+            Err(e) => {
+                println!("Failed to read {}: {}", MOUNTINFO, e);
+                Containers::default()
+            }
+        }
+    }
+
+    fn from_mountinfo(
+        mountinfo: &str,
+        dirs: &[String],
+        container_id: impl Fn(&Path) -> Option<String>,
+    ) -> Containers {
+        let mounts: Vec<(PathBuf, PathBuf)> = mountinfo
+            .lines()
+            .filter_map(overlay_mount)
+            .collect();
+        let overlays = dirs
+            .iter()
+            .filter_map(|dir| {
+                // The innermost mount, the last one of those mounted at the same point
+                let (mount_point, upper) = mounts
+                    .iter()
+                    .filter(|(mount_point, _)| Path::new(dir).starts_with(mount_point))
+                    .max_by_key(|(mount_point, _)| mount_point.as_os_str().len())?;
+                let relative = Path::new(dir)
+                    .strip_prefix(mount_point)
+                    .ok()?;
+                Some(Overlay {
+                    dir: dir.clone(),
+                    upper: upper
+                        .join(relative)
+                        .components()
+                        .collect::<PathBuf>()
+                        .to_string_lossy()
+                        .to_string(),
+                    container_id: container_id(upper),
+                })
+            })
+            .collect();
+        Containers { overlays }
+    }
+
+    pub fn overlays(&self) -> &[Overlay] {
+        &self.overlays
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    /// The overlay containing the file, in the merged view.
+    pub fn overlay(&self, path: &str) -> Option<&Overlay> {
+        self.overlays
+            .iter()
+            .filter(|overlay| Path::new(path).starts_with(&overlay.dir))
+            .max_by_key(|overlay| overlay.dir.len())
+    }
+
+    pub fn container_id(&self, path: &str) -> Option<&str> {
+        self.overlay(path)?
+            .container_id
+            .as_deref()
+    }
+
+    /// Whether the file is removed from the merged view by a whiteout, a 0/0 character
+    /// device in the upper directory hiding the file of a lower layer.
+    #[cfg(unix)]
+    pub fn is_whiteout(&self, path: &str) -> bool {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let Some(overlay) = self.overlay(path) else {
+            return false;
+        };
+        let Ok(relative) = Path::new(path).strip_prefix(&overlay.dir) else {
+            return false;
+        };
+        fs::symlink_metadata(Path::new(&overlay.upper).join(relative))
+            .is_ok_and(|m| m.file_type().is_char_device() && m.rdev() == 0)
+    }
+
+    // The overlays and their whiteouts are Linux's
+    #[cfg(not(unix))]
+    pub fn is_whiteout(&self, _path: &str) -> bool {
+        false
+    }
+}
+
+// The mount point and the upper directory of an overlayfs mount, e.g.
+// `36 35 0:52 / /var/lib/docker/overlay2/<layer>/merged rw - overlay overlay rw,lowerdir=...,upperdir=...,workdir=...`
+fn overlay_mount(line: &str) -> Option<(PathBuf, PathBuf)> {
+    let (mount, superblock) = line.split_once(" - ")?;
+    let mount_point = mount.split(' ').nth(4)?;
+    let mut superblock = superblock.split(' ');
+    if superblock.next()? != "overlay" {
+        return None;
+    }
+    let upper = superblock
+        .nth(1)?
+        .split(',')
+        .find_map(|option| option.strip_prefix("upperdir="))?;
+    Some((unescape(mount_point).into(), unescape(upper).into()))
+}
+
+// The mount table escapes the spaces, tabs, newlines and backslashes as octal, e.g. `\040`
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(i) = rest.find('\\') {
+        unescaped.push_str(&rest[..i]);
+        match rest
+            .get(i + 1..i + 4)
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok())
+        {
+            Some(c) => {
+                unescaped.push(char::from(c));
+                rest = &rest[i + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+// The upper directory is `<storage>/overlay[2]/<layer>/diff`, the storage maps
+// the container layers to the containers
+fn container_id(upper: &Path) -> Option<String> {
+    let layer_dir = upper.parent()?;
+    let layer = layer_dir
+        .file_name()?
+        .to_str()?;
+    let storage = layer_dir.parent()?.parent()?;
+    docker_container(storage, layer).or_else(|| podman_container(storage, layer))
+}
+
+// Docker keeps the container's layer in image/overlay2/layerdb/mounts/<container>/mount-id
+fn docker_container(storage: &Path, layer: &str) -> Option<String> {
+    fs::read_dir(storage.join("image/overlay2/layerdb/mounts"))
+        .ok()?
+        .flatten()
+        .find(|entry| {
+            fs::read_to_string(entry.path().join("mount-id"))
+                .is_ok_and(|mount_id| mount_id.trim() == layer)
+        })
+        .map(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .to_string()
+        })
+}
+
+// Podman lists the containers with their layers in overlay-containers/containers.json
+fn podman_container(storage: &Path, layer: &str) -> Option<String> {
+    let containers = fs::read_to_string(storage.join("overlay-containers/containers.json")).ok()?;
+    let containers: Vec<serde_json::Value> = serde_json::from_str(&containers).ok()?;
+    containers
+        .iter()
+        .find(|c| c["layer"] == layer)?["id"]
+        .as_str()
+        .map(|id| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirs_resolved_to_upper() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime - ext4 /dev/nvme0n1p2 rw
+36 22 0:52 / /var/lib/docker/overlay2/f3a1/merged rw,relatime - overlay overlay rw,lowerdir=/var/lib/docker/overlay2/l/ABC:/var/lib/docker/overlay2/l/DEF,upperdir=/var/lib/docker/overlay2/f3a1/diff,workdir=/var/lib/docker/overlay2/f3a1/work
+37 22 0:53 / /srv/my\\040app rw - overlay overlay rw,lowerdir=/srv/lower,upperdir=/srv/upper,workdir=/srv/work
+";
+        let dirs = vec![
+            "/var/lib/docker/overlay2/f3a1/merged/app/data".to_string(),
+            "/srv/my app".to_string(),
+            "/srv/plain".to_string(),
+        ];
+        let containers = Containers::from_mountinfo(mountinfo, &dirs, |upper| {
+            (upper == Path::new("/var/lib/docker/overlay2/f3a1/diff")).then(|| "4c2e".to_string())
+        });
+
+        assert_eq!(
+            containers.overlays(),
+            &[
+                Overlay {
+                    dir: dirs[0].clone(),
+                    upper: "/var/lib/docker/overlay2/f3a1/diff/app/data".to_string(),
+                    container_id: Some("4c2e".to_string()),
+                },
+                Overlay {
+                    dir: dirs[1].clone(),
+                    upper: "/srv/upper".to_string(),
+                    container_id: None,
+                },
+            ]
+        );
+        assert_eq!(
+            containers.container_id("/var/lib/docker/overlay2/f3a1/merged/app/data/a.txt"),
+            Some("4c2e")
+        );
+        assert_eq!(containers.container_id("/srv/plain/a.txt"), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! Inventory of the files existing when the tracker starts, reported as created so the
+//! vault reflects the whole tree and not only the files changed since.
+
+use super::{read_file_info, GlobFilter};
+use crate::event::{Event, EventType};
+use std::path::{Path, PathBuf};
+
+/// The regular files in the directory, in the subdirectories too if `recursive`,
+/// sorted within each directory. The symlinks are not followed.
+pub fn existing_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk(dir, if recursive { usize::MAX } else { 0 }, &mut files);
+    files
+}
+
+// Walks `depth` levels of the subdirectories
+fn walk(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Failed to read the directory {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    // Sorted, so a scan of the same tree reports the files in the same order
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let file_type = match path.symlink_metadata() {
+            Ok(metadata) => metadata.file_type(),
+            Err(e) => {
+                println!("Failed to read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if file_type.is_dir() && depth > 0 {
+            walk(&path, depth - 1, files);
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+/// A created event with the file info of every existing file passing the `filter`,
+/// listed in the first path of each pair and reported in the second one, with `depth`
+/// levels of their subdirectories.
+pub fn scan(dirs: &[(&str, &str)], depth: usize, filter: &GlobFilter) -> Vec<Event> {
+    listed(dirs, depth, filter)
+        .into_iter()
+        .filter_map(|(file, reported)| {
+            // Gone since it was listed
+            let file_info = read_file_info(&file.to_string_lossy())?;
+            // Timestamped by the listener
+            Some(Event {
+                file_info: Some(file_info),
+                ..Event::new(reported, EventType::Created)
+            })
+        })
+        .collect()
+}
+
+/// The existing files passing the `filter`, each one where it's listed and its reported
+/// path, see [scan].
+pub(super) fn listed(
+    dirs: &[(&str, &str)],
+    depth: usize,
+    filter: &GlobFilter,
+) -> Vec<(PathBuf, String)> {
+    let mut listed = Vec::new();
+    for (path, dir) in dirs {
+        let mut files = Vec::new();
+        walk(Path::new(path), depth, &mut files);
+        for file in files {
+            let reported = match file.strip_prefix(path) {
+                Ok(relative) => Path::new(dir)
+                    .join(relative)
+                    .to_string_lossy()
+                    .into_owned(),
+                Err(_) => continue,
+            };
+            if filter.matches(&reported) {
+                listed.push((file, reported));
+            }
+        }
+    }
+    listed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_existing_files_reported_as_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("b.txt"), "test").unwrap();
+        fs::write(dir.path().join("a.tmp"), "").unwrap();
+        fs::write(dir.path().join("sub/c.txt"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("b.txt"), dir.path().join("link")).unwrap();
+
+        let filter = GlobFilter::new(&[], &["*.tmp".to_string()]).unwrap();
+        let events = scan(&[(root, "/srv/data")], usize::MAX, &filter);
+        let reported: Vec<(&str, Option<u64>)> = events
+            .iter()
+            .map(|e| {
+                assert_eq!(e.event_type, EventType::Created);
+                (
+                    e.file_path.as_str(),
+                    e.file_info
+                        .as_ref()
+                        .map(|fi| fi.size),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                ("/srv/data/b.txt", Some(4)),
+                ("/srv/data/sub/c.txt", Some(0))
+            ]
+        );
+
+        let events = scan(&[(root, root)], 0, &GlobFilter::default());
+        assert_eq!(events.len(), 2);
+        // The files in the subdirectories one level deep
+        fs::create_dir(dir.path().join("sub/deep")).unwrap();
+        fs::write(
+            dir.path()
+                .join("sub/deep/d.txt"),
+            "",
+        )
+        .unwrap();
+        let events = scan(&[(root, root)], 1, &GlobFilter::default());
+        assert_eq!(events.len(), 3);
+    }
+}
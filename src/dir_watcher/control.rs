@@ -0,0 +1,114 @@
+//! Changing the watched paths of a running [DirWatcher](super::DirWatcher), so a
+//! long-running tracker tracks a new directory, or stops tracking one, without a restart:
+//! the vault and its path dictionary are kept, the new directories' events are sent
+//! with their full paths. The filter is replaced the same way, e.g. on a reload of the
+//! configuration.
+
+use super::{config_filter, watched_dirs, GlobFilter};
+use crate::config::WatcherConfig;
+use crate::error::Error;
+use tokio::sync::{mpsc, oneshot, watch};
+
+pub(super) enum Control {
+    Add(String),
+    Remove(String),
+    Filter(GlobFilter),
+}
+
+/// The change and where to reply with its outcome.
+pub(super) type Request = (Control, oneshot::Sender<Result<(), Error>>);
+
+/// Handle of a [DirWatcher](super::DirWatcher), valid while it runs.
+#[derive(Clone)]
+pub struct WatchController {
+    tx: mpsc::UnboundedSender<Request>,
+}
+
+impl WatchController {
+    pub(super) fn new(tx: mpsc::UnboundedSender<Request>) -> WatchController {
+        WatchController { tx }
+    }
+
+    /// Watches the directory too, in the watcher's mode, its events reported with its path.
+    pub async fn add_path(&self, path: &str) -> Result<(), Error> {
+        self.request(Control::Add(path.to_string()))
+            .await
+    }
+
+    /// Stops watching the directory, and its subdirectories in the recursive mode,
+    /// as reported. Fails if it's not watched.
+    pub async fn remove_path(&self, path: &str) -> Result<(), Error> {
+        self.request(Control::Remove(path.to_string()))
+            .await
+    }
+
+    /// Reports only the files passing the `filter` from now on.
+    pub async fn set_filter(&self, filter: GlobFilter) -> Result<(), Error> {
+        self.request(Control::Filter(filter))
+            .await
+    }
+
+    /// Applies the watched paths and the filter of each of the `configs`, until they
+    /// end. The paths are watched as they are, not in the containers' upper directories.
+    pub async fn follow(self, mut configs: watch::Receiver<WatcherConfig>) {
+        let mut running = configs
+            .borrow_and_update()
+            .clone();
+        while configs
+            .changed()
+            .await
+            .is_ok()
+        {
+            let config = configs
+                .borrow_and_update()
+                .clone();
+            let paths = |config: &WatcherConfig| {
+                config
+                    .dirs
+                    .iter()
+                    .chain(config.files.iter())
+                    .cloned()
+                    .collect::<Vec<String>>()
+            };
+            let (before, after) = (paths(&running), paths(&config));
+            for path in before
+                .iter()
+                .filter(|path| !after.contains(path))
+            {
+                if let Err(e) = self.remove_path(path).await {
+                    println!("Failed to stop watching {}: {}", path, e);
+                }
+            }
+            for path in after
+                .iter()
+                .filter(|path| !before.contains(path))
+            {
+                if let Err(e) = self.add_path(path).await {
+                    println!("Failed to watch {}: {}", path, e);
+                }
+            }
+            // The ignore files are read in the watched directories
+            if config.filter != running.filter
+                || config.ignore_file != running.ignore_file
+                || (config.ignore_file.is_some() && config.dirs != running.dirs)
+            {
+                let filter = config_filter(&config, &watched_dirs(&config));
+                match self.set_filter(filter).await {
+                    Ok(()) => println!("Filtering the events with the reloaded filter"),
+                    Err(e) => println!("Failed to replace the filter: {}", e),
+                }
+            }
+            running = config;
+        }
+    }
+
+    async fn request(&self, control: Control) -> Result<(), Error> {
+        let (reply, replied) = oneshot::channel();
+        self.tx
+            .send((control, reply))
+            .map_err(|_| Error::Watcher("The watcher is not running".to_string()))?;
+        replied
+            .await
+            .map_err(|_| Error::Watcher("The watcher stopped".to_string()))?
+    }
+}
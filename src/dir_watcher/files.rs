@@ -0,0 +1,147 @@
+//! Watching single files, e.g. `/etc/passwd`: an inotify watch of a file reports its own
+//! changes, without a name, so its events are named after the file, reported in its
+//! directory.
+//!
+//! The watch follows the file's inode, not its path. A file replaced atomically, by a new
+//! file renamed over it as the editors and `useradd` do, leaves the watch on the old inode,
+//! which is gone or no longer under the path. So once the watched inode is deleted, moved or
+//! loses a link, the path is checked: a new file there is watched instead and reported as
+//! written, otherwise the file is reported as deleted or moved from and no longer watched.
+
+use inotify::{EventMask, WatchDescriptor, WatchMask, Watches};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub struct Files {
+    watches: Watches,
+    // The file's deletion, move and link count are watched to follow its replacements
+    mask: WatchMask,
+    reported: EventMask,
+    // Watched path, reported path and inode of each watched file
+    paths: HashMap<WatchDescriptor, (String, String, u64)>,
+}
+
+impl Files {
+    pub fn new(watches: Watches, mask: WatchMask) -> Files {
+        Files {
+            watches,
+            mask: mask | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF | WatchMask::ATTRIB,
+            reported: EventMask::from_bits_truncate(mask.bits()),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Watches the file at `path`, its events are reported in `file`.
+    pub fn add(
+        &mut self,
+        dirs: &mut HashMap<WatchDescriptor, String>,
+        path: &str,
+        file: &str,
+    ) -> io::Result<()> {
+        let inode = fs::metadata(path)?.ino();
+        let wd = self
+            .watches
+            .add(path, self.mask)?;
+        let dir = Path::new(file)
+            .parent()
+            .map_or(String::new(), |dir| {
+                dir.to_string_lossy()
+                    .into_owned()
+            });
+        dirs.insert(wd.clone(), dir);
+        self.paths
+            .insert(wd, (path.to_string(), file.to_string(), inode));
+        Ok(())
+    }
+
+    /// Unwatches the file, as reported. Returns whether it was watched.
+    pub fn remove(&mut self, dirs: &mut HashMap<WatchDescriptor, String>, file: &str) -> bool {
+        let Some(wd) = self
+            .paths
+            .iter()
+            .find(|(_, (_, reported, _))| Path::new(reported) == Path::new(file))
+            .map(|(wd, _)| wd.clone())
+        else {
+            return false;
+        };
+        self.unwatch(dirs, wd);
+        true
+    }
+
+    /// The events of the watched files named after the file, with the event types watched
+    /// only to follow the file dropped, the others as they are. Watches the files replaced
+    /// under the watched paths.
+    pub fn named(
+        &mut self,
+        dirs: &mut HashMap<WatchDescriptor, String>,
+        events: Vec<inotify::Event<OsString>>,
+    ) -> Vec<inotify::Event<OsString>> {
+        let mut named = Vec::with_capacity(events.len());
+        for mut event in events {
+            let Some((path, file, inode)) = self
+                .paths
+                .get(&event.wd)
+                .cloned()
+            else {
+                named.push(event);
+                continue;
+            };
+            if event.mask.intersects(
+                EventMask::DELETE_SELF
+                    | EventMask::MOVE_SELF
+                    | EventMask::ATTRIB
+                    | EventMask::IGNORED,
+            ) && current_inode(&path) != Some(inode)
+            {
+                self.unwatch(dirs, event.wd.clone());
+                event.mask = match self.add(dirs, &path, &file) {
+                    Ok(()) => {
+                        println!("Watching the replaced file {} again", file);
+                        EventMask::CLOSE_WRITE
+                    }
+                    Err(e) => {
+                        if e.kind() != io::ErrorKind::NotFound {
+                            println!("Failed to watch the replaced file {}: {}", file, e);
+                        }
+                        println!("{} is gone, no longer watched", file);
+                        match event
+                            .mask
+                            .contains(EventMask::MOVE_SELF)
+                        {
+                            true => EventMask::MOVED_FROM,
+                            false => EventMask::DELETE,
+                        }
+                    }
+                };
+            }
+            event.mask &= self.reported;
+            event.name = Path::new(&file)
+                .file_name()
+                .map(|name| name.to_os_string());
+            if !event.mask.is_empty() {
+                named.push(event);
+            }
+        }
+        named
+    }
+
+    fn unwatch(&mut self, dirs: &mut HashMap<WatchDescriptor, String>, wd: WatchDescriptor) {
+        // Fails if the file is gone meanwhile, the watch with it
+        let _ = self
+            .watches
+            .remove(wd.clone());
+        self.paths.remove(&wd);
+        dirs.remove(&wd);
+    }
+}
+
+// The inode under the path now, none if nothing is there
+fn current_inode(path: &str) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.ino())
+}
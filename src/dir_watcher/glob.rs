@@ -0,0 +1,314 @@
+//! Include and exclude glob patterns of the watched files, e.g. `*.tmp`, applied before
+//! the events get their metadata and reach the sinks.
+//!
+//! A pattern without a `/` matches the file name, one with a `/` the whole path.
+//! `*` matches within a path component, `**` across them, `?` any one character,
+//! `[abc]` and `[a-z]` one of the characters, `[!abc]` any other one.
+//!
+//! The filter also applies the [IgnoreFiles] of the watched directories.
+//!
+//! The size range applies to the files once their metadata is looked up.
+//!
+//! The presets are named sets of the exclude patterns of the usual noise, see [preset].
+
+use super::ignore::IgnoreFiles;
+use crate::error::Error;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GlobFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    ignores: IgnoreFiles,
+    // Bytes, inclusive
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+}
+
+impl GlobFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<GlobFilter, Error> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Glob::new(p))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(GlobFilter {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+            ignores: IgnoreFiles::default(),
+            min_size: None,
+            max_size: None,
+        })
+    }
+
+    /// Reports only the files of `min` to `max` bytes, unbounded if not set.
+    pub fn with_size_range(mut self, min: Option<u64>, max: Option<u64>) -> GlobFilter {
+        self.min_size = min;
+        self.max_size = max;
+        self
+    }
+
+    pub fn with_ignore_files(mut self, ignores: IgnoreFiles) -> GlobFilter {
+        self.ignores = ignores;
+        self
+    }
+
+    /// Picks up the changes of the ignore files.
+    pub fn reload(&mut self) {
+        self.ignores.reload();
+    }
+
+    /// Whether the file matches one of the include patterns, if any,
+    /// and none of the exclude ones.
+    pub fn matches(&self, path: &str) -> bool {
+        self.matches_entry(path, false)
+    }
+
+    /// Whether a file of `size` bytes is in the size range.
+    pub fn matches_size(&self, size: u64) -> bool {
+        self.min_size
+            .is_none_or(|min| size >= min)
+            && self
+                .max_size
+                .is_none_or(|max| size <= max)
+    }
+
+    /// [GlobFilter::matches] knowing whether the path is a directory, for the ignore
+    /// patterns of the directories only.
+    pub fn matches_entry(&self, path: &str, is_dir: bool) -> bool {
+        if self
+            .ignores
+            .ignores(path, is_dir)
+        {
+            return false;
+        }
+        let path: Vec<char> = path.chars().collect();
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|glob| glob.matches(&path));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|glob| glob.matches(&path))
+    }
+}
+
+/// Names of the built-in presets.
+pub const PRESETS: &[&str] = &["hidden", "editor_temp"];
+
+/// The exclude patterns of the preset: `hidden` skips the dotfiles and the dot directories,
+/// `editor_temp` the backup, swap and lock files of vim and emacs, and the file vim creates
+/// to check if it can write the directory.
+pub fn preset(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "hidden" => Some(&[".*"]),
+        "editor_temp" => Some(&["*~", "*.swp", "*.swo", "*.swx", "4913", ".#*", "#*#"]),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Glob {
+    tokens: Vec<Token>,
+    // Matches the file name only
+    name_only: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    // ?
+    Any,
+    // *
+    Star,
+    // ** at the end or within a component
+    AnyPath,
+    // **/, any leading directories, none included
+    AnyDirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub(super) fn new(pattern: &str) -> Result<Glob, Error> {
+        let invalid =
+            |reason: &str| Error::Config(format!("Invalid glob pattern {}: {}", pattern, reason));
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::AnyDirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                }
+                '*' => {
+                    tokens.push(Token::Star);
+                    i += 1;
+                }
+                '?' => {
+                    tokens.push(Token::Any);
+                    i += 1;
+                }
+                '[' => {
+                    let negated = chars.get(i + 1) == Some(&'!');
+                    let start = if negated { i + 2 } else { i + 1 };
+                    // A ] right after the opening one is a member
+                    let end = (start + 1..chars.len())
+                        .find(|&j| chars[j] == ']')
+                        .ok_or_else(|| invalid("unclosed ["))?;
+                    let members = &chars[start..end];
+                    let mut ranges = Vec::new();
+                    let mut j = 0;
+                    while j < members.len() {
+                        match members.get(j + 1..j + 3) {
+                            Some(&['-', last]) => {
+                                ranges.push((members[j], last));
+                                j += 3;
+                            }
+                            _ => {
+                                ranges.push((members[j], members[j]));
+                                j += 1;
+                            }
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    i = end + 1;
+                }
+                c => {
+                    tokens.push(Token::Char(c));
+                    i += 1;
+                }
+            }
+        }
+        if tokens.is_empty() {
+            return Err(invalid("empty"));
+        }
+        Ok(Glob {
+            tokens,
+            name_only: !pattern.contains('/'),
+        })
+    }
+
+    fn matches(&self, path: &[char]) -> bool {
+        let text = match self.name_only {
+            true => match path
+                .iter()
+                .rposition(|&c| c == '/')
+            {
+                Some(i) => &path[i + 1..],
+                None => path,
+            },
+            false => path,
+        };
+        matches(&self.tokens, text)
+    }
+
+    /// Matches the whole text, even if the pattern has no `/`.
+    pub(super) fn matches_all(&self, text: &[char]) -> bool {
+        matches(&self.tokens, text)
+    }
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Char(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+        Token::Any => {
+            text.first()
+                .is_some_and(|&c| c != '/')
+                && matches(rest, &text[1..])
+        }
+        Token::Class { negated, ranges } => {
+            text.first()
+                .is_some_and(|&c| {
+                    c != '/'
+                        && ranges
+                            .iter()
+                            .any(|&(first, last)| first <= c && c <= last)
+                            != *negated
+                })
+                && matches(rest, &text[1..])
+        }
+        Token::Star => {
+            for i in 0..=text.len() {
+                if matches(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Token::AnyPath => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        Token::AnyDirs => (0..=text.len())
+            .filter(|&i| i == 0 || text[i - 1] == '/')
+            .any(|i| matches(rest, &text[i..])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_and_exclude_globs() {
+        let filter = GlobFilter::new(
+            &["*.conf".to_string(), "/srv/**/data-[0-9].json".to_string()],
+            &["/etc/ssl/**".to_string(), "*.[!cj]*".to_string()],
+        )
+        .unwrap();
+        assert!(filter.matches("/etc/nginx/nginx.conf"));
+        assert!(filter.matches("/srv/data-1.json"));
+        assert!(filter.matches("/srv/app/v2/data-7.json"));
+        assert!(!filter.matches("/srv/app/data-x.json"));
+        assert!(!filter.matches("/etc/ssl/openssl.conf"));
+        assert!(!filter.matches("/etc/nginx/nginx.conf.tmp"));
+        assert!(!filter.matches("/etc/hosts"));
+
+        let filter =
+            GlobFilter::new(&[], &["*.tmp".to_string(), "/tmp/*/cache".to_string()]).unwrap();
+        assert!(!filter.matches("/srv/.a.txt.tmp"));
+        assert!(!filter.matches("/tmp/app/cache"));
+        assert!(filter.matches("/tmp/app/v2/cache"));
+        assert!(filter.matches("/srv/a.txt"));
+
+        assert!(GlobFilter::new(&["[a-".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_presets_exclude_noise() {
+        let exclude: Vec<String> = PRESETS
+            .iter()
+            .flat_map(|name| preset(name).unwrap())
+            .map(|p| p.to_string())
+            .collect();
+        let filter = GlobFilter::new(&[], &exclude).unwrap();
+        for noise in [
+            "/srv/.env",
+            "/srv/.notes.txt.swp",
+            "/srv/notes.txt~",
+            "/srv/4913",
+            "/srv/.#notes.txt",
+            "/srv/#notes.txt#",
+        ] {
+            assert!(!filter.matches(noise), "{}", noise);
+        }
+        assert!(filter.matches("/srv/notes.txt"));
+        assert!(filter.matches("/srv/49130"));
+        assert!(preset("backups").is_none());
+    }
+}
@@ -0,0 +1,237 @@
+//! Ignore files in the watched directories, e.g. `.trackerignore`, in the gitignore syntax:
+//!
+//! ```text
+//! # build output, anywhere
+//! target/
+//! *.log
+//! !keep.log
+//! /cache/**/*.bin
+//! ```
+//!
+//! A pattern with a `/` before its end is relative to the watched directory, one without
+//! matches the names at any depth. A trailing `/` matches only directories, a leading `!`
+//! includes again what an earlier pattern ignored, except below an ignored directory.
+//! The last matching pattern decides. The files are read again once changed.
+
+use super::glob::Glob;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IgnoreFiles {
+    files: Vec<IgnoreFile>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct IgnoreFile {
+    // The watched directory, as reported
+    dir: String,
+    path: PathBuf,
+    // Modification time and size when read, none if missing
+    stamp: Option<(SystemTime, u64)>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    // Matches the path relative to the directory, the name at any depth otherwise
+    anchored: bool,
+}
+
+impl IgnoreFiles {
+    /// Reads the ignore file `name` of each watched directory, `dirs` pairs the watched
+    /// path with the reported one.
+    pub fn new(dirs: &[(&str, &str)], name: &str) -> IgnoreFiles {
+        let mut ignores = IgnoreFiles {
+            files: dirs
+                .iter()
+                .map(|(path, dir)| IgnoreFile {
+                    dir: dir.to_string(),
+                    path: Path::new(path).join(name),
+                    stamp: None,
+                    rules: Vec::new(),
+                })
+                .collect(),
+        };
+        ignores.reload();
+        ignores
+    }
+
+    /// Reads again the ignore files changed, created or removed since read.
+    pub fn reload(&mut self) {
+        for file in self.files.iter_mut() {
+            let stamp = fs::metadata(&file.path)
+                .ok()
+                .map(|m| {
+                    (
+                        m.modified()
+                            .unwrap_or(SystemTime::UNIX_EPOCH),
+                        m.len(),
+                    )
+                });
+            if stamp == file.stamp {
+                continue;
+            }
+            file.stamp = stamp;
+            file.rules = match fs::read_to_string(&file.path) {
+                Ok(content) => {
+                    println!("Loaded the ignore file {}", file.path.display());
+                    parse(&content)
+                }
+                Err(e) => {
+                    if e.kind() != io::ErrorKind::NotFound {
+                        println!(
+                            "Failed to read the ignore file {}: {}",
+                            file.path.display(),
+                            e
+                        );
+                    }
+                    Vec::new()
+                }
+            };
+        }
+    }
+
+    /// Whether an ignore file of a watched directory above the path ignores it.
+    pub fn ignores(&self, path: &str, is_dir: bool) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.ignores(path, is_dir))
+    }
+}
+
+impl IgnoreFile {
+    fn ignores(&self, path: &str, is_dir: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let Ok(relative) = Path::new(path).strip_prefix(&self.dir) else {
+            return false;
+        };
+        let relative: Vec<char> = relative
+            .to_string_lossy()
+            .chars()
+            .collect();
+        // Every parent directory first, nothing below an ignored one is included again
+        let ends: Vec<usize> = relative
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c == '/')
+            .map(|(i, _)| i)
+            .chain([relative.len()])
+            .collect();
+        for (i, &end) in ends.iter().enumerate() {
+            let last = i == ends.len() - 1;
+            let ignored = self.decide(&relative[..end], !last || is_dir);
+            if ignored || last {
+                return ignored;
+            }
+        }
+        false
+    }
+
+    // The last rule matching the path decides
+    fn decide(&self, relative: &[char], is_dir: bool) -> bool {
+        let name = match relative
+            .iter()
+            .rposition(|&c| c == '/')
+        {
+            Some(i) => &relative[i + 1..],
+            None => relative,
+        };
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && rule
+                        .glob
+                        .matches_all(if rule.anchored { relative } else { name })
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+fn parse(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // \# and \! start the patterns with the character itself
+        let pattern = pattern
+            .strip_prefix('\\')
+            .filter(|rest| rest.starts_with(['#', '!']))
+            .unwrap_or(pattern);
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern
+            .strip_prefix('/')
+            .unwrap_or(pattern);
+        match Glob::new(pattern) {
+            Ok(glob) => rules.push(Rule {
+                glob,
+                negated,
+                dir_only,
+                anchored,
+            }),
+            Err(e) => println!("Skipping the ignore pattern {}: {}", line, e),
+        }
+    }
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_file_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = dir
+            .path()
+            .join(".trackerignore");
+        fs::write(
+            &path,
+            "# comment\ntarget/\n*.log\n!keep.log\n/cache/**/*.bin\nbuild/\n!build/a.txt\n",
+        )
+        .unwrap();
+        let mut ignores = IgnoreFiles::new(&[(root, "/srv/data")], ".trackerignore");
+
+        assert!(ignores.ignores("/srv/data/app/target/x.o", false));
+        assert!(ignores.ignores("/srv/data/target", true));
+        assert!(!ignores.ignores("/srv/data/target", false));
+        assert!(ignores.ignores("/srv/data/logs/a.log", false));
+        assert!(!ignores.ignores("/srv/data/logs/keep.log", false));
+        assert!(ignores.ignores("/srv/data/cache/v1/a.bin", false));
+        assert!(!ignores.ignores("/srv/data/app/cache/a.bin", false));
+        // Not included again below an ignored directory
+        assert!(ignores.ignores("/srv/data/build/a.txt", false));
+        assert!(!ignores.ignores("/srv/data/a.txt", false));
+        assert!(!ignores.ignores("/srv/other/a.log", false));
+
+        // The size differs, picked up even within the modification time's granularity
+        fs::write(&path, "*.txt\n").unwrap();
+        ignores.reload();
+        assert!(ignores.ignores("/srv/data/a.txt", false));
+        assert!(!ignores.ignores("/srv/data/logs/a.log", false));
+
+        fs::remove_file(&path).unwrap();
+        ignores.reload();
+        assert!(!ignores.ignores("/srv/data/a.txt", false));
+    }
+}
@@ -0,0 +1,189 @@
+//! Correlates the hard links of a file: the names of a file with more than one link are
+//! kept by its device and inode number, and every event of one of them is reported with
+//! the others. Only the names the watcher has seen an event of are known, the links
+//! outside of the watched directories are not searched for.
+
+use crate::event::{Event, EventType, FileKind};
+use std::collections::{BTreeSet, HashMap};
+
+/// The known names of the files with hard links, by device and inode number.
+#[derive(Debug, Default)]
+pub struct Links {
+    names: HashMap<(u64, u64), BTreeSet<String>>,
+    // Device and inode number of each known name
+    files: HashMap<String, (u64, u64)>,
+}
+
+impl Links {
+    /// Records the names of the events, in order, and fills in their `hard_links`.
+    pub fn correlate(&mut self, mut events: Vec<Event>) -> Vec<Event> {
+        for event in events.iter_mut() {
+            self.follow(event);
+        }
+        events
+    }
+
+    fn follow(&mut self, event: &mut Event) {
+        if event.kind != FileKind::File {
+            return;
+        }
+        match &event.event_type {
+            // Still the file's other names, if it had any
+            EventType::Deleted | EventType::MovedFrom => {
+                event.hard_links = self.others(&event.file_path);
+                self.forget(&event.file_path);
+                return;
+            }
+            EventType::Renamed { from, .. } => {
+                let from = from.clone();
+                self.forget(&from);
+            }
+            _ => {}
+        }
+        let file = event
+            .file_info
+            .as_ref()
+            .and_then(|info| Some((info.device?, info.inode?, info.nlink?)));
+        match file {
+            Some((device, inode, nlink)) if nlink > 1 => {
+                let key = (device, inode);
+                // Replaced by another file under the same name
+                if self
+                    .files
+                    .get(&event.file_path)
+                    .is_some_and(|known| *known != key)
+                {
+                    self.forget(&event.file_path);
+                }
+                self.files
+                    .insert(event.file_path.clone(), key);
+                self.names
+                    .entry(key)
+                    .or_default()
+                    .insert(event.file_path.clone());
+            }
+            // The last link left, or another file
+            _ => self.forget(&event.file_path),
+        }
+        event.hard_links = self.others(&event.file_path);
+    }
+
+    // The other known names of the file
+    fn others(&self, file_path: &str) -> Vec<String> {
+        let Some(key) = self.files.get(file_path) else {
+            return Vec::new();
+        };
+        self.names
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter(|name| *name != file_path)
+            .cloned()
+            .collect()
+    }
+
+    fn forget(&mut self, file_path: &str) {
+        let Some(key) = self.files.remove(file_path) else {
+            return;
+        };
+        if let Some(names) = self.names.get_mut(&key) {
+            names.remove(file_path);
+            if names.is_empty() {
+                self.names.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::FileInfo;
+
+    fn event(path: &str, event_type: EventType, link: Option<(u64, u64)>) -> Event {
+        let file_info = link.map(|(inode, nlink)| FileInfo {
+            access_ts: None,
+            modify_ts: None,
+            created_ts: None,
+            size: 1,
+            mode: 0o100644,
+            uid: None,
+            gid: None,
+            user: None,
+            group: None,
+            xattrs: Vec::new(),
+            is_symlink: false,
+            link_target: None,
+            inode: Some(inode),
+            device: Some(2049),
+            nlink: Some(nlink),
+            file_type: None,
+        });
+        Event {
+            file_info,
+            ..Event::new(path, event_type)
+        }
+    }
+
+    #[test]
+    fn test_hard_links_correlated() {
+        let mut links = Links::default();
+        let hard_links = |events: Vec<Event>| -> Vec<Vec<String>> {
+            events
+                .into_iter()
+                .map(|e| e.hard_links)
+                .collect()
+        };
+
+        let events = links.correlate(vec![
+            event("/srv/a", EventType::Created, Some((7, 1))),
+            event("/srv/b", EventType::Created, Some((7, 2))),
+            event("/srv/a", EventType::Written, Some((7, 2))),
+            event("/srv/c", EventType::Created, Some((7, 3))),
+        ]);
+        assert_eq!(
+            hard_links(events),
+            vec![
+                vec![],
+                vec![],
+                vec!["/srv/b".to_string()],
+                vec!["/srv/a".to_string(), "/srv/b".to_string()],
+            ]
+        );
+
+        // The other names of a deleted link, and a rename keeping the link
+        let events = links.correlate(vec![
+            event("/srv/c", EventType::Deleted, None),
+            event(
+                "/srv/d",
+                EventType::Renamed {
+                    from: "/srv/b".to_string(),
+                    to: "/srv/d".to_string(),
+                },
+                Some((7, 2)),
+            ),
+        ]);
+        assert_eq!(
+            hard_links(events),
+            vec![
+                vec!["/srv/a".to_string(), "/srv/b".to_string()],
+                vec!["/srv/a".to_string()],
+            ]
+        );
+
+        // The last link left, and a name taken by another file
+        let events = links.correlate(vec![
+            event("/srv/d", EventType::Written, Some((8, 2))),
+            event("/srv/a", EventType::Written, Some((7, 1))),
+        ]);
+        assert_eq!(hard_links(events), vec![Vec::<String>::new(), vec![]]);
+        assert!(
+            links
+                .files
+                .contains_key("/srv/d")
+                && !links
+                    .files
+                    .contains_key("/srv/a")
+        );
+    }
+}
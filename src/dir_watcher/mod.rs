@@ -1,211 +1,1371 @@
+#[cfg(target_os = "linux")]
+use crate::config::EventSource;
+use crate::config::WatcherConfig;
 use crate::error::Error;
-use crate::event::{self, Event, EventType, FileInfo};
+use crate::event::{Event, FileInfo};
+#[cfg(target_os = "linux")]
+use crate::event::{EventType, FileKind};
+#[cfg(target_os = "linux")]
+use crate::replay::Recorder;
+#[cfg(target_os = "linux")]
+use crate::supervisor::Shutdown;
+use async_trait::async_trait;
+pub use baseline::existing_files;
+#[cfg(target_os = "linux")]
+pub use control::WatchController;
+#[cfg(target_os = "linux")]
+use control::{Control, Request};
+#[cfg(target_os = "linux")]
+use files::Files;
+#[cfg(target_os = "linux")]
+use futures_util::{FutureExt, Stream, StreamExt};
+pub use glob::{preset, GlobFilter, PRESETS};
+pub use ignore::IgnoreFiles;
+#[cfg(target_os = "linux")]
+use links::Links;
+pub use native::NotifyWatcher;
+pub use poll::PollWatcher;
+#[cfg(target_os = "linux")]
+use recursive::{is_out_of_watches, Tree};
+pub use rename::{Renames, RENAME_WINDOW};
+pub use state::{keep_state, resumed_events, save_state};
+pub use stats::{report_stats, Stats, WatchStats};
+#[cfg(target_os = "linux")]
+use std::ffi::OsStr;
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
-use std::time::SystemTime;
-use std::{collections::HashMap, io, sync::mpsc};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+#[cfg(target_os = "linux")]
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+use tokio::sync::mpsc;
+#[cfg(target_os = "linux")]
+use tokio::task::JoinHandle;
 
+mod baseline;
+#[cfg(target_os = "linux")]
+mod control;
+#[cfg(target_os = "linux")]
+mod files;
+mod glob;
+mod ignore;
+mod links;
+mod native;
+mod owner;
+mod poll;
+#[cfg(target_os = "linux")]
+mod recursive;
+mod rename;
+mod state;
+mod stats;
+
+// Upper limit of inotify events handled together, the events read at once
+// share the file metadata lookups. The kernel's default queue length,
+// fs.inotify.max_queued_events, so that a batch drains the queue.
+#[cfg(target_os = "linux")]
+const MAX_EVENTS_BATCH: usize = 16384;
+
+/// Bytes the inotify events are read into at once, fits more than a hundred events
+/// with the longest names.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+/// Fits an event with the longest name, 16 bytes of its header and NAME_MAX + 1 of the name.
+pub const MIN_BUFFER_SIZE: usize = 16 + 256;
+
+/// Source of the events of the watched directories: inotify, the platform's native API,
+/// polling or the audit log.
+#[async_trait]
+pub trait WatchBackend: Send {
+    /// Name used in the logs.
+    fn name(&self) -> &str;
+
+    /// Reports the events until the backend fails or the receiver is gone.
+    async fn run(self: Box<Self>, tx: mpsc::Sender<Event>) -> Result<(), Error>;
+}
+
+#[cfg(target_os = "linux")]
 pub struct DirWatcher {
     inotify: inotify::Inotify,
-    dir: String,
+    watched: Watched,
+    filter: GlobFilter,
+    recorder: Option<Recorder>,
+    // Pairs the halves of the renames if the renamed events are reported
+    renames: Renames,
+    buffer_size: usize,
+    // Stops the watcher once requested, after sending the pending events
+    shutdown: Option<Shutdown>,
+    // The directories out of inotify watches are polled this often if set
+    poll_fallback: Option<Duration>,
+    event_types: Vec<EventType>,
+    stats: WatchStats,
+    // Requests of the controllers, handled while running
+    control_tx: mpsc::UnboundedSender<Request>,
+    control_rx: mpsc::UnboundedReceiver<Request>,
+}
+
+// The inotify watches of the directories
+#[cfg(target_os = "linux")]
+struct Watched {
+    watches: inotify::Watches,
+    mask: inotify::WatchMask,
+    // Watched directory of each watch, needed to build the file paths
+    dirs: HashMap<inotify::WatchDescriptor, String>,
+    // Set in the recursive mode
+    tree: Option<Tree>,
+    // The files watched on their own
+    files: Files,
+}
+
+#[cfg(target_os = "linux")]
+impl Watched {
+    // Watches the path, the events are reported in the directory `dir`, or in the file
+    // if it's a file
+    fn add(&mut self, path: &str, dir: &str) -> Result<(), Error> {
+        if fs::metadata(path).is_ok_and(|metadata| !metadata.is_dir()) {
+            return self
+                .files
+                .add(&mut self.dirs, path, dir)
+                .map_err(|e| match is_out_of_watches(&e) {
+                    true => watch_limit(self.dirs.len(), self.dirs.len() + 1),
+                    false => e.into(),
+                });
+        }
+        match self.tree.as_mut() {
+            // The entries already there aren't new
+            Some(tree) => {
+                tree.add(&mut self.dirs, path, dir, 0)?;
+            }
+            None => {
+                let wd = self
+                    .watches
+                    .add(path, self.mask)
+                    .map_err(|e| match is_out_of_watches(&e) {
+                        true => watch_limit(self.dirs.len(), self.dirs.len() + 1),
+                        false => e.into(),
+                    })?;
+                self.dirs
+                    .insert(wd, dir.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Unwatches the directory, as reported, with its subdirectories if recursive,
+    // or the file
+    fn remove(&mut self, dir: &str) -> Result<(), Error> {
+        if self
+            .files
+            .remove(&mut self.dirs, dir)
+        {
+            return Ok(());
+        }
+        let removed = match self.tree.as_mut() {
+            Some(tree) => tree.remove(&mut self.dirs, dir),
+            None => {
+                let removed: Vec<_> = self
+                    .dirs
+                    .iter()
+                    .filter(|(_, watched)| Path::new(watched) == Path::new(dir))
+                    .map(|(wd, _)| wd.clone())
+                    .collect();
+                for wd in removed.iter() {
+                    // Fails if the directory is gone meanwhile, the watch with it
+                    let _ = self
+                        .watches
+                        .remove(wd.clone());
+                    self.dirs.remove(wd);
+                }
+                removed.len()
+            }
+        };
+        match removed {
+            0 => Err(Error::Watcher(format!("{} is not watched", dir))),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&mut self, control: Control) -> Result<(), Error> {
+        match control {
+            Control::Add(path) => {
+                self.add(&path, &path)?;
+                println!("Watching {} too", path);
+            }
+            Control::Remove(dir) => {
+                self.remove(&dir)?;
+                println!("No longer watching {}", dir);
+            }
+            // The running watcher holds the filter, it replaces it itself
+            Control::Filter(_) => unreachable!("Replaced by the running watcher"),
+        }
+        Ok(())
+    }
 }
 
+#[cfg(target_os = "linux")]
 impl DirWatcher {
-    pub fn new(directory: &str, event_types: Vec<EventType>) -> Result<DirWatcher, Error> {
-        let watch_mask = event_types_to_watch_mask(event_types);
+    pub fn new(directories: &[String], event_types: Vec<EventType>) -> Result<DirWatcher, Error> {
+        let dirs: Vec<(&str, &str)> = directories
+            .iter()
+            .map(|dir| (dir.as_str(), dir.as_str()))
+            .collect();
+        DirWatcher::watch(&dirs, event_types, 0)?.checked()
+    }
+
+    /// Watches the subdirectories too, including those created while running.
+    pub fn new_recursive(
+        directories: &[String],
+        event_types: Vec<EventType>,
+    ) -> Result<DirWatcher, Error> {
+        DirWatcher::new_recursive_to(directories, event_types, usize::MAX)
+    }
+
+    /// Watches the subdirectories too, down to `max_depth` levels below the directories.
+    pub fn new_recursive_to(
+        directories: &[String],
+        event_types: Vec<EventType>,
+        max_depth: usize,
+    ) -> Result<DirWatcher, Error> {
+        let dirs: Vec<(&str, &str)> = directories
+            .iter()
+            .map(|dir| (dir.as_str(), dir.as_str()))
+            .collect();
+        DirWatcher::watch(&dirs, event_types, max_depth)?.checked()
+    }
+
+    // Watches the first path of each pair, the events are reported in the second one,
+    // and `depth` levels of their subdirectories
+    fn watch(
+        dirs: &[(&str, &str)],
+        event_types: Vec<EventType>,
+        depth: usize,
+    ) -> Result<DirWatcher, Error> {
+        let renames = Renames::for_types(&event_types);
+        let watch_mask = event_types_to_watch_mask(event_types.clone());
         if watch_mask.is_empty() {
-            return Err(Error::new("No known event types found in event_types"));
+            return Err(Error::Watcher(
+                "No known event types found in event_types".to_string(),
+            ));
         }
 
         let inotify = inotify::Inotify::init()?;
 
-        inotify
-            .watches()
-            .add(directory, watch_mask)?;
+        let mut watched = Watched {
+            watches: inotify.watches(),
+            mask: watch_mask,
+            dirs: HashMap::new(),
+            tree: (depth > 0).then(|| Tree::new(inotify.watches(), watch_mask, depth)),
+            files: Files::new(inotify.watches(), watch_mask),
+        };
+        for (path, directory) in dirs {
+            watched.add(path, directory)?;
+        }
 
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
         Ok(DirWatcher {
             inotify,
-            dir: directory.to_string(),
+            watched,
+            filter: GlobFilter::default(),
+            recorder: None,
+            renames,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            shutdown: None,
+            poll_fallback: None,
+            event_types,
+            stats: WatchStats::default(),
+            control_tx,
+            control_rx,
         })
     }
 
-    pub fn run_blocking(&mut self, tx: mpsc::Sender<Event>) -> Result<(), Error> {
-        loop {
-            // Read events from inotify
-            let mut buffer = [0; 1024];
-            let events = self
-                .inotify
-                .read_events_blocking(&mut buffer)?;
-
-            // Extract them and enrich with file metadata
-            let events = self.extract_events(events)?;
-
-            // Send events to the listener
-            for event in events {
-                let _ = tx
-                    .send(event.clone())
-                    .and_then(|_| {
-                        // println!("Event reported: {} {:?}", event.event_type, event.file_path);
-                        Ok(())
-                    })
-                    .is_err_and(|e| {
-                        println!(
-                            "Failed to send event of a file: {:?}, reason: {}",
-                            event.file_path, e
-                        );
-                        false
-                    });
+    // Fails if the directories couldn't all be watched for the lack of inotify watches
+    fn checked(self) -> Result<DirWatcher, Error> {
+        match self.watched.tree.as_ref() {
+            Some(tree) if tree.count_unwatched() > 0 => {
+                let watched = self.watched.dirs.len();
+                Err(watch_limit(watched, watched + tree.count_unwatched()))
             }
+            _ => Ok(self),
         }
     }
 
-    // Inotify event is a mask, which means that potentially more events
-    // are encoded within one inotify::Event. We want to create a separate
-    // event for each of them. Stat of a file should be checked just once for each file
-    // every time when `inotify.read_events_blocking` returns,
-    // so every time when this function is called.
-    fn extract_events(&self, inotify_events: inotify::Events) -> Result<Vec<Event>, Error> {
-        let mut file_infos: HashMap<String, FileInfo> = HashMap::new();
-        let mut ret_events = Vec::new();
-
-        for ie in inotify_events {
-            let file_path = match ie.name {
-                // We care only about the events with associated file names
-                None => continue,
-                Some(n) => std::path::Path::new(&self.dir)
-                    .join(
-                        n.to_string_lossy()
-                            .to_string(),
-                    )
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            };
+    /// Watches the directory, or the file, too, its events reported with its path.
+    /// Once running, through the [Self::controller].
+    pub fn add_path(&mut self, path: &str) -> Result<(), Error> {
+        self.watched
+            .apply(Control::Add(path.to_string()))
+    }
 
-            let event_types = match EventType::from_event_mask(ie.mask) {
-                // No known events found
-                None => continue,
-                Some(et) => et,
-            };
+    /// Stops watching the directory, as reported, and its subdirectories if recursive,
+    /// or the file. Once running, through the [Self::controller].
+    pub fn remove_path(&mut self, path: &str) -> Result<(), Error> {
+        self.watched
+            .apply(Control::Remove(path.to_string()))
+    }
 
-            // Now we get the file metadata, if not present in the map
-            let file_info = match file_infos.get(&file_path) {
-                Some(fi) => Some(fi.clone()),
-                None => match self.read_file_metadata(&file_path) {
-                    Err(e) => {
-                        if !e
-                            .io_kind()
-                            // To skip errors reported by potentially deleted files
-                            .is_some_and(|e| e == io::ErrorKind::NotFound)
-                        {
-                            println!("Failed to read file info of a file {}: {}", file_path, e);
+    /// Handle changing the watched paths while running. The changes requested before
+    /// the watcher runs are applied once it starts.
+    pub fn controller(&self) -> WatchController {
+        WatchController::new(self.control_tx.clone())
+    }
+
+    /// Handle reading the watcher's statistics, while it runs and after.
+    pub fn stats(&self) -> WatchStats {
+        self.stats.clone()
+    }
+
+    /// Reports only the files passing the glob patterns.
+    pub fn with_filter(mut self, filter: GlobFilter) -> DirWatcher {
+        self.filter = filter;
+        self
+    }
+
+    /// Records the raw inotify events read, for the replays.
+    pub fn with_recorder(mut self, recorder: Recorder) -> DirWatcher {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Reads the events into a buffer of `size` bytes, at least [MIN_BUFFER_SIZE],
+    /// [DEFAULT_BUFFER_SIZE] if not set.
+    pub fn with_buffer_size(mut self, size: usize) -> DirWatcher {
+        self.buffer_size = size.max(MIN_BUFFER_SIZE);
+        self
+    }
+
+    /// Polls the directories which couldn't be watched for the lack of inotify watches
+    /// every `interval`, with their subdirectories. Only the created, written and deleted
+    /// files are reported there, see [PollWatcher].
+    pub fn with_poll_fallback(mut self, interval: Duration) -> DirWatcher {
+        self.poll_fallback = Some(interval);
+        self
+    }
+
+    /// Stops once the `shutdown` is requested: the events already queued by the kernel
+    /// are read and sent, the watches dropped, and the moved from events waiting for
+    /// their moved to events sent as they are.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> DirWatcher {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// The directories inside the containers are watched in their upper directories.
+    pub fn from_config(config: &WatcherConfig) -> Result<DirWatcher, Error> {
+        let dirs = watched_dirs(config);
+        let filter = config_filter(config, &dirs);
+        let mut watcher = DirWatcher::watch(&dirs, config.events.clone(), watched_depth(config))?
+            .with_filter(filter);
+        for file in config.files.iter() {
+            watcher
+                .watched
+                .add(file, file)?;
+        }
+        watcher = match config.poll_fallback {
+            Some(interval) => watcher.with_poll_fallback(interval),
+            None => watcher.checked()?,
+        };
+        if let EventSource::Inotify(buffer_size) = config.source {
+            watcher = watcher.with_buffer_size(buffer_size);
+        }
+        match config.record.as_ref() {
+            Some(path) => Ok(watcher.with_recorder(Recorder::create(path)?)),
+            None => Ok(watcher),
+        }
+    }
+
+    pub async fn run(self, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        WatchBackend::run(Box::new(self), tx).await
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl WatchBackend for DirWatcher {
+    fn name(&self) -> &str {
+        "inotify"
+    }
+
+    async fn run(self: Box<Self>, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        // The controllers' requests end once all of them are dropped
+        let DirWatcher {
+            inotify,
+            mut watched,
+            mut filter,
+            mut recorder,
+            mut renames,
+            buffer_size,
+            shutdown,
+            poll_fallback,
+            event_types,
+            stats,
+            mut control_rx,
+            ..
+        } = *self;
+
+        let mut stream = inotify.into_event_stream(vec![0; buffer_size])?;
+        let poll = |watched: &mut Watched, filter: &GlobFilter| {
+            poll_unwatched(watched, poll_fallback, &event_types, filter, &tx)
+        };
+        let mut polling = poll(&mut watched, &filter);
+        let mut links = Links::default();
+        stats.watching(watched.dirs.len());
+
+        loop {
+            let deadline = renames.deadline();
+            let (events, stopping) = tokio::select! {
+                event = stream.next() => match event {
+                    Some(event) => (drain(&mut stream, vec![event]), false),
+                    None => break,
+                },
+                // The last batch is what the kernel queued until now
+                _ = requested(shutdown.as_ref()) => (drain(&mut stream, Vec::new()), true),
+                Some((control, reply)) = control_rx.recv() => {
+                    let _ = reply.send(match control {
+                        Control::Filter(new) => {
+                            filter = new;
+                            Ok(())
                         }
-                        None
-                    }
-                    Ok(fi) => {
-                        // We want to store it in our map, maybe there are more events
-                        // associated with this file in the input events.
-                        file_infos.insert(file_path.clone(), fi.clone());
-                        Some(fi)
+                        control => watched.apply(control),
+                    });
+                    polling.extend(poll(&mut watched, &filter));
+                    stats.watching(watched.dirs.len());
+                    continue;
+                }
+                // The moved from events not paired in time
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()),
+                    if deadline.is_some() =>
+                {
+                    send_events(&tx, links.correlate(renames.expired(Instant::now()))).await;
+                    continue;
+                }
+            };
+            let Watched {
+                dirs, tree, files, ..
+            } = &mut watched;
+            let events = events
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            let read = events.len();
+            if events.iter().any(|event| {
+                event
+                    .mask
+                    .contains(inotify::EventMask::Q_OVERFLOW)
+            }) {
+                println!(
+                    "The inotify queue overflowed, events were lost, \
+                     raise fs.inotify.max_queued_events"
+                );
+                stats.overflowed();
+            }
+            let events = files.named(dirs, events);
+            if let Some(r) = recorder.as_mut() {
+                if let Err(e) = r.record(dirs, &events) {
+                    println!("Failed to record the inotify events: {}", e);
+                }
+            }
+
+            // Before the extraction, the batch may have changed an ignore file
+            filter.reload();
+
+            // Extract them and enrich with file metadata
+            let mut filtered = 0;
+            let events = match tree.as_mut() {
+                None => extract_counted(dirs, events, &filter, &mut renames, &mut filtered)?,
+                Some(tree) => {
+                    let reported = events
+                        .iter()
+                        .map(|event| tree.reported(event))
+                        .collect();
+                    let mut extracted =
+                        extract_counted(dirs, reported, &filter, &mut renames, &mut filtered)?;
+                    // The new directories may have entries already
+                    for file_path in tree
+                        .update(dirs, &events)
+                        .into_iter()
+                        .filter(|path| filter.matches(path))
+                    {
+                        let file_info = read_file_info(&file_path);
+                        extracted.push(Event {
+                            kind: FileKind::of(file_info.as_ref()),
+                            captured_at: capture_time(),
+                            file_info,
+                            ..Event::new(file_path, EventType::Created)
+                        });
                     }
-                },
+                    extracted
+                }
             };
 
-            for event_type in event_types {
-                // Enrich with metadata only if the event type is not 'Deleted'
-                let file_info = match event_type {
-                    event::EventType::Deleted => None,
-                    _ => file_info.clone(),
-                };
+            polling.extend(poll(&mut watched, &filter));
+            stats.read(read, filtered);
+            stats.watching(watched.dirs.len());
 
-                ret_events.push(Event {
-                    event_type: event_type,
-                    file_path: file_path.clone(),
-                    solana_ts_received_at: 0, // filled in by the listener
-                    file_info: file_info,
-                });
+            send_events(&tx, links.correlate(renames.expired(Instant::now()))).await;
+            send_events(&tx, links.correlate(events)).await;
+            if stopping {
+                break;
             }
         }
-        Ok(ret_events)
+        for handle in polling {
+            handle.abort();
+        }
+        // Closes the inotify instance, the kernel drops its watches
+        drop(stream);
+        drop(watched);
+        // Moved out for good
+        send_events(&tx, links.correlate(renames.drain())).await;
+        Ok(())
     }
+}
 
-    fn read_file_metadata(&self, file_path: &str) -> Result<FileInfo, Error> {
-        let metadata = fs::metadata(file_path)?;
+// Adds the events read until the kernel's queue is empty, before their metadata is looked up
+#[cfg(target_os = "linux")]
+fn drain<S: Stream + Unpin>(stream: &mut S, mut events: Vec<S::Item>) -> Vec<S::Item> {
+    while events.len() < MAX_EVENTS_BATCH {
+        match stream.next().now_or_never() {
+            Some(Some(event)) => events.push(event),
+            // Nothing ready, or the stream ended, seen by the next read
+            _ => break,
+        }
+    }
+    events
+}
 
-        let to_unix_ts = |t: std::time::SystemTime| -> i128 {
-            match t.duration_since(SystemTime::UNIX_EPOCH) {
-                Ok(n) => n.as_secs().into(),
-                Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-            }
-        };
+// Polls the directories left unwatched for the lack of inotify watches, if configured
+#[cfg(target_os = "linux")]
+fn poll_unwatched(
+    watched: &mut Watched,
+    fallback: Option<Duration>,
+    event_types: &[EventType],
+    filter: &GlobFilter,
+    tx: &mpsc::Sender<Event>,
+) -> Vec<JoinHandle<()>> {
+    let Some(tree) = watched.tree.as_mut() else {
+        return Vec::new();
+    };
+    let unwatched = tree.take_unwatched();
+    if unwatched.is_empty() {
+        return Vec::new();
+    }
+    let Some(interval) = fallback else {
+        println!(
+            "Out of inotify watches, {} directories and their subdirectories are not watched, \
+             raise fs.inotify.max_user_watches",
+            unwatched.len()
+        );
+        return Vec::new();
+    };
+    println!(
+        "Out of inotify watches, polling {} directories every {:?} instead",
+        unwatched.len(),
+        interval
+    );
 
-        Ok(FileInfo {
-            size: metadata.len(),
-            mode: metadata.permissions().mode(),
-            access_ts: metadata
-                .accessed()
-                .ok()
-                .map(|t| to_unix_ts(t)),
-            modify_ts: metadata
-                .modified()
-                .ok()
-                .map(|t| to_unix_ts(t)),
-            created_ts: metadata
-                .created()
-                .ok()
-                .map(|t| to_unix_ts(t)),
+    // Those at the same level are listed as deep
+    let mut levels: BTreeMap<usize, Vec<(String, String)>> = BTreeMap::new();
+    for (path, dir, depth) in unwatched {
+        levels
+            .entry(depth)
+            .or_default()
+            .push((path, dir));
+    }
+    let max_depth = tree.max_depth();
+    levels
+        .into_iter()
+        .map(|(depth, dirs)| {
+            let (event_types, filter, tx) = (event_types.to_vec(), filter.clone(), tx.clone());
+            tokio::spawn(async move {
+                let dirs: Vec<(&str, &str)> = dirs
+                    .iter()
+                    .map(|(path, dir)| (path.as_str(), dir.as_str()))
+                    .collect();
+                let watcher = match PollWatcher::new(&dirs, true, interval, event_types) {
+                    Ok(watcher) => watcher
+                        .with_max_depth(max_depth - depth)
+                        .with_filter(filter),
+                    Err(e) => {
+                        println!("Failed to poll the unwatched directories: {}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = WatchBackend::run(Box::new(watcher), tx).await {
+                    println!("Failed to poll the unwatched directories: {}", e);
+                }
+            })
         })
+        .collect()
+}
+
+// The limit error, with the user's limit if it can be read
+#[cfg(target_os = "linux")]
+fn watch_limit(watched: usize, needed: usize) -> Error {
+    let limit = fs::read_to_string("/proc/sys/fs/inotify/max_user_watches")
+        .ok()
+        .and_then(|limit| limit.trim().parse().ok());
+    Error::WatchLimit {
+        watched,
+        needed,
+        limit,
     }
 }
 
-impl EventType {
-    fn from_event_mask(mask: inotify::EventMask) -> Option<Vec<EventType>> {
-        let mut ret = Vec::with_capacity(1);
-        if mask.contains(inotify::EventMask::ATTRIB) {
-            ret.push(EventType::AttributeChanged);
-        }
-        if mask.contains(inotify::EventMask::CREATE) {
-            ret.push(EventType::Created);
-        }
-        if mask.contains(inotify::EventMask::DELETE) {
-            ret.push(EventType::Deleted);
+// Resolves once the shutdown is requested, never without one
+#[cfg(target_os = "linux")]
+async fn requested(shutdown: Option<&Shutdown>) {
+    match shutdown {
+        Some(shutdown) => shutdown.requested().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Sends the events to the listener, waits for room while its queue is full
+#[cfg(target_os = "linux")]
+async fn send_events(tx: &mpsc::Sender<Event>, events: Vec<Event>) {
+    for event in events {
+        let file_path = event.file_path.clone();
+        if let Err(e) = tx.send(event).await {
+            println!(
+                "Failed to send event of a file: {:?}, reason: {}",
+                file_path, e
+            );
         }
-        if mask.contains(inotify::EventMask::MOVED_FROM) {
-            ret.push(EventType::MovedFrom);
+    }
+}
+
+impl PollWatcher {
+    /// [DirWatcher::from_config] polling every `interval` instead.
+    pub fn from_config(config: &WatcherConfig, interval: Duration) -> Result<PollWatcher, Error> {
+        let dirs = watched_dirs(config);
+        let filter = config_filter(config, &dirs);
+        Ok(
+            PollWatcher::new(&dirs, config.recursive, interval, config.events.clone())?
+                .with_max_depth(watched_depth(config))
+                .with_filter(filter),
+        )
+    }
+}
+
+impl NotifyWatcher {
+    /// [DirWatcher::from_config] through the platform's native API.
+    pub fn from_config(config: &WatcherConfig) -> Result<NotifyWatcher, Error> {
+        let dirs = watched_dirs(config);
+        let filter = config_filter(config, &dirs);
+        Ok(NotifyWatcher::new(&dirs, config.recursive, config.events.clone())?.with_filter(filter))
+    }
+}
+
+/// The files existing in the watched directories, reported as created, see [baseline::scan].
+pub fn baseline_events(config: &WatcherConfig) -> Vec<Event> {
+    let dirs = watched_dirs(config);
+    let filter = config_filter(config, &dirs);
+    baseline::scan(&dirs, watched_depth(config), &filter)
+}
+
+// Levels of subdirectories watched below the watched directories
+fn watched_depth(config: &WatcherConfig) -> usize {
+    match config.recursive {
+        true => config
+            .max_depth
+            .unwrap_or(usize::MAX),
+        false => 0,
+    }
+}
+
+// Pairs the watched path of each directory with the reported one
+fn watched_dirs(config: &WatcherConfig) -> Vec<(&str, &str)> {
+    config
+        .dirs
+        .iter()
+        .map(|dir| match config
+            .containers
+            .as_ref()
+            .and_then(|c| c.overlay(dir))
+        {
+            Some(overlay) if Path::new(&overlay.upper).is_dir() => {
+                println!("Watching {} in the upper directory {}", dir, overlay.upper);
+                (overlay.upper.as_str(), dir.as_str())
+            }
+            // Not copied up yet while only in the image's layers
+            Some(overlay) => {
+                println!(
+                    "{} is not in the upper directory {} yet, the changes made outside the container are missed",
+                    dir, overlay.upper
+                );
+                (dir.as_str(), dir.as_str())
+            }
+            None => (dir.as_str(), dir.as_str()),
+        })
+        .collect()
+}
+
+fn config_filter(config: &WatcherConfig, dirs: &[(&str, &str)]) -> GlobFilter {
+    match config.ignore_file.as_ref() {
+        Some(name) => config
+            .filter
+            .clone()
+            .with_ignore_files(IgnoreFiles::new(dirs, name)),
+        None => config.filter.clone(),
+    }
+}
+
+/// Turns a batch of inotify events into [Event]s, `dirs` maps the watches
+/// to the watched directories. The files not passing the `filter` are skipped.
+// Inotify event is a mask, which means that potentially more events
+// are encoded within one inotify::Event. We want to create a separate
+// event for each of them. Stat of a file should be checked just once for each file
+// every time when the inotify stream returns a batch,
+// so every time when this function is called.
+#[cfg(target_os = "linux")]
+pub fn extract_events<S: AsRef<OsStr>>(
+    dirs: &HashMap<inotify::WatchDescriptor, String>,
+    inotify_events: Vec<inotify::Event<S>>,
+    filter: &GlobFilter,
+    renames: &mut Renames,
+) -> Result<Vec<Event>, Error> {
+    extract_counted(dirs, inotify_events, filter, renames, &mut 0)
+}
+
+// Counts the events of the files not passing the filter in `filtered`
+#[cfg(target_os = "linux")]
+fn extract_counted<S: AsRef<OsStr>>(
+    dirs: &HashMap<inotify::WatchDescriptor, String>,
+    inotify_events: Vec<inotify::Event<S>>,
+    filter: &GlobFilter,
+    renames: &mut Renames,
+    filtered: &mut u64,
+) -> Result<Vec<Event>, Error> {
+    extract_filtered(
+        inotify_events
+            .iter()
+            .filter_map(|ie| {
+                let dir = dirs.get(&ie.wd)?;
+                Some((dir.as_str(), ie.mask, ie.cookie, ie.name.as_ref()?.as_ref()))
+            }),
+        filter,
+        read_file_metadata,
+        renames,
+        Instant::now(),
+        filtered,
+    )
+}
+
+/// Wall-clock time of the capture of an event, unix milliseconds by the system clock.
+pub fn capture_time() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+/// [extract_events] of the events already resolved to their watched directory,
+/// `(dir, mask, cookie, name)`, e.g. the recorded ones, read `now`, with the metadata
+/// from `read_metadata`.
+#[cfg(target_os = "linux")]
+pub fn extract_named<'a>(
+    inotify_events: impl IntoIterator<Item = (&'a str, inotify::EventMask, u32, &'a OsStr)>,
+    filter: &GlobFilter,
+    read_metadata: impl Fn(&str) -> Result<FileInfo, Error>,
+    renames: &mut Renames,
+    now: Instant,
+) -> Result<Vec<Event>, Error> {
+    extract_filtered(inotify_events, filter, read_metadata, renames, now, &mut 0)
+}
+
+#[cfg(target_os = "linux")]
+fn extract_filtered<'a>(
+    inotify_events: impl IntoIterator<Item = (&'a str, inotify::EventMask, u32, &'a OsStr)>,
+    filter: &GlobFilter,
+    read_metadata: impl Fn(&str) -> Result<FileInfo, Error>,
+    renames: &mut Renames,
+    now: Instant,
+    filtered: &mut u64,
+) -> Result<Vec<Event>, Error> {
+    let mut file_infos: HashMap<String, FileInfo> = HashMap::new();
+    let mut ret_events = Vec::new();
+    let captured_at = capture_time();
+
+    // We care only about the events with associated file names, the others are skipped
+    for (dir, mask, cookie, name) in inotify_events {
+        let file_path = std::path::Path::new(dir)
+            .join(
+                name.to_string_lossy()
+                    .to_string(),
+            )
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let event_types = match event_types_from_mask(mask) {
+            // No known events found
+            None => continue,
+            Some(et) => et,
+        };
+        // Before the metadata lookup, the noise costs nothing
+        if !filter.matches_entry(&file_path, mask.contains(inotify::EventMask::ISDIR)) {
+            *filtered += 1;
+            continue;
         }
-        if mask.contains(inotify::EventMask::MOVED_TO) {
-            ret.push(EventType::MovedTo);
+
+        // Now we get the file metadata, if not present in the map
+        let file_info = match file_infos.get(&file_path) {
+            Some(fi) => Some(fi.clone()),
+            None => match read_metadata(&file_path) {
+                Err(e) => {
+                    // To skip errors reported by potentially deleted files
+                    if !matches!(&e, Error::Io(e) if e.kind() == io::ErrorKind::NotFound) {
+                        println!("Failed to read file info of a file {}: {}", file_path, e);
+                    }
+                    None
+                }
+                Ok(fi) => {
+                    // We want to store it in our map, maybe there are more events
+                    // associated with this file in the input events.
+                    file_infos.insert(file_path.clone(), fi.clone());
+                    Some(fi)
+                }
+            },
+        };
+
+        // Known from the flag also when the directory is gone
+        let kind = match mask.contains(inotify::EventMask::ISDIR) {
+            true => FileKind::Dir,
+            false => FileKind::of(file_info.as_ref()),
+        };
+        // The sizes of the directories and the links say nothing, the gone files have none
+        if kind == FileKind::File
+            && file_info
+                .as_ref()
+                .is_some_and(|fi| !filter.matches_size(fi.size))
+        {
+            *filtered += 1;
+            continue;
         }
-        if mask.contains(inotify::EventMask::OPEN) {
-            ret.push(EventType::Opened);
+
+        for event_type in event_types {
+            // Enrich with metadata only if the event type is not 'Deleted'
+            let file_info = match event_type {
+                EventType::Deleted => None,
+                _ => file_info.clone(),
+            };
+
+            // Timestamped by the listener
+            let event = Event {
+                file_info,
+                kind,
+                captured_at,
+                ..Event::new(file_path.clone(), event_type)
+            };
+            // The moved from events wait for their moved to events
+            if let Some(event) = renames.pair(cookie, event, now) {
+                ret_events.push(event);
+            }
         }
-        if mask.contains(inotify::EventMask::CLOSE_WRITE) {
-            ret.push(EventType::Written);
+    }
+    Ok(ret_events)
+}
+
+/// Reads the [FileInfo] of the file from its metadata, of a symlink its own and its target's path.
+pub fn read_file_metadata(file_path: &str) -> Result<FileInfo, Error> {
+    let metadata = fs::symlink_metadata(file_path)?;
+    let is_symlink = metadata.is_symlink();
+    let link_target = match is_symlink {
+        true => Some(
+            fs::read_link(file_path)?
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        false => None,
+    };
+
+    let to_unix_ts = |t: std::time::SystemTime| -> i128 {
+        match t.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(n) => n.as_secs().into(),
+            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
         }
+    };
+
+    // Only the read-only flag outside of unix
+    #[cfg(unix)]
+    let mode = metadata.permissions().mode();
+    #[cfg(not(unix))]
+    let mode = match metadata
+        .permissions()
+        .readonly()
+    {
+        true => 0o444,
+        false => 0o644,
+    };
+    #[cfg(unix)]
+    let (uid, gid) = (Some(metadata.uid()), Some(metadata.gid()));
+    #[cfg(not(unix))]
+    let (uid, gid) = (None, None);
+    #[cfg(unix)]
+    let (inode, device, nlink) = (
+        Some(metadata.ino()),
+        Some(metadata.dev()),
+        Some(metadata.nlink()),
+    );
+    #[cfg(not(unix))]
+    let (inode, device, nlink) = (None, None, None);
 
-        if ret.len() > 0 {
-            return Some(ret);
+    Ok(FileInfo {
+        size: metadata.len(),
+        mode,
+        uid,
+        gid,
+        user: uid.and_then(owner::user_name),
+        group: gid.and_then(owner::group_name),
+        xattrs: Vec::new(),
+        is_symlink,
+        link_target,
+        inode,
+        device,
+        nlink,
+        file_type: None,
+        access_ts: metadata
+            .accessed()
+            .ok()
+            .map(|t| to_unix_ts(t)),
+        modify_ts: metadata
+            .modified()
+            .ok()
+            .map(|t| to_unix_ts(t)),
+        created_ts: metadata
+            .created()
+            .ok()
+            .map(|t| to_unix_ts(t)),
+    })
+}
+
+/// The file's inode number, told apart from the device's other files.
+#[cfg(unix)]
+pub fn inode(metadata: &fs::Metadata) -> u64 {
+    metadata.ino()
+}
+
+// Without inodes, a replaced file is told by its other metadata
+#[cfg(not(unix))]
+pub fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+/// [read_file_metadata] for the sources reporting a file when it may be gone already,
+/// a missing file has no [FileInfo], the other errors are logged.
+pub fn read_file_info(file_path: &str) -> Option<FileInfo> {
+    match read_file_metadata(file_path) {
+        Ok(fi) => Some(fi),
+        Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            println!("Failed to read file info of a file {}: {}", file_path, e);
+            None
         }
-        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn event_types_from_mask(mask: inotify::EventMask) -> Option<Vec<EventType>> {
+    let mut ret = Vec::with_capacity(1);
+    if mask.contains(inotify::EventMask::ATTRIB) {
+        ret.push(EventType::AttributeChanged);
+    }
+    if mask.contains(inotify::EventMask::CREATE) {
+        ret.push(EventType::Created);
+    }
+    if mask.contains(inotify::EventMask::DELETE) {
+        ret.push(EventType::Deleted);
+    }
+    if mask.contains(inotify::EventMask::MOVED_FROM) {
+        ret.push(EventType::MovedFrom);
+    }
+    if mask.contains(inotify::EventMask::MOVED_TO) {
+        ret.push(EventType::MovedTo);
+    }
+    if mask.contains(inotify::EventMask::OPEN) {
+        ret.push(EventType::Opened);
+    }
+    if mask.contains(inotify::EventMask::CLOSE_WRITE) {
+        ret.push(EventType::Written);
+    }
+    if mask.contains(inotify::EventMask::CLOSE_NOWRITE) {
+        ret.push(EventType::ReadClosed);
+    }
+    if mask.contains(inotify::EventMask::ACCESS) {
+        ret.push(EventType::Accessed);
     }
 
-    fn to_watch_mask(&self) -> inotify::WatchMask {
-        match self {
-            EventType::AttributeChanged => inotify::WatchMask::ATTRIB,
-            EventType::Created => inotify::WatchMask::CREATE,
-            EventType::Deleted => inotify::WatchMask::DELETE,
-            EventType::MovedFrom => inotify::WatchMask::MOVED_FROM,
-            EventType::MovedTo => inotify::WatchMask::MOVED_TO,
-            EventType::Opened => inotify::WatchMask::OPEN,
-            EventType::Written => inotify::WatchMask::CLOSE_WRITE,
-        }
+    if ret.len() > 0 {
+        return Some(ret);
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn to_watch_mask(event_type: &EventType) -> inotify::WatchMask {
+    match event_type {
+        EventType::AttributeChanged => inotify::WatchMask::ATTRIB,
+        EventType::Created => inotify::WatchMask::CREATE,
+        EventType::Deleted => inotify::WatchMask::DELETE,
+        EventType::MovedFrom => inotify::WatchMask::MOVED_FROM,
+        EventType::MovedTo => inotify::WatchMask::MOVED_TO,
+        EventType::Opened => inotify::WatchMask::OPEN,
+        EventType::Written => inotify::WatchMask::CLOSE_WRITE,
+        EventType::Renamed { .. } => inotify::WatchMask::MOVED_FROM | inotify::WatchMask::MOVED_TO,
+        EventType::ReadClosed => inotify::WatchMask::CLOSE_NOWRITE,
+        EventType::Accessed => inotify::WatchMask::ACCESS,
     }
 }
 
+#[cfg(target_os = "linux")]
 fn event_types_to_watch_mask(event_types: Vec<EventType>) -> inotify::WatchMask {
     let mut ret = inotify::WatchMask::empty();
     for et in event_types {
-        ret |= et.to_watch_mask();
+        ret |= to_watch_mask(&et);
     }
     ret
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn next_event(rx: &mut mpsc::Receiver<Event>) -> (String, EventType) {
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .ok()
+            .flatten()
+            .expect("file event not reported");
+        (event.file_path, event.event_type)
+    }
+
+    async fn next(rx: &mut mpsc::Receiver<Event>) -> String {
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .ok()
+            .flatten()
+            .expect("file event not reported");
+        assert_eq!(event.event_type, EventType::Created);
+        event.file_path
+    }
+
+    #[tokio::test]
+    async fn test_subdirectories_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        let watcher =
+            DirWatcher::new_recursive(std::slice::from_ref(&root), vec![EventType::Created])
+                .unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(async move { watcher.run(tx).await });
+
+        fs::write(dir.path().join("sub/a.txt"), "a").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/sub/a.txt", root));
+
+        fs::create_dir(dir.path().join("new")).unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/new", root));
+        fs::write(dir.path().join("new/b.txt"), "b").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/new/b.txt", root));
+
+        // Moved in with its content, the moves aren't reported
+        let outside = tempfile::tempdir().unwrap();
+        fs::create_dir(outside.path().join("inner")).unwrap();
+        fs::write(
+            outside
+                .path()
+                .join("inner/c.txt"),
+            "c",
+        )
+        .unwrap();
+        fs::rename(outside.path(), dir.path().join("moved")).unwrap();
+        let mut found = vec![next(&mut rx).await, next(&mut rx).await];
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                format!("{}/moved/inner", root),
+                format!("{}/moved/inner/c.txt", root)
+            ]
+        );
+        fs::write(
+            dir.path()
+                .join("moved/inner/d.txt"),
+            "d",
+        )
+        .unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/moved/inner/d.txt", root));
+    }
+
+    #[tokio::test]
+    async fn test_subdirectories_watched_to_max_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        fs::create_dir_all(dir.path().join("sub/deep")).unwrap();
+        let watcher =
+            DirWatcher::new_recursive_to(std::slice::from_ref(&root), vec![EventType::Created], 1)
+                .unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(async move { watcher.run(tx).await });
+
+        // Too deep, the next event is the one after it
+        fs::write(
+            dir.path()
+                .join("sub/deep/a.txt"),
+            "a",
+        )
+        .unwrap();
+        fs::write(dir.path().join("sub/b.txt"), "b").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/sub/b.txt", root));
+
+        // Reported, but not watched
+        fs::create_dir(dir.path().join("sub/new")).unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/sub/new", root));
+        fs::write(
+            dir.path()
+                .join("sub/new/c.txt"),
+            "c",
+        )
+        .unwrap();
+        fs::write(dir.path().join("d.txt"), "d").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/d.txt", root));
+    }
+
+    #[tokio::test]
+    async fn test_replaced_file_watched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir
+            .path()
+            .join("passwd")
+            .to_string_lossy()
+            .into_owned();
+        fs::write(&file, "root").unwrap();
+        fs::write(dir.path().join("other.txt"), "a").unwrap();
+        let watcher = DirWatcher::new(
+            std::slice::from_ref(&file),
+            vec![EventType::Written, EventType::Deleted],
+        )
+        .unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(async move { watcher.run(tx).await });
+
+        fs::write(&file, "root,alice").unwrap();
+        assert_eq!(
+            next_event(&mut rx).await,
+            (file.clone(), EventType::Written)
+        );
+
+        // Renamed over, the new file is watched instead, the rest of the directory isn't
+        fs::write(dir.path().join("passwd.new"), "root,bob").unwrap();
+        fs::rename(dir.path().join("passwd.new"), &file).unwrap();
+        assert_eq!(
+            next_event(&mut rx).await,
+            (file.clone(), EventType::Written)
+        );
+        fs::write(dir.path().join("other.txt"), "b").unwrap();
+        fs::write(&file, "root").unwrap();
+        assert_eq!(
+            next_event(&mut rx).await,
+            (file.clone(), EventType::Written)
+        );
+
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            next_event(&mut rx).await,
+            (file.clone(), EventType::Deleted)
+        );
+    }
+
+    #[test]
+    fn test_read_events_from_mask() {
+        assert_eq!(
+            event_types_from_mask(inotify::EventMask::ACCESS | inotify::EventMask::CLOSE_NOWRITE),
+            Some(vec![EventType::ReadClosed, EventType::Accessed])
+        );
+        assert_eq!(
+            event_types_to_watch_mask(vec![EventType::ReadClosed, EventType::Accessed]),
+            inotify::WatchMask::CLOSE_NOWRITE | inotify::WatchMask::ACCESS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paths_changed_while_running() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let path = |dir: &tempfile::TempDir| {
+            dir.path()
+                .to_str()
+                .unwrap()
+                .to_string()
+        };
+        let watcher = DirWatcher::new(&[path(&first)], vec![EventType::Created]).unwrap();
+        let controller = watcher.controller();
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(async move { watcher.run(tx).await });
+
+        controller
+            .add_path(&path(&second))
+            .await
+            .unwrap();
+        fs::write(second.path().join("a.txt"), "a").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/a.txt", path(&second)));
+
+        controller
+            .remove_path(&path(&first))
+            .await
+            .unwrap();
+        fs::write(first.path().join("b.txt"), "b").unwrap();
+        fs::write(second.path().join("c.txt"), "c").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/c.txt", path(&second)));
+
+        assert!(controller
+            .remove_path(&path(&first))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stats_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let watcher = DirWatcher::new(std::slice::from_ref(&root), vec![EventType::Created])
+            .unwrap()
+            .with_filter(GlobFilter::new(&[], &["*.tmp".to_string()]).unwrap());
+        let stats = watcher.stats();
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(async move { watcher.run(tx).await });
+
+        fs::write(dir.path().join("a.tmp"), "a").unwrap();
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/b.txt", root));
+        let stats = stats.snapshot();
+        assert_eq!(
+            (
+                stats.events_read,
+                stats.events_filtered,
+                stats.overflows,
+                stats.watches
+            ),
+            (2, 1, 0, 1)
+        );
+    }
+
+    #[test]
+    fn test_files_out_of_size_range_skipped() {
+        let filter = GlobFilter::default().with_size_range(Some(1), Some(100));
+        let file_info = |size| FileInfo {
+            access_ts: None,
+            modify_ts: None,
+            created_ts: None,
+            size,
+            mode: 0o100644,
+            uid: None,
+            gid: None,
+            user: None,
+            group: None,
+            xattrs: Vec::new(),
+            is_symlink: false,
+            link_target: None,
+            inode: None,
+            device: None,
+            nlink: None,
+            file_type: None,
+        };
+        let read_metadata = |path: &str| match path {
+            "/srv/empty" => Ok(file_info(0)),
+            "/srv/small" => Ok(file_info(100)),
+            "/srv/large" => Ok(file_info(101)),
+            _ => Err(Error::Io(io::ErrorKind::NotFound.into())),
+        };
+        let events = extract_named(
+            [
+                (
+                    "/srv",
+                    inotify::EventMask::CLOSE_WRITE,
+                    0,
+                    OsStr::new("empty"),
+                ),
+                (
+                    "/srv",
+                    inotify::EventMask::CLOSE_WRITE,
+                    0,
+                    OsStr::new("small"),
+                ),
+                (
+                    "/srv",
+                    inotify::EventMask::CLOSE_WRITE,
+                    0,
+                    OsStr::new("large"),
+                ),
+                ("/srv", inotify::EventMask::DELETE, 0, OsStr::new("gone")),
+            ],
+            &filter,
+            read_metadata,
+            &mut Renames::default(),
+            Instant::now(),
+        )
+        .unwrap();
+        let reported: Vec<&str> = events
+            .iter()
+            .map(|e| e.file_path.as_str())
+            .collect();
+        assert_eq!(reported, vec!["/srv/small", "/srv/gone"]);
+    }
+
+    #[tokio::test]
+    async fn test_pending_events_sent_on_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .to_str()
+            .unwrap()
+            .to_string();
+        fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let shutdown = Shutdown::new();
+        let watcher = DirWatcher::new(
+            std::slice::from_ref(&root),
+            vec![
+                EventType::Created,
+                EventType::Renamed {
+                    from: String::new(),
+                    to: String::new(),
+                },
+            ],
+        )
+        .unwrap()
+        .with_shutdown(shutdown.clone());
+        let (tx, mut rx) = mpsc::channel(64);
+        let running = tokio::spawn(async move { watcher.run(tx).await });
+
+        fs::write(dir.path().join("b.txt"), "b").unwrap();
+        assert_eq!(next(&mut rx).await, format!("{}/b.txt", root));
+
+        // Still waiting for its moved to event
+        fs::rename(dir.path().join("a.txt"), outside.path().join("a.txt")).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.request();
+        tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .expect("watcher not stopped")
+            .unwrap()
+            .unwrap();
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.event_type, EventType::MovedFrom);
+        assert_eq!(event.file_path, format!("{}/a.txt", root));
+        assert!(rx.recv().await.is_none());
+    }
+}
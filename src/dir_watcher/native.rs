@@ -0,0 +1,247 @@
+//! Watching through the notify crate, which uses the native API of each platform:
+//! inotify on Linux, FSEvents on macOS and ReadDirectoryChangesW on Windows.
+//!
+//! The platforms report less than inotify: the files are written as they change,
+//! not when they're closed, so a file written in several calls is reported as written
+//! several times, and the opened files are reported only where the platform tells.
+//! FSEvents doesn't tell the two sides of a rename apart, the side still existing is
+//! reported as moved to and the other one as moved from.
+
+use super::links::Links;
+use super::{capture_time, read_file_info, GlobFilter, WatchBackend};
+use crate::error::Error;
+use crate::event::{Event, EventType, FileKind};
+use async_trait::async_trait;
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use tokio::sync::mpsc;
+
+// The notify events read ahead of the watcher, notify's thread waits for room beyond them
+const READ_AHEAD: usize = 1024;
+
+pub struct NotifyWatcher {
+    // Stops watching once dropped
+    _watcher: notify::RecommendedWatcher,
+    rx: mpsc::Receiver<notify::Result<notify::Event>>,
+    // Watched path and reported path of each watched directory
+    dirs: Vec<(String, String)>,
+    events: Vec<EventType>,
+    filter: GlobFilter,
+    links: Links,
+}
+
+impl NotifyWatcher {
+    /// Watches the first path of each pair, the files are reported in the second one.
+    pub fn new(
+        dirs: &[(&str, &str)],
+        recursive: bool,
+        events: Vec<EventType>,
+    ) -> Result<NotifyWatcher, Error> {
+        if events.is_empty() {
+            return Err(Error::Watcher(
+                "No known event types found in event_types".to_string(),
+            ));
+        }
+        let (tx, rx) = mpsc::channel(READ_AHEAD);
+        // Called on the watcher's own thread, held back there while the sinks are
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.blocking_send(event);
+        })
+        .map_err(watcher_error)?;
+        let mode = match recursive {
+            true => RecursiveMode::Recursive,
+            false => RecursiveMode::NonRecursive,
+        };
+        for (path, _) in dirs {
+            watcher
+                .watch(Path::new(path), mode)
+                .map_err(watcher_error)?;
+        }
+        Ok(NotifyWatcher {
+            _watcher: watcher,
+            rx,
+            dirs: dirs
+                .iter()
+                .map(|(path, dir)| (path.to_string(), dir.to_string()))
+                .collect(),
+            events,
+            filter: GlobFilter::default(),
+            links: Links::default(),
+        })
+    }
+
+    /// Reports only the files passing the glob patterns.
+    pub fn with_filter(mut self, filter: GlobFilter) -> NotifyWatcher {
+        self.filter = filter;
+        self
+    }
+
+    // The events of the tracker in a notify event, with the reported paths
+    fn extract(&self, event: notify::Event) -> Vec<Event> {
+        let mut extracted = Vec::new();
+        let captured_at = capture_time();
+        for path in event.paths.iter() {
+            let Some(event_type) = event_type(&event.kind, path) else {
+                continue;
+            };
+            let Some(file_path) = self.reported(path) else {
+                continue;
+            };
+            if !self
+                .events
+                .contains(&event_type)
+                || !self
+                    .filter
+                    .matches_entry(&file_path, path.is_dir())
+            {
+                continue;
+            }
+            let file_info = match event_type {
+                EventType::Deleted | EventType::MovedFrom => None,
+                _ => path
+                    .to_str()
+                    .and_then(read_file_info),
+            };
+            // Told by some platforms also when the directory is gone
+            let kind = match event.kind {
+                EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder) => {
+                    FileKind::Dir
+                }
+                _ => FileKind::of(file_info.as_ref()),
+            };
+            // Timestamped by the listener
+            extracted.push(Event {
+                file_info,
+                kind,
+                captured_at,
+                ..Event::new(file_path, event_type)
+            });
+        }
+        extracted
+    }
+
+    // The path under the reported directory instead of the watched one
+    fn reported(&self, path: &Path) -> Option<String> {
+        self.dirs
+            .iter()
+            .find_map(|(watched, dir)| {
+                let relative = path
+                    .strip_prefix(watched)
+                    .ok()?;
+                Some(
+                    Path::new(dir)
+                        .join(relative)
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            })
+    }
+}
+
+fn event_type(kind: &EventKind, path: &Path) -> Option<EventType> {
+    match kind {
+        EventKind::Create(_) => Some(EventType::Created),
+        EventKind::Remove(_) => Some(EventType::Deleted),
+        EventKind::Modify(ModifyKind::Data(_)) | EventKind::Modify(ModifyKind::Any) => {
+            Some(EventType::Written)
+        }
+        EventKind::Modify(ModifyKind::Metadata(_)) => Some(EventType::AttributeChanged),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Some(EventType::MovedFrom),
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some(EventType::MovedTo),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Any)) => match path.exists() {
+            true => Some(EventType::MovedTo),
+            false => Some(EventType::MovedFrom),
+        },
+        EventKind::Access(AccessKind::Open(_)) => Some(EventType::Opened),
+        EventKind::Access(AccessKind::Close(AccessMode::Read)) => Some(EventType::ReadClosed),
+        EventKind::Access(AccessKind::Read) => Some(EventType::Accessed),
+        // Both sides of the rename are reported on their own too
+        _ => None,
+    }
+}
+
+fn watcher_error(e: notify::Error) -> Error {
+    Error::Watcher(e.to_string())
+}
+
+#[async_trait]
+impl WatchBackend for NotifyWatcher {
+    fn name(&self) -> &str {
+        "notify"
+    }
+
+    async fn run(self: Box<Self>, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        let mut watcher = *self;
+        while let Some(event) = watcher.rx.recv().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    println!("Failed to watch {:?}: {}", e.paths, e);
+                    continue;
+                }
+            };
+            // Before the extraction, the event may have changed an ignore file
+            watcher.filter.reload();
+            let events = watcher.extract(event);
+            for event in watcher
+                .links
+                .correlate(events)
+            {
+                if tx.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_native_events_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let watcher = NotifyWatcher::new(
+            &[(root, "/srv/data")],
+            true,
+            vec![
+                EventType::Created,
+                EventType::Written,
+                EventType::Deleted,
+                EventType::MovedFrom,
+                EventType::MovedTo,
+            ],
+        )
+        .unwrap()
+        .with_filter(GlobFilter::new(&[], &["*.tmp".to_string()]).unwrap());
+        let (tx, mut rx) = mpsc::channel(64);
+        tokio::spawn(Box::new(watcher).run(tx));
+
+        fs::write(dir.path().join("a.tmp"), "a").unwrap();
+        fs::write(dir.path().join("a.txt"), "hello").unwrap();
+        fs::rename(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+        fs::remove_file(dir.path().join("b.txt")).unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(Some(event)) = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await {
+            events.push((event.file_path, event.event_type));
+        }
+        let path = |name: &str| format!("/srv/data/{}", name);
+        assert_eq!(
+            events,
+            vec![
+                (path("a.txt"), EventType::Created),
+                (path("a.txt"), EventType::Written),
+                (path("a.txt"), EventType::MovedFrom),
+                (path("b.txt"), EventType::MovedTo),
+                (path("b.txt"), EventType::Deleted),
+            ]
+        );
+    }
+}
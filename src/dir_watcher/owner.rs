@@ -0,0 +1,71 @@
+//! The names of the files' owners, from the local user and group databases. The users
+//! only known to NSS, e.g. from LDAP, are reported by their ids alone.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+const PASSWD: &str = "/etc/passwd";
+const GROUP: &str = "/etc/group";
+
+// Names found so far, by database and id, the databases are read again on a miss
+static NAMES: Mutex<Option<HashMap<(&'static str, u32), String>>> = Mutex::new(None);
+
+pub fn user_name(uid: u32) -> Option<String> {
+    name(PASSWD, uid)
+}
+
+pub fn group_name(gid: u32) -> Option<String> {
+    name(GROUP, gid)
+}
+
+fn name(database: &'static str, id: u32) -> Option<String> {
+    let mut names = NAMES.lock().unwrap();
+    let names = names.get_or_insert_with(HashMap::new);
+    if let Some(name) = names.get(&(database, id)) {
+        return Some(name.clone());
+    }
+    // Added since, e.g. by a package install
+    let content = std::fs::read_to_string(database).ok()?;
+    for (name, found) in parse(&content) {
+        names.insert((database, found), name.to_string());
+    }
+    names
+        .get(&(database, id))
+        .cloned()
+}
+
+// The names and ids of the passwd or group lines, both have them first and third:
+// name:password:id:...
+fn parse(content: &str) -> impl Iterator<Item = (&str, u32)> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name, id))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_databases() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      # comment\n\
+                      alice:x:1000:1000::/home/alice:/bin/sh\n\
+                      broken\n";
+        assert_eq!(
+            parse(passwd).collect::<Vec<_>>(),
+            vec![("root", 0), ("alice", 1000)]
+        );
+        let group = "wheel:x:10:alice,bob\nstaff:x:50:";
+        assert_eq!(
+            parse(group).collect::<Vec<_>>(),
+            vec![("wheel", 10), ("staff", 50)]
+        );
+    }
+}
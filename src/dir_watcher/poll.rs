@@ -0,0 +1,325 @@
+//! Polling of the watched directories, for the filesystems inotify doesn't report the
+//! changes of, e.g. NFS, FUSE or the bind mounts changed outside a container.
+//!
+//! Every interval the tree is listed and its files stat'ed, the difference from the previous
+//! listing is reported: the new files as created, the changed ones (modification time, size
+//! or inode, or target of a symlink) as written and the gone ones as deleted. The first
+//! listing is the baseline.
+//! A file changed and changed back between two listings is missed, so are the directories.
+
+use super::links::Links;
+use super::{capture_time, inode, read_file_info, GlobFilter, WatchBackend};
+use crate::error::Error;
+use crate::event::{Event, EventType, FileKind};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+
+pub struct PollWatcher {
+    // Listed path and reported path of each watched directory
+    dirs: Vec<(String, String)>,
+    recursive: bool,
+    // Levels of subdirectories listed if recursive
+    max_depth: usize,
+    interval: Duration,
+    events: Vec<EventType>,
+    filter: GlobFilter,
+    // The files of the last listing, by reported path
+    files: HashMap<String, Stamp>,
+    links: Links,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Stamp {
+    // Where the file is stat'ed, differs from the reported path in the overlays
+    path: String,
+    modified: SystemTime,
+    size: u64,
+    inode: u64,
+    // Set for the symlinks, which are reported apart from their targets
+    target: Option<std::path::PathBuf>,
+}
+
+impl PollWatcher {
+    /// Lists the directories every `interval`, the files are reported in the second path
+    /// of each pair. Only the created, written and deleted `events` are reported.
+    pub fn new(
+        dirs: &[(&str, &str)],
+        recursive: bool,
+        interval: Duration,
+        events: Vec<EventType>,
+    ) -> Result<PollWatcher, Error> {
+        let events: Vec<EventType> = events
+            .into_iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    EventType::Created | EventType::Written | EventType::Deleted
+                )
+            })
+            .collect();
+        if events.is_empty() {
+            return Err(Error::Watcher(
+                "Polling reports only the created, written and deleted events".to_string(),
+            ));
+        }
+        let mut watcher = PollWatcher {
+            dirs: dirs
+                .iter()
+                .map(|(path, dir)| (path.to_string(), dir.to_string()))
+                .collect(),
+            recursive,
+            max_depth: usize::MAX,
+            interval,
+            events,
+            filter: GlobFilter::default(),
+            files: HashMap::new(),
+            links: Links::default(),
+        };
+        watcher.files = watcher.list();
+        Ok(watcher)
+    }
+
+    /// Lists the subdirectories only down to `max_depth` levels below the directories.
+    pub fn with_max_depth(mut self, max_depth: usize) -> PollWatcher {
+        self.max_depth = max_depth;
+        self.files = self.list();
+        self
+    }
+
+    /// Reports only the files passing the glob patterns.
+    pub fn with_filter(mut self, filter: GlobFilter) -> PollWatcher {
+        self.filter = filter;
+        self
+    }
+
+    /// Lists the directories again, returns the changes since the last listing.
+    pub fn poll(&mut self) -> Vec<Event> {
+        self.filter.reload();
+        let files = self.list();
+        let captured_at = capture_time();
+
+        let mut changed: Vec<(&String, &Stamp, EventType)> = Vec::new();
+        for (path, stamp) in files.iter() {
+            match self.files.get(path) {
+                None => changed.push((path, stamp, EventType::Created)),
+                Some(last) if last != stamp => changed.push((path, stamp, EventType::Written)),
+                Some(_) => {}
+            }
+        }
+        for (path, stamp) in self.files.iter() {
+            if !files.contains_key(path) {
+                changed.push((path, stamp, EventType::Deleted));
+            }
+        }
+        changed.sort_by_key(|(path, _, _)| *path);
+
+        let events = changed
+            .into_iter()
+            .filter(|(path, _, event_type)| {
+                self.events
+                    .contains(event_type)
+                    && self.filter.matches(path)
+            })
+            .map(|(path, stamp, event_type)| Event {
+                file_info: match event_type {
+                    EventType::Deleted => None,
+                    _ => read_file_info(&stamp.path),
+                },
+                kind: match stamp.target {
+                    Some(_) => FileKind::Symlink,
+                    None => FileKind::File,
+                },
+                captured_at,
+                // Timestamped by the listener
+                ..Event::new(path.clone(), event_type)
+            })
+            .collect();
+        self.files = files;
+        self.links.correlate(events)
+    }
+
+    // The regular files and the symlinks, which are not followed
+    fn list(&self) -> HashMap<String, Stamp> {
+        let mut files = HashMap::new();
+        let mut pending: Vec<(String, String, usize)> = self
+            .dirs
+            .iter()
+            .map(|(path, dir)| (path.clone(), dir.clone(), 0))
+            .collect();
+        while let Some((path, dir, depth)) = pending.pop() {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    println!("Failed to list the directory {}: {}", path, e);
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let reported = Path::new(&dir)
+                    .join(entry.file_name())
+                    .to_string_lossy()
+                    .into_owned();
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() && self.recursive && depth < self.max_depth {
+                    pending.push((
+                        entry
+                            .path()
+                            .to_string_lossy()
+                            .into_owned(),
+                        reported,
+                        depth + 1,
+                    ));
+                } else if metadata.is_file() || metadata.is_symlink() {
+                    let stamp = Stamp {
+                        path: entry
+                            .path()
+                            .to_string_lossy()
+                            .into_owned(),
+                        modified: metadata
+                            .modified()
+                            .unwrap_or(SystemTime::UNIX_EPOCH),
+                        size: metadata.len(),
+                        inode: inode(&metadata),
+                        target: fs::read_link(entry.path()).ok(),
+                    };
+                    files.insert(reported, stamp);
+                }
+            }
+        }
+        files
+    }
+}
+
+#[async_trait]
+impl WatchBackend for PollWatcher {
+    fn name(&self) -> &str {
+        "poll"
+    }
+
+    async fn run(self: Box<Self>, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        let interval = self.interval;
+        let mut watcher = self;
+        loop {
+            tokio::time::sleep(interval).await;
+            // Listing a large tree blocks
+            let events;
+            (watcher, events) = tokio::task::spawn_blocking(move || {
+                let events = watcher.poll();
+                (watcher, events)
+            })
+            .await?;
+            for event in events {
+                if tx.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_changes_found_by_listing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = |name: &str| format!("/srv/data/{}", name);
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("kept.txt"), "a").unwrap();
+        fs::write(dir.path().join("changed.txt"), "a").unwrap();
+        fs::write(dir.path().join("deleted.txt"), "a").unwrap();
+
+        let mut watcher = PollWatcher::new(
+            &[(root, "/srv/data")],
+            true,
+            Duration::from_secs(1),
+            vec![
+                EventType::Created,
+                EventType::Written,
+                EventType::Deleted,
+                EventType::Opened,
+            ],
+        )
+        .unwrap()
+        .with_filter(GlobFilter::new(&[], &["*.tmp".to_string()]).unwrap());
+        assert!(watcher.poll().is_empty());
+
+        // A different size, the modification time may not tell within its granularity
+        fs::write(dir.path().join("changed.txt"), "ab").unwrap();
+        fs::remove_file(dir.path().join("deleted.txt")).unwrap();
+        fs::write(dir.path().join("sub/new.txt"), "a").unwrap();
+        fs::write(dir.path().join("sub/new.tmp"), "a").unwrap();
+        let events: Vec<(String, EventType, Option<u64>)> = watcher
+            .poll()
+            .into_iter()
+            .map(|e| {
+                let size = e.file_info.map(|fi| fi.size);
+                (e.file_path, e.event_type, size)
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (path("changed.txt"), EventType::Written, Some(2)),
+                (path("deleted.txt"), EventType::Deleted, None),
+                (path("sub/new.txt"), EventType::Created, Some(1)),
+            ]
+        );
+        assert!(watcher.poll().is_empty());
+
+        // The link and its retargeting, apart from the target
+        std::os::unix::fs::symlink("kept.txt", dir.path().join("link")).unwrap();
+        let link = |e: Event| {
+            let target = e.file_info.and_then(|fi| {
+                fi.is_symlink
+                    .then_some(fi.link_target)?
+            });
+            (e.file_path, e.event_type, target)
+        };
+        let events: Vec<_> = watcher
+            .poll()
+            .into_iter()
+            .map(link)
+            .collect();
+        assert_eq!(
+            events,
+            vec![(
+                path("link"),
+                EventType::Created,
+                Some("kept.txt".to_string())
+            )]
+        );
+        fs::remove_file(dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("changed.txt", dir.path().join("link")).unwrap();
+        let events: Vec<_> = watcher
+            .poll()
+            .into_iter()
+            .map(link)
+            .collect();
+        assert_eq!(
+            events,
+            vec![(
+                path("link"),
+                EventType::Written,
+                Some("changed.txt".to_string())
+            )]
+        );
+
+        assert!(PollWatcher::new(
+            &[(root, root)],
+            false,
+            Duration::from_secs(1),
+            vec![EventType::Opened]
+        )
+        .is_err());
+    }
+}
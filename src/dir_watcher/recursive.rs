@@ -0,0 +1,266 @@
+//! Recursive watching: an inotify watch covers a single directory, so every subdirectory
+//! of the watched directories gets its own watch, and the directories created or moved
+//! in while running are watched as they appear. The entries already in a new directory
+//! when its watch is added are reported as created, they could be written before.
+//! The directories deeper than the maximum depth are reported, but not watched.
+//! Once the user's inotify watches run out, the directories which couldn't be watched
+//! are kept, with their subdirectories, to be polled instead.
+
+use inotify::{EventMask, WatchDescriptor, WatchMask, Watches};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Tree {
+    watches: Watches,
+    // Creations and moves are watched to follow the directories, even if not reported
+    mask: WatchMask,
+    reported: EventMask,
+    // Watched path of each watch, differs from the reported one in the overlays
+    paths: HashMap<WatchDescriptor, String>,
+    // Levels of subdirectories watched below the added directories
+    max_depth: usize,
+    // Level of each watched directory, 0 for the added ones
+    depths: HashMap<WatchDescriptor, usize>,
+    // Watched and reported path, and level, of the directories out of watches
+    unwatched: Vec<(String, String, usize)>,
+}
+
+impl Tree {
+    pub fn new(watches: Watches, mask: WatchMask, max_depth: usize) -> Tree {
+        Tree {
+            watches,
+            mask: mask | WatchMask::CREATE | WatchMask::MOVED_FROM | WatchMask::MOVED_TO,
+            reported: EventMask::from_bits_truncate(mask.bits()),
+            paths: HashMap::new(),
+            max_depth,
+            depths: HashMap::new(),
+            unwatched: Vec::new(),
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The directories not watched since the last time for the lack of inotify watches,
+    /// with their level. Their subdirectories aren't watched either.
+    pub fn take_unwatched(&mut self) -> Vec<(String, String, usize)> {
+        std::mem::take(&mut self.unwatched)
+    }
+
+    /// How many directories the unwatched ones have, themselves included, down to
+    /// the maximum depth.
+    pub fn count_unwatched(&self) -> usize {
+        let mut count = 0;
+        let mut pending: Vec<(PathBuf, usize)> = self
+            .unwatched
+            .iter()
+            .map(|(path, _, depth)| (PathBuf::from(path), *depth))
+            .collect();
+        while let Some((path, depth)) = pending.pop() {
+            count += 1;
+            if depth >= self.max_depth {
+                continue;
+            }
+            for entry in fs::read_dir(&path)
+                .into_iter()
+                .flatten()
+                .flatten()
+            {
+                if entry
+                    .file_type()
+                    .is_ok_and(|t| t.is_dir())
+                {
+                    pending.push((entry.path(), depth + 1));
+                }
+            }
+        }
+        count
+    }
+
+    /// Watches `path`, at the `depth` level, and its subdirectories down to the maximum
+    /// depth, the events are reported in `dir`. Returns the paths of the entries found
+    /// in the watched directories, as reported.
+    pub fn add(
+        &mut self,
+        dirs: &mut HashMap<WatchDescriptor, String>,
+        path: &str,
+        dir: &str,
+        depth: usize,
+    ) -> io::Result<Vec<String>> {
+        let wd = match self
+            .watches
+            .add(path, self.mask)
+        {
+            Ok(wd) => wd,
+            Err(e) if is_out_of_watches(&e) => {
+                self.unwatched
+                    .push((path.to_string(), dir.to_string(), depth));
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(e),
+        };
+        self.paths
+            .insert(wd.clone(), path.to_string());
+        self.depths
+            .insert(wd.clone(), depth);
+        dirs.insert(wd, dir.to_string());
+
+        let mut found = Vec::new();
+        let mut pending = vec![(path.to_string(), dir.to_string(), depth)];
+        while let Some((path, dir, depth)) = pending.pop() {
+            let entries = match fs::read_dir(&path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    println!("Failed to list the directory {}: {}", path, e);
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let entry_dir = Path::new(&dir)
+                    .join(&name)
+                    .to_string_lossy()
+                    .to_string();
+                // Not following the symlinks, they could loop
+                if depth < self.max_depth
+                    && entry
+                        .file_type()
+                        .is_ok_and(|t| t.is_dir())
+                {
+                    let entry_path = Path::new(&path)
+                        .join(&name)
+                        .to_string_lossy()
+                        .to_string();
+                    match self
+                        .watches
+                        .add(&entry_path, self.mask)
+                    {
+                        Ok(wd) => {
+                            self.paths
+                                .insert(wd.clone(), entry_path.clone());
+                            self.depths
+                                .insert(wd.clone(), depth + 1);
+                            dirs.insert(wd, entry_dir.clone());
+                            pending.push((entry_path, entry_dir.clone(), depth + 1));
+                        }
+                        Err(e) if is_out_of_watches(&e) => {
+                            self.unwatched
+                                .push((entry_path, entry_dir.clone(), depth + 1));
+                        }
+                        Err(e) => println!("Failed to watch the directory {}: {}", entry_path, e),
+                    }
+                }
+                found.push(entry_dir);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Follows the directories created, moved in or out, and removed, after the batch
+    /// of events is extracted. Returns the entries found in the new directories, if the
+    /// creations are reported.
+    pub fn update<S: AsRef<std::ffi::OsStr>>(
+        &mut self,
+        dirs: &mut HashMap<WatchDescriptor, String>,
+        events: &[inotify::Event<S>],
+    ) -> Vec<String> {
+        let mut found = Vec::new();
+        for event in events {
+            if event
+                .mask
+                .contains(EventMask::IGNORED)
+            {
+                // Removed, or unwatched below
+                self.paths.remove(&event.wd);
+                self.depths.remove(&event.wd);
+                dirs.remove(&event.wd);
+                continue;
+            }
+            if !event
+                .mask
+                .contains(EventMask::ISDIR)
+            {
+                continue;
+            }
+            let (Some(name), Some(path), Some(dir), Some(depth)) = (
+                event.name.as_ref(),
+                self.paths.get(&event.wd),
+                dirs.get(&event.wd),
+                self.depths.get(&event.wd),
+            ) else {
+                continue;
+            };
+            let depth = depth + 1;
+            let path = Path::new(path)
+                .join(name.as_ref())
+                .to_string_lossy()
+                .to_string();
+            let dir = Path::new(dir)
+                .join(name.as_ref())
+                .to_string_lossy()
+                .to_string();
+            if event
+                .mask
+                .contains(EventMask::MOVED_FROM)
+            {
+                // Watched again under the new name if moved within the tree
+                self.remove(dirs, &dir);
+            } else if depth <= self.max_depth
+                && event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+            {
+                match self.add(dirs, &path, &dir, depth) {
+                    Ok(entries)
+                        if self
+                            .reported
+                            .contains(EventMask::CREATE) =>
+                    {
+                        found.extend(entries)
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("Failed to watch the directory {}: {}", path, e),
+                }
+            }
+        }
+        found
+    }
+
+    /// The event with the event types watched only to follow the directories dropped.
+    pub fn reported<'a, S>(&self, event: &'a inotify::Event<S>) -> inotify::Event<&'a S> {
+        inotify::Event {
+            wd: event.wd.clone(),
+            mask: event.mask & (self.reported | EventMask::ISDIR),
+            cookie: event.cookie,
+            name: event.name.as_ref(),
+        }
+    }
+
+    /// Unwatches the directory and its subdirectories, as reported.
+    /// Returns how many directories were watched.
+    pub fn remove(&mut self, dirs: &mut HashMap<WatchDescriptor, String>, dir: &str) -> usize {
+        let removed: Vec<WatchDescriptor> = dirs
+            .iter()
+            .filter(|(_, watched)| Path::new(watched).starts_with(dir))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in removed.iter() {
+            // Fails if the directory is gone meanwhile, the watch with it
+            let _ = self
+                .watches
+                .remove(wd.clone());
+            self.paths.remove(wd);
+            self.depths.remove(wd);
+            dirs.remove(wd);
+        }
+        removed.len()
+    }
+}
+
+/// Whether adding a watch failed for the lack of the user's inotify watches, ENOSPC.
+pub fn is_out_of_watches(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::StorageFull
+}
@@ -0,0 +1,107 @@
+//! Pairs the two halves of a rename, inotify gives the moved from and the moved to event
+//! of one rename the same cookie. A moved from event is held for [RENAME_WINDOW], if its
+//! moved to event comes meanwhile both are reported as one renamed event, otherwise it's
+//! reported as it is, e.g. when the file was moved out of the watched directories.
+
+use crate::event::{Event, EventType};
+use std::time::{Duration, Instant};
+
+/// How long a moved from event waits for its moved to event.
+pub const RENAME_WINDOW: Duration = Duration::from_millis(500);
+
+/// The moved from events waiting for their pair. Pairs nothing unless [Self::enabled].
+#[derive(Debug, Default)]
+pub struct Renames {
+    window: Option<Duration>,
+    // Cookie, event, and when it was read
+    pending: Vec<(u32, Event, Instant)>,
+}
+
+impl Renames {
+    pub fn enabled(window: Duration) -> Renames {
+        Renames {
+            window: Some(window),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Enabled if the renamed events are reported.
+    pub fn for_types(event_types: &[EventType]) -> Renames {
+        let renamed = EventType::Renamed {
+            from: String::new(),
+            to: String::new(),
+        };
+        match event_types
+            .iter()
+            .any(|event_type| event_type.same_type(&renamed))
+        {
+            true => Renames::enabled(RENAME_WINDOW),
+            false => Renames::default(),
+        }
+    }
+
+    /// The `event` read `now`, or nothing if it's held for its pair. The moved to event
+    /// of a held moved from event is returned as renamed. A cookie 0 pairs nothing.
+    pub fn pair(&mut self, cookie: u32, event: Event, now: Instant) -> Option<Event> {
+        if self.window.is_none() || cookie == 0 {
+            return Some(event);
+        }
+        match event.event_type {
+            EventType::MovedFrom => {
+                self.pending
+                    .push((cookie, event, now));
+                None
+            }
+            EventType::MovedTo => {
+                let Some(i) = self
+                    .pending
+                    .iter()
+                    .position(|(pending, _, _)| *pending == cookie)
+                else {
+                    return Some(event);
+                };
+                let (_, from, _) = self.pending.remove(i);
+                Some(Event {
+                    event_type: EventType::Renamed {
+                        from: from.file_path,
+                        to: event.file_path.clone(),
+                    },
+                    ..event
+                })
+            }
+            _ => Some(event),
+        }
+    }
+
+    /// The held events whose pair didn't come within the window by `now`, as they are.
+    pub fn expired(&mut self, now: Instant) -> Vec<Event> {
+        let Some(window) = self.window else {
+            return Vec::new();
+        };
+        let (expired, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(_, _, at)| now.duration_since(*at) >= window);
+        self.pending = pending;
+        expired
+            .into_iter()
+            .map(|(_, event, _)| event)
+            .collect()
+    }
+
+    /// When the oldest held event expires, `None` if none is held.
+    pub fn deadline(&self) -> Option<Instant> {
+        let window = self.window?;
+        self.pending
+            .iter()
+            .map(|(_, _, at)| *at + window)
+            .min()
+    }
+
+    /// All the held events, e.g. at the end of a replay.
+    pub fn drain(&mut self) -> Vec<Event> {
+        self.pending
+            .drain(..)
+            .map(|(_, event, _)| event)
+            .collect()
+    }
+}
@@ -0,0 +1,285 @@
+//! The watched files' state kept across the restarts, so the changes made while the tracker
+//! was stopped are reported too: the size, modification time, inode and content hash of
+//! each file are saved to the state file every interval and once the watcher stops.
+//! Starting again, the saved state is compared to the files found, the difference is
+//! reported as created, written and deleted events.
+//!
+//! A file modified with the same content is not reported, its hash is the same. Without
+//! the solana feature the files aren't hashed, every modified one is reported.
+
+use super::{
+    baseline, capture_time, config_filter, inode, read_file_info, watched_depth, watched_dirs,
+};
+use crate::config::WatcherConfig;
+use crate::error::Error;
+use crate::event::{Event, EventType, FileInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::sync::oneshot;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    size: u64,
+    // Unix time in nanoseconds
+    modified: u64,
+    inode: u64,
+    // Hex SHA-256 of the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl Stamp {
+    fn read(path: &Path) -> Option<Stamp> {
+        let metadata = path.metadata().ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        Some(Stamp {
+            size: metadata.len(),
+            modified,
+            inode: inode(&metadata),
+            sha256: None,
+        })
+    }
+
+    // Whether the file is the same, the content compared only if both are hashed
+    fn is_unchanged(&self, other: &Stamp) -> bool {
+        match (self.sha256.as_ref(), other.sha256.as_ref()) {
+            (Some(a), Some(b)) => a == b && self.inode == other.inode,
+            _ => self.without_hash() == other.without_hash(),
+        }
+    }
+
+    fn without_hash(&self) -> Stamp {
+        Stamp {
+            sha256: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// The watched files as last seen, by reported path.
+#[derive(Debug, Default)]
+pub struct State {
+    files: BTreeMap<String, Stamp>,
+    // Where the files were listed, differs from the reported path in the overlays
+    listed: HashMap<String, PathBuf>,
+}
+
+impl State {
+    /// Reads the state saved in the file, none if there's none yet.
+    pub fn load(path: &str) -> Result<Option<State>, Error> {
+        let files = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::serialization(&format!("{}: {}", path, e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(State {
+            files,
+            listed: HashMap::new(),
+        }))
+    }
+
+    /// Writes the state to the file, whole or not at all.
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let json =
+            serde_json::to_string(&self.files).map_err(|e| Error::serialization(&e.to_string()))?;
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, json)?;
+        Ok(std::fs::rename(&tmp, path)?)
+    }
+
+    /// The `listed` files, each one where it's listed and its reported path. The files
+    /// unchanged since the `previous` state aren't hashed again.
+    pub fn scan(listed: Vec<(PathBuf, String)>, previous: &State) -> State {
+        let mut state = State::default();
+        for (file, reported) in listed {
+            // Gone since it was listed
+            let Some(mut stamp) = Stamp::read(&file) else {
+                continue;
+            };
+            stamp.sha256 = match previous.files.get(&reported) {
+                Some(seen) if seen.without_hash() == stamp => seen.sha256.clone(),
+                _ => hash(&file),
+            };
+            state
+                .files
+                .insert(reported.clone(), stamp);
+            state
+                .listed
+                .insert(reported, file);
+        }
+        state
+    }
+
+    /// The changes since the `previous` state, as the events of the `types`: the new files
+    /// created, the changed ones written, the gone ones deleted.
+    pub fn changes(&self, previous: &State, types: &[EventType]) -> Vec<Event> {
+        let mut changes = Vec::new();
+        for (path, stamp) in self.files.iter() {
+            let event_type = match previous.files.get(path) {
+                None => EventType::Created,
+                Some(seen) if !seen.is_unchanged(stamp) => EventType::Written,
+                Some(_) => continue,
+            };
+            // Gone since it was listed
+            let Some(file_info) = self
+                .listed
+                .get(path)
+                .and_then(|file| read_file_info(&file.to_string_lossy()))
+            else {
+                continue;
+            };
+            changes.push(change(path, event_type, Some(file_info)));
+        }
+        for path in previous
+            .files
+            .keys()
+            .filter(|path| !self.files.contains_key(*path))
+        {
+            changes.push(change(path, EventType::Deleted, None));
+        }
+        changes.retain(|event| types.contains(&event.event_type));
+        changes
+    }
+}
+
+fn change(path: &str, event_type: EventType, file_info: Option<FileInfo>) -> Event {
+    // Timestamped by the listener
+    Event {
+        captured_at: capture_time(),
+        file_info,
+        ..Event::new(path, event_type)
+    }
+}
+
+#[cfg(feature = "solana")]
+fn hash(path: &Path) -> Option<String> {
+    crate::backfill::hash_file(path)
+        .ok()
+        .map(|sha256| crate::integrity::hex(&sha256))
+}
+
+#[cfg(not(feature = "solana"))]
+fn hash(_path: &Path) -> Option<String> {
+    None
+}
+
+// The current state of the configured watched directories and files
+fn current(config: &WatcherConfig, previous: &State) -> State {
+    let dirs = watched_dirs(config);
+    let filter = config_filter(config, &dirs);
+    let mut listed = baseline::listed(&dirs, watched_depth(config), &filter);
+    listed.extend(
+        config
+            .files
+            .iter()
+            .map(|file| (PathBuf::from(file), file.clone())),
+    );
+    State::scan(listed, previous)
+}
+
+/// The changes of the watched files since the state saved in the `state_file`, none if
+/// none was saved yet. Saves the current state.
+pub fn resumed_events(config: &WatcherConfig) -> Result<Option<Vec<Event>>, Error> {
+    let Some(path) = config.state_file.as_ref() else {
+        return Ok(None);
+    };
+    let previous = State::load(path)?;
+    let state = current(
+        config,
+        previous
+            .as_ref()
+            .unwrap_or(&State::default()),
+    );
+    state.save(path)?;
+    Ok(previous.map(|previous| state.changes(&previous, &config.events)))
+}
+
+/// Saves the state of the watched files in the `state_file`, if set.
+pub fn save_state(config: &WatcherConfig) -> Result<(), Error> {
+    let Some(path) = config.state_file.as_ref() else {
+        return Ok(());
+    };
+    // Only spares hashing the unchanged files again, a broken state is replaced
+    let previous = State::load(path)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    current(config, &previous).save(path)
+}
+
+/// Saves the state every `state_interval`, and once more when `stop` is sent or dropped.
+pub async fn keep_state(config: WatcherConfig, mut stop: oneshot::Receiver<()>) {
+    loop {
+        let stopped = tokio::select! {
+            _ = tokio::time::sleep(config.state_interval) => false,
+            _ = &mut stop => true,
+        };
+        let watcher = config.clone();
+        let saved = tokio::task::spawn_blocking(move || save_state(&watcher)).await;
+        if let Err(e) = saved
+            .map_err(Error::from)
+            .and_then(|saved| saved)
+        {
+            println!("Failed to save the watcher state: {}", e);
+        }
+        if stopped {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_watcher::GlobFilter;
+    use std::fs;
+
+    #[test]
+    fn test_changes_while_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let state_file = dir.path().join("state.json");
+        fs::write(dir.path().join("kept.txt"), "kept").unwrap();
+        fs::write(dir.path().join("written.txt"), "before").unwrap();
+        fs::write(dir.path().join("deleted.txt"), "").unwrap();
+        let filter = GlobFilter::new(&[], &["*.json*".to_string()]).unwrap();
+        let listed = || baseline::listed(&[(root, "/srv")], 0, &filter);
+        State::scan(listed(), &State::default())
+            .save(state_file.to_str().unwrap())
+            .unwrap();
+
+        // While stopped
+        fs::write(dir.path().join("written.txt"), "after").unwrap();
+        fs::remove_file(dir.path().join("deleted.txt")).unwrap();
+        fs::write(dir.path().join("created.txt"), "").unwrap();
+
+        let previous = State::load(state_file.to_str().unwrap())
+            .unwrap()
+            .unwrap();
+        let state = State::scan(listed(), &previous);
+        let all = [EventType::Created, EventType::Written, EventType::Deleted];
+        let events = state.changes(&previous, &all);
+        let changes: Vec<(&str, EventType)> = events
+            .iter()
+            .map(|e| (e.file_path.as_str(), e.event_type.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("/srv/created.txt", EventType::Created),
+                ("/srv/written.txt", EventType::Written),
+                ("/srv/deleted.txt", EventType::Deleted),
+            ]
+        );
+        assert!(state
+            .changes(&state, &all)
+            .is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+//! Health of a running [DirWatcher](super::DirWatcher), read through its [WatchStats]
+//! handle, e.g. reported in the logs or by a metrics endpoint: the events read and filtered,
+//! the kernel's queue overflows, the watches held and how far behind the queue it is.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct Counters {
+    events_read: AtomicU64,
+    events_filtered: AtomicU64,
+    overflows: AtomicU64,
+    watches: AtomicU64,
+    queue_depth: AtomicU64,
+}
+
+/// The statistics of a watcher at one moment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Inotify events read since the start.
+    pub events_read: u64,
+    /// Inotify events of the files not passing the filter.
+    pub events_filtered: u64,
+    /// Times the kernel's queue overflowed, each one losing events.
+    pub overflows: u64,
+    /// Inotify watches held now, of the directories and the single files.
+    pub watches: u64,
+    /// Events read at once last time, the whole batch when the watcher is behind.
+    pub queue_depth: u64,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} events read, {} filtered, {} queue overflows, {} watches, {} events queued",
+            self.events_read, self.events_filtered, self.overflows, self.watches, self.queue_depth
+        )
+    }
+}
+
+/// Handle of a [DirWatcher](super::DirWatcher)'s statistics, kept once it stops.
+#[derive(Clone, Debug, Default)]
+pub struct WatchStats(Arc<Counters>);
+
+impl WatchStats {
+    pub fn snapshot(&self) -> Stats {
+        let counters = &self.0;
+        Stats {
+            events_read: counters
+                .events_read
+                .load(Ordering::Relaxed),
+            events_filtered: counters
+                .events_filtered
+                .load(Ordering::Relaxed),
+            overflows: counters
+                .overflows
+                .load(Ordering::Relaxed),
+            watches: counters
+                .watches
+                .load(Ordering::Relaxed),
+            queue_depth: counters
+                .queue_depth
+                .load(Ordering::Relaxed),
+        }
+    }
+
+    // A batch of `read` events, `filtered` of them
+    pub(super) fn read(&self, read: usize, filtered: u64) {
+        self.0
+            .events_read
+            .fetch_add(read as u64, Ordering::Relaxed);
+        self.0
+            .events_filtered
+            .fetch_add(filtered, Ordering::Relaxed);
+        self.0
+            .queue_depth
+            .store(read as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn overflowed(&self) {
+        self.0
+            .overflows
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn watching(&self, watches: usize) {
+        self.0
+            .watches
+            .store(watches as u64, Ordering::Relaxed);
+    }
+}
+
+/// Prints the statistics every `interval`.
+pub async fn report_stats(stats: WatchStats, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        println!("Watcher: {}", stats.snapshot());
+    }
+}
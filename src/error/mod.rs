@@ -1,35 +1,134 @@
-use std::{fmt::Display, io};
+//! Error type shared by all the modules.
+//!
+//! Callers branch on the variant, e.g. a submitter retries only the [Error::Rpc]
+//! errors marked as retryable, instead of matching the error messages.
 
-#[derive(Debug, Clone)]
-pub struct Error {
-    kind: Option<io::ErrorKind>,
-    message: String,
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The file watcher can't be set up or failed.
+    #[error("{0}")]
+    Watcher(String),
+
+    /// The snapshots can't be diffed or the diff can't be parsed.
+    #[error("{0}")]
+    Snapshot(String),
+
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    /// A request to the RPC node or another remote endpoint failed.
+    /// `retryable` tells whether the same request may succeed later.
+    #[error("Request failed: {source}")]
+    Rpc {
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+        retryable: bool,
+    },
+
+    /// An event or an instruction can't be (de)serialized.
+    #[error("Serialization failed: {0}")]
+    Serialization(#[source] io::Error),
+
+    /// The on-chain program rejected the instruction.
+    #[cfg(feature = "solana")]
+    #[error("Program error: {0}")]
+    Program(#[from] file_event_tracker::error::TrackerError),
+
+    #[error("{0}")]
+    Config(String),
+
+    /// Problems found by validating the configuration, see [crate::config::Problem].
+    #[error("{}", display_problems(.0))]
+    InvalidConfig(Vec<crate::config::Problem>),
+
+    /// A background task panicked or was cancelled.
+    #[error("Task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+
+    /// The receiving end of an event channel was dropped.
+    #[error("Event receiver dropped")]
+    Disconnected,
+
+    /// Another instance took the leader's lease over, see [crate::leader].
+    #[error("The lease is held by {0}")]
+    LeadershipLost(String),
+
+    /// The inotify watches of the user ran out, the directories can't all be watched.
+    #[error(
+        "Out of inotify watches: {watched} directories watched, {needed} needed{}",
+        display_limit(.limit)
+    )]
+    WatchLimit {
+        watched: usize,
+        needed: usize,
+        /// `fs.inotify.max_user_watches`, shared by all the user's processes.
+        limit: Option<usize>,
+    },
+
+    /// The spend budget is exhausted, the event was journaled instead of submitted.
+    #[error("Spend budget exhausted, the event is journaled to {journal}")]
+    OverBudget { journal: String },
 }
 
 impl Error {
-    pub fn new(message: &str) -> Error {
-        Error {
-            kind: None,
-            message: message.to_string(),
-        }
+    pub fn serialization(message: &str) -> Error {
+        Error::Serialization(io::Error::new(io::ErrorKind::InvalidData, message))
     }
+}
 
-    pub fn io_kind(&self) -> Option<io::ErrorKind> {
-        self.kind
+fn display_limit(limit: &Option<usize>) -> String {
+    match limit {
+        Some(limit) => format!(", fs.inotify.max_user_watches is {}", limit),
+        None => String::new(),
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Error {
-            kind: Some(err.kind()),
-            message: err.to_string(),
+fn display_problems(problems: &[crate::config::Problem]) -> String {
+    problems
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl From<ureq::Error> for Error {
+    fn from(err: ureq::Error) -> Self {
+        let retryable = match &err {
+            // Rate limited or a server side problem
+            ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+            ureq::Error::Transport(_) => true,
+        };
+        Error::Rpc {
+            source: Box::new(err),
+            retryable,
         }
     }
 }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Error: {}", self.message)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_source_preserved() {
+        let err: Error = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+        assert!(matches!(&err, Error::Io(e) if e.kind() == io::ErrorKind::NotFound));
+        assert_eq!(err.to_string(), "gone");
+    }
+
+    #[test]
+    fn test_watch_limit_counts_shown() {
+        let err = Error::WatchLimit {
+            watched: 8190,
+            needed: 12000,
+            limit: Some(8192),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Out of inotify watches: 8190 directories watched, 12000 needed, \
+             fs.inotify.max_user_watches is 8192"
+        );
     }
 }
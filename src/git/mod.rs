@@ -0,0 +1,123 @@
+//! Git-aware tracking: the watched directories which are roots of git repositories.
+//!
+//! The changes git makes in its own `.git/` directory are not worth an event,
+//! the other ones are tied to the repository's state, its HEAD commit and whether
+//! the working tree is dirty.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::process::Command;
+
+const GIT_DIR: &str = ".git";
+
+/// State of a repository when a change in it is submitted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepoState {
+    pub head: String,
+    /// Uncommitted changes, including the untracked files.
+    pub dirty: bool,
+}
+
+impl Display for RepoState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HEAD {}", self.head)?;
+        if self.dirty {
+            write!(f, ", dirty")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Repositories {
+    roots: Vec<String>,
+}
+
+impl Repositories {
+    /// Finds the directories containing `.git`, either the repository or a worktree's link to it.
+    pub fn discover(dirs: &[String]) -> Repositories {
+        Repositories {
+            roots: dirs
+                .iter()
+                .filter(|dir| {
+                    Path::new(dir)
+                        .join(GIT_DIR)
+                        .exists()
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Whether the file is git's own, in a repository's `.git/` directory.
+    pub fn is_git_internal(&self, path: &str) -> bool {
+        self.roots
+            .iter()
+            .any(|root| Path::new(path).starts_with(Path::new(root).join(GIT_DIR)))
+    }
+
+    /// Reads the state of the repository containing the file, runs `git`.
+    pub fn state(&self, path: &str) -> Option<RepoState> {
+        let root = self
+            .roots
+            .iter()
+            .find(|root| Path::new(path).starts_with(root))?;
+        let head = git(root, &["rev-parse", "HEAD"])?;
+        let status = git(root, &["status", "--porcelain"])?;
+        Some(RepoState {
+            head,
+            dirty: !status.is_empty(),
+        })
+    }
+}
+
+// Output of a successful git command run in the repository,
+// e.g. a repository without any commit has no HEAD
+fn git(root: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| println!("Failed to run git in {}: {}", root, e))
+        .ok()?;
+    if !output.status.success() {
+        println!(
+            "git {} failed in {}: {}",
+            args.join(" "),
+            root,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_internal_paths() {
+        let repos = Repositories {
+            roots: vec!["/srv/repo".to_string(), "/srv/other/".to_string()],
+        };
+        assert!(repos.is_git_internal("/srv/repo/.git/index"));
+        assert!(repos.is_git_internal("/srv/other/.git/objects/ab/cdef"));
+        assert!(!repos.is_git_internal("/srv/repo/.gitignore"));
+        assert!(!repos.is_git_internal("/srv/repo/src/.git"));
+        assert!(!repos.is_git_internal("/srv/plain/.git/index"));
+    }
+}
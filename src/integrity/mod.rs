@@ -0,0 +1,259 @@
+//! Verification of the files when they're opened, catching the changes made while
+//! the tracker wasn't watching, e.g. a disk edited offline, at the moment of use.
+//!
+//! The SHA-256 hashes of the files' content recorded on chain, by the backfill and
+//! with the events of the written files, are kept in a local index. An opened file
+//! found in it is hashed again, a different hash is an integrity violation: alerted,
+//! and recorded on chain with the opened event.
+
+pub mod pool;
+
+use crate::backfill::hash_file;
+use crate::error::Error;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Hashing a file opens it, the opened events within this time after hashing are our own
+const OWN_OPEN_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct Index {
+    // Hex SHA-256 of each file's content, as last recorded on chain
+    hashes: HashMap<String, String>,
+    // Not saved yet
+    changed: bool,
+    // When the files were last hashed by this process
+    hashed: HashMap<String, Instant>,
+}
+
+/// The content hashes recorded on chain, shared by the submitters recording
+/// and verifying them and the pipeline dropping the opens made by hashing.
+#[derive(Clone, Debug)]
+pub struct ContentHashes {
+    path: String,
+    index: Arc<Mutex<Index>>,
+}
+
+impl ContentHashes {
+    /// Reads the index saved in the file, empty if there is none yet.
+    pub fn load(path: &str) -> Result<ContentHashes, Error> {
+        let hashes = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| Error::serialization(&format!("{}: {}", path, e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ContentHashes {
+            path: path.to_string(),
+            index: Arc::new(Mutex::new(Index {
+                hashes,
+                ..Default::default()
+            })),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Writes the index to its file if it changed, whole or not at all.
+    pub fn save(&self) -> Result<(), Error> {
+        let json = {
+            let mut index = self.index.lock().unwrap();
+            if !index.changed {
+                return Ok(());
+            }
+            index.changed = false;
+            serde_json::to_string_pretty(&index.hashes)
+                .map_err(|e| Error::serialization(&e.to_string()))?
+        };
+        let tmp = format!("{}.tmp", self.path);
+        std::fs::write(&tmp, json)?;
+        Ok(std::fs::rename(&tmp, &self.path)?)
+    }
+
+    /// Hashes the file's content, the opened event it causes is marked as our own.
+    pub fn hash(&self, path: &str) -> std::io::Result<String> {
+        // Before the file is opened, the watcher may report it before the hash is done
+        self.index
+            .lock()
+            .unwrap()
+            .hashed
+            .insert(path.to_string(), Instant::now());
+        hash_file(Path::new(path)).map(|sha256| hex(&sha256))
+    }
+
+    pub fn is_recorded(&self, path: &str) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .hashes
+            .contains_key(path)
+    }
+
+    /// Remembers the hash recorded on chain with the file's event.
+    pub fn record(&self, path: &str, sha256: String) {
+        let mut index = self.index.lock().unwrap();
+        index
+            .hashes
+            .insert(path.to_string(), sha256);
+        index.changed = true;
+    }
+
+    /// Forgets the hash of a deleted or moved file.
+    pub fn forget(&self, path: &str) {
+        let mut index = self.index.lock().unwrap();
+        if index
+            .hashes
+            .remove(path)
+            .is_some()
+        {
+            index.changed = true;
+        }
+    }
+
+    /// Compares the file's current hash with the recorded one. A violation is reported
+    /// once, the current hash is recorded on chain with it and replaces the old one.
+    pub fn verify(&self, path: &str, sha256: String) -> Option<Violation> {
+        let mut index = self.index.lock().unwrap();
+        let recorded = index.hashes.get_mut(path)?;
+        if *recorded == sha256 {
+            return None;
+        }
+        let violation = Violation {
+            path: path.to_string(),
+            recorded: std::mem::replace(recorded, sha256.clone()),
+            found: sha256,
+        };
+        index.changed = true;
+        Some(violation)
+    }
+
+    /// Whether the file was hashed by this process within the last seconds,
+    /// its opened event is then most likely our own.
+    pub fn hashed_recently(&self, path: &str) -> bool {
+        let mut index = self.index.lock().unwrap();
+        index
+            .hashed
+            .retain(|_, at| at.elapsed() < OWN_OPEN_WINDOW);
+        index
+            .hashed
+            .contains_key(path)
+    }
+
+    /// Whether the file's opened event is the hashing done by this process.
+    /// Every hashing is matched with a single event.
+    pub fn is_own_open(&self, path: &str) -> bool {
+        let mut index = self.index.lock().unwrap();
+        index
+            .hashed
+            .retain(|_, at| at.elapsed() < OWN_OPEN_WINDOW);
+        index
+            .hashed
+            .remove(path)
+            .is_some()
+    }
+}
+
+/// An opened file whose content doesn't match its hash recorded on chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub path: String,
+    pub recorded: String,
+    pub found: String,
+}
+
+impl Violation {
+    /// Sent with the opened event.
+    pub fn memo(&self) -> String {
+        format!(
+            "integrity violation sha256 {}, recorded {}",
+            self.found, self.recorded
+        )
+    }
+
+    pub async fn notify(&self, url: &str) -> Result<(), Error> {
+        let url = url.to_string();
+        let body = serde_json::json!({
+            "alert": "IntegrityViolation",
+            "file_path": self.path,
+            "sha256": self.found,
+            "recorded_sha256": self.recorded,
+        });
+        // ureq is blocking, keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(body)
+                .map(|_| ())
+                .map_err(Error::from)
+        })
+        .await?
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "IntegrityViolation: {} has sha256 {}, {} is recorded on chain",
+            self.path, self.found, self.recorded
+        )
+    }
+}
+
+pub fn hex(sha256: &[u8; 32]) -> String {
+    sha256
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_content_reported_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir
+            .path()
+            .join("a.txt")
+            .to_string_lossy()
+            .to_string();
+        let index = dir
+            .path()
+            .join("hashes.json")
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&file, "test").unwrap();
+
+        let hashes = ContentHashes::load(&index).unwrap();
+        let recorded = hashes.hash(&file).unwrap();
+        hashes.record(&file, recorded.clone());
+        hashes.save().unwrap();
+        // The hashing's own open
+        assert!(hashes.is_own_open(&file));
+        assert!(!hashes.is_own_open(&file));
+
+        // Edited while the tracker was stopped
+        let hashes = ContentHashes::load(&index).unwrap();
+        assert_eq!(hashes.verify(&file, recorded.clone()), None);
+        std::fs::write(&file, "tampered").unwrap();
+        let found = hashes.hash(&file).unwrap();
+        assert_eq!(
+            hashes.verify(&file, found.clone()),
+            Some(Violation {
+                path: file.clone(),
+                recorded,
+                found: found.clone(),
+            })
+        );
+        assert_eq!(hashes.verify(&file, found), None);
+
+        hashes.forget(&file);
+        assert!(!hashes.is_recorded(&file));
+    }
+}
@@ -0,0 +1,104 @@
+//! Threads hashing the written files in the background, a multi-GB file takes seconds
+//! to hash and the events of the other files don't wait for it.
+
+use super::ContentHashes;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+struct Job {
+    id: u64,
+    path: String,
+}
+
+/// The hex SHA-256 of the file of the job, or why it failed.
+pub type Digest = std::io::Result<String>;
+
+/// Hashes the submitted files on a fixed number of threads, in the order submitted.
+pub struct HashPool {
+    // Closed on drop, the workers stop then
+    jobs: Option<mpsc::Sender<Job>>,
+    done: mpsc::Receiver<(u64, Digest)>,
+    next_id: u64,
+    // Submitted and not collected yet
+    pending: usize,
+}
+
+impl HashPool {
+    /// Starts the `workers` threads, the files are hashed through the `hashes`
+    /// so the opens they cause are known as our own.
+    pub fn new(workers: usize, hashes: ContentHashes) -> HashPool {
+        let (jobs_tx, jobs_rx) = mpsc::channel::<Job>();
+        let (done_tx, done_rx) = mpsc::channel();
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+        for _ in 0..workers.max(1) {
+            let jobs = jobs_rx.clone();
+            let done = done_tx.clone();
+            let hashes = hashes.clone();
+            thread::spawn(move || loop {
+                // Released before hashing, the other workers take the next jobs meanwhile
+                let job = match jobs.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                if done
+                    .send((job.id, hashes.hash(&job.path)))
+                    .is_err()
+                {
+                    return;
+                }
+            });
+        }
+        HashPool {
+            jobs: Some(jobs_tx),
+            done: done_rx,
+            next_id: 0,
+            pending: 0,
+        }
+    }
+
+    /// Queues the file, returns the job's id.
+    pub fn submit(&mut self, path: &str) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let job = Job {
+            id,
+            path: path.to_string(),
+        };
+        if let Some(jobs) = self.jobs.as_ref() {
+            // The workers only stop once the pool is dropped
+            let _ = jobs.send(job);
+        }
+        self.pending += 1;
+        id
+    }
+
+    /// The digests finished since the last call, by job id, without waiting.
+    pub fn finished(&mut self) -> HashMap<u64, Digest> {
+        let finished: HashMap<_, _> = self.done.try_iter().collect();
+        self.pending -= finished.len();
+        finished
+    }
+
+    /// Waits for all the submitted jobs.
+    pub fn wait_all(&mut self) -> HashMap<u64, Digest> {
+        let mut finished = HashMap::new();
+        while self.pending > 0 {
+            match self.done.recv() {
+                Ok((id, digest)) => {
+                    finished.insert(id, digest);
+                    self.pending -= 1;
+                }
+                Err(_) => break,
+            }
+        }
+        finished
+    }
+}
+
+impl Drop for HashPool {
+    fn drop(&mut self) {
+        self.jobs.take();
+    }
+}
@@ -0,0 +1,561 @@
+//! Framed event stream over a Unix domain socket.
+//!
+//! Used when the watcher and the submitter run as separate processes, on Unix:
+//! the watcher (privileged, sees all files) connects to the socket owned
+//! by the submitter (unprivileged, holds the keypair) and streams events.
+//!
+//! The framing is transport agnostic and reused by the remote agent over TLS.
+//! Each frame is a little-endian u32 length, with its top bit set, followed by the
+//! frame's version, a borsh-serialized Event and the extensions: what the Event's layout
+//! leaves out, each one serialized as length-prefixed bytes, in the order of [Extension].
+//! A reader skips the extensions added after it was built, and the fields added to
+//! an extension, and defaults the ones missing from the frames of the older writers.
+//! The frames without the top bit are the older watchers' ones, of the event alone.
+
+use crate::error::Error;
+use crate::event::{Event, FileKind};
+use crate::outbox::Outbox;
+use async_trait::async_trait;
+use borsh::{BorshDeserialize, BorshSerialize};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_SOCKET_PATH: &str = "/tmp/blkchn-file-tracker.sock";
+
+// Events are small, anything bigger than this is a corrupted stream.
+const MAX_FRAME_SIZE: u32 = 64 * 1024;
+// Set in the length of the versioned frames
+const VERSIONED: u32 = 1 << 31;
+// Bumped when the layout changes in a way the older readers can't skip
+const FRAME_VERSION: u8 = 1;
+const RECONNECT_DELAY_MIN: Duration = Duration::from_millis(100);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(10);
+
+/// The extensions of a frame, in their order. New ones are only added at the end.
+#[derive(Clone, Copy)]
+enum Extension {
+    // The file's owner, extended attributes, link target, inode and type
+    FileInfo,
+    Kind,
+    SuppressedCount,
+    Process,
+    Sequence,
+    CapturedAt,
+    HardLinks,
+    Diff,
+    Sha256,
+}
+
+// Fields of the file info the Event's layout leaves out
+type FileInfoExtension = (
+    Option<u32>,
+    Option<u32>,
+    Option<String>,
+    Option<String>,
+    Vec<(String, String)>,
+    (bool, Option<String>),
+    (Option<u64>, Option<u64>, Option<u64>),
+    Option<String>,
+);
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event: &Event,
+) -> Result<(), Error> {
+    let mut payload = frame_payload(event, event.diff.as_ref())?;
+    if payload.len() > MAX_FRAME_SIZE as usize && event.diff.is_some() {
+        // The diff is the only field which can be that long, the event is sent without it
+        println!("Diff of {} too big to be framed, left out", event.file_path);
+        payload = frame_payload(event, None)?;
+    }
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_SIZE)
+        .ok_or(Error::serialization("Event too big to be framed"))?;
+
+    writer
+        .write_all(&(len | VERSIONED).to_le_bytes())
+        .await?;
+    writer
+        .write_all(&payload)
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+// The frame's version, the event and its extensions
+fn frame_payload(event: &Event, diff: Option<&String>) -> Result<Vec<u8>, Error> {
+    let mut payload = vec![FRAME_VERSION];
+    event
+        .serialize(&mut payload)
+        .map_err(Error::Serialization)?;
+    // In the order of the extensions
+    let file_info = event
+        .file_info
+        .as_ref()
+        .map(|info| {
+            (
+                &info.uid,
+                &info.gid,
+                &info.user,
+                &info.group,
+                &info.xattrs,
+                (&info.is_symlink, &info.link_target),
+                (&info.inode, &info.device, &info.nlink),
+                &info.file_type,
+            )
+        });
+    extend(&mut payload, &file_info)?;
+    extend(&mut payload, &event.kind)?;
+    extend(&mut payload, &event.suppressed_count)?;
+    extend(&mut payload, &event.process)?;
+    extend(&mut payload, &event.sequence)?;
+    extend(&mut payload, &event.captured_at)?;
+    extend(&mut payload, &event.hard_links)?;
+    extend(&mut payload, &diff)?;
+    extend(&mut payload, &event.sha256)?;
+    Ok(payload)
+}
+
+// Appends the next extension as length-prefixed bytes
+fn extend<T: BorshSerialize>(payload: &mut Vec<u8>, value: &T) -> Result<(), Error> {
+    borsh::to_vec(value)
+        .and_then(|bytes| bytes.serialize(payload))
+        .map_err(Error::Serialization)
+}
+
+/// Reads a single frame. Returns `Ok(None)` when the peer closed the connection
+/// cleanly between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Event>, Error> {
+    let mut len_buf = [0u8; 4];
+    match reader
+        .read_exact(&mut len_buf)
+        .await
+    {
+        Ok(_) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let len = u32::from_le_bytes(len_buf);
+    let versioned = len & VERSIONED != 0;
+    let len = len & !VERSIONED;
+    if len > MAX_FRAME_SIZE {
+        return Err(Error::serialization(&format!(
+            "Frame of {} bytes exceeds the limit of {} bytes",
+            len, MAX_FRAME_SIZE
+        )));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut payload)
+        .await?;
+    let mut payload = payload.as_slice();
+    if !versioned {
+        // The event alone is known, the rest of the older watchers' frames changed
+        // from one version to the next
+        let mut event = Event::deserialize(&mut payload).map_err(Error::Serialization)?;
+        event.kind = FileKind::of(event.file_info.as_ref());
+        return Ok(Some(event));
+    }
+    match u8::deserialize(&mut payload).map_err(Error::Serialization)? {
+        FRAME_VERSION => (),
+        version => {
+            return Err(Error::serialization(&format!(
+                "Frame of the unknown version {}",
+                version
+            )))
+        }
+    }
+    let mut event = Event::deserialize(&mut payload).map_err(Error::Serialization)?;
+
+    let mut extensions = Vec::new();
+    while !payload.is_empty() {
+        let len = u32::deserialize(&mut payload).map_err(Error::Serialization)? as usize;
+        if len > payload.len() {
+            return Err(Error::serialization("Extension longer than the frame"));
+        }
+        let (extension, rest) = payload.split_at(len);
+        extensions.push(extension);
+        payload = rest;
+    }
+    // Not sent by the older watchers
+    let extension = |which: Extension| {
+        extensions
+            .get(which as usize)
+            .copied()
+    };
+
+    if let (Some(info), Some(mut bytes)) =
+        (event.file_info.as_mut(), extension(Extension::FileInfo))
+    {
+        let file_info: Option<FileInfoExtension> =
+            BorshDeserialize::deserialize(&mut bytes).map_err(Error::Serialization)?;
+        if let Some(file_info) = file_info {
+            (
+                info.uid,
+                info.gid,
+                info.user,
+                info.group,
+                info.xattrs,
+                (info.is_symlink, info.link_target),
+                (info.inode, info.device, info.nlink),
+                info.file_type,
+            ) = file_info;
+        }
+    }
+    event.kind = match extension(Extension::Kind) {
+        Some(mut bytes) => FileKind::deserialize(&mut bytes).map_err(Error::Serialization)?,
+        None => FileKind::of(event.file_info.as_ref()),
+    };
+    if let Some(mut bytes) = extension(Extension::SuppressedCount) {
+        event.suppressed_count = u32::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::Process) {
+        event.process = BorshDeserialize::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::Sequence) {
+        event.sequence = u64::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::CapturedAt) {
+        event.captured_at = i64::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::HardLinks) {
+        event.hard_links =
+            BorshDeserialize::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::Diff) {
+        event.diff = BorshDeserialize::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    if let Some(mut bytes) = extension(Extension::Sha256) {
+        event.sha256 = BorshDeserialize::deserialize(&mut bytes).map_err(Error::Serialization)?;
+    }
+    Ok(Some(event))
+}
+
+/// Opens a new stream to the receiving side, used by [FrameSender]
+/// every time the connection has to be (re)established.
+#[async_trait]
+pub trait Connector: Send + Sync {
+    type Stream: AsyncWrite + Unpin + Send;
+
+    async fn connect(&self) -> Result<Self::Stream, Error>;
+    fn peer(&self) -> String;
+}
+
+#[cfg(unix)]
+pub struct UnixConnector {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Connector for UnixConnector {
+    type Stream = UnixStream;
+
+    async fn connect(&self) -> Result<UnixStream, Error> {
+        Ok(UnixStream::connect(&self.path).await?)
+    }
+
+    fn peer(&self) -> String {
+        self.path
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Sending side of a framed stream. Connects lazily and reconnects
+/// with a backoff whenever the receiver goes away.
+pub struct FrameSender<C: Connector> {
+    connector: C,
+    stream: Option<C::Stream>,
+}
+
+/// Watcher side of the Unix socket.
+#[cfg(unix)]
+pub type SocketSender = FrameSender<UnixConnector>;
+
+#[cfg(unix)]
+impl SocketSender {
+    pub fn new(path: &str) -> SocketSender {
+        FrameSender::with_connector(UnixConnector {
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl<C: Connector> FrameSender<C> {
+    pub fn with_connector(connector: C) -> FrameSender<C> {
+        FrameSender {
+            connector,
+            stream: None,
+        }
+    }
+
+    /// Returns once the event is written to a connected receiver.
+    pub async fn send(&mut self, event: &Event) -> Result<(), Error> {
+        let mut delay = RECONNECT_DELAY_MIN;
+        loop {
+            let stream = match self.stream.as_mut() {
+                Some(s) => s,
+                None => match self.connector.connect().await {
+                    Ok(s) => {
+                        println!("Connected to {}", self.connector.peer());
+                        self.stream.insert(s)
+                    }
+                    Err(e) => {
+                        println!(
+                            "Failed to connect to {}: {}, retrying in {:?}",
+                            self.connector.peer(),
+                            e,
+                            delay
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay = std::cmp::min(delay * 2, RECONNECT_DELAY_MAX);
+                        continue;
+                    }
+                },
+            };
+
+            match write_frame(stream, event).await {
+                Ok(()) => return Ok(()),
+                Err(Error::Io(e)) => {
+                    // The receiver is gone, the frame will be resent on a new connection.
+                    println!("Lost connection to {}: {}", self.connector.peer(), e);
+                    self.stream = None;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Forwards all events from the outbox until its channel is closed.
+    pub async fn forward(&mut self, outbox: &mut Outbox) -> Result<(), Error> {
+        while let Some(event) = outbox.recv().await {
+            self.send(&event).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Submitter side of the socket. Accepts watcher connections one after another
+/// and passes the received events to the channel.
+#[cfg(unix)]
+pub async fn serve(path: &str, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+    let path = Path::new(path);
+    // A socket file left behind by a previous run would make bind fail.
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // Only the owner (and root) can stream events to us.
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    println!("Waiting for watcher connections on {:?}", path);
+
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((s, _)) => s,
+            Err(e) => {
+                println!("Failed to accept a watcher connection: {}", e);
+                continue;
+            }
+        };
+        println!("Watcher connected");
+        receive_frames(&mut stream, &tx).await?;
+    }
+}
+
+/// Reads frames from a single connection until it's closed or broken.
+/// Fails only when the event receiver is gone.
+pub async fn receive_frames<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    tx: &mpsc::Sender<Event>,
+) -> Result<(), Error> {
+    loop {
+        match read_frame(stream).await {
+            Ok(Some(event)) => {
+                if tx.send(event).await.is_err() {
+                    return Err(Error::Disconnected);
+                }
+            }
+            Ok(None) => {
+                println!("Watcher disconnected");
+                return Ok(());
+            }
+            Err(e) => {
+                println!("Dropping watcher connection: {}", e);
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let events = [
+            Event {
+                kind: FileKind::Dir,
+                ..Event::new("./tmp/a", EventType::Deleted)
+            },
+            Event {
+                kind: FileKind::Symlink,
+                suppressed_count: 99,
+                process: Some(crate::event::Process {
+                    pid: 1234,
+                    exe: "/usr/bin/vim".to_string(),
+                }),
+                sequence: 42,
+                captured_at: 1760000000123,
+                hard_links: vec!["./tmp/d.txt".to_string()],
+                diff: Some("@@ -1 +1 @@\n-a\n+b\n".to_string()),
+                sha256: Some(
+                    "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string(),
+                ),
+                file_info: Some(crate::event::FileInfo {
+                    access_ts: None,
+                    modify_ts: None,
+                    created_ts: None,
+                    size: 1,
+                    mode: 0o120777,
+                    uid: Some(1000),
+                    gid: Some(1000),
+                    user: Some("alice".to_string()),
+                    group: None,
+                    xattrs: vec![(
+                        "security.selinux".to_string(),
+                        "system_u:object_r:etc_t:s0".to_string(),
+                    )],
+                    is_symlink: true,
+                    link_target: Some("./tmp/c.txt".to_string()),
+                    inode: Some(7),
+                    device: Some(2049),
+                    nlink: Some(2),
+                    file_type: Some("image/png".to_string()),
+                }),
+                ..Event::new("./tmp/b.txt", EventType::AttributeChanged)
+            },
+        ];
+
+        let mut buf: Vec<u8> = Vec::new();
+        for event in events.iter() {
+            write_frame(&mut buf, event)
+                .await
+                .unwrap();
+        }
+
+        let mut reader = buf.as_slice();
+        assert_eq!(
+            read_frame(&mut reader)
+                .await
+                .unwrap()
+                .as_ref(),
+            Some(&events[0])
+        );
+        assert_eq!(
+            read_frame(&mut reader)
+                .await
+                .unwrap()
+                .as_ref(),
+            Some(&events[1])
+        );
+        assert_eq!(
+            read_frame(&mut reader)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frame_versions() {
+        let event = Event {
+            suppressed_count: 3,
+            sequence: 7,
+            diff: Some("+".repeat(MAX_FRAME_SIZE as usize)),
+            ..Event::new("./tmp/a.txt", EventType::Written)
+        };
+        let framed = |payload: &[u8], len: u32| {
+            let mut buf = len.to_le_bytes().to_vec();
+            buf.extend_from_slice(payload);
+            buf
+        };
+
+        // Sent without the diff too big to be framed
+        let mut buf: Vec<u8> = Vec::new();
+        write_frame(&mut buf, &event)
+            .await
+            .unwrap();
+        let read = read_frame(&mut buf.as_slice())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.diff, None);
+        assert_eq!(read.sequence, 7);
+
+        // A newer watcher's extension is skipped, an older one's missing extensions defaulted
+        let mut payload = frame_payload(&event, None).unwrap();
+        extend(&mut payload, &"newer".to_string()).unwrap();
+        let len = payload.len() as u32 | VERSIONED;
+        let read = read_frame(&mut framed(&payload, len).as_slice())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.sequence, 7);
+        let mut payload = vec![FRAME_VERSION];
+        event
+            .serialize(&mut payload)
+            .unwrap();
+        extend(&mut payload, &None::<()>).unwrap();
+        extend(&mut payload, &FileKind::File).unwrap();
+        extend(&mut payload, &3u32).unwrap();
+        let len = payload.len() as u32 | VERSIONED;
+        let read = read_frame(&mut framed(&payload, len).as_slice())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((read.suppressed_count, read.sequence), (3, 0));
+
+        // An older watcher's frame, of the event and the fields which changed since
+        let mut payload = borsh::to_vec(&event).unwrap();
+        payload.extend_from_slice(&[1, 2, 3]);
+        let len = payload.len() as u32;
+        let read = read_frame(&mut framed(&payload, len).as_slice())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read.file_path, event.file_path);
+        assert_eq!((read.suppressed_count, read.diff), (0, None));
+
+        // A version this reader doesn't know
+        let mut payload = frame_payload(&event, None).unwrap();
+        payload[0] = FRAME_VERSION + 1;
+        let len = payload.len() as u32 | VERSIONED;
+        assert!(read_frame(&mut framed(&payload, len).as_slice())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_frame_too_big() {
+        let mut buf = (MAX_FRAME_SIZE + 1)
+            .to_le_bytes()
+            .to_vec();
+        buf.extend_from_slice(&[0u8; 16]);
+        assert!(read_frame(&mut buf.as_slice())
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! Local binary journal of the captured events, written before they're passed
+//! to the sinks, so every event can be recovered even if all the sinks failed.
+//!
+//! Every record is the little-endian u32 length of the borsh-serialized event,
+//! the little-endian u32 CRC-32 of it, and the event. The journal is split into
+//! files of a limited size, `journal-00000001.bin`, ..., the oldest ones are deleted.
+//!
+//! The `gc` mode prunes the rotated files over the retention policy earlier,
+//! once their events are on chain, see [expired] and [is_confirmed].
+
+use crate::config::JournalConfig;
+use crate::error::Error;
+use crate::event::Event;
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const FILE_PREFIX: &str = "journal-";
+const FILE_SUFFIX: &str = ".bin";
+const HEADER_SIZE: u64 = 8;
+
+pub struct Journal {
+    config: JournalConfig,
+    file: File,
+    seq: u64,
+    size: u64,
+}
+
+impl Journal {
+    /// Continues the newest file in the directory, creates the directory if needed.
+    pub fn open(config: &JournalConfig) -> Result<Journal, Error> {
+        std::fs::create_dir_all(&config.dir)?;
+        let seq = files(Path::new(&config.dir))?
+            .last()
+            .map_or(1, |(seq, _)| *seq);
+        let (file, size) = open_file(&config.dir, seq)?;
+        Ok(Journal {
+            config: config.clone(),
+            file,
+            seq,
+            size,
+        })
+    }
+
+    /// Appends the event, rotating the files when the current one is full.
+    pub fn append(&mut self, event: &Event) -> Result<(), Error> {
+        let mut payload = Vec::new();
+        event
+            .serialize(&mut payload)
+            .map_err(Error::Serialization)?;
+        let record_size = HEADER_SIZE + payload.len() as u64;
+        if self.size > 0 && self.size + record_size > self.config.max_file_size {
+            self.rotate()?;
+        }
+
+        let mut record = Vec::with_capacity(record_size as usize);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        self.file.write_all(&record)?;
+        self.size += record_size;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Error> {
+        self.file.sync_all()?;
+        self.seq += 1;
+        (self.file, self.size) = open_file(&self.config.dir, self.seq)?;
+
+        // Retention, the current file included
+        for (seq, path) in files(Path::new(&self.config.dir))? {
+            if seq + self.config.max_files as u64 <= self.seq {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads all the events in the journal's files, oldest first. A record cut off
+/// by a crash at the end of a file is skipped, a corrupted one is an error.
+pub fn read(dir: &str) -> Result<Vec<Event>, Error> {
+    let mut events = Vec::new();
+    for (_, path) in files(Path::new(dir))? {
+        events.extend(read_file(&path)?);
+    }
+    Ok(events)
+}
+
+/// Reads the events in a single file of the journal, see [read].
+pub fn read_file(path: &Path) -> Result<Vec<Event>, Error> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    let mut events = Vec::new();
+    read_records(&data, &mut events).map_err(|e| {
+        Error::Serialization(io::Error::new(
+            e.kind(),
+            format!("{}: {}", path.display(), e),
+        ))
+    })?;
+    Ok(events)
+}
+
+/// File of the journal which is not written anymore.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RotatedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+/// The rotated files over the retention policy, oldest first: those not written for
+/// longer than `retention_age`, and the oldest ones while all the files together
+/// are larger than `retention_size`. The newest file is never expired.
+pub fn expired(config: &JournalConfig, now: SystemTime) -> Result<Vec<RotatedFile>, Error> {
+    let mut files = files(Path::new(&config.dir))?
+        .into_iter()
+        .map(|(_, path)| {
+            let metadata = std::fs::metadata(&path)?;
+            Ok(RotatedFile {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified()?,
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut total_size: u64 = files
+        .iter()
+        .map(|f| f.size)
+        .sum();
+    files.pop();
+
+    Ok(files
+        .into_iter()
+        .take_while(|file| {
+            let too_old = config
+                .retention_age
+                .is_some_and(|age| {
+                    now.duration_since(file.modified)
+                        .is_ok_and(|d| d > age)
+                });
+            let too_large = config
+                .retention_size
+                .is_some_and(|size| total_size > size);
+            total_size -= file.size;
+            too_old || too_large
+        })
+        .collect())
+}
+
+/// Whether the journaled event is on chain, given the last event of its file in the vault:
+/// the same change, or a later one. The vault keeps only the last event of every file.
+pub fn is_confirmed(journaled: &Event, on_chain: &Event) -> bool {
+    let same =
+        on_chain.event_type == journaled.event_type && on_chain.file_info == journaled.file_info;
+    let modified = |event: &Event| {
+        event
+            .file_info
+            .as_ref()
+            .and_then(|fi| fi.modify_ts)
+    };
+    let later = matches!(
+        (modified(journaled), modified(on_chain)),
+        (Some(journaled), Some(on_chain)) if on_chain > journaled
+    );
+    same || later
+}
+
+fn read_records(mut data: &[u8], events: &mut Vec<Event>) -> io::Result<()> {
+    let mut offset = 0;
+    while data.len() >= HEADER_SIZE as usize {
+        let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        let payload = match data.get(8..8 + len) {
+            Some(payload) => payload,
+            None => break,
+        };
+        if crc32fast::hash(payload) != crc {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("CRC mismatch of the record at {}", offset),
+            ));
+        }
+        events.push(Event::try_from_slice(payload)?);
+        data = &data[8 + len..];
+        offset += 8 + len;
+    }
+    Ok(())
+}
+
+// The journal's files, ordered by their sequence number
+fn files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut files: Vec<(u64, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let seq = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix(FILE_PREFIX)?
+                .strip_suffix(FILE_SUFFIX)?
+                .parse()
+                .ok()?;
+            Some((seq, entry.path()))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn open_file(dir: &str, seq: u64) -> io::Result<(File, u64)> {
+    let path = Path::new(dir).join(format!("{}{:08}{}", FILE_PREFIX, seq, FILE_SUFFIX));
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    #[test]
+    fn test_rotated_journal_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = JournalConfig {
+            dir: dir
+                .path()
+                .to_str()
+                .unwrap()
+                .to_string(),
+            max_file_size: 100,
+            max_files: 2,
+            retention_age: None,
+            retention_size: None,
+        };
+        let event = |i: usize| Event::new(format!("/srv/data/{:02}.txt", i), EventType::Written);
+
+        let mut journal = Journal::open(&config).unwrap();
+        // 8 + 37 bytes each, two in a file
+        for i in 0..5 {
+            journal
+                .append(&event(i))
+                .unwrap();
+        }
+        // The first file is deleted
+        assert_eq!(
+            read(&config.dir).unwrap(),
+            vec![event(2), event(3), event(4)]
+        );
+
+        // Continued after a restart, a record cut off by a crash is skipped
+        drop(journal);
+        let mut journal = Journal::open(&config).unwrap();
+        journal
+            .file
+            .write_all(&[10, 0, 0, 0, 1])
+            .unwrap();
+        assert_eq!(
+            read(&config.dir)
+                .unwrap()
+                .len(),
+            3
+        );
+
+        journal
+            .file
+            .set_len(journal.size)
+            .unwrap();
+        journal
+            .append(&event(5))
+            .unwrap();
+        journal
+            .append(&event(6))
+            .unwrap();
+        assert_eq!(
+            read(&config.dir).unwrap(),
+            vec![event(4), event(5), event(6)]
+        );
+    }
+
+    #[test]
+    fn test_gc_of_confirmed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = JournalConfig {
+            dir: dir
+                .path()
+                .to_str()
+                .unwrap()
+                .to_string(),
+            max_file_size: 200,
+            max_files: 8,
+            retention_age: Some(std::time::Duration::from_secs(3600)),
+            retention_size: Some(250),
+        };
+        let event = |modify_ts: i128| Event {
+            file_info: Some(crate::event::FileInfo {
+                access_ts: None,
+                modify_ts: Some(modify_ts),
+                created_ts: None,
+                size: 1,
+                mode: 0o100644,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            }),
+            ..Event::new("/srv/data/a.txt", EventType::Written)
+        };
+
+        let mut journal = Journal::open(&config).unwrap();
+        // 8 + 68 bytes each, 3 files of 152, 152 and 76 bytes
+        for i in 0..5 {
+            journal
+                .append(&event(i))
+                .unwrap();
+        }
+        let paths = |files: Vec<RotatedFile>| {
+            files
+                .into_iter()
+                .map(|f| f.path)
+                .collect::<Vec<_>>()
+        };
+        let all = files(dir.path()).unwrap();
+        // Over the size, the newest file is kept
+        assert_eq!(
+            paths(expired(&config, SystemTime::now()).unwrap()),
+            vec![all[0].1.clone()]
+        );
+        let later = SystemTime::now() + std::time::Duration::from_secs(7200);
+        assert_eq!(
+            paths(expired(&config, later).unwrap()),
+            vec![all[0].1.clone(), all[1].1.clone()]
+        );
+
+        // The same change or a later one is in the vault
+        assert!(is_confirmed(&event(1), &event(1)));
+        assert!(is_confirmed(&event(1), &event(2)));
+        assert!(!is_confirmed(&event(2), &event(1)));
+    }
+}
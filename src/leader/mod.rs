@@ -0,0 +1,220 @@
+//! Leader election of a hot-standby pair of trackers watching the same directories.
+//!
+//! Both instances watch, only the leader submits. The leadership is a lease kept in
+//! a small coordination file, e.g. on storage shared by the two hosts: the leader renews
+//! it every third of its duration and the standby takes it over once it expired.
+//! Meanwhile the standby keeps the events of the last two lease durations and submits
+//! them when it takes over, so the events the dead leader didn't get to are not lost.
+//! A few of them may be submitted twice.
+
+use crate::config::LeaderConfig;
+use crate::error::Error;
+use crate::event::Event;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(5);
+
+/// The leader's lease, a line with its holder and the expiry in unix milliseconds.
+#[derive(Clone, Debug)]
+pub struct Lease {
+    path: PathBuf,
+    // Host and process of this instance
+    holder: String,
+    duration: Duration,
+}
+
+impl Lease {
+    pub fn new(config: &LeaderConfig) -> Lease {
+        let host = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|h| h.trim().to_string())
+            .unwrap_or_else(|_| "localhost".to_string());
+        Lease {
+            path: PathBuf::from(&config.lease_file),
+            holder: format!("{}:{}", host, std::process::id()),
+            duration: config.lease,
+        }
+    }
+
+    fn renew_interval(&self) -> Duration {
+        self.duration / 3
+    }
+
+    /// Takes the lease, or extends it, unless another instance holds it.
+    /// Returns the holder of the valid lease otherwise.
+    pub fn claim(&self, now: SystemTime) -> Result<Result<(), String>, Error> {
+        let now = unix_millis(now);
+        if let Some((holder, expires)) = self.read()? {
+            if holder != self.holder && expires > now {
+                return Ok(Err(holder));
+            }
+        }
+        // Written whole or not at all, the other instance never reads half a line
+        let tmp = self
+            .path
+            .with_extension(format!("{}.tmp", std::process::id()));
+        let expires = now + self.duration.as_millis() as u64;
+        std::fs::write(&tmp, format!("{} {}\n", self.holder, expires))?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(Ok(()))
+    }
+
+    fn read(&self) -> Result<Option<(String, u64)>, Error> {
+        let record = match std::fs::read_to_string(&self.path) {
+            Ok(record) => record,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // An unreadable lease is taken over like an expired one
+        Ok(record
+            .split_once(' ')
+            .and_then(|(holder, expires)| {
+                let expires = expires.trim().parse().ok()?;
+                Some((holder.to_string(), expires))
+            }))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Stands by until this instance is elected the leader, keeping the recent events.
+/// Returns the receiver of the events to submit, the recent ones first.
+pub async fn elect(
+    lease: &Lease,
+    mut rx: mpsc::Receiver<Event>,
+) -> Result<mpsc::Receiver<Event>, Error> {
+    let keep = lease.duration * 2;
+    let mut recent: VecDeque<(Instant, Event)> = VecDeque::new();
+    let mut ticker = tokio::time::interval(lease.renew_interval());
+    // Held in two rounds in a row, no other instance claimed it at the same time
+    let mut claimed = false;
+    println!(
+        "Standing by until elected the leader, lease {}",
+        lease.path.display()
+    );
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => recent.push_back((Instant::now(), event)),
+                None => return Err(Error::Disconnected),
+            },
+            _ = ticker.tick() => {
+                match lease.claim(SystemTime::now()) {
+                    Ok(Ok(())) if claimed => break,
+                    Ok(Ok(())) => claimed = true,
+                    Ok(Err(holder)) => {
+                        if claimed {
+                            println!("Standing by, {} is the leader", holder);
+                        }
+                        claimed = false;
+                    }
+                    Err(e) => {
+                        println!("Failed to claim the lease {}: {}", lease.path.display(), e);
+                        claimed = false;
+                    }
+                }
+                while recent
+                    .front()
+                    .is_some_and(|(received, _)| received.elapsed() > keep)
+                {
+                    recent.pop_front();
+                }
+            }
+        }
+    }
+    println!(
+        "Elected the leader as {}, submitting {} recent events",
+        lease.holder,
+        recent.len()
+    );
+
+    // As bounded as the input, the sinks falling behind hold the watcher back
+    let (tx, leader_rx) = mpsc::channel(rx.max_capacity());
+    tokio::spawn(async move {
+        for (_, event) in recent {
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+        while let Some(event) = rx.recv().await {
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(leader_rx)
+}
+
+/// Renews the lease of the elected leader. Returns once the leadership is lost:
+/// another instance took the lease over, or it couldn't be renewed before expiring.
+pub async fn hold(lease: &Lease) -> Error {
+    let mut ticker = tokio::time::interval(lease.renew_interval());
+    let mut renewed = Instant::now();
+    loop {
+        ticker.tick().await;
+        match lease.claim(SystemTime::now()) {
+            Ok(Ok(())) => renewed = Instant::now(),
+            Ok(Err(holder)) => return Error::LeadershipLost(holder),
+            Err(e) if renewed.elapsed() >= lease.duration => return e,
+            Err(e) => println!("Failed to renew the lease {}: {}", lease.path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_taken_over_once_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LeaderConfig {
+            lease_file: dir
+                .path()
+                .join("leader")
+                .to_string_lossy()
+                .to_string(),
+            lease: Duration::from_secs(5),
+        };
+        let leader = Lease::new(&config);
+        let standby = Lease {
+            holder: "standby:1".to_string(),
+            ..leader.clone()
+        };
+        let start = SystemTime::now();
+
+        assert_eq!(leader.claim(start).unwrap(), Ok(()));
+        assert_eq!(
+            standby
+                .claim(start + Duration::from_secs(4))
+                .unwrap(),
+            Err(leader.holder.clone())
+        );
+        // Renewed, still the leader's
+        assert_eq!(
+            leader
+                .claim(start + Duration::from_secs(3))
+                .unwrap(),
+            Ok(())
+        );
+        assert!(standby
+            .claim(start + Duration::from_secs(7))
+            .unwrap()
+            .is_err());
+
+        // The leader died, the standby takes over
+        let expired = start + Duration::from_secs(8);
+        assert_eq!(
+            standby
+                .claim(expired)
+                .unwrap(),
+            Ok(())
+        );
+        assert_eq!(leader.claim(expired).unwrap(), Err("standby:1".to_string()));
+    }
+}
@@ -0,0 +1,34 @@
+//! The tracker's modules, used by the `blkchn-file-tracker` binary
+//! and by the benchmarks.
+
+pub use file_event_types as event;
+
+pub mod anchor;
+#[cfg(target_os = "linux")]
+pub mod audit;
+#[cfg(feature = "solana")]
+pub mod backfill;
+pub mod config;
+pub mod container;
+pub mod dir_watcher;
+pub mod error;
+pub mod git;
+#[cfg(feature = "solana")]
+pub mod integrity;
+pub mod ipc;
+pub mod journal;
+pub mod leader;
+pub mod outbox;
+pub mod pipeline;
+pub mod receipt;
+pub mod remote;
+#[cfg(target_os = "linux")]
+pub mod replay;
+pub mod sink;
+pub mod snapshot;
+#[cfg(feature = "solana")]
+pub mod solana_client;
+pub mod supervisor;
+pub mod transport;
+#[cfg(feature = "solana")]
+pub mod verify;
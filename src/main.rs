@@ -1,60 +1,1531 @@
+#[cfg(any(feature = "solana", feature = "ethereum"))]
+use blkchn_file_tracker::anchor;
+#[cfg(target_os = "linux")]
+use blkchn_file_tracker::{audit, replay};
+#[cfg(feature = "solana")]
+use blkchn_file_tracker::{backfill, solana_client, verify};
+use blkchn_file_tracker::{
+    config, dir_watcher, error, event, ipc, journal, leader, outbox, pipeline, remote, sink,
+    snapshot, supervisor, transport,
+};
 use std::env;
 
-mod dir_watcher;
-mod error;
-mod event;
-mod solana_client;
+/// Process exit codes, so that automation (systemd, k8s, scripts)
+/// can tell why the tracker failed to start.
+#[derive(Clone, Copy, Debug)]
+// The codes stay the same whether or not the solana feature is built in
+#[cfg_attr(not(feature = "solana"), allow(dead_code))]
+enum ExitCode {
+    Failed = 1,
+    Config = 2,
+    RpcUnreachable = 3,
+    InsufficientFunds = 4,
+    WatchSetupFailed = 5,
+    VaultInitFailed = 6,
+    LeadershipLost = 7,
+    VerificationFailed = 8,
+}
+
+/// Error reported to the user, rendered by miette together with the hint how to fix it.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+#[error("{message}")]
+struct Failure {
+    message: String,
+    #[help]
+    help: Option<String>,
+    #[related]
+    related: Vec<Failure>,
+}
 
-const DEFAULT_PROGRAM_ID: &str = "BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd";
+impl Failure {
+    fn new(message: impl Into<String>) -> Failure {
+        Failure {
+            message: message.into(),
+            help: None,
+            related: Vec::new(),
+        }
+    }
 
-fn main() {
-    let dir = "./tmp"; // path relative to Cargo.toml
-    let solana_url = "http://127.0.0.1:8899";
-    let mut client = solana_client::SolanaClient::new(solana_url, get_program(), get_wallet());
-    client.init_account().unwrap();
+    fn help(mut self, help: impl Into<String>) -> Failure {
+        self.help = Some(help.into());
+        self
+    }
+}
 
-    let event_types = vec![
-        event::EventType::AttributeChanged,
-        event::EventType::Created,
-        event::EventType::Deleted,
-        event::EventType::MovedFrom,
-        event::EventType::MovedTo,
-        event::EventType::Written,
-    ];
+impl From<error::Error> for Failure {
+    fn from(e: error::Error) -> Failure {
+        match e {
+            error::Error::InvalidConfig(problems) => Failure {
+                message: "Invalid configuration".to_string(),
+                help: None,
+                related: problems
+                    .into_iter()
+                    .map(|p| Failure {
+                        message: p.to_string(),
+                        help: p.help,
+                        related: Vec::new(),
+                    })
+                    .collect(),
+            },
+            e => Failure::new(e.to_string()),
+        }
+    }
+}
 
-    let (tx, rx) = std::sync::mpsc::channel();
+fn exit(code: ExitCode, failure: Failure) -> ! {
+    eprintln!("{:?}", miette::Report::new(failure));
+    std::process::exit(code as i32);
+}
 
-    // Start a solana client processing events
-    std::thread::spawn(move || {
-        client
-            .process_events(rx)
-            .expect("Should never return");
+/// Exits if a task running for the whole life of the process fails.
+fn check_task(task: &str, result: Result<(), error::Error>) {
+    if let Err(e) = result {
+        exit(
+            ExitCode::Failed,
+            Failure::new(format!("The {} stopped: {}", task, e)),
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Panics are rendered the same way as the other failures
+    miette::set_panic_hook();
+
+    let mut config = config::Config::load().unwrap_or_else(|e| {
+        exit(
+            ExitCode::Config,
+            Failure::new(format!("Invalid configuration: {}", e))
+                .help("Check the config file (TRACKER_CONFIG) and the TRACKER_* variables"),
+        )
     });
 
+    let args = config
+        .apply_args(env::args().skip(1))
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::Config,
+                Failure::new(format!("Invalid arguments: {}", e))
+                    .help("Pass the event types as --events created,written,deleted, the files as --report <file>"),
+            )
+        });
+
+    // Optional mode argument:
+    //   (none)  - watcher and submitter in one process
+    //   watch   - only watch, stream events to the submitter's socket (run as root)
+    //   submit  - only submit, receive events from the socket (holds the keypair)
+    //   agent     - only watch, forward events over TLS to a remote collector
+    //   collector - only submit, receive events over TLS from remote agents
+    //   snapshot  - diff two filesystem snapshots, submit the changes and exit
+    //   backfill  - record the existing files with their hashes and exit
+    //   status    - print the vault's usage and exit
+    //   journal   - print the journaled events as JSON lines and exit
+    //   replay    - replay the recorded inotify events through the pipeline and exit
+    //   gc        - prune the journal's files confirmed on chain and exit
+    //   verify    - verify the watched files against the vault without a keypair and exit
+    let mode = args
+        .first()
+        .map(|m| m.parse::<config::Mode>())
+        .unwrap_or(Ok(config::Mode::All))
+        .unwrap_or_else(|e| exit(ExitCode::Config, e.into()));
+
+    let config = config
+        .validate(mode)
+        .unwrap_or_else(|e| exit(ExitCode::Config, e.into()));
+    match config.mode {
+        config::Mode::All => run_all(config).await,
+        #[cfg(unix)]
+        config::Mode::Watch => run_watcher(config).await,
+        #[cfg(unix)]
+        config::Mode::Submit => run_submitter(config).await,
+        config::Mode::Agent => run_agent(config).await,
+        config::Mode::Collector => run_collector(config).await,
+        config::Mode::Snapshot => run_snapshot(config).await,
+        config::Mode::Journal => run_journal(config).await,
+        #[cfg(target_os = "linux")]
+        config::Mode::Replay => run_replay(config).await,
+        #[cfg(feature = "solana")]
+        config::Mode::Backfill => run_backfill(config).await,
+        #[cfg(feature = "solana")]
+        config::Mode::Status => run_status(config).await,
+        #[cfg(feature = "solana")]
+        config::Mode::Gc => run_gc(config).await,
+        #[cfg(feature = "solana")]
+        config::Mode::Verify => run_verify(config).await,
+        #[cfg(not(feature = "solana"))]
+        config::Mode::Backfill | config::Mode::Status | config::Mode::Gc | config::Mode::Verify => {
+            unreachable!("Validated, needs the solana feature")
+        }
+        #[cfg(not(unix))]
+        config::Mode::Watch | config::Mode::Submit => unreachable!("Validated, needs Unix"),
+        #[cfg(not(target_os = "linux"))]
+        config::Mode::Replay => unreachable!("Validated, needs Linux"),
+    }
+}
+
+async fn run_all(config: config::TrackerConfig) {
+    let shutdown = get_shutdown();
+    let (tx, rx) = transport::channel(&config.transport, channel_capacity(&config));
+
+    // Start the sinks (solana client and others) processing events
+    let sinks = tokio::spawn(supervise_sinks(&config, rx, &shutdown));
+
     // And run a dir watcher
-    dir_watcher::DirWatcher::new(dir, event_types)
-        .unwrap()
-        .run_blocking(tx)
-        .expect("Should never return");
+    let reloads = get_reloads(&config, &shutdown);
+    supervisor::supervise_until("watcher", &shutdown, || {
+        watch_reloaded(&config, reloads.as_ref(), &shutdown, tx.clone())
+    })
+    .await;
+
+    // The sinks stop once they've taken the last events
+    drop(tx);
+    check_task(
+        "event sinks",
+        sinks
+            .await
+            .map_err(Into::into),
+    );
+}
+
+#[cfg(unix)]
+async fn run_watcher(config: config::TrackerConfig) {
+    let shutdown = get_shutdown();
+    let (tx, rx) = tokio::sync::mpsc::channel(channel_capacity(&config));
+
+    // Forward events to the submitter process
+    let socket_path = config.socket_path.clone();
+    let forwarder = tokio::spawn(supervise_forwarder(
+        get_outbox(&config, rx),
+        shutdown.clone(),
+        move || Ok(ipc::SocketSender::new(&socket_path)),
+    ));
+
+    let reloads = get_reloads(&config, &shutdown);
+    supervisor::supervise_until("watcher", &shutdown, || {
+        watch_reloaded(&config, reloads.as_ref(), &shutdown, tx.clone())
+    })
+    .await;
+
+    drop(tx);
+    check_task(
+        "forwarder",
+        forwarder
+            .await
+            .map_err(Into::into),
+    );
 }
 
-fn get_program() -> solana_sdk::pubkey::Pubkey {
-    let program_id = env::var("PROGRAM_ID").unwrap_or(DEFAULT_PROGRAM_ID.to_string());
-    let program: solana_sdk::pubkey::Pubkey = program_id
-        .parse::<solana_sdk::pubkey::Pubkey>()
-        .expect("Invalid program id (check ${PROGRAM_ID})");
-    program
+#[cfg(unix)]
+async fn run_submitter(config: config::TrackerConfig) {
+    let shutdown = get_shutdown();
+    let (tx, rx) = tokio::sync::mpsc::channel(channel_capacity(&config));
+    let sinks = supervise_sinks(&config, rx, &shutdown);
+
+    // Receive events from the watcher process, until the shutdown
+    let socket_path = config.socket_path.clone();
+    let server_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = supervisor::supervise("socket server", || ipc::serve(&socket_path, tx.clone())) => (),
+            _ = server_shutdown.requested() => (),
+        }
+    });
+
+    sinks.await;
+}
+
+async fn run_agent(config: config::TrackerConfig) {
+    let collector = config
+        .collector_addr
+        .clone()
+        .expect("Validated for the agent mode");
+    let tls = config
+        .tls
+        .clone()
+        .expect("Validated for the agent mode");
+    // Checked once, the restarted forwarders create their own
+    if let Err(e) = remote::AgentSender::new(&collector, &tls) {
+        exit(
+            ExitCode::Config,
+            Failure::new(format!("Invalid TLS setup: {}", e))
+                .help("Check that the TLS files are PEM encoded and readable"),
+        )
+    }
+
+    let shutdown = get_shutdown();
+    let (tx, rx) = tokio::sync::mpsc::channel(channel_capacity(&config));
+
+    // Forward events to the remote collector
+    let forwarder = tokio::spawn(supervise_forwarder(
+        get_outbox(&config, rx),
+        shutdown.clone(),
+        move || remote::AgentSender::new(&collector, &tls),
+    ));
+
+    let reloads = get_reloads(&config, &shutdown);
+    supervisor::supervise_until("watcher", &shutdown, || {
+        watch_reloaded(&config, reloads.as_ref(), &shutdown, tx.clone())
+    })
+    .await;
+
+    drop(tx);
+    check_task(
+        "forwarder",
+        forwarder
+            .await
+            .map_err(Into::into),
+    );
 }
 
-fn get_wallet() -> solana_sdk::signer::keypair::Keypair {
-    let default_path_env = env::var("HOME")
-        .map(|mut s| {
-            s.push_str("/.config/solana/id.json");
-            s
+/// Events queued between the event source and the sinks. Once the queues are full the
+/// event source waits, e.g. while the sinks are held back by the block policy.
+fn channel_capacity(config: &config::TrackerConfig) -> usize {
+    config
+        .channel_capacity
+        .unwrap_or(transport::DEFAULT_CAPACITY)
+}
+
+/// Requested by SIGINT or SIGTERM: the watcher stops, and the process exits once
+/// the queued events are passed on.
+fn get_shutdown() -> supervisor::Shutdown {
+    supervisor::Shutdown::on_signals().unwrap_or_else(|e| {
+        exit(
+            ExitCode::Failed,
+            Failure::new(format!("Failed to handle the signals: {}", e)),
+        )
+    })
+}
+
+fn get_outbox(
+    config: &config::TrackerConfig,
+    rx: tokio::sync::mpsc::Receiver<event::Event>,
+) -> outbox::Outbox {
+    outbox::Outbox::new(
+        rx,
+        config.max_event_age,
+        outbox::dead_letter::DeadLetters::new(&config.dead_letter_path),
+    )
+}
+
+/// Forwards the outbox's events with the sender from `connect`, a new one on every restart.
+/// The outbox is kept, the restarted forwarder resumes with the queued events.
+/// After the `shutdown`, returns once the outbox is empty and closed.
+async fn supervise_forwarder<C, F>(
+    outbox: outbox::Outbox,
+    shutdown: supervisor::Shutdown,
+    mut connect: F,
+) where
+    C: ipc::Connector,
+    F: FnMut() -> Result<ipc::FrameSender<C>, error::Error>,
+{
+    let outbox = tokio::sync::Mutex::new(outbox);
+    supervisor::supervise_until("forwarder", &shutdown, || {
+        let sender = connect();
+        let outbox = &outbox;
+        async move {
+            let mut outbox = outbox.lock().await;
+            sender?
+                .forward(&mut outbox)
+                .await
+        }
+    })
+    .await;
+}
+
+/// Runs the sinks, built again on every restart, on the events of `rx`.
+/// After the `shutdown`, returns once they've taken all the events.
+fn supervise_sinks(
+    config: &config::TrackerConfig,
+    rx: tokio::sync::mpsc::Receiver<event::Event>,
+    shutdown: &supervisor::Shutdown,
+) -> impl std::future::Future<Output = ()> {
+    let config = config.clone();
+    let shutdown = shutdown.clone();
+    async move {
+        let rx = match config.leader.as_ref() {
+            Some(leader) => elect_leader(leader, rx).await,
+            None => rx,
+        };
+        let rx = supervisor::SharedReceiver::new(rx);
+        supervisor::supervise_until("event sinks", &shutdown, || async {
+            get_sinks(&config)
+                .await
+                .run(rx.lease())
+                .await
         })
-        .unwrap();
-    let wallet_keypair_path = env::var("WALLET_KEYPAIR").unwrap_or(default_path_env);
+        .await;
+    }
+}
+
+/// Stands by until elected the leader, then exits once the leadership is lost,
+/// to be restarted as the standby.
+async fn elect_leader(
+    config: &config::LeaderConfig,
+    rx: tokio::sync::mpsc::Receiver<event::Event>,
+) -> tokio::sync::mpsc::Receiver<event::Event> {
+    let lease = leader::Lease::new(config);
+    let rx = leader::elect(&lease, rx)
+        .await
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Leader election failed: {}", e)),
+            )
+        });
+    tokio::spawn(async move {
+        let e = leader::hold(&lease).await;
+        exit(
+            ExitCode::LeadershipLost,
+            Failure::new(format!("Lost the leadership: {}", e))
+                .help("Restart the tracker, it stands by until the lease is free again"),
+        )
+    });
+    rx
+}
+
+async fn run_collector(config: config::TrackerConfig) {
+    let shutdown = get_shutdown();
+    let (tx, rx) = tokio::sync::mpsc::channel(channel_capacity(&config));
+    let sinks = supervise_sinks(&config, rx, &shutdown);
+
+    // Receive events from all the agents
+    let listen = config
+        .collector_listen
+        .clone();
+    let tls = config
+        .tls
+        .clone()
+        .expect("Validated for the collector mode");
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = supervisor::supervise("collector server", || {
+                remote::serve_collector(&listen, &tls, tx.clone())
+            }) => (),
+            _ = shutdown.requested() => (),
+        }
+    });
+
+    sinks.await;
+}
+
+async fn run_snapshot(config: config::TrackerConfig) {
+    let (tx, rx) = tokio::sync::mpsc::channel(channel_capacity(&config));
+
+    let sinks = get_sinks(&config).await;
+    let sinks = tokio::spawn(sinks.run(rx));
+
+    let snapshot = config
+        .snapshot
+        .clone()
+        .expect("Validated for the snapshot mode");
+    let diff = snapshot::SnapshotDiff::from_config(&snapshot, &config.watcher);
+    if let Err(e) = diff
+        .run(with_pipeline(&config, None, tx))
+        .await
+    {
+        exit(
+            ExitCode::WatchSetupFailed,
+            Failure::new(format!(
+                "Failed to diff {} and {}: {}",
+                snapshot.from, snapshot.to, e
+            ))
+            .help("Check that both snapshots exist and that zfs or btrfs can be run by this user"),
+        );
+    }
+
+    // All the changes are sent, the sinks exit once they have processed them
+    match sinks.await {
+        Ok(result) => check_task("event sinks", result),
+        Err(e) => check_task("event sinks", Err(e.into())),
+    }
+}
+
+#[cfg(feature = "solana")]
+async fn run_backfill(config: config::TrackerConfig) {
+    let solana = config
+        .solana
+        .clone()
+        .expect("Validated for the backfill mode");
+
+    // Walks the directories and reads all the files
+    let dirs = config.watcher.dirs.clone();
+    let mut records = tokio::task::spawn_blocking(move || backfill::scan(&dirs))
+        .await
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Scan failed: {}", e)),
+            )
+        });
+    if let Some(git) = config.git.as_ref() {
+        records.retain(|r| !git.is_git_internal(&r.event.file_path));
+    }
+    let files = records.len();
+
+    // The routed directories are recorded in their programs' vaults
+    let mut targets = Vec::new();
+    for route in solana.routes.iter() {
+        let (routed, rest) = records
+            .into_iter()
+            .partition(|r| solana.route(&r.event.file_path) == Some(route));
+        records = rest;
+        targets.push((solana.for_route(route), routed));
+    }
+    targets.insert(0, (solana.clone(), records));
+
+    // Unlocked once for all the programs
+    let wallet = get_wallet(&solana, config.source("wallet_keypair"));
+    let mut batches = Vec::new();
+    for (target, records) in targets {
+        // A new vault is created by the first transaction of the baseline
+        let mut client = connect_solana_client(&config, &target, wallet.insecure_clone()).await;
+        if let Err(e) = client
+            .init_account_with_baseline()
+            .await
+        {
+            vault_init_failed(&config, &target, e);
+        }
+        client.monitor_capacity();
+        let transactions = client
+            .baseline_transactions(records)
+            .await
+            .unwrap_or_else(|e| {
+                exit(
+                    ExitCode::Failed,
+                    Failure::new(format!("Failed to build the transactions: {}", e)),
+                )
+            });
+        batches.push((client, transactions));
+    }
+
+    let transactions: usize = batches
+        .iter()
+        .map(|(_, t)| t.len())
+        .sum();
+    let fees: u64 = batches
+        .iter()
+        .map(|(_, t)| solana_client::transaction_fees(t))
+        .sum();
+    println!(
+        "{} files in {} transactions, {} lamports in fees",
+        files, transactions, fees
+    );
+    // All the programs are paid by the same wallet
+    match batches[0]
+        .0
+        .wallet_balance()
+        .await
+    {
+        Ok(balance) if balance < fees => exit(
+            ExitCode::InsufficientFunds,
+            Failure::new(format!(
+                "Wallet balance is {} lamports, the backfill needs {} lamports",
+                balance, fees
+            ))
+            .help("Fund the wallet, or backfill fewer directories at once"),
+        ),
+        Ok(_) => (),
+        Err(e) => exit(
+            ExitCode::RpcUnreachable,
+            Failure::new(format!("Failed to check the wallet balance: {}", e)),
+        ),
+    }
+
+    let mut sent = 0;
+    let mut failed = 0;
+    for (client, client_transactions) in batches.iter_mut() {
+        for instructions in client_transactions.iter() {
+            sent += 1;
+            let description = format!("baseline {}/{}", sent, transactions);
+            match client
+                .send_batch(instructions, description.clone())
+                .await
+            {
+                Ok(signature) => println!(
+                    "Sent {}: {} {}",
+                    description,
+                    signature,
+                    client
+                        .explorer()
+                        .transaction(&signature)
+                ),
+                Err(e) => {
+                    println!("Failed to send {}: {}", description, e);
+                    failed += 1;
+                }
+            }
+        }
+        // Waits for the confirmations
+        check_task("backfill", anchor::ChainAnchor::shutdown(client).await);
+    }
+    if failed > 0 {
+        exit(
+            ExitCode::Failed,
+            Failure::new(format!(
+                "{} of {} transactions failed",
+                failed, transactions
+            ))
+            .help("Run the backfill again, the files already recorded are overwritten"),
+        );
+    }
+}
+
+async fn run_journal(config: config::TrackerConfig) {
+    let journal = config
+        .journal
+        .expect("Validated for the journal mode");
+    let events = tokio::task::spawn_blocking(move || journal::read(&journal.dir))
+        .await
+        .map_err(error::Error::from)
+        .and_then(|events| events)
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to read the journal: {}", e)),
+            )
+        });
+    for event in events.iter() {
+        println!("{}", sink::webhook::event_to_json(event));
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn run_replay(config: config::TrackerConfig) {
+    let path = config
+        .watcher
+        .record
+        .clone()
+        .expect("Validated for the replay mode");
+    let events = replay::read(&path)
+        .and_then(|capture| {
+            replay::replay(
+                &capture,
+                &config.watcher.filter,
+                &mut dir_watcher::Renames::for_types(&config.watcher.events),
+                &mut get_pipeline(&config),
+            )
+        })
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to replay {}: {}", path, e)),
+            )
+        });
+    for event in events.iter() {
+        println!("{}", sink::webhook::event_to_json(event));
+    }
+}
+
+#[cfg(feature = "solana")]
+async fn run_status(config: config::TrackerConfig) {
+    let solana = config
+        .solana
+        .clone()
+        .expect("Validated for the status mode");
+    // Read-only, the keypair isn't needed if the wallet's address is given
+    let wallet = solana
+        .wallet_pubkey
+        .unwrap_or_else(|| {
+            let keypair = get_wallet(&solana, config.source("wallet_keypair"));
+            solana_sdk::signer::Signer::pubkey(&keypair)
+        });
+    let client = solana_client::reader::VaultReader::from_config(&solana, wallet);
+
+    let first = client.vault();
+    let usage = client
+        .vault_usage()
+        .await
+        .unwrap_or_else(|e| {
+            exit(
+                ExitCode::RpcUnreachable,
+                Failure::new(format!("Failed to read the vault: {}", e)),
+            )
+        });
+    let (vault, usage) = match usage {
+        Some(usage) => usage,
+        None => {
+            println!("Vault {} is not created yet", first);
+            return;
+        }
+    };
+    if vault != first {
+        println!("Vault {} is full, linked to the overflow vaults", first);
+    }
+    println!("Vault {}: {}", vault, usage);
+    println!(
+        "{}",
+        client
+            .explorer()
+            .address(&vault)
+    );
+    if let (Some(bytes), Some(files)) = (usage.bytes_per_file(), usage.files_left()) {
+        println!("{:.0} bytes per file, room for {} more files", bytes, files);
+    }
+    if let Some(lamports) = usage.top_up(usage.files as u64) {
+        println!(
+            "Transfer {} lamports to the vault to make room for {} more files",
+            lamports, usage.files
+        );
+    }
+
+    // Saved by the running tracker's metrics sink
+    if let Ok(latencies) = sink::latency::Latencies::load(&config.metrics_file) {
+        println!("{}", latencies);
+    }
+}
+
+/// Compares the watched files with their last events in the vaults of the wallet given by
+/// its address, of the default program and of the routes, and reports the differences.
+#[cfg(feature = "solana")]
+async fn run_verify(config: config::TrackerConfig) {
+    let solana = config
+        .solana
+        .clone()
+        .expect("Validated for the verify mode");
+    let wallet = solana
+        .wallet_pubkey
+        .expect("Validated for the verify mode");
+
+    let mut recorded = std::collections::HashMap::new();
+    let programs = std::iter::once(solana.clone()).chain(
+        solana
+            .routes
+            .iter()
+            .map(|route| solana.for_route(route)),
+    );
+    for program in programs {
+        let reader = solana_client::reader::VaultReader::from_config(&program, wallet);
+        let events = reader
+            .vault_events()
+            .await
+            .unwrap_or_else(|e| {
+                exit(
+                    ExitCode::RpcUnreachable,
+                    Failure::new(format!(
+                        "Failed to read the vault {}: {}",
+                        reader.vault(),
+                        e
+                    )),
+                )
+            });
+        println!(
+            "Vault {}: {} files recorded\n{}",
+            reader.vault(),
+            events.len(),
+            reader
+                .explorer()
+                .address(&reader.vault())
+        );
+        // Only the routed directories are in the route's vault
+        recorded.extend(
+            events
+                .into_iter()
+                .filter(|(path, _)| {
+                    solana
+                        .route(path)
+                        .map_or(solana.program, |route| route.program)
+                        == program.program
+                }),
+        );
+    }
+
+    let reader = solana_client::reader::VaultReader::from_config(&solana, wallet);
+    let mut report = verify::verify(
+        &config.watcher.dirs,
+        config.watcher.recursive,
+        &recorded,
+        &reader.vault().to_string(),
+    );
+    // Only the events in the default program's vaults are proven
+    if let Some((since, until)) = config.report_range {
+        let records = reader
+            .vault_records(since, until)
+            .await
+            .unwrap_or_else(|e| {
+                exit(
+                    ExitCode::RpcUnreachable,
+                    Failure::new(format!(
+                        "Failed to read the transactions of the vault {}: {}",
+                        reader.vault(),
+                        e
+                    )),
+                )
+            });
+        let proofs = verify::proof::proofs(records, &config.watcher.dirs, &recorded);
+        println!("{} events recorded in the time range proven", proofs.len());
+        report = report.with_evidence(verify::proof::Evidence {
+            program: solana.program.to_string(),
+            since,
+            until,
+            proofs,
+        });
+    }
+    for entry in report
+        .entries
+        .iter()
+        .filter(|e| e.status != verify::Status::Matches)
+    {
+        println!("{}: {}", entry.status.as_str(), entry.path);
+    }
+    println!("{}", report);
+
+    let json = serde_json::to_string_pretty(&report.to_json()).expect("Serializable");
+    match config.report_file.as_ref() {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, json) {
+                exit(
+                    ExitCode::Failed,
+                    Failure::new(format!("Failed to write the report to {}: {}", path, e)),
+                )
+            }
+            println!("Report written to {}", path);
+        }
+        None => println!("{}", json),
+    }
+
+    if !report.is_clean() {
+        exit(
+            ExitCode::VerificationFailed,
+            Failure::new("The files differ from their records on chain")
+                .help("See the report for the modified, missing and unrecorded files"),
+        );
+    }
+}
+
+/// Deletes the journal's files over the retention policy whose events are all on chain,
+/// in the vault of the default program or of their route.
+#[cfg(feature = "solana")]
+async fn run_gc(config: config::TrackerConfig) {
+    let journal = config
+        .journal
+        .clone()
+        .expect("Validated for the gc mode");
+    let solana = config
+        .solana
+        .clone()
+        .expect("Validated for the gc mode");
+
+    let expired = tokio::task::spawn_blocking(move || {
+        journal::expired(&journal, std::time::SystemTime::now())
+    })
+    .await
+    .map_err(error::Error::from)
+    .and_then(|files| files)
+    .unwrap_or_else(|e| {
+        exit(
+            ExitCode::Failed,
+            Failure::new(format!("Failed to list the journal's files: {}", e)),
+        )
+    });
+    if expired.is_empty() {
+        println!("Nothing to prune, the journal is within its retention");
+        return;
+    }
+
+    let wallet = get_wallet(&solana, config.source("wallet_keypair"));
+    let mut vaults = std::collections::HashMap::new();
+    let programs = std::iter::once(solana.clone()).chain(
+        solana
+            .routes
+            .iter()
+            .map(|route| solana.for_route(route)),
+    );
+    for program in programs {
+        let client = solana_client::SolanaClient::from_config(&program, wallet.insecure_clone());
+        let events = client
+            .vault_events()
+            .await
+            .unwrap_or_else(|e| {
+                exit(
+                    ExitCode::RpcUnreachable,
+                    Failure::new(format!(
+                        "Failed to read the vault {}: {}",
+                        client.vault(),
+                        e
+                    )),
+                )
+            });
+        vaults.insert(program.program, events);
+    }
+
+    let mut pruned = 0;
+    for file in expired.iter() {
+        let events = journal::read_file(&file.path).unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to read the journal: {}", e)),
+            )
+        });
+        // The vault has only the last event of every file, the earlier ones preceded it
+        let last_events: std::collections::HashMap<&str, &event::Event> = events
+            .iter()
+            .map(|e| (e.file_path.as_str(), e))
+            .collect();
+        let unconfirmed = last_events
+            .values()
+            .filter(|event| {
+                let program = solana
+                    .route(&event.file_path)
+                    .map_or(solana.program, |route| route.program);
+                !vaults[&program]
+                    .get(&event.file_path)
+                    .is_some_and(|on_chain| journal::is_confirmed(event, on_chain))
+            })
+            .count();
+        if unconfirmed > 0 {
+            println!(
+                "Kept {}: {} of its {} files not confirmed on chain",
+                file.path.display(),
+                unconfirmed,
+                last_events.len()
+            );
+            continue;
+        }
+        if let Err(e) = std::fs::remove_file(&file.path) {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to delete {}: {}", file.path.display(), e)),
+            )
+        }
+        println!(
+            "Pruned {}: {} events, {} bytes",
+            file.path.display(),
+            events.len(),
+            file.size
+        );
+        pruned += 1;
+    }
+    println!("Pruned {} of {} expired files", pruned, expired.len());
+}
+
+#[cfg(feature = "solana")]
+async fn get_solana_client(
+    config: &config::TrackerConfig,
+    solana: &config::SolanaConfig,
+    wallet: solana_sdk::signer::keypair::Keypair,
+) -> solana_client::SolanaClient {
+    let mut client = connect_solana_client(config, solana, wallet).await;
+    if let Err(e) = client.init_account().await {
+        vault_init_failed(config, solana, e);
+    }
+    client.monitor_capacity();
+    client
+}
+
+/// [get_solana_client] with the vault not initialized yet.
+#[cfg(feature = "solana")]
+async fn connect_solana_client(
+    config: &config::TrackerConfig,
+    solana: &config::SolanaConfig,
+    wallet: solana_sdk::signer::keypair::Keypair,
+) -> solana_client::SolanaClient {
+    let pool = get_wallet_pool(solana, config.source("wallet_pool"), &wallet);
+    let mut client = solana_client::SolanaClient::from_config(solana, wallet);
+    if let Some(pool) = pool {
+        client = client.with_wallet_pool(pool);
+    }
+    if let Some(git) = config.git.as_ref() {
+        client = client.with_repositories(git.clone());
+    }
+    if let Some(containers) = config
+        .watcher
+        .containers
+        .as_ref()
+    {
+        client = client.with_containers(containers.clone());
+    }
+    if let Some(receipts) = config.receipts.as_ref() {
+        client = client.with_receipts(receipts.clone());
+    }
+    if let Some(hashes) = config.integrity.as_ref() {
+        client = client.with_integrity(hashes.clone());
+    }
+
+    if let Err(e) = client
+        .check_connection()
+        .await
+    {
+        exit(
+            ExitCode::RpcUnreachable,
+            Failure::new(format!(
+                "RPC node at {} (rpc_url from {}) is unreachable: {}",
+                solana.rpc_url,
+                config.source("rpc_url"),
+                e
+            ))
+            .help("Check TRACKER_RPC_URL or start a validator with `solana-test-validator`"),
+        );
+    }
+
+    let balance = match client.wallet_balance().await {
+        Ok(balance) => client
+            .required_balance()
+            .await
+            .map(|required| (balance, required)),
+        Err(e) => Err(e),
+    };
+    match balance {
+        Ok((balance, required)) if balance < required => exit(
+            ExitCode::InsufficientFunds,
+            Failure::new(format!(
+                "Wallet balance is {} lamports, at least {} lamports are needed",
+                balance, required
+            ))
+            .help("Fund the wallet, e.g. `solana airdrop 1` on a test validator"),
+        ),
+        Ok(_) => (),
+        Err(e) => exit(
+            ExitCode::RpcUnreachable,
+            Failure::new(format!("Failed to check the wallet balance: {}", e)),
+        ),
+    }
+    client
+        .check_wallet_pool()
+        .await;
+
+    if !solana
+        .lookup_tables
+        .is_empty()
+    {
+        if let Err(e) = client
+            .load_lookup_tables(&solana.lookup_tables)
+            .await
+        {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to load the address lookup tables: {}", e))
+                    .help("Check TRACKER_LOOKUP_TABLES, the tables have to exist on the cluster"),
+            );
+        }
+    }
+    client
+}
+
+#[cfg(feature = "solana")]
+fn vault_init_failed(
+    config: &config::TrackerConfig,
+    solana: &config::SolanaConfig,
+    e: blkchn_file_tracker::error::Error,
+) -> ! {
+    exit(
+        ExitCode::VaultInitFailed,
+        Failure::new(format!("Failed to initialize the vault: {}", e)).help(format!(
+            "Check that the program {} (program_id from {}) is deployed on {}",
+            solana.program,
+            config.source("program_id"),
+            solana.rpc_url
+        )),
+    )
+}
+
+/// Starts the configured pipeline stages as a separate task, replaced on the `reloads`.
+/// Returns the sender feeding the pipeline, which passes the events on to `tx`.
+fn with_pipeline(
+    config: &config::TrackerConfig,
+    reloads: Option<&config::Reloads>,
+    tx: tokio::sync::mpsc::Sender<event::Event>,
+) -> tokio::sync::mpsc::Sender<event::Event> {
+    let mut pipeline = get_pipeline(config);
+    if let Some(reloads) = reloads {
+        pipeline = pipeline.with_reloads(reloads.pipeline.clone());
+    }
+    if pipeline.is_empty() {
+        return tx;
+    }
+
+    let (pipeline_tx, pipeline_rx) = tokio::sync::mpsc::channel(channel_capacity(config));
+    tokio::spawn(async move {
+        check_task(
+            "pipeline",
+            pipeline
+                .run(pipeline_rx, tx)
+                .await,
+        );
+    });
+    pipeline_tx
+}
+
+/// The configured pipeline stages, preceded by the ones of the enabled features.
+fn get_pipeline(config: &config::TrackerConfig) -> pipeline::Pipeline {
+    let mut pipeline = pipeline::Pipeline::new(&config.pipeline);
+    // Right before the configured stages, which filter by the types
+    if config.detect_file_types {
+        pipeline = pipeline.with_first_stage(Box::new(pipeline::file_type::FileTypes));
+    }
+    if let Some(receipts) = config.receipts.as_ref() {
+        let stage = pipeline::receipt::ReceiptFilter::new(receipts.clone());
+        pipeline = pipeline.with_first_stage(Box::new(stage));
+    }
+    if let Some(git) = config.git.as_ref() {
+        let stage = pipeline::git::GitFilter::new(git.clone());
+        pipeline = pipeline.with_first_stage(Box::new(stage));
+    }
+    if let Some(containers) = config
+        .watcher
+        .containers
+        .as_ref()
+    {
+        let stage = pipeline::container::Whiteouts::new(containers.clone());
+        pipeline = pipeline.with_first_stage(Box::new(stage));
+    }
+    #[cfg(target_os = "linux")]
+    if !config
+        .capture_xattrs
+        .is_empty()
+    {
+        let stage = pipeline::xattrs::XattrCapture::new(config.capture_xattrs.clone());
+        pipeline = pipeline.with_last_stage(Box::new(stage));
+    }
+    if let Some(max_size) = config.diff_max_size {
+        let stage = pipeline::diff::ContentDiff::new(max_size);
+        pipeline = pipeline.with_last_stage(Box::new(stage));
+    }
+    #[cfg(feature = "solana")]
+    if let Some(hashes) = config.integrity.as_ref() {
+        let stage = pipeline::integrity::OwnOpenFilter::new(hashes.clone());
+        pipeline = pipeline.with_first_stage(Box::new(stage));
+        // The digests are handed to the submitter in memory, in the same process
+        if config.mode == config::Mode::All && config.hash_workers > 0 {
+            let stage = pipeline::integrity::Hashing::new(config.hash_workers, hashes.clone());
+            pipeline = pipeline.with_last_stage(Box::new(stage));
+        }
+    }
+    pipeline
+}
+
+async fn get_sinks(config: &config::TrackerConfig) -> sink::FanOut {
+    let mut sinks = sink::FanOut::new().with_priorities(config.priorities.clone());
+    if let Some(capacity) = config.channel_capacity {
+        sinks = sinks
+            .with_capacity(capacity)
+            .with_backpressure(config.backpressure.clone());
+    }
+    if let Some(journal) = config.journal.as_ref() {
+        let journal = journal::Journal::open(journal).unwrap_or_else(|e| {
+            exit(
+                ExitCode::Failed,
+                Failure::new(format!("Failed to open the journal {}: {}", journal.dir, e)),
+            )
+        });
+        sinks = sinks.with_journal(journal);
+    }
+    // Measured by the Solana submitter, reported by the metrics sink
+    let latencies = config
+        .metrics
+        .then(sink::latency::Latencies::shared);
+    #[cfg(feature = "solana")]
+    if let Some(solana) = config.solana.as_ref() {
+        // Every program's vault checks the numbers of its own events, the default program's
+        // are the stream 0 and the ones of the n-th route the stream n
+        if !solana.routes.is_empty() {
+            let routing = solana.clone();
+            sinks = sinks.with_streams(move |path| {
+                routing
+                    .route(path)
+                    .and_then(|route| {
+                        routing
+                            .routes
+                            .iter()
+                            .position(|r| r == route)
+                    })
+                    .map_or(0, |i| i + 1)
+            });
+        }
+        // Numbered by the fan-out, confirmed by the program's Solana submitter
+        let programs = std::iter::once(solana.program).chain(
+            solana
+                .routes
+                .iter()
+                .map(|route| route.program),
+        );
+        let mut streams = Vec::new();
+        for (stream, program) in programs.enumerate() {
+            let completeness = solana
+                .completeness_report
+                .map(|interval| {
+                    let completeness = sink::completeness::Completeness::shared();
+                    // Told apart by their program once there are several
+                    let label = (!solana.routes.is_empty()).then(|| program.to_string());
+                    tokio::spawn(sink::completeness::report(
+                        completeness.clone(),
+                        label,
+                        interval,
+                    ));
+                    completeness
+                });
+            if let Some(completeness) = completeness.as_ref() {
+                sinks = sinks.with_completeness(stream, completeness.clone());
+            }
+            streams.push(completeness);
+        }
+        let measured = |client: solana_client::SolanaClient, stream: usize| {
+            let client = match latencies.as_ref() {
+                Some(latencies) => client.with_latencies(latencies.clone()),
+                None => client,
+            };
+            match streams[stream].as_ref() {
+                Some(completeness) => {
+                    client.with_completeness(completeness.clone(), solana.completeness_on_chain)
+                }
+                None => client,
+            }
+        };
+        // Unlocked once for all the programs
+        let wallet = get_wallet(solana, config.source("wallet_keypair"));
+        let client = measured(
+            get_solana_client(config, solana, wallet.insecure_clone()).await,
+            0,
+        );
+        let (size, max_latency) = (solana.batch_size, solana.batch_max_latency);
+        let sink: Box<dyn sink::EventSink> = if solana.routes.is_empty() {
+            Box::new(measured_sink(
+                anchor::AnchorSink::new(client).with_batching(size, max_latency),
+                latencies.as_ref(),
+            ))
+        } else {
+            let mut router = solana_client::router::ProgramRouter::new(solana, client);
+            for (i, route) in solana
+                .routes
+                .iter()
+                .enumerate()
+            {
+                let routed = solana.for_route(route);
+                let client = measured(
+                    get_solana_client(config, &routed, wallet.insecure_clone()).await,
+                    i + 1,
+                );
+                router = router.with_route(route, client);
+            }
+            Box::new(measured_sink(
+                anchor::AnchorSink::new(router).with_batching(size, max_latency),
+                latencies.as_ref(),
+            ))
+        };
+        sinks = sinks.with_sink(sink);
+    }
+    #[cfg(feature = "ethereum")]
+    if let Some(ethereum) = config.ethereum.as_ref() {
+        let anchor = anchor::ethereum::EthereumAnchor::from_config(ethereum);
+        sinks = sinks.with_sink(Box::new(anchor::AnchorSink::new(anchor)));
+    }
+    if let Some(url) = config.webhook_url.as_ref() {
+        sinks = sinks.with_sink(Box::new(sink::webhook::WebhookSink::new(url)));
+    }
+    if let Some(latencies) = latencies {
+        let metrics =
+            sink::metrics::MetricsSink::new().with_latencies(latencies, &config.metrics_file);
+        sinks = sinks.with_sink(Box::new(metrics));
+    }
+    if config.anomaly_detection {
+        let anomaly = sink::anomaly::AnomalySink::new(config.webhook_url.clone());
+        sinks = sinks.with_sink(Box::new(anomaly));
+    }
+    if sinks.is_empty() {
+        exit(
+            ExitCode::Config,
+            Failure::new("No event sinks configured").help(
+                "Set TRACKER_WEBHOOK_URL or TRACKER_METRICS, or build with the solana feature",
+            ),
+        );
+    }
+    sinks
+}
+
+#[cfg(feature = "solana")]
+fn measured_sink<A: anchor::ChainAnchor>(
+    sink: anchor::AnchorSink<A>,
+    latencies: Option<&sink::latency::SharedLatencies>,
+) -> anchor::AnchorSink<A> {
+    match latencies {
+        Some(latencies) => sink.with_latencies(latencies.clone()),
+        None => sink,
+    }
+}
+
+/// Starts the config file's reloads, if enabled.
+fn get_reloads(
+    config: &config::TrackerConfig,
+    shutdown: &supervisor::Shutdown,
+) -> Option<config::Reloads> {
+    let path = config.reload.as_ref()?;
+    let (reloader, reloads) = config::Reloader::new(path, config).unwrap_or_else(|e| {
+        exit(
+            ExitCode::Config,
+            Failure::new(format!("Failed to reload {}: {}", path, e))
+                .help("Check the config file (TRACKER_CONFIG) and the TRACKER_* variables"),
+        )
+    });
+    tokio::spawn(reloader.run(shutdown.clone()));
+    Some(reloads)
+}
+
+/// Runs the event source and the pipeline as last reloaded, restarted with the
+/// reloaded configuration.
+async fn watch_reloaded(
+    config: &config::TrackerConfig,
+    reloads: Option<&config::Reloads>,
+    shutdown: &supervisor::Shutdown,
+    tx: tokio::sync::mpsc::Sender<event::Event>,
+) -> Result<(), error::Error> {
+    let config = match reloads {
+        Some(reloads) => reloads.current(config),
+        None => config.clone(),
+    };
+    let tx = with_pipeline(&config, reloads, tx);
+    watch(&config, reloads, shutdown, tx).await
+}
+
+/// Runs the configured event source until the `shutdown`. The inotify watcher
+/// applies the reloaded watched paths and filters.
+async fn watch(
+    config: &config::TrackerConfig,
+    reloads: Option<&config::Reloads>,
+    shutdown: &supervisor::Shutdown,
+    tx: tokio::sync::mpsc::Sender<event::Event>,
+) -> Result<(), error::Error> {
+    // Applying the reloads to the inotify watcher and reporting its statistics until it stops
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut following: Vec<tokio::task::JoinHandle<()>> = Vec::new();
+    // Only the inotify watcher follows the reloads
+    #[cfg(not(target_os = "linux"))]
+    let _ = reloads;
+    let backend: Box<dyn dir_watcher::WatchBackend> = match &config.watcher.source {
+        #[cfg(target_os = "linux")]
+        config::EventSource::Inotify(_) => {
+            let watcher = get_dir_watcher(config).with_shutdown(shutdown.clone());
+            if let Some(reloads) = reloads {
+                following.push(tokio::spawn(
+                    watcher
+                        .controller()
+                        .follow(reloads.watcher.clone()),
+                ));
+            }
+            if let Some(interval) = config.watcher.stats_report {
+                following.push(tokio::spawn(dir_watcher::report_stats(
+                    watcher.stats(),
+                    interval,
+                )));
+            }
+            Box::new(watcher)
+        }
+        config::EventSource::Notify => Box::new(get_notify_watcher(config)),
+        config::EventSource::Poll(interval) => Box::new(get_poll_watcher(config, *interval)),
+        #[cfg(target_os = "linux")]
+        config::EventSource::Audit(path) => {
+            Box::new(audit::AuditSource::from_config(path, &config.watcher))
+        }
+        #[cfg(not(target_os = "linux"))]
+        config::EventSource::Inotify(_) | config::EventSource::Audit(_) => {
+            unreachable!("Validated, needs Linux")
+        }
+    };
+    println!("Watching {:?} with {}", config.watcher.dirs, backend.name());
+    // Once watched, the files changed during the scans are reported again
+    let resumed = match config
+        .watcher
+        .state_file
+        .as_ref()
+    {
+        Some(path) => {
+            let watcher = config.watcher.clone();
+            match tokio::task::spawn_blocking(move || dir_watcher::resumed_events(&watcher)).await?
+            {
+                Ok(Some(events)) => {
+                    println!(
+                        "Resumed from {}: {} changes while stopped",
+                        path,
+                        events.len()
+                    );
+                    Some(events)
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    println!("Failed to resume from {}: {}", path, e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    let events = match resumed {
+        Some(events) => events,
+        None if config.watcher.baseline_scan => {
+            let watcher = config.watcher.clone();
+            let events =
+                tokio::task::spawn_blocking(move || dir_watcher::baseline_events(&watcher)).await?;
+            println!("Baseline scan: {} existing files", events.len());
+            events
+        }
+        None => Vec::new(),
+    };
+    for event in events {
+        if tx.send(event).await.is_err() {
+            for task in following {
+                task.abort();
+            }
+            return Ok(());
+        }
+    }
+    // Saved until the watcher stops, and once more then
+    let keeping = config
+        .watcher
+        .state_file
+        .as_ref()
+        .map(|_| {
+            let (stop, stopped) = tokio::sync::oneshot::channel();
+            let keeping = tokio::spawn(dir_watcher::keep_state(config.watcher.clone(), stopped));
+            (stop, keeping)
+        });
+    // The inotify watcher sends its pending events before it stops, the others hold none
+    let stops_itself = matches!(config.watcher.source, config::EventSource::Inotify(_));
+    let result = tokio::select! {
+        result = backend.run(tx) => result,
+        _ = shutdown.requested(), if !stops_itself => Ok(()),
+    };
+    for task in following {
+        task.abort();
+    }
+    if let Some((stop, keeping)) = keeping {
+        let _ = stop.send(());
+        keeping.await?;
+    }
+    result
+}
+
+fn check_watch_dirs(config: &config::TrackerConfig) {
+    for dir in config.watcher.dirs.iter() {
+        if !std::path::Path::new(dir).is_dir() {
+            exit(
+                ExitCode::WatchSetupFailed,
+                Failure::new(format!(
+                    "Watched directory {} (watch_dirs from {}) doesn't exist or is not a directory",
+                    dir,
+                    config.source("watch_dirs")
+                ))
+                .help("Create the directory or fix TRACKER_WATCH_DIRS"),
+            );
+        }
+    }
+    for file in config.watcher.files.iter() {
+        if !std::path::Path::new(file).is_file() {
+            exit(
+                ExitCode::WatchSetupFailed,
+                Failure::new(format!(
+                    "Watched file {} (watch_files from {}) doesn't exist or is not a file",
+                    file,
+                    config.source("watch_files")
+                ))
+                .help("Create the file or fix TRACKER_WATCH_FILES"),
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_dir_watcher(config: &config::TrackerConfig) -> dir_watcher::DirWatcher {
+    check_watch_dirs(config);
+    dir_watcher::DirWatcher::from_config(&config.watcher).unwrap_or_else(|e| {
+        exit(
+            ExitCode::WatchSetupFailed,
+            Failure::new(format!("Failed to watch {:?}: {}", config.watcher.dirs, e))
+                .help("Check the permissions and the inotify limits (fs.inotify.max_user_watches)"),
+        )
+    })
+}
+
+fn get_notify_watcher(config: &config::TrackerConfig) -> dir_watcher::NotifyWatcher {
+    check_watch_dirs(config);
+    dir_watcher::NotifyWatcher::from_config(&config.watcher).unwrap_or_else(|e| {
+        exit(
+            ExitCode::WatchSetupFailed,
+            Failure::new(format!("Failed to watch {:?}: {}", config.watcher.dirs, e))
+                .help("Check the permissions, or use the poll event source instead"),
+        )
+    })
+}
+
+fn get_poll_watcher(
+    config: &config::TrackerConfig,
+    interval: std::time::Duration,
+) -> dir_watcher::PollWatcher {
+    check_watch_dirs(config);
+    dir_watcher::PollWatcher::from_config(&config.watcher, interval).unwrap_or_else(|e| {
+        exit(
+            ExitCode::WatchSetupFailed,
+            Failure::new(format!("Failed to poll {:?}: {}", config.watcher.dirs, e))
+                .help("Set TRACKER_EVENTS to created, written or deleted"),
+        )
+    })
+}
+
+/// The wallets paying the events' transactions in turn, `None` without a pool.
+#[cfg(feature = "solana")]
+fn get_wallet_pool(
+    config: &config::SolanaConfig,
+    source: config::Source,
+    wallet: &solana_sdk::signer::keypair::Keypair,
+) -> Option<solana_client::wallets::WalletPool> {
+    use solana_sdk::signer::Signer;
+
+    if config.wallet_pool.is_empty() {
+        return None;
+    }
+    let keypairs = config
+        .wallet_pool
+        .iter()
+        .map(|path| match solana_client::keypair::read(path) {
+            Ok(keypair) if keypair.pubkey() == wallet.pubkey() => exit(
+                ExitCode::Config,
+                Failure::new(format!(
+                    "The pool wallet {} (wallet_pool from {}) is the tracker's wallet",
+                    path, source
+                ))
+                .help("Pay the fees from other wallets, the tracker's wallet signs the events"),
+            ),
+            Ok(keypair) => keypair,
+            Err(e) => exit(
+                ExitCode::Config,
+                Failure::new(format!(
+                    "Failed to read the pool wallet keypair from {} (wallet_pool from {}): {}",
+                    path, source, e
+                ))
+                .help(format!(
+                    "Create it with `solana-keygen new -o {}` and fund it",
+                    path
+                )),
+            ),
+        })
+        .collect();
+    println!(
+        "Fees paid by a pool of {} wallets",
+        config.wallet_pool.len()
+    );
+    Some(solana_client::wallets::WalletPool::new(
+        keypairs,
+        config.wallet_pool_min_balance,
+    ))
+}
+
+#[cfg(feature = "solana")]
+fn get_wallet(
+    config: &config::SolanaConfig,
+    source: config::Source,
+) -> solana_sdk::signer::keypair::Keypair {
+    let wallet_keypair_path = &config.wallet_keypair;
     println!("Wallet keys obtained from: {}", wallet_keypair_path);
-    let wallet = solana_sdk::signer::keypair::read_keypair_file(wallet_keypair_path).unwrap();
-    return wallet;
+    solana_client::keypair::read(wallet_keypair_path).unwrap_or_else(|e| {
+        exit(
+            ExitCode::Config,
+            Failure::new(format!(
+                "Failed to read the wallet keypair from {} (wallet_keypair from {}): {}",
+                wallet_keypair_path, source, e
+            ))
+            .help(format!(
+                "Set TRACKER_WALLET_KEYPAIR or create one with `solana-keygen new -o {}`, \
+                 an encrypted one is unlocked with the {} systemd credential or a prompt",
+                wallet_keypair_path,
+                solana_client::keypair::PASSPHRASE_CREDENTIAL
+            )),
+        )
+    })
 }
@@ -0,0 +1,49 @@
+use crate::error::Error;
+use crate::event::Event;
+use crate::sink::webhook::event_to_json;
+use std::io::Write;
+
+pub const DEFAULT_DEAD_LETTER_PATH: &str = "./dead-letters.jsonl";
+
+/// Events which were not forwarded, appended to a file as JSON lines
+/// with the reason: `{"reason": "expired", "event": {...}}`.
+#[derive(Clone, Debug)]
+pub struct DeadLetters {
+    path: String,
+}
+
+impl DeadLetters {
+    pub fn new(path: &str) -> DeadLetters {
+        DeadLetters {
+            path: path.to_string(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub async fn append(&self, events: Vec<Event>, reason: &str) -> Result<(), Error> {
+        let mut lines = Vec::new();
+        for event in events.iter() {
+            let line = serde_json::json!({
+                "reason": reason,
+                "event": event_to_json(event),
+            });
+            serde_json::to_writer(&mut lines, &line).map_err(|e| Error::Serialization(e.into()))?;
+            lines.push(b'\n');
+        }
+
+        // Blocking file I/O, keep it off the async workers
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+                .write_all(&lines)
+        })
+        .await??;
+        Ok(())
+    }
+}
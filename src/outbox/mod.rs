@@ -0,0 +1,163 @@
+//! Events queued for forwarding to the submitter or the collector.
+//!
+//! While the receiver is down the events pile up in the outbox, up to the capacity of
+//! its channel, then the watcher waits for room. With a maximum age set, the events
+//! captured longer ago are not replayed one by one once it's back: they're moved to
+//! the dead-letter store and only the latest event of each of their files is forwarded,
+//! a summary of where the files ended up.
+
+pub mod dead_letter;
+
+use crate::dir_watcher::capture_time;
+use crate::event::Event;
+use dead_letter::DeadLetters;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub struct Outbox {
+    rx: mpsc::Receiver<Event>,
+    max_age: Option<Duration>,
+    dead_letters: DeadLetters,
+    // Ready to be forwarded, e.g. the summary of the expired events
+    ready: VecDeque<Event>,
+}
+
+impl Outbox {
+    /// Starts queueing the events from `rx`.
+    pub fn new(
+        rx: mpsc::Receiver<Event>,
+        max_age: Option<Duration>,
+        dead_letters: DeadLetters,
+    ) -> Outbox {
+        Outbox {
+            rx,
+            max_age,
+            dead_letters,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next event to forward, `None` once the input is closed and all are forwarded.
+    pub async fn recv(&mut self) -> Option<Event> {
+        if let Some(event) = self.ready.pop_front() {
+            return Some(event);
+        }
+        let event = self.rx.recv().await?;
+        let max_age = match self.max_age {
+            Some(max_age) if expired(&event, max_age) => max_age,
+            _ => return Some(event),
+        };
+
+        // All the expired events are queued before the first one which isn't
+        let mut expired_events = vec![event];
+        while let Ok(event) = self.rx.try_recv() {
+            if expired(&event, max_age) {
+                expired_events.push(event);
+            } else {
+                self.ready.push_back(event);
+                break;
+            }
+        }
+
+        let summary = latest_per_file(&expired_events);
+        println!(
+            "{} events captured longer than {:?} ago, forwarding the latest event of {} files instead",
+            expired_events.len(),
+            max_age,
+            summary.len()
+        );
+        if let Err(e) = self
+            .dead_letters
+            .append(expired_events, "expired")
+            .await
+        {
+            println!("Failed to store the expired events: {}", e);
+        }
+        for event in summary.into_iter().rev() {
+            self.ready.push_front(event);
+        }
+        self.ready.pop_front()
+    }
+}
+
+// Captured longer than `max_age` ago by the watcher's clock, the events of an unknown
+// capture time never expire
+fn expired(event: &Event, max_age: Duration) -> bool {
+    event.captured_at > 0
+        && capture_time().saturating_sub(event.captured_at) > max_age.as_millis() as i64
+}
+
+// The last event of each file, in the order of the last events
+fn latest_per_file(events: &[Event]) -> Vec<Event> {
+    let mut seen = HashSet::new();
+    let mut latest: Vec<Event> = events
+        .iter()
+        .rev()
+        .filter(|e| seen.insert(e.file_path.as_str()))
+        .cloned()
+        .collect();
+    latest.reverse();
+    latest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    fn event(path: &str, event_type: EventType, captured_at: i64) -> Event {
+        Event {
+            captured_at,
+            ..Event::new(path, event_type)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_events_summarized() {
+        let dir = tempfile::tempdir().unwrap();
+        let dead_letters_path = dir
+            .path()
+            .join("dead-letters.jsonl");
+        let (tx, rx) = mpsc::channel(8);
+        let mut outbox = Outbox::new(
+            rx,
+            Some(Duration::from_millis(50)),
+            DeadLetters::new(
+                dead_letters_path
+                    .to_str()
+                    .unwrap(),
+            ),
+        );
+
+        let now = capture_time();
+        tx.send(event("/srv/a", EventType::Created, now - 300))
+            .await
+            .unwrap();
+        tx.send(event("/srv/b", EventType::Written, now - 200))
+            .await
+            .unwrap();
+        tx.send(event("/srv/a", EventType::Deleted, now - 100))
+            .await
+            .unwrap();
+        tx.send(event("/srv/c", EventType::Created, now))
+            .await
+            .unwrap();
+        drop(tx);
+
+        let mut forwarded = Vec::new();
+        while let Some(event) = outbox.recv().await {
+            forwarded.push(event);
+        }
+        assert_eq!(
+            forwarded,
+            vec![
+                event("/srv/b", EventType::Written, now - 200),
+                event("/srv/a", EventType::Deleted, now - 100),
+                event("/srv/c", EventType::Created, now),
+            ]
+        );
+        let stored = std::fs::read_to_string(dead_letters_path).unwrap();
+        assert_eq!(stored.lines().count(), 3);
+    }
+}
@@ -0,0 +1,33 @@
+use super::Stage;
+use crate::container::Containers;
+use crate::event::{Event, EventType};
+
+/// Reports the whiteouts created in the containers' upper directories as deletions,
+/// a file of the image's layers removed inside the container leaves one behind.
+pub struct Whiteouts {
+    containers: Containers,
+}
+
+impl Whiteouts {
+    pub fn new(containers: Containers) -> Whiteouts {
+        Whiteouts { containers }
+    }
+}
+
+impl Stage for Whiteouts {
+    fn name(&self) -> &str {
+        "whiteouts"
+    }
+
+    fn process(&mut self, mut event: Event) -> Vec<Event> {
+        if matches!(event.event_type, EventType::Created | EventType::MovedTo)
+            && self
+                .containers
+                .is_whiteout(&event.file_path)
+        {
+            event.event_type = EventType::Deleted;
+            event.file_info = None;
+        }
+        vec![event]
+    }
+}
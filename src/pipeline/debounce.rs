@@ -0,0 +1,196 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Coalesces the bursts of writes and attribute changes of a file, e.g. an editor saving
+/// it, into its last event, released once the file is quiet for the `window`. A file
+/// written continuously is released at least every `max_wait`. The other events of the
+/// file release the held one first, keeping the order.
+pub struct Debounce {
+    window: Duration,
+    max_wait: Duration,
+    held: HashMap<String, Held>,
+    // Orders the released events as they were first held
+    seq: u64,
+}
+
+struct Held {
+    event: Event,
+    seq: u64,
+    first: Instant,
+    last: Instant,
+}
+
+impl Debounce {
+    pub fn new(window: Duration, max_wait: Duration) -> Debounce {
+        Debounce {
+            window,
+            max_wait,
+            held: HashMap::new(),
+            seq: 0,
+        }
+    }
+
+    fn release(&mut self, due: impl Fn(&Held) -> bool) -> Vec<Event> {
+        let paths: Vec<String> = self
+            .held
+            .iter()
+            .filter(|(_, held)| due(held))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let mut released: Vec<Held> = paths
+            .iter()
+            .filter_map(|path| self.held.remove(path))
+            .collect();
+        released.sort_by_key(|held| held.seq);
+        released
+            .into_iter()
+            .map(|held| held.event)
+            .collect()
+    }
+}
+
+impl Stage for Debounce {
+    fn name(&self) -> &str {
+        "debounce"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        self.process_at(event, Instant::now())
+    }
+
+    fn process_at(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        if !matches!(
+            event.event_type,
+            EventType::Written | EventType::AttributeChanged
+        ) {
+            return match self
+                .held
+                .remove(&event.file_path)
+            {
+                Some(held) => vec![held.event, event],
+                None => vec![event],
+            };
+        }
+        match self
+            .held
+            .get_mut(&event.file_path)
+        {
+            Some(held) => {
+                held.last = now;
+                // A write isn't hidden by the attribute changes following it
+                if event.event_type == EventType::Written
+                    || held.event.event_type == EventType::AttributeChanged
+                {
+                    held.event = event;
+                } else {
+                    held.event.file_info = event.file_info;
+                }
+            }
+            None => {
+                self.seq += 1;
+                self.held.insert(
+                    event.file_path.clone(),
+                    Held {
+                        event,
+                        seq: self.seq,
+                        first: now,
+                        last: now,
+                    },
+                );
+            }
+        }
+        Vec::new()
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Event> {
+        let (window, max_wait) = (self.window, self.max_wait);
+        self.release(|held| {
+            now.duration_since(held.last) >= window || now.duration_since(held.first) >= max_wait
+        })
+    }
+
+    fn drain(&mut self) -> Vec<Event> {
+        self.release(|_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::FileInfo;
+
+    fn event(path: &str, event_type: EventType, size: u64) -> Event {
+        Event {
+            file_info: Some(FileInfo {
+                size,
+                mode: 0o644,
+                access_ts: None,
+                modify_ts: None,
+                created_ts: None,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            }),
+            ..Event::new(path, event_type)
+        }
+    }
+
+    #[test]
+    fn test_bursts_coalesced() {
+        let mut debounce = Debounce::new(Duration::from_millis(500), Duration::from_secs(2));
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        for (ms, size) in [(0, 1), (100, 2), (200, 3)] {
+            assert!(debounce
+                .process_at(event("/a", EventType::Written, size), at(ms))
+                .is_empty());
+        }
+        debounce.process_at(event("/a", EventType::AttributeChanged, 4), at(300));
+        debounce.process_at(event("/b", EventType::AttributeChanged, 1), at(300));
+        assert!(debounce
+            .tick(at(700))
+            .is_empty());
+        // The write with the metadata of the last attribute change
+        assert_eq!(
+            debounce.tick(at(800)),
+            vec![
+                event("/a", EventType::Written, 4),
+                event("/b", EventType::AttributeChanged, 1)
+            ]
+        );
+
+        // Released before the file's deletion
+        debounce.process_at(event("/a", EventType::Written, 5), at(900));
+        assert_eq!(
+            debounce.process_at(event("/a", EventType::Deleted, 0), at(950)),
+            vec![
+                event("/a", EventType::Written, 5),
+                event("/a", EventType::Deleted, 0)
+            ]
+        );
+
+        // Written continuously, released after the maximum wait
+        for ms in (1000..3000).step_by(100) {
+            debounce.process_at(event("/c", EventType::Written, ms), at(ms));
+            assert!(debounce
+                .tick(at(ms))
+                .is_empty());
+        }
+        assert_eq!(
+            debounce.tick(at(3000)),
+            vec![event("/c", EventType::Written, 2900)]
+        );
+        assert!(debounce.drain().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Larger files are compared by their metadata only
+const MAX_HASHED_SIZE: u64 = 1024 * 1024;
+
+/// Drops the events repeating the previous event of the file within `window`: of the same
+/// type, or a write or an attribute change right after the file's creation or write, with
+/// the same size, modification time, mode, owner and content. E.g. the attribute change and
+/// the write some tools make right after creating a file add nothing to its creation.
+pub struct Dedup {
+    window: Duration,
+    last: HashMap<String, Seen>,
+}
+
+struct Seen {
+    at: Instant,
+    event_type: EventType,
+    stamp: Option<Stamp>,
+}
+
+#[derive(PartialEq)]
+struct Stamp {
+    size: u64,
+    modify_ts: Option<i128>,
+    mode: u32,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    // CRC32 of the content, none for the large files
+    crc: Option<u32>,
+}
+
+impl Stamp {
+    fn of(event: &Event) -> Option<Stamp> {
+        let info = event.file_info.as_ref()?;
+        let crc = match info.size <= MAX_HASHED_SIZE && !info.is_symlink {
+            true => crc(&event.file_path).ok(),
+            false => None,
+        };
+        Some(Stamp {
+            size: info.size,
+            modify_ts: info.modify_ts,
+            mode: info.mode,
+            uid: info.uid,
+            gid: info.gid,
+            crc,
+        })
+    }
+}
+
+fn crc(path: &str) -> std::io::Result<u32> {
+    Ok(crc32fast::hash(&std::fs::read(path)?))
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Dedup {
+        Dedup {
+            window,
+            last: HashMap::new(),
+        }
+    }
+}
+
+// Whether the event adds nothing to the previous one of its file
+fn repeats(previous: &EventType, event_type: &EventType) -> bool {
+    previous == event_type
+        || (matches!(
+            previous,
+            EventType::Created | EventType::Written | EventType::AttributeChanged
+        ) && matches!(event_type, EventType::Written | EventType::AttributeChanged))
+}
+
+impl Stage for Dedup {
+    fn name(&self) -> &str {
+        "dedup"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        self.process_at(event, Instant::now())
+    }
+
+    fn process_at(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        let stamp = Stamp::of(&event);
+        let duplicate = self
+            .last
+            .get(&event.file_path)
+            .is_some_and(|seen| {
+                now.saturating_duration_since(seen.at) < self.window
+                    && repeats(&seen.event_type, &event.event_type)
+                    && seen.stamp == stamp
+            });
+        if duplicate {
+            return Vec::new();
+        }
+        self.last.insert(
+            event.file_path.clone(),
+            Seen {
+                at: now,
+                event_type: event.event_type.clone(),
+                stamp,
+            },
+        );
+        vec![event]
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Event> {
+        let window = self.window;
+        self.last
+            .retain(|_, seen| now.saturating_duration_since(seen.at) < window);
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_watcher::read_file_info;
+
+    fn event(path: &str, event_type: EventType) -> Event {
+        Event {
+            file_info: read_file_info(path),
+            ..Event::new(path, event_type)
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_repeated_events_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        let file = file.to_str().unwrap();
+        std::fs::write(file, "test").unwrap();
+        let mut dedup = Dedup::new(Duration::from_millis(500));
+        let now = Instant::now();
+        let passed = |dedup: &mut Dedup, event_type: EventType, at: Instant| {
+            dedup
+                .process_at(event(file, event_type), at)
+                .len()
+        };
+
+        assert_eq!(passed(&mut dedup, EventType::Created, now), 1);
+        assert_eq!(passed(&mut dedup, EventType::AttributeChanged, now), 0);
+        assert_eq!(passed(&mut dedup, EventType::Written, now), 0);
+        // Another event of the file
+        assert_eq!(passed(&mut dedup, EventType::Opened, now), 1);
+        assert_eq!(passed(&mut dedup, EventType::Written, now), 1);
+
+        // Changed
+        std::fs::write(file, "tesT").unwrap();
+        assert_eq!(passed(&mut dedup, EventType::Written, now), 1);
+        std::fs::set_permissions(file, std::os::unix::fs::PermissionsExt::from_mode(0o604))
+            .unwrap();
+        assert_eq!(passed(&mut dedup, EventType::AttributeChanged, now), 1);
+
+        // Out of the window
+        let later = now + Duration::from_secs(1);
+        assert_eq!(passed(&mut dedup, EventType::AttributeChanged, later), 1);
+        dedup.tick(later + Duration::from_secs(1));
+        assert!(dedup.last.is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+use similar::TextDiff;
+use std::collections::{HashMap, VecDeque};
+
+// The contents kept for the next diffs, the least recently read ones are forgotten first
+const MAX_KEPT_BYTES: usize = 64 * 1024 * 1024;
+// Unchanged lines around each change in the diffs
+const CONTEXT_LINES: usize = 3;
+// Longer diffs are left out, the event has to fit in a frame to the submitter
+const MAX_DIFF_LEN: usize = 32 * 1024;
+
+/// Attaches a unified diff from the previous content to the written events of the small
+/// text files, e.g. the config files: the content of each one is kept from its creation
+/// or first write, so the next write shows the lines changed.
+pub struct ContentDiff {
+    max_size: u64,
+    contents: HashMap<String, String>,
+    // The kept files, the least recently read first
+    order: VecDeque<String>,
+    kept_bytes: usize,
+}
+
+impl ContentDiff {
+    /// Diffs the text files of at most `max_size` bytes.
+    pub fn new(max_size: u64) -> ContentDiff {
+        ContentDiff {
+            max_size,
+            contents: HashMap::new(),
+            order: VecDeque::new(),
+            kept_bytes: 0,
+        }
+    }
+
+    // The content if the file is small and text, none otherwise
+    fn read(&self, path: &str) -> Option<String> {
+        let metadata = std::fs::metadata(path).ok()?;
+        if !metadata.is_file() || metadata.len() > self.max_size {
+            return None;
+        }
+        let content = String::from_utf8(std::fs::read(path).ok()?).ok()?;
+        // Valid UTF-8 but binary
+        (!content.contains('\0')).then_some(content)
+    }
+
+    fn keep(&mut self, path: &str, content: String) {
+        self.forget(path);
+        self.kept_bytes += content.len();
+        self.contents
+            .insert(path.to_string(), content);
+        self.order
+            .push_back(path.to_string());
+        while self.kept_bytes > MAX_KEPT_BYTES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(content) = self.contents.remove(&oldest) {
+                self.kept_bytes -= content.len();
+            }
+        }
+    }
+
+    fn forget(&mut self, path: &str) -> Option<String> {
+        let content = self.contents.remove(path)?;
+        self.kept_bytes -= content.len();
+        self.order
+            .retain(|kept| kept != path);
+        Some(content)
+    }
+}
+
+impl Stage for ContentDiff {
+    fn name(&self) -> &str {
+        "diff"
+    }
+
+    fn process(&mut self, mut event: Event) -> Vec<Event> {
+        match &event.event_type {
+            EventType::Created => {
+                if let Some(content) = self.read(&event.file_path) {
+                    self.keep(&event.file_path, content);
+                }
+            }
+            EventType::Written => {
+                let previous = self.forget(&event.file_path);
+                // Not diffed further once it's too big or binary
+                if let Some(content) = self.read(&event.file_path) {
+                    event.diff = previous
+                        .filter(|previous| *previous != content)
+                        .map(|previous| unified_diff(&event.file_path, &previous, &content))
+                        .filter(|diff| diff.len() <= MAX_DIFF_LEN);
+                    self.keep(&event.file_path, content);
+                }
+            }
+            EventType::Renamed { from, to } => {
+                if let Some(content) = self.forget(from) {
+                    self.keep(to, content);
+                }
+            }
+            EventType::Deleted | EventType::MovedFrom => {
+                self.forget(&event.file_path);
+            }
+            _ => (),
+        }
+        vec![event]
+    }
+}
+
+fn unified_diff(path: &str, previous: &str, content: &str) -> String {
+    TextDiff::from_lines(previous, content)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(path, path)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(path: &str, event_type: EventType) -> Event {
+        Event::new(path, event_type)
+    }
+
+    #[test]
+    fn test_written_text_files_diffed() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf = dir.path().join("app.conf");
+        let conf = conf.to_str().unwrap();
+        let binary = dir.path().join("app.bin");
+        let binary = binary.to_str().unwrap();
+        let mut stage = ContentDiff::new(1024);
+
+        std::fs::write(conf, "port = 80\nhost = a\n").unwrap();
+        std::fs::write(binary, [0u8, 1, 2]).unwrap();
+        for path in [conf, binary] {
+            let created = stage.process(event(path, EventType::Created));
+            assert_eq!(created[0].diff, None);
+        }
+
+        std::fs::write(conf, "port = 8080\nhost = a\n").unwrap();
+        let written = stage.process(event(conf, EventType::Written));
+        let diff = written[0]
+            .diff
+            .as_ref()
+            .unwrap();
+        assert!(diff.contains("-port = 80\n+port = 8080\n"));
+        // Unchanged since
+        let written = stage.process(event(conf, EventType::Written));
+        assert_eq!(written[0].diff, None);
+
+        std::fs::write(binary, [0u8, 1, 3]).unwrap();
+        let written = stage.process(event(binary, EventType::Written));
+        assert_eq!(written[0].diff, None);
+
+        // Too big to be kept
+        std::fs::write(conf, "x".repeat(2048)).unwrap();
+        stage.process(event(conf, EventType::Written));
+        std::fs::write(conf, "port = 80\n").unwrap();
+        let written = stage.process(event(conf, EventType::Written));
+        assert_eq!(written[0].diff, None);
+    }
+}
@@ -0,0 +1,86 @@
+use super::Stage;
+use crate::event::{Event, EventType, FileKind};
+
+/// Sniffs the MIME type of the created and written files from their first bytes into
+/// their file info, e.g. `image/png` or `application/x-executable`. The text files have
+/// no magic bytes, their type is left unset.
+pub struct FileTypes;
+
+impl Stage for FileTypes {
+    fn name(&self) -> &str {
+        "file_types"
+    }
+
+    fn process(&mut self, mut event: Event) -> Vec<Event> {
+        if !matches!(event.event_type, EventType::Created | EventType::Written)
+            || event.kind != FileKind::File
+        {
+            return vec![event];
+        }
+        // Gone already if it has no file info
+        let path = event.file_path.clone();
+        if let Some(info) = event.file_info.as_mut() {
+            match infer::get_from_path(&path) {
+                Ok(file_type) => {
+                    info.file_type = file_type.map(|file_type| {
+                        file_type
+                            .mime_type()
+                            .to_string()
+                    })
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => println!("Failed to read the type of {}: {}", path, e),
+            }
+        }
+        vec![event]
+    }
+}
+
+/// Whether the MIME type is one of the `types`, or starts with one ending with `/*`,
+/// e.g. `image/*`.
+pub fn matches(types: &[String], file_type: &str) -> bool {
+    types
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => file_type.starts_with(prefix),
+            None => file_type == pattern,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dir_watcher::read_file_info;
+
+    #[test]
+    fn test_types_sniffed() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("a.dat");
+        std::fs::write(&png, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let text = dir.path().join("b.txt");
+        std::fs::write(&text, "port = 80\n").unwrap();
+
+        let mut stage = FileTypes;
+        let mut types = Vec::new();
+        for path in [&png, &text] {
+            let path = path.to_str().unwrap();
+            let event = Event {
+                file_info: read_file_info(path),
+                ..Event::new(path, EventType::Written)
+            };
+            let event = stage.process(event).remove(0);
+            types.push(
+                event
+                    .file_info
+                    .unwrap()
+                    .file_type,
+            );
+        }
+        assert_eq!(types, vec![Some("image/png".to_string()), None]);
+
+        let patterns = vec!["image/*".to_string(), "application/pdf".to_string()];
+        assert!(matches(&patterns, "image/png"));
+        assert!(matches(&patterns, "application/pdf"));
+        assert!(!matches(&patterns, "application/zip"));
+    }
+}
@@ -0,0 +1,100 @@
+use super::{file_type, Stage};
+use crate::event::Event;
+
+/// Passes only the events of files matching the include prefixes
+/// (all files if none given) and none of the exclude prefixes.
+pub struct PathFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    include_types: Vec<String>,
+    exclude_types: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> PathFilter {
+        PathFilter {
+            include,
+            exclude,
+            include_types: Vec::new(),
+            exclude_types: Vec::new(),
+        }
+    }
+
+    /// Passes only the events of the files of the included MIME types, if any given,
+    /// and none of the excluded ones. The files without a sniffed type are of none.
+    pub fn with_types(mut self, include: Vec<String>, exclude: Vec<String>) -> PathFilter {
+        self.include_types = include;
+        self.exclude_types = exclude;
+        self
+    }
+
+    fn matches_type(&self, file_type: Option<&str>) -> bool {
+        match file_type {
+            Some(file_type) => {
+                (self.include_types.is_empty()
+                    || file_type::matches(&self.include_types, file_type))
+                    && !file_type::matches(&self.exclude_types, file_type)
+            }
+            None => self.include_types.is_empty(),
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let included = self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| path.starts_with(p));
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|p| path.starts_with(p))
+    }
+}
+
+impl Stage for PathFilter {
+    fn name(&self) -> &str {
+        "filter"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        let file_type = event
+            .file_info
+            .as_ref()
+            .and_then(|info| info.file_type.as_deref());
+        if self.matches(&event.file_path) && self.matches_type(file_type) {
+            vec![event]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_and_exclude() {
+        let filter = PathFilter::new(vec!["/etc/".to_string()], vec!["/etc/ssl/".to_string()]);
+        assert!(filter.matches("/etc/passwd"));
+        assert!(!filter.matches("/etc/ssl/cert.pem"));
+        assert!(!filter.matches("/var/log/syslog"));
+    }
+
+    #[test]
+    fn test_include_and_exclude_types() {
+        let filter = PathFilter::new(vec![], vec![])
+            .with_types(vec!["image/*".to_string()], vec!["image/gif".to_string()]);
+        assert!(filter.matches_type(Some("image/png")));
+        assert!(!filter.matches_type(Some("image/gif")));
+        assert!(!filter.matches_type(Some("application/pdf")));
+        assert!(!filter.matches_type(None));
+
+        let filter =
+            PathFilter::new(vec![], vec![]).with_types(vec![], vec!["image/*".to_string()]);
+        assert!(!filter.matches_type(Some("image/png")));
+        assert!(filter.matches_type(None));
+    }
+}
@@ -0,0 +1,31 @@
+use super::Stage;
+use crate::event::Event;
+use crate::git::Repositories;
+
+/// Drops the changes git makes in the `.git/` directories of the watched repositories.
+pub struct GitFilter {
+    repos: Repositories,
+}
+
+impl GitFilter {
+    pub fn new(repos: Repositories) -> GitFilter {
+        GitFilter { repos }
+    }
+}
+
+impl Stage for GitFilter {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        if self
+            .repos
+            .is_git_internal(&event.file_path)
+        {
+            Vec::new()
+        } else {
+            vec![event]
+        }
+    }
+}
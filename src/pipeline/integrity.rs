@@ -0,0 +1,204 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+use crate::integrity::pool::{Digest, HashPool};
+use crate::integrity::ContentHashes;
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// Drops the opened events caused by hashing the files to record or verify them.
+pub struct OwnOpenFilter {
+    hashes: ContentHashes,
+}
+
+impl OwnOpenFilter {
+    pub fn new(hashes: ContentHashes) -> OwnOpenFilter {
+        OwnOpenFilter { hashes }
+    }
+}
+
+impl Stage for OwnOpenFilter {
+    fn name(&self) -> &str {
+        "integrity"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        if event.event_type == EventType::Opened
+            && self
+                .hashes
+                .is_own_open(&event.file_path)
+        {
+            Vec::new()
+        } else {
+            vec![event]
+        }
+    }
+}
+
+/// Hashes the content of the files recorded on chain on the [HashPool]'s threads, the
+/// events wait here for their digest, attached to them for the submitter, instead of in
+/// the submitter. The events keep their order, the ones after an event being hashed wait
+/// for it too.
+pub struct Hashing {
+    pool: HashPool,
+    // The events waiting for the digests, in their order, with the job hashing the file
+    held: VecDeque<(Event, Option<u64>)>,
+    finished: HashMap<u64, Digest>,
+}
+
+impl Hashing {
+    pub fn new(workers: usize, hashes: ContentHashes) -> Hashing {
+        Hashing {
+            pool: HashPool::new(workers, hashes),
+            held: VecDeque::new(),
+            finished: HashMap::new(),
+        }
+    }
+
+    // The first events whose digest, and the digests of the events before them, are ready
+    fn release(&mut self) -> Vec<Event> {
+        let mut out = Vec::new();
+        while let Some((_, job)) = self.held.front() {
+            let digest = match job {
+                Some(id) => match self.finished.remove(id) {
+                    Some(digest) => Some(digest),
+                    None => break,
+                },
+                None => None,
+            };
+            let Some((mut event, _)) = self.held.pop_front() else {
+                break;
+            };
+            match digest {
+                Some(Ok(sha256)) => event.sha256 = Some(sha256),
+                // The submitter hashes it again, and tells if it's gone
+                Some(Err(e)) => println!("Failed to hash {}: {}", event.file_path, e),
+                None => (),
+            }
+            out.push(event);
+        }
+        out
+    }
+}
+
+impl Stage for Hashing {
+    fn name(&self) -> &str {
+        "hashing"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        // The content recorded with the event, see the submitter
+        let symlink = event
+            .file_info
+            .as_ref()
+            .is_some_and(|info| info.is_symlink);
+        let job = match event.event_type {
+            EventType::Created
+            | EventType::MovedTo
+            | EventType::Written
+            | EventType::Renamed { .. }
+                if !symlink =>
+            {
+                Some(
+                    self.pool
+                        .submit(&event.file_path),
+                )
+            }
+            _ => None,
+        };
+        if job.is_none() && self.held.is_empty() {
+            return vec![event];
+        }
+        self.held
+            .push_back((event, job));
+        Vec::new()
+    }
+
+    fn tick(&mut self, _now: Instant) -> Vec<Event> {
+        let finished = self.pool.finished();
+        self.finished.extend(finished);
+        self.release()
+    }
+
+    // Blocks until the files are hashed, run off the async workers, see [super::Pipeline::run]
+    fn drain(&mut self) -> Vec<Event> {
+        let finished = self.pool.wait_all();
+        self.finished.extend(finished);
+        self.release()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_events_wait_for_their_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| {
+            dir.path()
+                .join(name)
+                .to_string_lossy()
+                .to_string()
+        };
+        std::fs::write(path("a.txt"), "test").unwrap();
+        let hashes = ContentHashes::load(&path("hashes.json")).unwrap();
+        let mut stage = Hashing::new(2, hashes.clone());
+        let event = |event_type: EventType, name: &str| Event::new(path(name), event_type);
+
+        assert!(stage
+            .process(event(EventType::Written, "a.txt"))
+            .is_empty());
+        // Behind the written event, of its file or another one
+        assert!(stage
+            .process(event(EventType::AttributeChanged, "a.txt"))
+            .is_empty());
+        assert!(stage
+            .process(event(EventType::Deleted, "b.txt"))
+            .is_empty());
+
+        let start = Instant::now();
+        let mut released = Vec::new();
+        while released.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            released = stage.tick(Instant::now());
+        }
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert_eq!(
+            released,
+            vec![
+                Event {
+                    sha256: Some(sha256.to_string()),
+                    ..event(EventType::Written, "a.txt")
+                },
+                event(EventType::AttributeChanged, "a.txt"),
+                event(EventType::Deleted, "b.txt")
+            ]
+        );
+        // The hashing's own open
+        assert!(hashes.is_own_open(&path("a.txt")));
+        // Nothing held, passed on
+        assert_eq!(
+            stage.process(event(EventType::Deleted, "b.txt")),
+            vec![event(EventType::Deleted, "b.txt")]
+        );
+
+        // Each written event with the digest of its own content
+        let mut written = stage.process(event(EventType::Written, "a.txt"));
+        written.extend(stage.drain());
+        std::fs::write(path("a.txt"), "other").unwrap();
+        written.extend(stage.process(event(EventType::Written, "a.txt")));
+        written.extend(stage.drain());
+        let written: Vec<_> = written
+            .into_iter()
+            .map(|event| event.sha256)
+            .collect();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0].as_deref(), Some(sha256));
+        assert!(written[1].is_some() && written[1] != written[0]);
+
+        // A file gone before it's hashed is passed on
+        stage.process(event(EventType::Created, "c.txt"));
+        assert_eq!(stage.drain(), vec![event(EventType::Created, "c.txt")]);
+    }
+}
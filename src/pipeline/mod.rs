@@ -0,0 +1,477 @@
+//! Middleware pipeline between the watcher and the sinks.
+//!
+//! The pipeline is a chain of [Stage]s, each one can drop, hold back, modify
+//! or multiply the events passed through it. Stages are configured declaratively:
+//!
+//! ```toml
+//! [[pipeline]]
+//! stage = "filter"
+//! exclude = ["./tmp/cache/"]
+//!
+//! [[pipeline]]
+//! stage = "debounce"
+//! window_ms = 500
+//! ```
+
+pub mod container;
+pub mod debounce;
+pub mod dedup;
+pub mod diff;
+pub mod file_type;
+pub mod filter;
+pub mod git;
+#[cfg(feature = "solana")]
+pub mod integrity;
+pub mod rate_limit;
+pub mod receipt;
+#[cfg(target_os = "linux")]
+pub mod xattrs;
+
+use crate::error::Error;
+use crate::event::Event;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+
+// How often the stages get a chance to release the events they hold back
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub trait Stage: Send {
+    /// Name used in the logs.
+    fn name(&self) -> &str;
+
+    /// Processes one event, returns the events to pass to the next stage.
+    fn process(&mut self, event: Event) -> Vec<Event>;
+
+    /// Like [Self::process], with the time the event arrived at the stage,
+    /// for the stages timing the events. Simulated in the replays.
+    fn process_at(&mut self, event: Event, _now: Instant) -> Vec<Event> {
+        self.process(event)
+    }
+
+    /// Called periodically, returns the held back events which are due.
+    fn tick(&mut self, _now: Instant) -> Vec<Event> {
+        Vec::new()
+    }
+
+    /// Called once when the input is closed, returns all the held back events.
+    /// Called off the async workers then, it may block.
+    fn drain(&mut self) -> Vec<Event> {
+        Vec::new()
+    }
+}
+
+/// Declarative configuration of a single stage.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StageConfig {
+    Filter {
+        // path prefixes, empty means everything is included
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+        // MIME types, or their prefixes ending with /*, sniffed with detect_file_types
+        #[serde(default)]
+        include_types: Vec<String>,
+        #[serde(default)]
+        exclude_types: Vec<String>,
+    },
+    Debounce {
+        // quiet period of a file before its held event is released
+        #[serde(default = "default_window_ms")]
+        window_ms: u64,
+        // longest a file's events are held, 10 windows if not given
+        #[serde(default)]
+        max_wait_ms: Option<u64>,
+    },
+    Dedup {
+        // how long an event of a file is repeated by the next ones
+        #[serde(default = "default_dedup_window_ms")]
+        window_ms: u64,
+    },
+    RateLimit {
+        // events of a file let through per second on average
+        #[serde(default = "default_per_second")]
+        per_second: f64,
+        // events of a file let through at once, a second's worth if not given
+        #[serde(default)]
+        burst: Option<u32>,
+    },
+}
+
+fn default_window_ms() -> u64 {
+    500
+}
+
+fn default_dedup_window_ms() -> u64 {
+    1000
+}
+
+fn default_per_second() -> f64 {
+    10.0
+}
+
+impl StageConfig {
+    fn build(&self) -> Box<dyn Stage> {
+        match self {
+            StageConfig::Filter {
+                include,
+                exclude,
+                include_types,
+                exclude_types,
+            } => Box::new(
+                filter::PathFilter::new(include.clone(), exclude.clone())
+                    .with_types(include_types.clone(), exclude_types.clone()),
+            ),
+            StageConfig::Debounce {
+                window_ms,
+                max_wait_ms,
+            } => Box::new(debounce::Debounce::new(
+                Duration::from_millis(*window_ms),
+                Duration::from_millis(max_wait_ms.unwrap_or(window_ms * 10)),
+            )),
+            StageConfig::Dedup { window_ms } => {
+                Box::new(dedup::Dedup::new(Duration::from_millis(*window_ms)))
+            }
+            StageConfig::RateLimit { per_second, burst } => Box::new(rate_limit::RateLimit::new(
+                *per_second,
+                burst.unwrap_or(per_second.ceil() as u32),
+            )),
+        }
+    }
+}
+
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    // Stages before the configured ones, and how many are configured
+    first: usize,
+    configured: usize,
+    // The configured stages replaced while running
+    reloads: Option<watch::Receiver<Vec<StageConfig>>>,
+}
+
+impl Pipeline {
+    pub fn new(config: &[StageConfig]) -> Pipeline {
+        Pipeline {
+            stages: config
+                .iter()
+                .map(|c| c.build())
+                .collect(),
+            first: 0,
+            configured: config.len(),
+            reloads: None,
+        }
+    }
+
+    /// Puts the stage before the configured ones.
+    pub fn with_first_stage(mut self, stage: Box<dyn Stage>) -> Pipeline {
+        self.stages.insert(0, stage);
+        self.first += 1;
+        self
+    }
+
+    /// Replaces the configured stages whenever `configs` change, the events they hold
+    /// back are released first.
+    pub fn with_reloads(mut self, mut configs: watch::Receiver<Vec<StageConfig>>) -> Pipeline {
+        // The configured stages are built from the current one
+        configs.borrow_and_update();
+        self.reloads = Some(configs);
+        self
+    }
+
+    /// Puts the stage after the configured ones.
+    pub fn with_last_stage(mut self, stage: Box<dyn Stage>) -> Pipeline {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Without stages, and none to be reloaded.
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty() && self.reloads.is_none()
+    }
+
+    /// Passes the events from `rx` through all the stages to `tx`
+    /// until `rx` is closed, then drains the stages.
+    pub async fn run(
+        mut self,
+        mut rx: mpsc::Receiver<Event>,
+        tx: mpsc::Sender<Event>,
+    ) -> Result<(), Error> {
+        let names: Vec<&str> = self
+            .stages
+            .iter()
+            .map(|s| s.name())
+            .collect();
+        println!("Event pipeline: {}", names.join(" -> "));
+
+        let mut ticker = tokio::time::interval(TICK_INTERVAL);
+        let mut reloads = self.reloads.take();
+        loop {
+            let out = tokio::select! {
+                event = rx.recv() => match event {
+                    Some(event) => self.process(event, Instant::now()),
+                    None => break,
+                },
+                _ = ticker.tick() => self.tick(Instant::now()),
+                configs = reloaded(reloads.as_mut()) => {
+                    self.reconfigure(&configs, Instant::now())
+                }
+            };
+            send_all(&tx, out).await?;
+        }
+
+        // May block, e.g. waiting for the files being hashed, keep it off the async workers
+        let out = tokio::task::spawn_blocking(move || self.drain(Instant::now())).await?;
+        send_all(&tx, out).await
+    }
+
+    /// Passes the batches of events through the stages on a simulated clock, every batch
+    /// at its offset from the start, ticking as often as [Self::run] does, then drains
+    /// the stages. The same batches always give the same events.
+    pub fn replay(&mut self, batches: Vec<(Duration, Vec<Event>)>) -> Vec<Event> {
+        let start = Instant::now();
+        let (mut ticked, mut now) = (Duration::ZERO, Duration::ZERO);
+        let mut out = Vec::new();
+        for (at, events) in batches {
+            while ticked + TICK_INTERVAL <= at {
+                ticked += TICK_INTERVAL;
+                out.extend(self.tick(start + ticked));
+            }
+            now = now.max(at);
+            for event in events {
+                out.extend(self.process(event, start + now));
+            }
+        }
+        out.extend(self.drain(start + now));
+        out
+    }
+
+    fn process(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        self.pass_from(0, vec![event], now)
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Event> {
+        // Events released by a stage still have to go through the following ones
+        let mut out = Vec::new();
+        for i in 0..self.stages.len() {
+            let released = self.stages[i].tick(now);
+            out.extend(self.pass_from(i + 1, released, now));
+        }
+        out
+    }
+
+    fn drain(&mut self, now: Instant) -> Vec<Event> {
+        let mut out = Vec::new();
+        for i in 0..self.stages.len() {
+            let released = self.stages[i].drain();
+            let passed = self.pass_from(i + 1, released, now);
+            out.extend(passed);
+        }
+        out
+    }
+
+    // Replaces the configured stages, their held back events pass the following ones
+    fn reconfigure(&mut self, configs: &[StageConfig], now: Instant) -> Vec<Event> {
+        let mut out = Vec::new();
+        let configured = self.first..self.first + self.configured;
+        for i in configured.clone() {
+            let released = self.stages[i].drain();
+            out.extend(self.pass_from(i + 1, released, now));
+        }
+        self.stages.splice(
+            configured,
+            configs
+                .iter()
+                .map(|c| c.build()),
+        );
+        self.configured = configs.len();
+        let names: Vec<&str> = self
+            .stages
+            .iter()
+            .map(|s| s.name())
+            .collect();
+        println!("Event pipeline reloaded: {}", names.join(" -> "));
+        out
+    }
+
+    fn pass_from(
+        &mut self,
+        first_stage: usize,
+        mut events: Vec<Event>,
+        now: Instant,
+    ) -> Vec<Event> {
+        for stage in self
+            .stages
+            .iter_mut()
+            .skip(first_stage)
+        {
+            if events.is_empty() {
+                break;
+            }
+            events = events
+                .into_iter()
+                .flat_map(|e| stage.process_at(e, now))
+                .collect();
+        }
+        events
+    }
+}
+
+// Resolves with the new stage configs once they change, never without reloads
+async fn reloaded(reloads: Option<&mut watch::Receiver<Vec<StageConfig>>>) -> Vec<StageConfig> {
+    match reloads {
+        Some(reloads)
+            if reloads
+                .changed()
+                .await
+                .is_ok() =>
+        {
+            reloads
+                .borrow_and_update()
+                .clone()
+        }
+        // The reloads ended, the stages stay
+        _ => std::future::pending().await,
+    }
+}
+
+// Waits for room while the next queue is full, holding back the pipeline's input
+async fn send_all(tx: &mpsc::Sender<Event>, events: Vec<Event>) -> Result<(), Error> {
+    for event in events {
+        tx.send(event)
+            .await
+            .map_err(|_| Error::Disconnected)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    fn event(path: &str) -> Event {
+        Event::new(path, EventType::Written)
+    }
+
+    // Holds back all the events until drained
+    struct Hold(Vec<Event>);
+
+    impl Stage for Hold {
+        fn name(&self) -> &str {
+            "hold"
+        }
+
+        fn process(&mut self, event: Event) -> Vec<Event> {
+            self.0.push(event);
+            Vec::new()
+        }
+
+        fn drain(&mut self) -> Vec<Event> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    #[test]
+    fn test_stage_config_from_toml() {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            pipeline: Vec<StageConfig>,
+        }
+        let w: Wrapper = toml::from_str(
+            r#"
+            [[pipeline]]
+            stage = "filter"
+            exclude = ["/tmp/cache/"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            w.pipeline,
+            vec![StageConfig::Filter {
+                include: vec![],
+                exclude: vec!["/tmp/cache/".to_string()],
+                include_types: vec![],
+                exclude_types: vec![],
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drained_events_pass_following_stages() {
+        let pipeline = Pipeline {
+            stages: vec![
+                Box::new(Hold(Vec::new())),
+                Box::new(filter::PathFilter::new(vec![], vec!["/skip/".to_string()])),
+            ],
+            first: 0,
+            configured: 2,
+            reloads: None,
+        };
+
+        let (in_tx, in_rx) = mpsc::channel(8);
+        let (out_tx, mut out_rx) = mpsc::channel(8);
+        in_tx
+            .send(event("/keep/a"))
+            .await
+            .unwrap();
+        in_tx
+            .send(event("/skip/b"))
+            .await
+            .unwrap();
+        drop(in_tx);
+
+        pipeline
+            .run(in_rx, out_tx)
+            .await
+            .unwrap();
+        assert_eq!(out_rx.recv().await, Some(event("/keep/a")));
+        assert_eq!(out_rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_configured_stages_reloaded() {
+        let (configs_tx, configs_rx) = watch::channel(Vec::new());
+        let pipeline = Pipeline {
+            stages: vec![Box::new(Hold(Vec::new()))],
+            first: 0,
+            configured: 1,
+            reloads: None,
+        }
+        .with_reloads(configs_rx);
+        let (in_tx, in_rx) = mpsc::channel(8);
+        let (out_tx, mut out_rx) = mpsc::channel(8);
+        let running = tokio::spawn(pipeline.run(in_rx, out_tx));
+
+        // Released by the replaced stage
+        in_tx
+            .send(event("/keep/a"))
+            .await
+            .unwrap();
+        tokio::task::yield_now().await;
+        configs_tx.send_replace(vec![StageConfig::Filter {
+            include: vec![],
+            exclude: vec!["/skip/".to_string()],
+            include_types: vec![],
+            exclude_types: vec![],
+        }]);
+        assert_eq!(out_rx.recv().await, Some(event("/keep/a")));
+
+        in_tx
+            .send(event("/skip/b"))
+            .await
+            .unwrap();
+        in_tx
+            .send(event("/keep/c"))
+            .await
+            .unwrap();
+        drop(in_tx);
+        assert_eq!(out_rx.recv().await, Some(event("/keep/c")));
+        assert_eq!(out_rx.recv().await, None);
+        running
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+use super::Stage;
+use crate::event::Event;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Lets through at most `per_second` events of a file on average, `burst` of them at once.
+/// The excess events of a file are held back, each one replacing the previous one of
+/// the same type, and released as a single event counting the others in its
+/// `suppressed_count` once the file has a token again. An event of another type releases
+/// the held one first, keeping the order.
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    buckets: HashMap<String, Bucket>,
+    // Orders the released events as they were first held
+    seq: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    held: Option<(Event, u64)>,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, per_second: f64, burst: f64) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(burst);
+        self.refilled = now;
+    }
+
+    fn take(&mut self) -> bool {
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: u32) -> RateLimit {
+        RateLimit {
+            per_second,
+            burst: f64::from(burst.max(1)),
+            buckets: HashMap::new(),
+            seq: 0,
+        }
+    }
+
+    fn release(&mut self, now: Instant, due: impl Fn(&mut Bucket) -> bool) -> Vec<Event> {
+        let (per_second, burst) = (self.per_second, self.burst);
+        let mut released = Vec::new();
+        for bucket in self.buckets.values_mut() {
+            bucket.refill(now, per_second, burst);
+            if bucket.held.is_some() && due(bucket) {
+                released.extend(bucket.held.take());
+            }
+        }
+        // The files quiet long enough to have all their tokens back are forgotten
+        self.buckets
+            .retain(|_, bucket| bucket.held.is_some() || bucket.tokens < burst);
+        released.sort_by_key(|(_, seq)| *seq);
+        released
+            .into_iter()
+            .map(|(event, _)| event)
+            .collect()
+    }
+}
+
+impl Stage for RateLimit {
+    fn name(&self) -> &str {
+        "rate_limit"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        self.process_at(event, Instant::now())
+    }
+
+    fn process_at(&mut self, mut event: Event, now: Instant) -> Vec<Event> {
+        let bucket = self
+            .buckets
+            .entry(event.file_path.clone())
+            .or_insert(Bucket {
+                tokens: self.burst,
+                refilled: now,
+                held: None,
+            });
+        bucket.refill(now, self.per_second, self.burst);
+        match bucket.held.take() {
+            Some((held, seq)) if held.event_type == event.event_type => {
+                event.suppressed_count += held.suppressed_count + 1;
+                bucket.held = Some((event, seq));
+                Vec::new()
+            }
+            // Released without a token, the file's next events wait for the summary
+            Some((held, _)) => {
+                self.seq += 1;
+                bucket.held = Some((event, self.seq));
+                vec![held]
+            }
+            None if bucket.take() => vec![event],
+            None => {
+                self.seq += 1;
+                bucket.held = Some((event, self.seq));
+                Vec::new()
+            }
+        }
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Event> {
+        self.release(now, Bucket::take)
+    }
+
+    fn drain(&mut self) -> Vec<Event> {
+        self.release(Instant::now(), |_| true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+    use std::time::Duration;
+
+    fn event(path: &str, event_type: EventType, suppressed_count: u32) -> Event {
+        Event {
+            suppressed_count,
+            ..Event::new(path, event_type)
+        }
+    }
+
+    #[test]
+    fn test_excess_events_summarized() {
+        let mut limit = RateLimit::new(1.0, 2);
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // A log appended 100 times a second
+        let mut passed = Vec::new();
+        for ms in (0..1000).step_by(10) {
+            passed.extend(limit.process_at(event("/log", EventType::Written, 0), at(ms)));
+        }
+        passed.extend(limit.process_at(event("/other", EventType::Written, 0), at(500)));
+        assert_eq!(
+            passed,
+            vec![
+                event("/log", EventType::Written, 0),
+                event("/log", EventType::Written, 0),
+                event("/other", EventType::Written, 0),
+            ]
+        );
+        assert_eq!(
+            limit.tick(at(1000)),
+            vec![event("/log", EventType::Written, 97)]
+        );
+
+        // Released before the file's deletion
+        limit.process_at(event("/log", EventType::Written, 0), at(1100));
+        assert_eq!(
+            limit.process_at(event("/log", EventType::Deleted, 0), at(1200)),
+            vec![event("/log", EventType::Written, 0)]
+        );
+        assert_eq!(limit.drain(), vec![event("/log", EventType::Deleted, 0)]);
+    }
+}
@@ -0,0 +1,32 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+use crate::receipt::Receipts;
+
+/// Drops the attribute changes made by writing the transaction receipts to the files.
+pub struct ReceiptFilter {
+    receipts: Receipts,
+}
+
+impl ReceiptFilter {
+    pub fn new(receipts: Receipts) -> ReceiptFilter {
+        ReceiptFilter { receipts }
+    }
+}
+
+impl Stage for ReceiptFilter {
+    fn name(&self) -> &str {
+        "receipts"
+    }
+
+    fn process(&mut self, event: Event) -> Vec<Event> {
+        if event.event_type == EventType::AttributeChanged
+            && self
+                .receipts
+                .is_own_change(&event.file_path)
+        {
+            Vec::new()
+        } else {
+            vec![event]
+        }
+    }
+}
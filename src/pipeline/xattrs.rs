@@ -0,0 +1,124 @@
+use super::Stage;
+use crate::event::{Event, EventType};
+
+/// Reads the extended attributes of the files whose attributes changed into their
+/// file info, e.g. the SELinux label or an ACL, which the mode doesn't show.
+pub struct XattrCapture {
+    // The names, or their prefixes ending with *
+    patterns: Vec<String>,
+}
+
+impl XattrCapture {
+    /// Captures the attributes named in `patterns`, e.g. `security.selinux`,
+    /// or starting with their prefix, e.g. `user.*`.
+    pub fn new(patterns: Vec<String>) -> XattrCapture {
+        XattrCapture { patterns }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    // The matching attributes, sorted by name
+    fn read(&self, path: &str) -> std::io::Result<Vec<(String, String)>> {
+        let mut xattrs = Vec::new();
+        for name in xattr::list(path)? {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if !self.matches(name) {
+                continue;
+            }
+            // Removed meanwhile
+            let Some(value) = xattr::get(path, name)? else {
+                continue;
+            };
+            xattrs.push((name.to_string(), text(value)));
+        }
+        xattrs.sort();
+        Ok(xattrs)
+    }
+}
+
+// The value as text, the binary ones, e.g. the ACLs, in hex
+fn text(value: Vec<u8>) -> String {
+    match String::from_utf8(value) {
+        // The SELinux labels end with a NUL
+        Ok(text) => text
+            .trim_end_matches('\0')
+            .to_string(),
+        Err(e) => {
+            let hex: String = e
+                .into_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            format!("0x{}", hex)
+        }
+    }
+}
+
+impl Stage for XattrCapture {
+    fn name(&self) -> &str {
+        "xattrs"
+    }
+
+    fn process(&mut self, mut event: Event) -> Vec<Event> {
+        if event.event_type != EventType::AttributeChanged {
+            return vec![event];
+        }
+        // Gone already if it has no file info
+        let path = event.file_path.clone();
+        if let Some(info) = event.file_info.as_mut() {
+            match self.read(&path) {
+                Ok(xattrs) => info.xattrs = xattrs,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => println!("Failed to read the extended attributes of {}: {}", path, e),
+            }
+        }
+        vec![event]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching_attributes_captured() {
+        let capture = XattrCapture::new(vec!["security.selinux".to_string(), "user.*".to_string()]);
+        assert!(capture.matches("security.selinux"));
+        assert!(capture.matches("user.tag"));
+        assert!(!capture.matches("security.capability"));
+        assert!(!capture.matches("trusted.x"));
+
+        assert_eq!(
+            text(b"system_u:object_r:etc_t:s0\0".to_vec()),
+            "system_u:object_r:etc_t:s0"
+        );
+        assert_eq!(text(vec![2, 0, 0xff]), "0x0200ff");
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "test").unwrap();
+        // Not every file system of the temporary directories has the user attributes
+        if xattr::set(&file, "user.tag", b"secret").is_err() {
+            return;
+        }
+        xattr::set(&file, "user.blkchn.sig", b"5xGh").unwrap();
+        assert_eq!(
+            capture
+                .read(file.to_str().unwrap())
+                .unwrap(),
+            vec![
+                ("user.blkchn.sig".to_string(), "5xGh".to_string()),
+                ("user.tag".to_string(), "secret".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Receipts of the confirmed transactions, written back to the files themselves
+//! in the `user.blkchn.sig` extended attribute: `<signature> <slot>`, so that
+//! the on-chain proof of the file's last change is found from the file.
+
+use crate::error::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const RECEIPT_XATTR: &str = "user.blkchn.sig";
+
+// Writing the attribute is a change reported by the watcher,
+// the attribute changes within this time after a write are our own
+const OWN_CHANGE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Writes the receipts and remembers them, shared by the submitter and the pipeline.
+#[derive(Clone, Debug, Default)]
+pub struct Receipts {
+    written: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Receipts {
+    pub fn write(&self, path: &str, signature: &str, slot: u64) -> Result<(), Error> {
+        // Before the change, the watcher may report it before `set` returns
+        self.written
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), Instant::now());
+        set_receipt(path, &format!("{} {}", signature, slot))
+    }
+
+    /// Whether the file's attribute change is the receipt written by this process.
+    /// Every write is matched with a single change.
+    pub fn is_own_change(&self, path: &str) -> bool {
+        let mut written = self.written.lock().unwrap();
+        written.retain(|_, at| at.elapsed() < OWN_CHANGE_WINDOW);
+        written.remove(path).is_some()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_receipt(path: &str, receipt: &str) -> Result<(), Error> {
+    xattr::set(path, RECEIPT_XATTR, receipt.as_bytes())?;
+    Ok(())
+}
+
+// Rejected by the validation, the extended attributes are written only on Linux
+#[cfg(not(target_os = "linux"))]
+fn set_receipt(_path: &str, _receipt: &str) -> Result<(), Error> {
+    Err(Error::Config(
+        "Receipts in extended attributes are only written on Linux".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_own_change_matched_once() {
+        let receipts = Receipts::default();
+        receipts
+            .written
+            .lock()
+            .unwrap()
+            .insert("/srv/data/a.txt".to_string(), Instant::now());
+
+        assert!(!receipts.is_own_change("/srv/data/b.txt"));
+        assert!(receipts.is_own_change("/srv/data/a.txt"));
+        assert!(!receipts.is_own_change("/srv/data/a.txt"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Remote agent/collector pair over mutually-authenticated TLS.
+//!
+//! Agents are lightweight watchers on edge hosts, they forward events to
+//! a central collector which owns the wallet and submits them on-chain,
+//! so the keys never live on the edge machines. Both sides present a
+//! certificate signed by the same CA.
+//!
+//! Uses the same framing as the Unix socket mode, see [crate::ipc].
+
+use crate::error::Error;
+use crate::event::Event;
+use crate::ipc::{self, Connector, FrameSender};
+use async_trait::async_trait;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_COLLECTOR_LISTEN: &str = "0.0.0.0:7878";
+
+/// PEM files needed to set up mutual TLS.
+#[derive(Clone, Debug)]
+pub struct TlsFiles {
+    pub ca_cert: String, // CA signing both the agents and the collector
+    pub cert: String,    // own certificate chain
+    pub key: String,     // own private key
+}
+
+pub type AgentSender = FrameSender<TlsConnector>;
+
+impl AgentSender {
+    /// `collector` is `host:port`, the host has to match the collector's certificate.
+    pub fn new(collector: &str, tls: &TlsFiles) -> Result<AgentSender, Error> {
+        let host = collector
+            .rsplit_once(':')
+            .map(|(host, _)| host)
+            .ok_or(Error::Config(
+                "Collector address has to be in the host:port format".to_string(),
+            ))?;
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| Error::Config(format!("Invalid collector host {}: {}", host, e)))?;
+
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(load_roots(&tls.ca_cert)?)
+            .with_client_auth_cert(load_certs(&tls.cert)?, load_key(&tls.key)?)
+            .map_err(|e| Error::Config(format!("Invalid TLS setup: {}", e)))?;
+
+        Ok(FrameSender::with_connector(TlsConnector {
+            addr: collector.to_string(),
+            server_name,
+            connector: tokio_rustls::TlsConnector::from(Arc::new(config)),
+        }))
+    }
+}
+
+pub struct TlsConnector {
+    addr: String,
+    server_name: ServerName<'static>,
+    connector: tokio_rustls::TlsConnector,
+}
+
+#[async_trait]
+impl Connector for TlsConnector {
+    type Stream = tokio_rustls::client::TlsStream<TcpStream>;
+
+    async fn connect(&self) -> Result<Self::Stream, Error> {
+        let sock = TcpStream::connect(&self.addr).await?;
+        // Handshake failures are I/O errors, so the sender retries the connection
+        let stream = self
+            .connector
+            .connect(self.server_name.clone(), sock)
+            .await?;
+        Ok(stream)
+    }
+
+    fn peer(&self) -> String {
+        self.addr.clone()
+    }
+}
+
+/// Accepts agent connections, each served by its own task,
+/// and passes the received events to the channel.
+pub async fn serve_collector(
+    listen: &str,
+    tls: &TlsFiles,
+    tx: mpsc::Sender<Event>,
+) -> Result<(), Error> {
+    let verifier =
+        rustls::server::WebPkiClientVerifier::builder(Arc::new(load_roots(&tls.ca_cert)?))
+            .build()
+            .map_err(|e| Error::Config(format!("Invalid TLS setup: {}", e)))?;
+    let config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(load_certs(&tls.cert)?, load_key(&tls.key)?)
+        .map_err(|e| Error::Config(format!("Invalid TLS setup: {}", e)))?;
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind(listen).await?;
+    println!("Waiting for agent connections on {}", listen);
+
+    loop {
+        let (sock, peer) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Failed to accept an agent connection: {}", e);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            // The handshake includes the client certificate check
+            let mut stream = match acceptor.accept(sock).await {
+                Ok(s) => s,
+                Err(e) => {
+                    println!("TLS handshake with agent {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            println!("Agent connected: {}", peer);
+            if let Err(e) = ipc::receive_frames(&mut stream, &tx).await {
+                println!("Stopped receiving from agent {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!("No certificates found in {}", path)));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, Error> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or(Error::Config(format!("No private key found in {}", path)))
+}
+
+fn load_roots(path: &str) -> Result<rustls::RootCertStore, Error> {
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|e| Error::Config(format!("Invalid CA certificate in {}: {}", path, e)))?;
+    }
+    Ok(roots)
+}
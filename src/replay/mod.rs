@@ -0,0 +1,306 @@
+//! Record and replay of the raw inotify events, to regression-test the event extraction
+//! and the pipeline (debouncing, renames, ordering) against real-world captures.
+//!
+//! With `record_file` the watcher appends every batch of inotify events it reads to the
+//! file, one JSON line per event:
+//!
+//! ```json
+//! {"at_ms":1520,"batch":3,"dir":"/srv/data","mask":["MOVED_FROM"],"cookie":7191,"name":"a.txt"}
+//! ```
+//!
+//! The `replay` mode reads them back, passes them through [extract_named] and the pipeline
+//! stages on a simulated clock, and prints the resulting events. The file metadata isn't
+//! looked up, so a replay gives the same events on any host. A capture can also be written
+//! by hand, the cookie and the name are optional.
+
+use crate::dir_watcher::{extract_named, GlobFilter, Renames};
+use crate::error::Error;
+use crate::event::Event;
+use crate::pipeline::Pipeline;
+use inotify::EventMask;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::time::{Duration, Instant};
+
+const MASKS: [(&str, EventMask); 16] = [
+    ("ACCESS", EventMask::ACCESS),
+    ("ATTRIB", EventMask::ATTRIB),
+    ("CLOSE_WRITE", EventMask::CLOSE_WRITE),
+    ("CLOSE_NOWRITE", EventMask::CLOSE_NOWRITE),
+    ("CREATE", EventMask::CREATE),
+    ("DELETE", EventMask::DELETE),
+    ("DELETE_SELF", EventMask::DELETE_SELF),
+    ("MODIFY", EventMask::MODIFY),
+    ("MOVE_SELF", EventMask::MOVE_SELF),
+    ("MOVED_FROM", EventMask::MOVED_FROM),
+    ("MOVED_TO", EventMask::MOVED_TO),
+    ("OPEN", EventMask::OPEN),
+    ("IGNORED", EventMask::IGNORED),
+    ("ISDIR", EventMask::ISDIR),
+    ("Q_OVERFLOW", EventMask::Q_OVERFLOW),
+    ("UNMOUNT", EventMask::UNMOUNT),
+];
+
+/// One recorded inotify event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RawEvent {
+    /// Milliseconds since the recording started.
+    pub at_ms: u64,
+    /// The events read from inotify at once share their batch.
+    pub batch: u64,
+    /// The watched directory, as the events are reported in it.
+    pub dir: String,
+    pub mask: Vec<String>,
+    #[serde(default)]
+    pub cookie: u32,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Appends the batches of inotify events to the capture file.
+pub struct Recorder {
+    file: File,
+    started: Instant,
+    batch: u64,
+}
+
+impl Recorder {
+    /// Starts a new capture, replacing the file.
+    pub fn create(path: &str) -> io::Result<Recorder> {
+        println!("Recording the inotify events to {}", path);
+        Ok(Recorder {
+            file: File::create(path)?,
+            started: Instant::now(),
+            batch: 0,
+        })
+    }
+
+    /// Records the batch, `dirs` maps the watches to the watched directories.
+    pub fn record<S: AsRef<OsStr>>(
+        &mut self,
+        dirs: &HashMap<inotify::WatchDescriptor, String>,
+        events: &[inotify::Event<S>],
+    ) -> io::Result<()> {
+        let at_ms = self
+            .started
+            .elapsed()
+            .as_millis() as u64;
+        let mut lines = String::new();
+        for event in events {
+            let raw = RawEvent {
+                at_ms,
+                batch: self.batch,
+                dir: dirs
+                    .get(&event.wd)
+                    .cloned()
+                    .unwrap_or_default(),
+                mask: mask_names(event.mask),
+                cookie: event.cookie,
+                name: event
+                    .name
+                    .as_ref()
+                    .map(|name| {
+                        name.as_ref()
+                            .to_string_lossy()
+                            .to_string()
+                    }),
+            };
+            lines.push_str(&serde_json::to_string(&raw).map_err(io::Error::other)?);
+            lines.push('\n');
+        }
+        self.batch += 1;
+        // Small appends, written right away so a crash keeps the capture
+        self.file
+            .write_all(lines.as_bytes())
+    }
+}
+
+/// Reads the capture file.
+pub fn read(path: &str) -> Result<Vec<RawEvent>, Error> {
+    let mut events = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line)
+            .map_err(|e| Error::serialization(&format!("{}:{}: {}", path, i + 1, e)))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Replays the captured batches through the event extraction, with the glob `filter`
+/// and the `renames` pairing, and the pipeline, returns the events in the order the sinks
+/// would get them.
+pub fn replay(
+    capture: &[RawEvent],
+    filter: &GlobFilter,
+    renames: &mut Renames,
+    pipeline: &mut Pipeline,
+) -> Result<Vec<Event>, Error> {
+    let started = Instant::now();
+    let mut batches: Vec<(Duration, Vec<Event>)> = Vec::new();
+    for batch in capture.chunk_by(|a, b| a.batch == b.batch) {
+        let mut masks = Vec::with_capacity(batch.len());
+        for raw in batch {
+            masks.push(parse_mask(&raw.mask)?);
+        }
+        let at = Duration::from_millis(batch[0].at_ms);
+        let mut events = renames.expired(started + at);
+        events.extend(extract_named(
+            batch
+                .iter()
+                .zip(masks)
+                .filter_map(|(raw, mask)| {
+                    Some((
+                        raw.dir.as_str(),
+                        mask,
+                        raw.cookie,
+                        OsStr::new(raw.name.as_ref()?),
+                    ))
+                }),
+            filter,
+            |_| Err(io::Error::from(io::ErrorKind::NotFound).into()),
+            renames,
+            started + at,
+        )?);
+        batches.push((at, events));
+    }
+    // The moved from events still waiting at the end of the capture
+    if let Some((_, events)) = batches.last_mut() {
+        events.extend(renames.drain());
+    }
+    Ok(pipeline.replay(batches))
+}
+
+fn mask_names(mask: EventMask) -> Vec<String> {
+    MASKS
+        .iter()
+        .filter(|(_, flag)| mask.contains(*flag))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+fn parse_mask(names: &[String]) -> Result<EventMask, Error> {
+    let mut mask = EventMask::empty();
+    for name in names {
+        let (_, flag) = MASKS
+            .iter()
+            .find(|(known, _)| known == name)
+            .ok_or_else(|| Error::Config(format!("Unknown inotify event {}", name)))?;
+        mask |= *flag;
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{EventType, FileKind};
+    use crate::pipeline::StageConfig;
+
+    #[test]
+    fn test_capture_replayed_through_pipeline() {
+        let capture = r#"
+{"at_ms":0,"batch":0,"dir":"/srv/data","mask":["CREATE"],"name":"a.txt"}
+{"at_ms":0,"batch":0,"dir":"/srv/data","mask":["CLOSE_WRITE"],"name":"a.txt"}
+{"at_ms":0,"batch":0,"dir":"/srv/data","mask":["CREATE","ISDIR"],"name":"cache"}
+{"at_ms":1500,"batch":1,"dir":"/srv/data","mask":["MOVED_FROM"],"cookie":7,"name":"a.txt"}
+{"at_ms":1500,"batch":1,"dir":"/srv/data/cache","mask":["MOVED_TO"],"cookie":7,"name":"a.txt"}
+{"at_ms":1500,"batch":1,"dir":"/srv/data","mask":["IGNORED"]}
+"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("capture.jsonl");
+        std::fs::write(&path, capture).unwrap();
+        let capture = read(path.to_str().unwrap()).unwrap();
+        assert_eq!(capture.len(), 6);
+        assert_eq!(capture[3].cookie, 7);
+
+        let mut pipeline = Pipeline::new(&[StageConfig::Filter {
+            include: vec![],
+            exclude: vec!["/srv/data/cache/".to_string()],
+            include_types: vec![],
+            exclude_types: vec![],
+        }]);
+        let replayed: Vec<(EventType, String, FileKind)> = replay(
+            &capture,
+            &GlobFilter::default(),
+            &mut Renames::default(),
+            &mut pipeline,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|e| (e.event_type, e.file_path, e.kind))
+        .collect();
+        assert_eq!(
+            replayed,
+            vec![
+                (
+                    EventType::Created,
+                    "/srv/data/a.txt".to_string(),
+                    FileKind::File
+                ),
+                (
+                    EventType::Written,
+                    "/srv/data/a.txt".to_string(),
+                    FileKind::File
+                ),
+                // Gone since, known to be a directory from the flag
+                (
+                    EventType::Created,
+                    "/srv/data/cache".to_string(),
+                    FileKind::Dir
+                ),
+                (
+                    EventType::MovedFrom,
+                    "/srv/data/a.txt".to_string(),
+                    FileKind::File
+                ),
+            ]
+        );
+
+        // Both halves of the move paired, the unpaired ones as they are
+        let mut renames = Renames::enabled(crate::dir_watcher::RENAME_WINDOW);
+        let unpaired = RawEvent {
+            at_ms: 3000,
+            batch: 2,
+            dir: "/srv/data".to_string(),
+            mask: vec!["MOVED_FROM".to_string()],
+            cookie: 9,
+            name: Some("b.txt".to_string()),
+        };
+        let capture = [capture, vec![unpaired]].concat();
+        let replayed = replay(
+            &capture,
+            &GlobFilter::default(),
+            &mut renames,
+            &mut Pipeline::new(&[]),
+        )
+        .unwrap();
+        assert_eq!(
+            replayed[3].event_type,
+            EventType::Renamed {
+                from: "/srv/data/a.txt".to_string(),
+                to: "/srv/data/cache/a.txt".to_string(),
+            }
+        );
+        assert_eq!(replayed[3].file_path, "/srv/data/cache/a.txt");
+        assert_eq!(replayed[4].event_type, EventType::MovedFrom);
+        assert_eq!(replayed.len(), 5);
+
+        assert_eq!(
+            mask_names(EventMask::CREATE | EventMask::ISDIR),
+            vec!["CREATE", "ISDIR"]
+        );
+        assert!(parse_mask(&["CREATED".to_string()]).is_err());
+    }
+}
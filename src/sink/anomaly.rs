@@ -0,0 +1,175 @@
+use super::EventSink;
+use crate::error::Error;
+use crate::event::Event;
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+// The rate is compared per hour
+const WINDOW: Duration = Duration::from_secs(3600);
+// More events than this many times the usual rate are an anomaly
+const RATE_FACTOR: f64 = 10.0;
+// Fewer events than this are never an anomaly, e.g. a file saved twice in a row
+const MIN_EVENTS: usize = 20;
+
+/// A file changing much more often than it used to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub path: String,
+    /// Events in the last window.
+    pub events: usize,
+    /// Usual events per window, measured before the last one.
+    pub baseline: f64,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AnomalyDetected: {} changed {} times in the last {}s, usually {:.2} times",
+            self.path,
+            self.events,
+            WINDOW.as_secs(),
+            self.baseline
+        )
+    }
+}
+
+struct PathRate {
+    first_seen: Instant,
+    // Events older than the window
+    earlier: u64,
+    recent: VecDeque<Instant>,
+    // Alerted once until the rate goes back to normal
+    alerted: bool,
+}
+
+/// Tracks the usual change rate of every file and alerts when a file changes much
+/// more often, e.g. a config file changing monthly suddenly changes 50 times an hour.
+/// The alerts are logged and POSTed to the webhook, if one is set.
+pub struct AnomalySink {
+    rates: HashMap<String, PathRate>,
+    webhook_url: Option<String>,
+}
+
+impl AnomalySink {
+    pub fn new(webhook_url: Option<String>) -> AnomalySink {
+        AnomalySink {
+            rates: HashMap::new(),
+            webhook_url,
+        }
+    }
+
+    /// Records the file's event, returns the anomaly the first time the rate is over the usual one.
+    pub fn observe(&mut self, path: &str, now: Instant) -> Option<Anomaly> {
+        let rate = self
+            .rates
+            .entry(path.to_string())
+            .or_insert_with(|| PathRate {
+                first_seen: now,
+                earlier: 0,
+                recent: VecDeque::new(),
+                alerted: false,
+            });
+        rate.recent.push_back(now);
+        while rate
+            .recent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > WINDOW)
+        {
+            rate.recent.pop_front();
+            rate.earlier += 1;
+        }
+
+        // The usual rate needs at least a window of history before the last one
+        let history = now
+            .duration_since(rate.first_seen)
+            .saturating_sub(WINDOW);
+        if history < WINDOW {
+            return None;
+        }
+        let baseline = rate.earlier as f64 * WINDOW.as_secs_f64() / history.as_secs_f64();
+        let threshold = (baseline * RATE_FACTOR).max(MIN_EVENTS as f64);
+
+        let anomalous = rate.recent.len() as f64 > threshold;
+        let first = anomalous && !rate.alerted;
+        rate.alerted = anomalous;
+        first.then(|| Anomaly {
+            path: path.to_string(),
+            events: rate.recent.len(),
+            baseline,
+        })
+    }
+
+    async fn notify(&self, anomaly: &Anomaly) -> Result<(), Error> {
+        let url = match self.webhook_url.as_ref() {
+            Some(url) => url.clone(),
+            None => return Ok(()),
+        };
+        let body = serde_json::json!({
+            "alert": "AnomalyDetected",
+            "file_path": anomaly.path,
+            "events": anomaly.events,
+            "window_secs": WINDOW.as_secs(),
+            "baseline": anomaly.baseline,
+        });
+        // ureq is blocking, keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(body)
+                .map(|_| ())
+                .map_err(Error::from)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl EventSink for AnomalySink {
+    fn name(&self) -> &str {
+        "anomaly"
+    }
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+        if let Some(anomaly) = self.observe(&event.file_path, Instant::now()) {
+            println!("ALERT: {}", anomaly);
+            self.notify(&anomaly).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_of_rare_file_detected_once() {
+        let mut sink = AnomalySink::new(None);
+        let start = Instant::now();
+        let hours = |h: u64| start + Duration::from_secs(h * 3600);
+
+        // Changes once a day for a month
+        for day in 0..30 {
+            assert_eq!(sink.observe("/etc/app.conf", hours(day * 24)), None);
+        }
+
+        // Then 50 times in an hour
+        let burst = hours(30 * 24);
+        let anomalies: Vec<_> = (0..50)
+            .filter_map(|i| sink.observe("/etc/app.conf", burst + Duration::from_secs(i * 60)))
+            .collect();
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].events, MIN_EVENTS + 1);
+        assert!(anomalies[0].baseline < 0.1);
+
+        // A file which is always busy is not an anomaly
+        for i in 0..3 * 3600 {
+            assert_eq!(
+                sink.observe("/var/log/app.log", start + Duration::from_secs(i)),
+                None
+            );
+        }
+    }
+}
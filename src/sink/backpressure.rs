@@ -0,0 +1,249 @@
+//! Capacity of the queue of each sink. Once the queue of a sink falling behind is full,
+//! the [Backpressure] policy decides: the fan-out waits, an event is dropped, or the new
+//! events are spilled to a file. The sink is told how many events were dropped and when
+//! once there's room again, e.g. for the submitter to record the gap on chain.
+
+use crate::error::Error;
+use crate::event::{Event, EventsDropped};
+use crate::ipc::{read_frame, write_frame};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::BufReader;
+
+// Under a lasting overload the drops are reported at least this often, in seconds
+const MAX_REPORT_DELAY: i64 = 60;
+
+/// What happens to a new event when the queue of its sink is full.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Backpressure {
+    /// The fan-out waits for room, holding back all the sinks, the events wait upstream.
+    Block,
+    /// The oldest waiting event is dropped for the new one.
+    DropOldest,
+    /// The new event is dropped.
+    #[default]
+    DropNewest,
+    /// The new events are written to a file in the directory, and read back in order
+    /// as the queue empties.
+    SpillToDisk(String),
+}
+
+impl Backpressure {
+    /// Parses the configured name, the events are spilled to `spill_dir`.
+    pub fn parse(name: &str, spill_dir: &str) -> Result<Backpressure, Error> {
+        match name {
+            "block" => Ok(Backpressure::Block),
+            "drop_oldest" => Ok(Backpressure::DropOldest),
+            "drop_newest" => Ok(Backpressure::DropNewest),
+            "spill_to_disk" => Ok(Backpressure::SpillToDisk(spill_dir.to_string())),
+            other => Err(Error::Config(format!(
+                "Unknown backpressure policy {}, expected `block`, `drop_oldest`, `drop_newest` or `spill_to_disk`",
+                other
+            ))),
+        }
+    }
+}
+
+/// Overflow of a sink's queue, framed like on the socket, so with the owner and the other
+/// fields left out of the events' layout. The events spilled by a previous run, e.g. before
+/// the sinks were restarted, are read back first.
+pub struct Spill {
+    path: PathBuf,
+    writer: File,
+    reader: BufReader<File>,
+    len: usize,
+}
+
+impl Spill {
+    /// Opens the sink's spill file in the directory, `<dir>/<name>.spill`.
+    pub async fn open(dir: &str, name: &str) -> Result<Spill, Error> {
+        tokio::fs::create_dir_all(dir).await?;
+        let path = Path::new(dir).join(format!("{}.spill", name));
+        let writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        // Counts the events left behind
+        let mut len = 0;
+        let mut reader = BufReader::new(File::open(&path).await?);
+        while read_frame(&mut reader)
+            .await?
+            .is_some()
+        {
+            len += 1;
+        }
+        let reader = BufReader::new(File::open(&path).await?);
+        if len > 0 {
+            println!("{} events spilled before, sent first", len);
+        }
+        Ok(Spill {
+            path,
+            writer,
+            reader,
+            len,
+        })
+    }
+
+    pub async fn push(&mut self, event: &Event) -> Result<(), Error> {
+        write_frame(&mut self.writer, event).await?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// The oldest spilled event, the file is emptied once all of them are read.
+    pub async fn pop(&mut self) -> Result<Option<Event>, Error> {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        let event = read_frame(&mut self.reader).await?;
+        self.len -= 1;
+        if self.len == 0 {
+            self.writer.set_len(0).await?;
+            self.reader = BufReader::new(File::open(&self.path).await?);
+        }
+        Ok(event)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Counts the events dropped since the last [Self::take].
+#[derive(Debug, Default)]
+pub struct DropCounter {
+    dropped: Option<EventsDropped>,
+}
+
+impl DropCounter {
+    /// Counts an event dropped now.
+    pub fn drop_event(&mut self) {
+        self.drop_event_at(unix_now());
+    }
+
+    /// Counts an event dropped at the unix time `at`.
+    pub fn drop_event_at(&mut self, at: i64) {
+        let dropped = self
+            .dropped
+            .get_or_insert(EventsDropped {
+                count: 0,
+                since: at,
+                until: at,
+            });
+        dropped.count += 1;
+        dropped.until = at;
+    }
+
+    /// Whether the drops are to be reported: once there's `room` in the queue again,
+    /// or if the first one is too old.
+    pub fn due(&self, room: bool) -> bool {
+        self.dropped
+            .as_ref()
+            .is_some_and(|dropped| room || unix_now() - dropped.since >= MAX_REPORT_DELAY)
+    }
+
+    /// The events dropped since the last time, `None` if none was dropped.
+    pub fn take(&mut self) -> Option<EventsDropped> {
+        self.dropped.take()
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    #[test]
+    fn test_dropped_events_summarized() {
+        let mut counter = DropCounter::default();
+        assert!(!counter.due(true));
+        assert_eq!(counter.take(), None);
+
+        for at in [1729000000, 1729000003, 1729000010] {
+            counter.drop_event_at(at);
+        }
+        // Long ago
+        assert!(counter.due(false));
+        assert_eq!(
+            counter.take(),
+            Some(EventsDropped {
+                count: 3,
+                since: 1729000000,
+                until: 1729000010,
+            })
+        );
+        // Counted again from the next drop
+        assert_eq!(counter.take(), None);
+        counter.drop_event();
+        assert!(!counter.due(false));
+        assert!(counter.due(true));
+        assert_eq!(
+            counter
+                .take()
+                .map(|d| d.count),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spilled_events_read_back_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_str().unwrap();
+        let event = |name: &str| Event::new(format!("/srv/data/{}", name), EventType::Written);
+
+        let mut spill = Spill::open(dir, "solana")
+            .await
+            .unwrap();
+        for name in ["a", "b", "c"] {
+            spill
+                .push(&event(name))
+                .await
+                .unwrap();
+        }
+        assert_eq!(spill.pop().await.unwrap(), Some(event("a")));
+
+        // Left behind by a stopped sink, read again from the start
+        drop(spill);
+        let mut spill = Spill::open(dir, "solana")
+            .await
+            .unwrap();
+        assert_eq!(spill.len(), 3);
+        assert_eq!(spill.pop().await.unwrap(), Some(event("a")));
+        spill
+            .push(&event("d"))
+            .await
+            .unwrap();
+        let mut read = Vec::new();
+        while let Some(event) = spill.pop().await.unwrap() {
+            read.push(event.file_path);
+        }
+        assert_eq!(read, ["/srv/data/b", "/srv/data/c", "/srv/data/d"]);
+
+        // Emptied, the next events start over
+        spill
+            .push(&event("e"))
+            .await
+            .unwrap();
+        assert_eq!(spill.pop().await.unwrap(), Some(event("e")));
+        assert!(spill.is_empty());
+
+        assert_eq!(
+            Backpressure::parse("spill_to_disk", dir).unwrap(),
+            Backpressure::SpillToDisk(dir.to_string())
+        );
+        assert!(Backpressure::parse("drop", dir).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Completeness of the on-chain record: proves not just what was recorded, but that
+//! nothing was silently dropped on the way.
+//!
+//! The fan-out numbers every event it passes on to the sinks, and the submitter feeds back
+//! the numbers of the events whose transaction is confirmed. The numbers still unconfirmed once
+//! the transactions would have expired are the gaps, reported periodically and optionally
+//! recorded on chain in a memo. The numbers restart with every run of the tracker.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Not confirmed by then the event is missing, well past the expiry of its transaction
+const GRACE: Duration = Duration::from_secs(300);
+
+/// Fed by the sinks' fan-out and the submitter, reported by [report].
+pub type SharedCompleteness = Arc<Mutex<Completeness>>;
+
+#[derive(Debug)]
+pub struct Completeness {
+    // Unix timestamp of the run the sequence numbers belong to
+    started: u64,
+    captured: u64,
+    // The captured events not confirmed yet, with their capture time
+    unconfirmed: BTreeMap<u64, Instant>,
+    confirmed: u64,
+    missing: BTreeSet<u64>,
+    // The gaps reported since the last memo
+    unrecorded: Vec<u64>,
+}
+
+impl Completeness {
+    pub fn new(started: u64) -> Completeness {
+        Completeness {
+            started,
+            captured: 0,
+            unconfirmed: BTreeMap::new(),
+            confirmed: 0,
+            missing: BTreeSet::new(),
+            unrecorded: Vec::new(),
+        }
+    }
+
+    pub fn shared() -> SharedCompleteness {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Arc::new(Mutex::new(Completeness::new(started)))
+    }
+
+    /// The event numbered `seq` is captured, it's expected on chain.
+    pub fn capture(&mut self, seq: u64, at: Instant) {
+        self.captured += 1;
+        self.unconfirmed
+            .insert(seq, at);
+    }
+
+    /// The event's transaction is confirmed.
+    pub fn confirmed(&mut self, seq: u64) {
+        if self
+            .unconfirmed
+            .remove(&seq)
+            .is_some()
+        {
+            self.confirmed += 1;
+        } else if self.missing.remove(&seq) {
+            self.confirmed += 1;
+            println!("Event {} reported missing is confirmed late", seq);
+        }
+    }
+
+    /// Takes the events not confirmed within the grace period, the new gaps.
+    pub fn gaps(&mut self, now: Instant) -> Vec<u64> {
+        let gaps: Vec<u64> = self
+            .unconfirmed
+            .iter()
+            .take_while(|(_, at)| now.saturating_duration_since(**at) >= GRACE)
+            .map(|(seq, _)| *seq)
+            .collect();
+        for seq in gaps.iter() {
+            self.unconfirmed.remove(seq);
+        }
+        self.missing
+            .extend(gaps.iter());
+        self.unrecorded
+            .extend(gaps.iter());
+        gaps
+    }
+
+    /// Memo recording the gaps reported since the last one if any, with their count.
+    pub fn memo(&self) -> Option<(String, usize)> {
+        if self.unrecorded.is_empty() {
+            return None;
+        }
+        let memo = format!(
+            "completeness gaps: {} of the run started at {}",
+            ranges(&self.unrecorded),
+            self.started
+        );
+        Some((memo, self.unrecorded.len()))
+    }
+
+    /// The [Self::memo] of the first `gaps` is confirmed on chain,
+    /// the ones reported meanwhile go to the next memo.
+    pub fn recorded(&mut self, gaps: usize) {
+        self.unrecorded.drain(..gaps);
+    }
+}
+
+impl Display for Completeness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} events captured, {} confirmed, {} pending, {} missing",
+            self.captured,
+            self.confirmed,
+            self.unconfirmed.len(),
+            self.missing.len()
+        )?;
+        if !self.missing.is_empty() {
+            let missing: Vec<u64> = self
+                .missing
+                .iter()
+                .copied()
+                .collect();
+            write!(f, ": {}", ranges(&missing))?;
+        }
+        Ok(())
+    }
+}
+
+// The ascending sequence numbers as ranges, e.g. `12-15, 20`
+fn ranges(seqs: &[u64]) -> String {
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for &seq in seqs {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == seq => *end = seq,
+            _ => ranges.push((seq, seq)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Reports the completeness every `interval`, and alerts on the new gaps. Prefixed with
+/// the `stream` if set, e.g. the program the events are recorded by.
+pub async fn report(completeness: SharedCompleteness, stream: Option<String>, interval: Duration) {
+    let prefix = stream.map_or(String::new(), |stream| format!("[{}] ", stream));
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let mut completeness = completeness.lock().unwrap();
+        let gaps = completeness.gaps(Instant::now());
+        if !gaps.is_empty() {
+            println!(
+                "{}ALERT: events {} captured but not confirmed on chain within {:?}",
+                prefix,
+                ranges(&gaps),
+                GRACE
+            );
+        }
+        println!("{}Completeness: {}", prefix, completeness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unconfirmed_events_reported_as_gaps() {
+        let mut completeness = Completeness::new(1700000000);
+        let at = Instant::now();
+        for seq in 1..=5 {
+            completeness.capture(seq, at);
+        }
+        completeness.confirmed(1);
+        completeness.confirmed(3);
+
+        assert!(completeness
+            .gaps(at + Duration::from_secs(1))
+            .is_empty());
+        assert_eq!(completeness.gaps(at + GRACE), vec![2, 4, 5]);
+        assert_eq!(
+            completeness.memo().unwrap(),
+            (
+                "completeness gaps: 2, 4-5 of the run started at 1700000000".to_string(),
+                3
+            )
+        );
+        completeness.recorded(3);
+        assert_eq!(completeness.memo(), None);
+
+        completeness.confirmed(4);
+        assert_eq!(
+            completeness.to_string(),
+            "5 events captured, 3 confirmed, 0 pending, 2 missing: 2, 5"
+        );
+    }
+}
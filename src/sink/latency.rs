@@ -0,0 +1,155 @@
+//! How fresh the on-chain record is: the latency of the events from their capture
+//! to the submission of their transaction, and from the submission to its confirmation.
+//!
+//! The latencies are counted in histograms with buckets doubling from 1ms,
+//! so the quantiles are upper bounds, precise to a factor of 2.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 1ms to about 70 minutes, the last bucket takes the longer ones
+const BUCKETS: usize = 24;
+
+/// The latencies measured by the submitter, shared with the metrics sink.
+pub type SharedLatencies = Arc<Mutex<Latencies>>;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Latencies {
+    pub capture_to_submit: Histogram,
+    pub submit_to_confirm: Histogram,
+    /// Slot and unix timestamp of the last confirmed transaction.
+    pub last_confirmed: Option<(u64, u64)>,
+}
+
+impl Latencies {
+    pub fn shared() -> SharedLatencies {
+        Arc::new(Mutex::new(Latencies::default()))
+    }
+
+    pub fn load(path: &str) -> Result<Latencies, crate::error::Error> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json)
+            .map_err(|e| crate::error::Error::serialization(&format!("{}: {}", path, e)))
+    }
+
+    /// Written whole, the status mode reads it while the tracker runs.
+    pub fn save(&self, path: &str) -> Result<(), crate::error::Error> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| crate::error::Error::serialization(&e.to_string()))?;
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, json)?;
+        Ok(std::fs::rename(&tmp, path)?)
+    }
+}
+
+impl Display for Latencies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Capture to submit: {}", self.capture_to_submit)?;
+        write!(f, "Submit to confirm: {}", self.submit_to_confirm)?;
+        if let Some((slot, ts)) = self.last_confirmed {
+            write!(f, "\nLast confirmed in slot {} at {} (unix)", slot, ts)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    // Bucket i counts the latencies up to 2^i ms
+    counts: Vec<u64>,
+    max_ms: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: vec![0; BUCKETS],
+            max_ms: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let bucket = (u64::BITS
+            - ms.saturating_sub(1)
+                .leading_zeros()) as usize;
+        self.counts[bucket.min(BUCKETS - 1)] += 1;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Upper bound of the `q` quantile, e.g. 0.99, none without any latency.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let rank = (q * self.count() as f64)
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = 1u64 << bucket;
+                return Some(Duration::from_millis(bound.min(self.max_ms)));
+            }
+        }
+        None
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(p50) = self.quantile(0.5) else {
+            return write!(f, "no events");
+        };
+        write!(
+            f,
+            "{} events, p50 <= {:?}, p90 <= {:?}, p99 <= {:?}, max {:?}",
+            self.count(),
+            p50,
+            self.quantile(0.9)
+                .unwrap_or_default(),
+            self.quantile(0.99)
+                .unwrap_or_default(),
+            Duration::from_millis(self.max_ms)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles_bounded_by_buckets() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for ms in [3, 3, 5, 90, 700, 1500] {
+            histogram.record(Duration::from_millis(ms));
+        }
+        // 3ms in the 4ms bucket, 5ms in the 8ms one
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(8)));
+        assert_eq!(histogram.quantile(0.8), Some(Duration::from_millis(1024)));
+        // Never over the longest one
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_millis(1500)));
+
+        let latencies = Latencies {
+            submit_to_confirm: histogram,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("metrics.json")
+            .to_string_lossy()
+            .to_string();
+        latencies.save(&path).unwrap();
+        assert_eq!(Latencies::load(&path).unwrap(), latencies);
+    }
+}
@@ -0,0 +1,74 @@
+use super::latency::{Latencies, SharedLatencies};
+use super::EventSink;
+use crate::error::Error;
+use crate::event::Event;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+
+/// Counts the events per type and logs the totals whenever the stream goes idle,
+/// together with the submitter's latencies, also saved for the status mode.
+#[derive(Default)]
+pub struct MetricsSink {
+    counts: BTreeMap<String, u64>,
+    // Avoids logging the same totals on every idle flush
+    changed: bool,
+    // The submitter's latencies, the file they're saved to and the last saved ones
+    latencies: Option<(SharedLatencies, String, Latencies)>,
+}
+
+impl MetricsSink {
+    pub fn new() -> MetricsSink {
+        MetricsSink {
+            counts: BTreeMap::new(),
+            changed: false,
+            latencies: None,
+        }
+    }
+
+    /// Logs the latencies and saves them to the file at `path`.
+    pub fn with_latencies(mut self, latencies: SharedLatencies, path: &str) -> MetricsSink {
+        self.latencies = Some((latencies, path.to_string(), Latencies::default()));
+        self
+    }
+}
+
+#[async_trait]
+impl EventSink for MetricsSink {
+    fn name(&self) -> &str {
+        "metrics"
+    }
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+        *self
+            .counts
+            .entry(event.event_type.to_string())
+            .or_insert(0) += 1;
+        self.changed = true;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        if self.changed {
+            let total: u64 = self.counts.values().sum();
+            println!("Events total: {} | per type: {:?}", total, self.counts);
+            self.changed = false;
+        }
+
+        // The confirmations keep coming after the last event
+        if let Some((latencies, path, saved)) = self.latencies.as_mut() {
+            let current = latencies
+                .lock()
+                .unwrap()
+                .clone();
+            if current == *saved {
+                return Ok(());
+            }
+            println!("{}", current);
+            // Written to a file, keep it off the async workers
+            let (path, latencies) = (path.clone(), current.clone());
+            tokio::task::spawn_blocking(move || latencies.save(&path)).await??;
+            *saved = current;
+        }
+        Ok(())
+    }
+}
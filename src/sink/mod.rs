@@ -0,0 +1,637 @@
+//! Event sinks consuming the stream of captured events.
+//!
+//! Every sink (Solana submitter, webhook, metrics, ...) implements [EventSink]
+//! and is plugged into a [FanOut], which numbers the events and delivers a copy of each
+//! to all of them.
+//! With a [Journal] the events are written to it first, so they're kept locally
+//! even if all the sinks fail.
+
+pub mod anomaly;
+pub mod backpressure;
+pub mod completeness;
+pub mod latency;
+pub mod metrics;
+pub mod priority;
+pub mod webhook;
+
+use crate::error::Error;
+use crate::event::{Event, EventsDropped};
+use crate::journal::Journal;
+use async_trait::async_trait;
+use backpressure::{Backpressure, DropCounter, Spill};
+use completeness::SharedCompleteness;
+use priority::{Priorities, PriorityQueue};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+
+/// Sinks are flushed whenever no new event arrived for this long, by default.
+pub const IDLE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[async_trait]
+pub trait EventSink: Send {
+    /// Name used in the logs.
+    fn name(&self) -> &str;
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error>;
+
+    /// Like [Self::on_event], with the time the event was captured, i.e. received
+    /// by the [FanOut], for the sinks measuring the latency.
+    async fn on_captured(&mut self, event: &Event, _captured: Instant) -> Result<(), Error> {
+        self.on_event(event).await
+    }
+
+    /// Called once there's room in the full queue of the sink again, with the events
+    /// dropped meanwhile. Ignored by default, they're logged anyway.
+    async fn on_dropped(&mut self, _dropped: &EventsDropped) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Pushes out anything the sink buffered. Called when the stream is idle.
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// How long the stream stays idle before [Self::flush] is called, for the sinks
+    /// buffering the events for a shorter time.
+    fn flush_interval(&self) -> Duration {
+        IDLE_FLUSH_INTERVAL
+    }
+
+    /// Called once, after the stream has ended.
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        self.flush().await
+    }
+}
+
+/// Delivers every event to all the sinks. Each sink runs as its own task
+/// so that a slow one (e.g. waiting for the RPC node) doesn't hold back the others.
+/// The events waiting for a sink are ordered by their [Priorities].
+#[derive(Default)]
+pub struct FanOut {
+    sinks: Vec<Box<dyn EventSink>>,
+    priorities: Priorities,
+    capacity: Option<usize>,
+    backpressure: Backpressure,
+    journal: Option<Journal>,
+    // The stream of each file's events, all of them are in the first one without it
+    stream_of: Option<Box<dyn Fn(&str) -> usize + Send + Sync>>,
+    streams: Vec<Stream>,
+}
+
+// The events numbered together, e.g. the ones recorded by one program
+#[derive(Default)]
+struct Stream {
+    last_seq: u64,
+    completeness: Option<SharedCompleteness>,
+}
+
+// The stream numbered `i`, added with the ones before it if new
+fn stream_at(streams: &mut Vec<Stream>, i: usize) -> &mut Stream {
+    if streams.len() <= i {
+        streams.resize_with(i + 1, Stream::default);
+    }
+    &mut streams[i]
+}
+
+impl FanOut {
+    pub fn new() -> FanOut {
+        FanOut::default()
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn EventSink>) -> FanOut {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn with_priorities(mut self, priorities: Priorities) -> FanOut {
+        self.priorities = priorities;
+        self
+    }
+
+    /// Keeps at most `capacity` events waiting for each sink, the next ones are dropped
+    /// until the sink catches up, see [EventSink::on_dropped], unless the [Backpressure]
+    /// policy says otherwise.
+    pub fn with_capacity(mut self, capacity: usize) -> FanOut {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// What happens to the events once a sink's queue is full, the new ones are dropped
+    /// by default. Applies only with a capacity.
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> FanOut {
+        self.backpressure = backpressure;
+        self
+    }
+
+    pub fn with_journal(mut self, journal: Journal) -> FanOut {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Numbers the events of every stream `stream_of` puts their file in from 1, e.g. of
+    /// every program recording them, so each one sees its numbers follow each other.
+    pub fn with_streams(
+        mut self,
+        stream_of: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> FanOut {
+        self.stream_of = Some(Box::new(stream_of));
+        self
+    }
+
+    /// Feeds the numbers of the `stream`'s events to the completeness, for the submitter
+    /// to confirm them.
+    pub fn with_completeness(mut self, stream: usize, completeness: SharedCompleteness) -> FanOut {
+        stream_at(&mut self.streams, stream).completeness = Some(completeness);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Consumes the events until the channel is closed, then shuts the sinks down.
+    /// Fails once a sink panicked, after the others processed their events.
+    pub async fn run(self, mut rx: mpsc::Receiver<Event>) -> Result<(), Error> {
+        if self.sinks.is_empty() {
+            return Err(Error::Config("No event sinks configured".to_string()));
+        }
+
+        let mut senders = Vec::with_capacity(self.sinks.len());
+        let mut handles = Vec::with_capacity(self.sinks.len());
+        for sink in self.sinks {
+            let (tx, sink_rx) = mpsc::unbounded_channel();
+            // The room in the sink's queue, with the block policy
+            let permits = match (self.capacity, &self.backpressure) {
+                (Some(capacity), Backpressure::Block) => Some(Arc::new(Semaphore::new(capacity))),
+                _ => None,
+            };
+            senders.push((tx, permits.clone()));
+            let waiting = Waiting::new(
+                PriorityQueue::new(self.priorities.clone()),
+                self.capacity,
+                self.backpressure.clone(),
+                permits,
+            );
+            handles.push(tokio::spawn(run_sink(sink, sink_rx, waiting)));
+        }
+
+        let mut journal = self.journal;
+        let mut streams = self.streams;
+        while let Some(mut event) = rx.recv().await {
+            let captured = Instant::now();
+            // Numbered once past the pipeline, the events it drops on purpose leave no gaps
+            let stream = self
+                .stream_of
+                .as_ref()
+                .map_or(0, |stream_of| stream_of(&event.file_path));
+            let stream = stream_at(&mut streams, stream);
+            stream.last_seq += 1;
+            event.sequence = stream.last_seq;
+            if let Some(completeness) = stream.completeness.as_ref() {
+                completeness
+                    .lock()
+                    .unwrap()
+                    .capture(event.sequence, captured);
+            }
+            if let Some(mut j) = journal.take() {
+                // The journal is written to a file, keep it off the async workers
+                let record = event.clone();
+                let (j, result) = tokio::task::spawn_blocking(move || {
+                    let result = j.append(&record);
+                    (j, result)
+                })
+                .await?;
+                if let Err(err) = result {
+                    println!("Failed to journal the event {}: {}", event, err);
+                }
+                journal = Some(j);
+            }
+            // A sink task exits only after the senders are dropped, unless it panicked
+            let mut alive = true;
+            for (tx, permits) in senders.iter() {
+                if let Some(permits) = permits {
+                    // Closed only if the sink is gone, then the send fails too
+                    if let Ok(permit) = permits.acquire().await {
+                        permit.forget();
+                    }
+                }
+                alive &= tx
+                    .send((event.clone(), captured))
+                    .is_ok();
+            }
+            if !alive {
+                break;
+            }
+        }
+
+        drop(senders);
+        let mut result = Ok(());
+        for handle in handles {
+            if let Err(e) = handle.await {
+                result = Err(e.into());
+            }
+        }
+        result
+    }
+}
+
+async fn run_sink(
+    mut sink: Box<dyn EventSink>,
+    mut rx: mpsc::UnboundedReceiver<(Event, Instant)>,
+    mut waiting: Waiting,
+) {
+    waiting
+        .spill_for(sink.name())
+        .await;
+    loop {
+        // The events which piled up while the sink was busy are taken by priority
+        while let Ok(received) = rx.try_recv() {
+            waiting.push(received).await;
+        }
+        if waiting
+            .dropped
+            .due(waiting.has_room())
+        {
+            report_dropped(&mut sink, &mut waiting.dropped).await;
+        }
+        if let Some((event, at)) = waiting.pop().await {
+            if let Err(err) = sink
+                .on_captured(&event, at)
+                .await
+            {
+                println!(
+                    "[{}] Failed to process the event {}: {}",
+                    sink.name(),
+                    event,
+                    err
+                );
+            }
+            continue;
+        }
+
+        match tokio::time::timeout(sink.flush_interval(), rx.recv()).await {
+            Ok(Some(received)) => waiting.push(received).await,
+            Err(_) => {
+                if let Err(err) = sink.flush().await {
+                    println!("[{}] Failed to flush: {}", sink.name(), err);
+                }
+            }
+            Ok(None) => break,
+        }
+    }
+
+    report_dropped(&mut sink, &mut waiting.dropped).await;
+    if let Err(err) = sink.shutdown().await {
+        println!("[{}] Failed to shut down: {}", sink.name(), err);
+    }
+}
+
+// The events waiting for a sink, at most `capacity` of them in its queue
+struct Waiting {
+    queue: PriorityQueue,
+    // Capture times of the queued events of each file, the queue keeps their order
+    captured: HashMap<String, VecDeque<Instant>>,
+    capacity: Option<usize>,
+    backpressure: Backpressure,
+    dropped: DropCounter,
+    spill: Option<Spill>,
+    // Capture times of the spilled events, the ones spilled by a previous run are unknown
+    spilled_at: VecDeque<Instant>,
+    // Taken by the fan-out for every event with the block policy, returned once it's sent
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl Waiting {
+    fn new(
+        queue: PriorityQueue,
+        capacity: Option<usize>,
+        backpressure: Backpressure,
+        permits: Option<Arc<Semaphore>>,
+    ) -> Waiting {
+        Waiting {
+            queue,
+            captured: HashMap::new(),
+            capacity,
+            backpressure,
+            dropped: DropCounter::default(),
+            spill: None,
+            spilled_at: VecDeque::new(),
+            permits,
+        }
+    }
+
+    // Opens the sink's spill file with the spill policy, the events are dropped without one
+    async fn spill_for(&mut self, name: &str) {
+        let (Some(_), Backpressure::SpillToDisk(dir)) = (self.capacity, &self.backpressure) else {
+            return;
+        };
+        match Spill::open(dir, name).await {
+            Ok(spill) => self.spill = Some(spill),
+            Err(e) => println!(
+                "[{}] Failed to open the spill file in {}, the events are dropped: {}",
+                name, dir, e
+            ),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.queue.len() >= capacity)
+    }
+
+    // Caught up with half of the queue
+    fn has_room(&self) -> bool {
+        self.capacity
+            .is_none_or(|capacity| self.queue.len() <= capacity / 2)
+    }
+
+    async fn push(&mut self, (event, at): (Event, Instant)) {
+        // Once spilling, the next events follow the spilled ones
+        let full = self.is_full();
+        if let Some(spill) = self.spill.as_mut() {
+            if full || !spill.is_empty() {
+                match spill.push(&event).await {
+                    Ok(()) => {
+                        self.spilled_at.push_back(at);
+                        return;
+                    }
+                    Err(e) => println!("Failed to spill the event {}: {}", event, e),
+                }
+            }
+        }
+        if self.is_full() {
+            self.dropped.drop_event();
+            match self.backpressure {
+                Backpressure::DropOldest => match self.queue.pop_oldest() {
+                    Some(oldest) => {
+                        self.captured_at(&oldest.file_path);
+                    }
+                    None => return,
+                },
+                // Not full with the block policy, the fan-out waits for room
+                _ => return,
+            }
+        }
+        self.captured
+            .entry(event.file_path.clone())
+            .or_default()
+            .push_back(at);
+        self.queue.push(event);
+    }
+
+    // The next event by priority, with the time it was captured
+    async fn pop(&mut self) -> Option<(Event, Instant)> {
+        self.refill().await;
+        let event = self.queue.pop()?;
+        if let Some(permits) = self.permits.as_ref() {
+            permits.add_permits(1);
+        }
+        let at = self.captured_at(&event.file_path);
+        Some((event, at))
+    }
+
+    // Takes the spilled events back while there's room
+    async fn refill(&mut self) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        while !spill.is_empty()
+            && self
+                .capacity
+                .is_some_and(|capacity| self.queue.len() < capacity)
+        {
+            let event = match spill.pop().await {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Failed to read a spilled event back: {}", e);
+                    break;
+                }
+            };
+            let at = self
+                .spilled_at
+                .pop_front()
+                .unwrap_or_else(Instant::now);
+            self.captured
+                .entry(event.file_path.clone())
+                .or_default()
+                .push_back(at);
+            self.queue.push(event);
+        }
+    }
+
+    // Capture time of the file's oldest queued event, now if not known
+    fn captured_at(&mut self, file_path: &str) -> Instant {
+        match self
+            .captured
+            .get_mut(file_path)
+        {
+            Some(times) if times.len() > 1 => times.pop_front(),
+            _ => self
+                .captured
+                .remove(file_path)
+                .and_then(|mut times| times.pop_front()),
+        }
+        .unwrap_or_else(Instant::now)
+    }
+}
+
+async fn report_dropped(sink: &mut Box<dyn EventSink>, dropped: &mut DropCounter) {
+    let Some(dropped) = dropped.take() else {
+        return;
+    };
+    println!("[{}] The queue was full, {}", sink.name(), dropped);
+    if let Err(err) = sink
+        .on_dropped(&dropped)
+        .await
+    {
+        println!(
+            "[{}] Failed to record the dropped events: {}",
+            sink.name(),
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingSink {
+        events: Arc<Mutex<Vec<Event>>>,
+        shut_down: Arc<Mutex<bool>>,
+    }
+
+    #[async_trait]
+    impl EventSink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(event.clone());
+            Ok(())
+        }
+
+        async fn shutdown(&mut self) -> Result<(), Error> {
+            *self.shut_down.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_delivers_to_all_sinks() {
+        let recorded: Vec<_> = (0..2)
+            .map(|_| {
+                (
+                    Arc::new(Mutex::new(Vec::new())),
+                    Arc::new(Mutex::new(false)),
+                )
+            })
+            .collect();
+
+        let mut fan_out = FanOut::new();
+        for (events, shut_down) in recorded.iter() {
+            fan_out = fan_out.with_sink(Box::new(RecordingSink {
+                events: events.clone(),
+                shut_down: shut_down.clone(),
+            }));
+        }
+
+        let (tx, rx) = mpsc::channel(4);
+        let event = Event::new("a.txt", EventType::Created);
+        tx.send(event.clone())
+            .await
+            .unwrap();
+        drop(tx);
+        fan_out.run(rx).await.unwrap();
+
+        let numbered = Event {
+            sequence: 1,
+            ..event
+        };
+        for (events, shut_down) in recorded.iter() {
+            assert_eq!(*events.lock().unwrap(), vec![numbered.clone()]);
+            assert!(*shut_down.lock().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streams_numbered_separately() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let completeness = Arc::new(Mutex::new(completeness::Completeness::new(1700000000)));
+        let fan_out = FanOut::new()
+            .with_sink(Box::new(RecordingSink {
+                events: events.clone(),
+                shut_down: Arc::new(Mutex::new(false)),
+            }))
+            .with_streams(|path| path.starts_with("/staging/") as usize)
+            .with_completeness(1, completeness.clone());
+
+        let (tx, rx) = mpsc::channel(4);
+        for path in ["/srv/a", "/staging/b", "/srv/c", "/staging/d"] {
+            tx.send(Event::new(path, EventType::Written))
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        fan_out.run(rx).await.unwrap();
+
+        let numbered: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| (event.file_path.clone(), event.sequence))
+            .collect();
+        assert_eq!(
+            numbered,
+            vec![
+                ("/srv/a".to_string(), 1),
+                ("/staging/b".to_string(), 1),
+                ("/srv/c".to_string(), 2),
+                ("/staging/d".to_string(), 2),
+            ]
+        );
+        assert_eq!(
+            completeness
+                .lock()
+                .unwrap()
+                .to_string(),
+            "2 events captured, 0 confirmed, 2 pending, 0 missing"
+        );
+    }
+
+    // Takes an event only once let through
+    struct StalledSink {
+        gate: Arc<Semaphore>,
+        events: Arc<Mutex<Vec<Event>>>,
+    }
+
+    #[async_trait]
+    impl EventSink for StalledSink {
+        fn name(&self) -> &str {
+            "stalled"
+        }
+
+        async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+            self.gate
+                .acquire()
+                .await
+                .unwrap()
+                .forget();
+            self.events
+                .lock()
+                .unwrap()
+                .push(event.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_sink_blocks_producer() {
+        let gate = Arc::new(Semaphore::new(0));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let fan_out = FanOut::new()
+            .with_sink(Box::new(StalledSink {
+                gate: gate.clone(),
+                events: events.clone(),
+            }))
+            .with_capacity(2)
+            .with_backpressure(Backpressure::Block);
+
+        let (tx, rx) = mpsc::channel(2);
+        let sent = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let producer = {
+            let sent = sent.clone();
+            tokio::spawn(async move {
+                for i in 0..20 {
+                    let event = Event::new(format!("/srv/{}", i), EventType::Written);
+                    tx.send(event).await.unwrap();
+                    sent.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                }
+            })
+        };
+        let running = tokio::spawn(fan_out.run(rx));
+
+        // The sink, its queue, the fan-out and the channel hold a few, the producer waits
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!producer.is_finished());
+        let held = sent.load(std::sync::atomic::Ordering::SeqCst);
+        assert!(held <= 6, "{} events sent to a stalled sink", held);
+
+        gate.add_permits(20);
+        producer.await.unwrap();
+        running
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(events.lock().unwrap().len(), 20);
+    }
+}
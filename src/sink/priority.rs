@@ -0,0 +1,207 @@
+//! Orders the events waiting for a sink by the importance of their type, so when
+//! the sink falls behind, e.g. the submitter waiting for the RPC node, the deletions
+//! and creations are sent before the noise of the opened and written files.
+//!
+//! The events of the same file are never reordered: the vault keeps the latest
+//! event of a file, a deletion overtaking an earlier write would leave the write.
+
+use crate::event::{Event, EventType};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Priority of each event type, the higher the sooner it's sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Priorities {
+    // Indexed like EventType::ALL
+    priorities: [u8; EventType::COUNT],
+}
+
+impl Default for Priorities {
+    fn default() -> Self {
+        Priorities::uniform(0)
+            .with(EventType::Deleted, 3)
+            .with(EventType::Created, 3)
+            .with(EventType::AttributeChanged, 2)
+            .with(EventType::MovedFrom, 2)
+            .with(EventType::MovedTo, 2)
+            .with(
+                EventType::Renamed {
+                    from: String::new(),
+                    to: String::new(),
+                },
+                2,
+            )
+            .with(EventType::Written, 1)
+    }
+}
+
+impl Priorities {
+    /// The same priority for all the event types, the events are sent in order.
+    pub fn uniform(priority: u8) -> Priorities {
+        Priorities {
+            priorities: [priority; EventType::COUNT],
+        }
+    }
+
+    pub fn with(mut self, event_type: EventType, priority: u8) -> Priorities {
+        self.priorities[index(&event_type)] = priority;
+        self
+    }
+
+    pub fn get(&self, event_type: &EventType) -> u8 {
+        self.priorities[index(event_type)]
+    }
+}
+
+fn index(event_type: &EventType) -> usize {
+    EventType::ALL
+        .iter()
+        .position(|t| t.same_type(event_type))
+        .expect("All the event types are listed")
+}
+
+// The highest priority first, then the oldest
+type Key = (Reverse<u8>, u64);
+
+pub struct PriorityQueue {
+    priorities: Priorities,
+    queue: BTreeMap<Key, Event>,
+    // Keys of the queued events of each file, oldest first
+    by_path: HashMap<String, VecDeque<Key>>,
+    next_seq: u64,
+}
+
+impl PriorityQueue {
+    pub fn new(priorities: Priorities) -> PriorityQueue {
+        PriorityQueue {
+            priorities,
+            queue: BTreeMap::new(),
+            by_path: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn push(&mut self, event: Event) {
+        let priority = self
+            .priorities
+            .get(&event.event_type);
+        let keys = self
+            .by_path
+            .entry(event.file_path.clone())
+            .or_default();
+        // The file's earlier events go along with it
+        for key in keys.iter_mut() {
+            if key.0 .0 < priority {
+                let earlier = self
+                    .queue
+                    .remove(key)
+                    .expect("Indexed events are queued");
+                key.0 = Reverse(priority);
+                self.queue
+                    .insert(*key, earlier);
+            }
+        }
+
+        let key = (Reverse(priority), self.next_seq);
+        self.next_seq += 1;
+        keys.push_back(key);
+        self.queue.insert(key, event);
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        let (_, event) = self.queue.pop_first()?;
+        if let Some(keys) = self
+            .by_path
+            .get_mut(&event.file_path)
+        {
+            keys.pop_front();
+            if keys.is_empty() {
+                self.by_path
+                    .remove(&event.file_path);
+            }
+        }
+        Some(event)
+    }
+
+    /// Takes out the event queued first, whatever its priority.
+    pub fn pop_oldest(&mut self) -> Option<Event> {
+        // Each file's keys are oldest first
+        let (path, key) = self
+            .by_path
+            .iter()
+            .filter_map(|(path, keys)| {
+                keys.front()
+                    .map(|key| (path, *key))
+            })
+            .min_by_key(|(_, key)| key.1)?;
+        let path = path.clone();
+        if let Some(keys) = self.by_path.get_mut(&path) {
+            keys.pop_front();
+            if keys.is_empty() {
+                self.by_path.remove(&path);
+            }
+        }
+        self.queue.remove(&key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(path: &str, event_type: EventType) -> Event {
+        Event::new(path, event_type)
+    }
+
+    #[test]
+    fn test_important_first_same_file_in_order() {
+        let mut queue = PriorityQueue::new(Priorities::default());
+        queue.push(event("/srv/a", EventType::Opened));
+        queue.push(event("/srv/b", EventType::Written));
+        queue.push(event("/srv/c", EventType::Written));
+        queue.push(event("/srv/d", EventType::Created));
+        queue.push(event("/srv/c", EventType::Deleted));
+        queue.push(event("/srv/e", EventType::Opened));
+
+        let mut sent = Vec::new();
+        while let Some(event) = queue.pop() {
+            sent.push(event);
+        }
+        assert!(queue.is_empty());
+        assert_eq!(
+            sent,
+            vec![
+                event("/srv/c", EventType::Written),
+                event("/srv/d", EventType::Created),
+                event("/srv/c", EventType::Deleted),
+                event("/srv/b", EventType::Written),
+                event("/srv/a", EventType::Opened),
+                event("/srv/e", EventType::Opened),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oldest_taken_out_first() {
+        let mut queue = PriorityQueue::new(Priorities::default());
+        queue.push(event("/srv/a", EventType::Opened));
+        queue.push(event("/srv/b", EventType::Deleted));
+        queue.push(event("/srv/a", EventType::Written));
+
+        assert_eq!(queue.pop_oldest(), Some(event("/srv/a", EventType::Opened)));
+        assert_eq!(queue.pop(), Some(event("/srv/b", EventType::Deleted)));
+        assert_eq!(
+            queue.pop_oldest(),
+            Some(event("/srv/a", EventType::Written))
+        );
+        assert_eq!(queue.pop_oldest(), None);
+    }
+}
@@ -0,0 +1,90 @@
+use super::EventSink;
+use crate::error::Error;
+use crate::event::{Event, EventType};
+use async_trait::async_trait;
+
+/// POSTs every event as JSON to the configured URL.
+pub struct WebhookSink {
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> WebhookSink {
+        WebhookSink {
+            url: url.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+        // ureq is blocking, keep it off the async workers
+        let url = self.url.clone();
+        let body = event_to_json(event);
+        tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(body)
+                .map(|_| ())
+                .map_err(Error::from)
+        })
+        .await?
+    }
+}
+
+pub fn event_to_json(event: &Event) -> serde_json::Value {
+    let file_info = event
+        .file_info
+        .as_ref()
+        .map(|fi| {
+            serde_json::json!({
+                "size": fi.size,
+                "mode": fi.mode,
+                // i128 is not supported by serde_json, unix timestamps fit in i64 anyway
+                "access_ts": fi.access_ts.map(|t| t as i64),
+                "modify_ts": fi.modify_ts.map(|t| t as i64),
+                "created_ts": fi.created_ts.map(|t| t as i64),
+                "uid": fi.uid,
+                "gid": fi.gid,
+                "user": fi.user,
+                "group": fi.group,
+                "xattrs": fi
+                    .xattrs
+                    .iter()
+                    .map(|(name, value)| (name.clone(), serde_json::json!(value)))
+                    .collect::<serde_json::Map<_, _>>(),
+                "is_symlink": fi.is_symlink,
+                "link_target": fi.link_target,
+                "inode": fi.inode,
+                "device": fi.device,
+                "nlink": fi.nlink,
+                "file_type": fi.file_type,
+            })
+        });
+
+    let mut json = serde_json::json!({
+        "file_path": event.file_path,
+        "event_type": event.event_type.to_string(),
+        "kind": event.kind.to_string(),
+        "suppressed_count": event.suppressed_count,
+        "sequence": event.sequence,
+        "captured_at": event.captured_at,
+        "hard_links": event.hard_links,
+        "diff": event.diff,
+        "process": event.process.as_ref().map(|process| serde_json::json!({
+            "pid": process.pid,
+            "exe": process.exe,
+        })),
+        "solana_ts_received_at": event.solana_ts_received_at as i64,
+        "file_info": file_info,
+    });
+    // Reported under the new path
+    if let EventType::Renamed { from, .. } = &event.event_type {
+        json["renamed_from"] = serde_json::json!(from);
+    }
+    json
+}
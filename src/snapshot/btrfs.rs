@@ -0,0 +1,193 @@
+//! Output of `btrfs send --no-data -p <from> <to> | btrfs receive --dump`:
+//! one command of the send stream per line, its path relative to the snapshot
+//! followed by `key=value` attributes.
+
+use super::Change;
+use crate::error::Error;
+use crate::event::EventType;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The paths are reported under `root`, where the snapshotted subvolume is mounted.
+pub fn parse(output: &str, root: &str) -> Result<Vec<Change>, Error> {
+    let mut changes: Vec<Change> = Vec::new();
+    // Files created in this diff, their attribute changes are a part of the creation
+    let mut created = HashSet::new();
+    // Snapshot's name, the first component of all the paths
+    let mut snapshot = None;
+
+    for line in output.lines() {
+        let invalid = || Error::Snapshot(format!("Unexpected btrfs dump line: {}", line));
+        let tokens = split(line);
+        let (command, path) = match &tokens[..] {
+            [] => continue,
+            [command, path, ..] => (command.as_str(), path.as_str()),
+            _ => return Err(invalid()),
+        };
+
+        if command == "snapshot" || command == "subvol" {
+            snapshot = Some(path.to_string());
+            continue;
+        }
+        let snapshot = snapshot
+            .as_deref()
+            .ok_or_else(invalid)?;
+        let path = to_root(root, snapshot, path).ok_or_else(invalid)?;
+
+        let event_type = match command {
+            "mkfile" | "mkdir" | "mknod" | "mkfifo" | "mksock" | "symlink" | "link" => {
+                created.insert(path.clone());
+                EventType::Created
+            }
+            "unlink" | "rmdir" => EventType::Deleted,
+            "rename" => {
+                let dest = tokens
+                    .iter()
+                    .find_map(|t| t.strip_prefix("dest="))
+                    .and_then(|dest| to_root(root, snapshot, dest))
+                    .ok_or_else(invalid)?;
+                // New files are created under a temporary name and renamed
+                if created.remove(&path) {
+                    for change in changes
+                        .iter_mut()
+                        .filter(|c| c.path == path)
+                    {
+                        change.path = dest.clone();
+                    }
+                    created.insert(dest);
+                    continue;
+                }
+                changes.push(Change {
+                    event_type: EventType::MovedFrom,
+                    path,
+                });
+                changes.push(Change {
+                    event_type: EventType::MovedTo,
+                    path: dest,
+                });
+                continue;
+            }
+            "write" | "clone" | "truncate" | "update_extent" | "fallocate" | "encoded_write" => {
+                EventType::Written
+            }
+            "chmod" | "chown" | "set_xattr" | "remove_xattr" | "fileattr" => {
+                if created.contains(&path) {
+                    continue;
+                }
+                EventType::AttributeChanged
+            }
+            // Including utimes, sent for every changed file and its directory
+            _ => continue,
+        };
+
+        // A file is changed by many commands, one event is enough
+        let change = Change { event_type, path };
+        if !changes.contains(&change) {
+            changes.push(change);
+        }
+    }
+    Ok(changes)
+}
+
+// Strips the snapshot's name from the dumped path, `./<snapshot>/<path>`
+fn to_root(root: &str, snapshot: &str, path: &str) -> Option<String> {
+    let relative = Path::new(path)
+        .strip_prefix(snapshot)
+        .ok()?;
+    Path::new(root)
+        .join(relative)
+        .to_str()
+        .map(|p| p.to_string())
+}
+
+// Splits the line on whitespace, except the escaped one, and unescapes the tokens
+fn split(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = Vec::new();
+    let mut bytes = line.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b' ' | b'\t' => {
+                if !token.is_empty() {
+                    tokens.push(String::from_utf8_lossy(&token).into_owned());
+                    token.clear();
+                }
+            }
+            b'\\' => {
+                let escaped = match bytes.next() {
+                    Some(b'a') => 0x07,
+                    Some(b'b') => 0x08,
+                    Some(b'e') => 0x1b,
+                    Some(b'f') => 0x0c,
+                    Some(b'n') => b'\n',
+                    Some(b'r') => b'\r',
+                    Some(b't') => b'\t',
+                    Some(b'v') => 0x0b,
+                    // Other non printable characters, 3 octal digits
+                    Some(d) if d.is_ascii_digit() => {
+                        let digits = [
+                            d,
+                            bytes.next().unwrap_or(b'0'),
+                            bytes.next().unwrap_or(b'0'),
+                        ];
+                        std::str::from_utf8(&digits)
+                            .ok()
+                            .and_then(|d| u8::from_str_radix(d, 8).ok())
+                            .unwrap_or(b'?')
+                    }
+                    Some(other) => other,
+                    None => break,
+                };
+                token.push(escaped);
+            }
+            _ => token.push(byte),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(String::from_utf8_lossy(&token).into_owned());
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = r"snapshot        ./tue                           uuid=2b1c transid=12 parent_uuid=9f3a parent_transid=9
+utimes          ./tue/                          atime=2024-10-15T10:00:00+0000
+mkfile          ./tue/o257-12-0
+rename          ./tue/o257-12-0                 dest=./tue/new\ file.txt
+chown           ./tue/new\ file.txt             gid=0 uid=0
+truncate        ./tue/new\ file.txt             size=5
+update_extent   ./tue/new\ file.txt             offset=0 len=5
+update_extent   ./tue/notes.txt                 offset=0 len=4096
+update_extent   ./tue/notes.txt                 offset=4096 len=4096
+chmod           ./tue/script.sh                 mode=755
+rename          ./tue/a.txt                     dest=./tue/b.txt
+unlink          ./tue/old.txt
+";
+
+        let changes = parse(output, "/mnt/home").unwrap();
+        let changes: Vec<(EventType, &str)> = changes
+            .iter()
+            .map(|c| (c.event_type.clone(), c.path.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (EventType::Created, "/mnt/home/new file.txt"),
+                (EventType::Written, "/mnt/home/new file.txt"),
+                (EventType::Written, "/mnt/home/notes.txt"),
+                (EventType::AttributeChanged, "/mnt/home/script.sh"),
+                (EventType::MovedFrom, "/mnt/home/a.txt"),
+                (EventType::MovedTo, "/mnt/home/b.txt"),
+                (EventType::Deleted, "/mnt/home/old.txt"),
+            ]
+        );
+
+        // Paths before the snapshot line
+        assert!(parse("unlink ./tue/old.txt", "/mnt/home").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! Event source diffing two filesystem snapshots, an alternative to the watcher
+//! for hosts where a resident watcher isn't allowed: run periodically, e.g. right
+//! after a new snapshot is taken, it reports what changed since the previous one.
+
+pub mod btrfs;
+pub mod zfs;
+
+use crate::config::{SnapshotConfig, WatcherConfig};
+use crate::dir_watcher::read_file_info;
+use crate::error::Error;
+use crate::event::{Event, EventType, FileKind};
+use std::process::{Command, Output};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// `zfs diff` of two snapshots of a dataset.
+    Zfs,
+    /// Metadata of `btrfs send` of a snapshot with a parent snapshot.
+    Btrfs,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zfs" => Ok(Backend::Zfs),
+            "btrfs" => Ok(Backend::Btrfs),
+            _ => Err(Error::Config(format!(
+                "Unknown snapshot backend: {}, expected `zfs` or `btrfs`",
+                s
+            ))),
+        }
+    }
+}
+
+/// A single change found in the diff.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub event_type: EventType,
+    pub path: String,
+}
+
+pub struct SnapshotDiff {
+    config: SnapshotConfig,
+    dirs: Vec<String>,
+    events: Vec<EventType>,
+}
+
+impl SnapshotDiff {
+    /// Only the changes in the watched directories, of the watched event types are reported.
+    pub fn from_config(config: &SnapshotConfig, watcher: &WatcherConfig) -> SnapshotDiff {
+        SnapshotDiff {
+            config: config.clone(),
+            dirs: watcher.dirs.clone(),
+            events: watcher.events.clone(),
+        }
+    }
+
+    /// Diffs the snapshots and sends the changes as events, returns once all of them are sent.
+    pub async fn run(self, tx: mpsc::Sender<Event>) -> Result<(), Error> {
+        let config = self.config.clone();
+        let changes = tokio::task::spawn_blocking(move || diff(&config)).await??;
+        println!(
+            "{} changes between {} and {}",
+            changes.len(),
+            self.config.from,
+            self.config.to
+        );
+
+        for event in self.to_events(changes) {
+            tx.send(event)
+                .await
+                .map_err(|_| Error::Disconnected)?;
+        }
+        Ok(())
+    }
+
+    fn to_events(&self, changes: Vec<Change>) -> Vec<Event> {
+        changes
+            .into_iter()
+            .filter(|c| {
+                self.events
+                    .contains(&c.event_type)
+            })
+            .filter(|c| {
+                self.dirs
+                    .iter()
+                    .any(|dir| is_under(&c.path, dir))
+            })
+            .map(|c| {
+                // Read from the live file system, as the watcher does
+                let file_info = match c.event_type {
+                    EventType::Deleted | EventType::MovedFrom => None,
+                    _ => read_file_info(&c.path),
+                };
+                // Timestamped by the listener
+                Event {
+                    kind: FileKind::of(file_info.as_ref()),
+                    file_info,
+                    ..Event::new(c.path, c.event_type)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Whether the path is the directory or a path in it.
+pub fn is_under(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+// Runs the backend's diff command and parses its output
+fn diff(config: &SnapshotConfig) -> Result<Vec<Change>, Error> {
+    match config.backend {
+        Backend::Zfs => {
+            let output = Command::new("zfs")
+                .args(["diff", "-FH", &config.from, &config.to])
+                .output()?;
+            zfs::parse(&command_output("zfs diff", output)?)
+        }
+        Backend::Btrfs => {
+            let mut send = Command::new("btrfs")
+                .args(["send", "--no-data", "-q", "-p", &config.from, &config.to])
+                .stdout(std::process::Stdio::piped())
+                .spawn()?;
+            let stream = send
+                .stdout
+                .take()
+                .ok_or(Error::Snapshot("btrfs send has no output".to_string()))?;
+            let dump = Command::new("btrfs")
+                .args(["receive", "--dump"])
+                .stdin(stream)
+                .output()?;
+            if !send.wait()?.success() {
+                return Err(Error::Snapshot("btrfs send failed".to_string()));
+            }
+            // Validated for the btrfs backend
+            let root = config
+                .root
+                .as_deref()
+                .unwrap_or("/");
+            btrfs::parse(&command_output("btrfs receive --dump", dump)?, root)
+        }
+    }
+}
+
+fn command_output(command: &str, output: Output) -> Result<String, Error> {
+    if !output.status.success() {
+        return Err(Error::Snapshot(format!(
+            "{} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_filtered() {
+        let diff = SnapshotDiff {
+            config: SnapshotConfig {
+                backend: Backend::Zfs,
+                from: "tank/home@mon".to_string(),
+                to: "tank/home@tue".to_string(),
+                root: None,
+            },
+            dirs: vec!["/tank/home/alice/".to_string()],
+            events: vec![EventType::Created, EventType::Deleted],
+        };
+        let change = |event_type, path: &str| Change {
+            event_type,
+            path: path.to_string(),
+        };
+
+        let events = diff.to_events(vec![
+            change(EventType::Deleted, "/tank/home/alice/a.txt"),
+            change(EventType::Written, "/tank/home/alice/b.txt"),
+            change(EventType::Deleted, "/tank/home/alice2/c.txt"),
+            change(EventType::Deleted, "/tank/home/bob/d.txt"),
+        ]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].file_path, "/tank/home/alice/a.txt");
+        assert_eq!(events[0].event_type, EventType::Deleted);
+        assert!(events[0].file_info.is_none());
+    }
+}
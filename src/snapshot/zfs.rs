@@ -0,0 +1,110 @@
+//! Output of `zfs diff -FH <from> <to>`: tab separated change, file type
+//! and path, followed by the new path of the renamed files.
+
+use super::Change;
+use crate::error::Error;
+use crate::event::EventType;
+
+// File type of the directories, a changed directory only means
+// that its entries changed, these are reported on their own
+const DIRECTORY: &str = "/";
+
+pub fn parse(output: &str) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+    for line in output.lines() {
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || Error::Snapshot(format!("Unexpected zfs diff line: {}", line));
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (kind, file_type, path) = match fields[..] {
+            [kind, file_type, path, ..] => (kind, file_type, unescape(path)),
+            _ => return Err(invalid()),
+        };
+        let change = |event_type| Change {
+            event_type,
+            path: path.clone(),
+        };
+        match (kind, file_type) {
+            ("M", DIRECTORY) => (),
+            ("+", _) => changes.push(change(EventType::Created)),
+            ("-", _) => changes.push(change(EventType::Deleted)),
+            // Content and metadata changes are not told apart
+            ("M", _) => changes.push(change(EventType::Written)),
+            ("R", _) => {
+                let new_path = fields
+                    .get(3)
+                    .ok_or_else(invalid)?;
+                changes.push(change(EventType::MovedFrom));
+                changes.push(Change {
+                    event_type: EventType::MovedTo,
+                    path: unescape(new_path),
+                });
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(changes)
+}
+
+// Special characters are printed as a backslash followed by 4 octal digits, e.g. `\0040`
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 5)
+            .filter(|digits| {
+                digits
+                    .iter()
+                    .all(|d| (b'0'..=b'7').contains(d))
+            })
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 5;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = "M\t/\t/tank/home/\n\
+                      +\tF\t/tank/home/new\\0040file.txt\n\
+                      M\tF\t/tank/home/notes.txt\n\
+                      -\tF\t/tank/home/old.txt\n\
+                      R\tF\t/tank/home/a.txt\t/tank/home/b.txt\n";
+
+        let changes = parse(output).unwrap();
+        let changes: Vec<(EventType, &str)> = changes
+            .iter()
+            .map(|c| (c.event_type.clone(), c.path.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (EventType::Created, "/tank/home/new file.txt"),
+                (EventType::Written, "/tank/home/notes.txt"),
+                (EventType::Deleted, "/tank/home/old.txt"),
+                (EventType::MovedFrom, "/tank/home/a.txt"),
+                (EventType::MovedTo, "/tank/home/b.txt"),
+            ]
+        );
+
+        assert!(parse("X\tF\t/tank/home/a.txt").is_err());
+        assert!(parse("R\tF\t/tank/home/a.txt").is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Reads many accounts at once with `getMultipleAccounts`, e.g. the vaults,
+//! instead of one `getAccountInfo` request per account.
+
+use super::rpc_error;
+use crate::error::Error;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+// Limit of the accounts in a single getMultipleAccounts request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Reads the accounts in chunks the RPC node accepts and decodes the existing ones.
+/// The result is in the order of `keys`, `None` where the account doesn't exist.
+pub async fn read_accounts<T, F>(
+    rpc_client: &RpcClient,
+    keys: &[Pubkey],
+    decode: F,
+) -> Result<Vec<Option<T>>, Error>
+where
+    F: Fn(&Pubkey, &Account) -> Result<T, Error>,
+{
+    let mut decoded = Vec::with_capacity(keys.len());
+    for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client
+            .get_multiple_accounts(chunk)
+            .await
+            .map_err(rpc_error)?;
+        decoded.extend(decode_chunk(chunk, accounts, &decode)?);
+    }
+    Ok(decoded)
+}
+
+fn decode_chunk<T, F>(
+    keys: &[Pubkey],
+    accounts: Vec<Option<Account>>,
+    decode: &F,
+) -> Result<Vec<Option<T>>, Error>
+where
+    F: Fn(&Pubkey, &Account) -> Result<T, Error>,
+{
+    if accounts.len() != keys.len() {
+        return Err(Error::Rpc {
+            source: format!(
+                "{} accounts requested, {} returned",
+                keys.len(),
+                accounts.len()
+            )
+            .into(),
+            retryable: false,
+        });
+    }
+    keys.iter()
+        .zip(accounts)
+        .map(|(key, account)| {
+            account
+                .map(|account| decode(key, &account))
+                .transpose()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_accounts_kept_in_order() {
+        let keys = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let account = |lamports| Account {
+            lamports,
+            ..Account::default()
+        };
+        let decode = |_: &Pubkey, account: &Account| Ok(account.lamports);
+
+        let decoded = decode_chunk(
+            &keys,
+            vec![Some(account(1)), None, Some(account(3))],
+            &decode,
+        );
+        assert_eq!(decoded.unwrap(), vec![Some(1), None, Some(3)]);
+
+        let truncated = decode_chunk(&keys, vec![Some(account(1))], &decode);
+        assert!(matches!(
+            truncated,
+            Err(Error::Rpc {
+                retryable: false,
+                ..
+            })
+        ));
+    }
+}
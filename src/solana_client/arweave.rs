@@ -0,0 +1,174 @@
+//! Permanent copies of the changed files on Arweave, uploaded through a bundlr node.
+//!
+//! The content is sent as an ANS-104 data item signed with the Solana wallet,
+//! bundlr charges the wallet's balance funded on the node, e.g. `irys fund`.
+//! The returned Arweave transaction id is recorded with the event.
+
+use crate::error::Error;
+use crate::event::{Event, EventType};
+use sha2::{Digest, Sha384};
+use solana_sdk::{signature::Keypair, signer::Signer};
+
+// ANS-104 signature type of the Solana wallets
+const SIGNATURE_TYPE_ED25519: u16 = 2;
+// File type bits of the mode
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+pub struct Arweave {
+    // bundlr node, e.g. https://node1.bundlr.network
+    url: String,
+    max_size: u64,
+}
+
+impl Arweave {
+    pub fn new(url: &str, max_size: u64) -> Arweave {
+        Arweave {
+            url: url
+                .trim_end_matches('/')
+                .to_string(),
+            max_size,
+        }
+    }
+
+    /// Whether the event has new content to upload: a regular file not larger than the limit.
+    pub fn wants(&self, event: &Event) -> bool {
+        let changed = matches!(
+            event.event_type,
+            EventType::Created | EventType::Written | EventType::MovedTo
+        );
+        changed
+            && event
+                .file_info
+                .as_ref()
+                .is_some_and(|fi| fi.mode & S_IFMT == S_IFREG && fi.size <= self.max_size)
+    }
+
+    /// Uploads the file's current content, returns the Arweave transaction id.
+    pub async fn upload(&self, wallet: &Keypair, path: &str) -> Result<String, Error> {
+        // Both the file and ureq are blocking, keep them off the async workers
+        let file_path = path.to_string();
+        let content = tokio::task::spawn_blocking(move || std::fs::read(file_path)).await??;
+        let item = data_item(wallet, &content);
+
+        let url = format!("{}/tx/solana", self.url);
+        let response: serde_json::Value = tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .set("Content-Type", "application/octet-stream")
+                .send_bytes(&item)?
+                .into_json()
+                .map_err(Error::Io)
+        })
+        .await??;
+        response["id"]
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or(Error::serialization(
+                "bundlr response has no transaction id",
+            ))
+    }
+}
+
+/// Builds the ANS-104 data item of `data` signed by the `wallet`, without target, anchor and tags.
+pub fn data_item(wallet: &Keypair, data: &[u8]) -> Vec<u8> {
+    let owner = wallet.pubkey().to_bytes();
+    let signature_type = SIGNATURE_TYPE_ED25519.to_string();
+    let message = deep_hash(&[
+        b"dataitem",
+        b"1",
+        signature_type.as_bytes(),
+        &owner,
+        b"", // target
+        b"", // anchor
+        b"", // tags
+        data,
+    ]);
+    let signature = wallet.sign_message(&message);
+
+    let mut item = Vec::with_capacity(2 + 64 + 32 + 2 + 16 + data.len());
+    item.extend_from_slice(&SIGNATURE_TYPE_ED25519.to_le_bytes());
+    item.extend_from_slice(signature.as_ref());
+    item.extend_from_slice(&owner);
+    item.push(0); // no target
+    item.push(0); // no anchor
+    item.extend_from_slice(&0u64.to_le_bytes()); // number of tags
+    item.extend_from_slice(&0u64.to_le_bytes()); // length of the tags
+    item.extend_from_slice(data);
+    item
+}
+
+// Arweave's deep hash of a list of blobs, the signed message of a data item
+fn deep_hash(chunks: &[&[u8]]) -> [u8; 48] {
+    let mut acc = sha384(format!("list{}", chunks.len()).as_bytes());
+    for chunk in chunks {
+        let tag = sha384(format!("blob{}", chunk.len()).as_bytes());
+        let blob = sha384(&[tag, sha384(chunk)].concat());
+        acc = sha384(&[acc, blob].concat());
+    }
+    acc
+}
+
+fn sha384(data: &[u8]) -> [u8; 48] {
+    Sha384::digest(data).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::FileInfo;
+    use solana_sdk::signature::Signature;
+
+    #[test]
+    fn test_data_item_signed_by_wallet() {
+        let wallet = Keypair::new();
+        let item = data_item(&wallet, b"hello");
+
+        assert_eq!(item.len(), 2 + 64 + 32 + 2 + 16 + 5);
+        assert_eq!(&item[..2], &[2, 0]);
+        assert_eq!(&item[66..98], wallet.pubkey().as_ref());
+        assert_eq!(&item[item.len() - 5..], b"hello");
+
+        let signature = Signature::try_from(&item[2..66]).unwrap();
+        let message = deep_hash(&[
+            b"dataitem",
+            b"1",
+            b"2",
+            wallet.pubkey().as_ref(),
+            b"",
+            b"",
+            b"",
+            b"hello",
+        ]);
+        assert!(signature.verify(wallet.pubkey().as_ref(), &message));
+    }
+
+    #[test]
+    fn test_only_regular_files_uploaded() {
+        let arweave = Arweave::new("https://node1.bundlr.network/", 1024);
+        let event = |event_type, mode, size| Event {
+            file_info: Some(FileInfo {
+                access_ts: None,
+                modify_ts: None,
+                created_ts: None,
+                size,
+                mode,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: None,
+            }),
+            ..Event::new("/srv/data/a.txt", event_type)
+        };
+        assert!(arweave.wants(&event(EventType::Written, 0o100644, 1024)));
+        assert!(!arweave.wants(&event(EventType::Written, 0o100644, 1025)));
+        assert!(!arweave.wants(&event(EventType::Created, 0o040755, 0)));
+        assert!(!arweave.wants(&event(EventType::AttributeChanged, 0o100644, 1)));
+    }
+}
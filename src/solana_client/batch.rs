@@ -0,0 +1,424 @@
+//! Packs several events into a transaction, fewer transactions cost fewer fees.
+//!
+//! A transaction has to fit in a packet, [PACKET_DATA_SIZE] bytes with its signatures.
+//! The candidate transactions are measured serialized, the accounts found in the address
+//! lookup tables, if any, take a one-byte index instead of their 32-byte key.
+//!
+//! The memos of an event too long to fit in a packet along with it are recorded by their
+//! hash, see [Packer::fit].
+
+use crate::error::Error;
+use crate::solana_client::memo_instruction;
+use sha2::{Digest, Sha256};
+use solana_sdk::{
+    address_lookup_table::AddressLookupTableAccount,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::VersionedTransaction,
+};
+
+// Signatures are 64 bytes, preceded by their count
+const SIGNATURE_SIZE: usize = 64;
+
+pub struct Packer {
+    payer: Pubkey,
+    // Set to send v0 transactions
+    lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+impl Packer {
+    pub fn new(payer: Pubkey) -> Packer {
+        Packer {
+            payer,
+            lookup_tables: Vec::new(),
+        }
+    }
+
+    /// Compiles the transactions as v0 messages looking the accounts up in the `tables`.
+    pub fn with_lookup_tables(mut self, tables: Vec<AddressLookupTableAccount>) -> Packer {
+        self.lookup_tables = tables;
+        self
+    }
+
+    /// The message paid by the payer, a legacy one unless there are lookup tables.
+    pub fn message(
+        &self,
+        instructions: &[Instruction],
+        blockhash: Hash,
+    ) -> Result<VersionedMessage, Error> {
+        self.message_paid_by(&self.payer, instructions, blockhash)
+    }
+
+    fn message_paid_by(
+        &self,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        blockhash: Hash,
+    ) -> Result<VersionedMessage, Error> {
+        if self.lookup_tables.is_empty() {
+            return Ok(VersionedMessage::Legacy(Message::new_with_blockhash(
+                instructions,
+                Some(payer),
+                &blockhash,
+            )));
+        }
+        v0::Message::try_compile(payer, instructions, &self.lookup_tables, blockhash)
+            .map(VersionedMessage::V0)
+            .map_err(|e| Error::Config(format!("Failed to compile the transaction: {}", e)))
+    }
+
+    /// The transaction signed by the payer, the only signer of the events' transactions.
+    pub fn sign(
+        &self,
+        instructions: &[Instruction],
+        payer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<VersionedTransaction, Error> {
+        VersionedTransaction::try_new(self.message(instructions, blockhash)?, &[payer])
+            .map_err(|e| Error::Config(format!("Failed to sign the transaction: {}", e)))
+    }
+
+    /// The transaction paid by the `fee_payer`, e.g. a wallet of a pool, and signed by
+    /// the `signer` of the events too.
+    pub fn sign_paid(
+        &self,
+        instructions: &[Instruction],
+        fee_payer: &Keypair,
+        signer: &Keypair,
+        blockhash: Hash,
+    ) -> Result<VersionedTransaction, Error> {
+        let message = self.message_paid_by(&fee_payer.pubkey(), instructions, blockhash)?;
+        VersionedTransaction::try_new(message, &[fee_payer, signer])
+            .map_err(|e| Error::Config(format!("Failed to sign the transaction: {}", e)))
+    }
+
+    /// Size of the signed transaction, as sent to the RPC node, every signer required
+    /// by the instructions counted. [usize::MAX] if it can't be compiled.
+    pub fn transaction_size(&self, instructions: &[Instruction]) -> usize {
+        let Ok(message) = self.message(instructions, Hash::default()) else {
+            return usize::MAX;
+        };
+        let signatures = usize::from(
+            message
+                .header()
+                .num_required_signatures,
+        );
+        short_vec_len(signatures) + signatures * SIGNATURE_SIZE + message.serialize().len()
+    }
+
+    /// The event's `instruction` followed by its `memos`, fitting in a packet with the `prefix`.
+    /// The longest memos are replaced by their tag and the hash of the rest, e.g.
+    /// `symlink sha256 <hex>`, until the group fits, then all of them by a single hash if
+    /// it still doesn't. Only an event whose own instruction is too big, e.g. with a path
+    /// of thousands of bytes, doesn't fit.
+    pub fn fit(
+        &self,
+        prefix: &[Instruction],
+        instruction: Instruction,
+        mut memos: Vec<String>,
+    ) -> Vec<Instruction> {
+        let group = |memos: &[String]| -> Vec<Instruction> {
+            std::iter::once(instruction.clone())
+                .chain(
+                    memos
+                        .iter()
+                        .map(|memo| memo_instruction(memo)),
+                )
+                .collect()
+        };
+        let fits = |memos: &[String]| {
+            let mut candidate = prefix.to_vec();
+            candidate.extend(group(memos));
+            self.transaction_size(&candidate) <= PACKET_DATA_SIZE
+        };
+        while !fits(&memos) {
+            // Hashed already, or shorter than its hash
+            let Some(longest) = memos
+                .iter_mut()
+                .filter(|memo| hashed(memo).len() < memo.len())
+                .max_by_key(|memo| memo.len())
+            else {
+                break;
+            };
+            *longest = hashed(longest);
+        }
+        if !fits(&memos) && memos.len() > 1 {
+            memos = vec![hashed(&format!("memos {}", memos.join("\n")))];
+        }
+        group(&memos)
+    }
+
+    /// Fills the transactions greedily with the groups of instructions, e.g. an event and
+    /// its memos, keeping every transaction within the packet size. A group is never split,
+    /// it fits in a packet alone once fitted by [Self::fit].
+    pub fn pack(&self, groups: Vec<Vec<Instruction>>) -> Vec<Vec<Instruction>> {
+        let counts = self.split(&[], &groups);
+        let mut groups = groups.into_iter();
+        counts
+            .into_iter()
+            .map(|count| {
+                groups
+                    .by_ref()
+                    .take(count)
+                    .flatten()
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Number of the consecutive groups packed in each transaction by [Self::pack],
+    /// every transaction starting with the `prefix`, e.g. a compute budget instruction.
+    pub fn split(&self, prefix: &[Instruction], groups: &[Vec<Instruction>]) -> Vec<usize> {
+        let mut counts = Vec::new();
+        let mut current: Vec<Instruction> = prefix.to_vec();
+        let mut count = 0;
+        for group in groups {
+            let mut candidate = current.clone();
+            candidate.extend(group.iter().cloned());
+            if count == 0 || self.transaction_size(&candidate) <= PACKET_DATA_SIZE {
+                current = candidate;
+                count += 1;
+            } else {
+                counts.push(count);
+                current = prefix.to_vec();
+                current.extend(group.iter().cloned());
+                count = 1;
+            }
+        }
+        if count > 0 {
+            counts.push(count);
+        }
+        counts
+    }
+}
+
+// The memo's tag followed by the hash of the rest, e.g. `diff sha256 <hex>`
+fn hashed(memo: &str) -> String {
+    let (tag, rest) = memo
+        .split_once(' ')
+        .unwrap_or((memo, ""));
+    let sha256: [u8; 32] = Sha256::digest(rest.as_bytes()).into();
+    format!("{} sha256 {}", tag, crate::integrity::hex(&sha256))
+}
+
+// Bytes of a compact-u16 length prefix
+fn short_vec_len(len: usize) -> usize {
+    match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventType, FileInfo, PathDictionary, Process};
+    use crate::solana_client::event_instruction;
+    use solana_sdk::{
+        compute_budget::ComputeBudgetInstruction, instruction::AccountMeta, signer::Signer,
+    };
+
+    #[test]
+    fn test_packed_within_packet_size() {
+        let payer = Keypair::new();
+        let packer = Packer::new(payer.pubkey());
+        let groups: Vec<_> = (0..40)
+            .map(|i| vec![memo_instruction(&format!("{:0>100}", i))])
+            .collect();
+
+        let transactions = packer.pack(groups.clone());
+        assert!(transactions.len() > 1 && transactions.len() < groups.len());
+        for transaction in transactions.iter() {
+            assert!(packer.transaction_size(transaction) <= PACKET_DATA_SIZE);
+        }
+        // Nothing lost or reordered
+        assert_eq!(transactions.concat(), groups.concat());
+
+        // The first transaction is full, the next memo doesn't fit in it
+        let mut overfull = transactions[0].clone();
+        overfull.push(transactions[1][0].clone());
+        assert!(packer.transaction_size(&overfull) > PACKET_DATA_SIZE);
+
+        // Measured as signed
+        let signed = packer
+            .sign(&transactions[0], &payer, Hash::default())
+            .unwrap();
+        let serialized = 1
+            + SIGNATURE_SIZE
+            + signed
+                .message
+                .serialize()
+                .len();
+        assert_eq!(packer.transaction_size(&transactions[0]), serialized);
+    }
+
+    #[test]
+    fn test_split_at_packet_boundary() {
+        let payer = Pubkey::new_unique();
+        let first = vec![memo_instruction(&"a".repeat(300))];
+        let with_memo = |len: usize| vec![first.clone(), vec![memo_instruction(&"b".repeat(len))]];
+        // The longest memo still fitting along
+        let packer = Packer::new(payer);
+        let len = (0..PACKET_DATA_SIZE)
+            .take_while(|&len| {
+                packer.transaction_size(&with_memo(len).concat()) <= PACKET_DATA_SIZE
+            })
+            .last()
+            .unwrap();
+        assert_eq!(
+            packer.transaction_size(&with_memo(len).concat()),
+            PACKET_DATA_SIZE
+        );
+        assert_eq!(packer.split(&[], &with_memo(len)), vec![2]);
+        assert_eq!(packer.split(&[], &with_memo(len + 1)), vec![1, 1]);
+        // The prefix counts in every transaction
+        let prefix = [memo_instruction("c")];
+        assert_eq!(packer.split(&prefix, &with_memo(len)), vec![1, 1]);
+
+        // A second signer takes another signature
+        let mut cosigned = with_memo(len);
+        cosigned[1][0]
+            .accounts
+            .push(AccountMeta::new_readonly(Pubkey::new_unique(), true));
+        assert!(packer.transaction_size(&cosigned.concat()) > PACKET_DATA_SIZE + SIGNATURE_SIZE);
+        assert_eq!(packer.split(&[], &cosigned), vec![1, 1]);
+
+        // The looked up accounts take an index, more of the events fit
+        let program = Pubkey::new_unique();
+        let vaults: Vec<Pubkey> = (0..30)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        let groups: Vec<Vec<Instruction>> = vaults
+            .iter()
+            .map(|vault| {
+                vec![Instruction::new_with_bytes(
+                    program,
+                    &[0; 40],
+                    vec![
+                        AccountMeta::new(payer, true),
+                        AccountMeta::new(*vault, false),
+                    ],
+                )]
+            })
+            .collect();
+        let looked_up = Packer::new(payer).with_lookup_tables(vec![AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vaults.clone(),
+        }]);
+        let legacy = packer.split(&[], &groups);
+        let v0 = looked_up.split(&[], &groups);
+        assert!(v0.len() < legacy.len(), "{:?} {:?}", v0, legacy);
+        for transaction in looked_up.pack(groups) {
+            assert!(looked_up.transaction_size(&transaction) <= PACKET_DATA_SIZE);
+        }
+    }
+
+    #[test]
+    fn test_event_fits_with_longest_memos() {
+        let payer = Pubkey::new_unique();
+        let packer = Packer::new(payer);
+        let prefix = [ComputeBudgetInstruction::set_compute_unit_price(0)];
+        let event = Event {
+            solana_ts_received_at: 1729000000,
+            process: Some(Process {
+                pid: u32::MAX,
+                exe: format!("/opt/{}", "e".repeat(200)),
+            }),
+            sequence: u64::MAX,
+            captured_at: i64::MAX,
+            file_info: Some(FileInfo {
+                access_ts: Some(1729000000),
+                modify_ts: Some(1729000000),
+                created_ts: Some(1729000000),
+                size: u64::MAX,
+                mode: 0o100644,
+                uid: None,
+                gid: None,
+                user: None,
+                group: None,
+                xattrs: Vec::new(),
+                is_symlink: false,
+                link_target: None,
+                inode: None,
+                device: None,
+                nlink: None,
+                file_type: Some(
+                    "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+                        .to_string(),
+                ),
+            }),
+            ..Event::new(format!("/srv/data/{}", "p".repeat(200)), EventType::Written)
+        };
+        let instruction = event_instruction(
+            &Pubkey::new_unique(),
+            &payer,
+            &Pubkey::new_unique(),
+            &PathDictionary::default(),
+            // The schema leaving the most to the memos
+            2,
+            event,
+        )
+        .unwrap();
+        let fitted = |memos: &[String]| {
+            let group = packer.fit(&prefix, instruction.clone(), memos.to_vec());
+            let mut transaction = prefix.to_vec();
+            transaction.extend(group.iter().cloned());
+            assert!(packer.transaction_size(&transaction) <= PACKET_DATA_SIZE);
+            assert_eq!(group[0], instruction);
+            group[1..]
+                .iter()
+                .map(|memo| String::from_utf8(memo.data.clone()).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Only the long symlink target is hashed, the rest is kept
+        let target = format!("symlink /{}", "t".repeat(4095));
+        let memos = vec!["kind other".to_string(), target.clone()];
+        let sha256: [u8; 32] = Sha256::digest(&target.as_bytes()["symlink ".len()..]).into();
+        assert_eq!(
+            fitted(&memos),
+            vec![
+                "kind other".to_string(),
+                format!("symlink sha256 {}", crate::integrity::hex(&sha256))
+            ]
+        );
+
+        // Every memo at its longest, all of them hashed together
+        let memos = vec![
+            format!("git HEAD {}, dirty", "c".repeat(40)),
+            format!("container {}", "d".repeat(64)),
+            format!("arweave {}", "a".repeat(43)),
+            format!("clock skew {:+}s", i64::MIN),
+            format!(
+                "owner uid {} ({}) gid {} ({})",
+                u32::MAX,
+                "u".repeat(32),
+                u32::MAX,
+                "g".repeat(32)
+            ),
+            format!("xattrs sha256 {}", "0".repeat(64)),
+            format!("inode {}:{}", u64::MAX, u64::MAX),
+            format!("diff sha256 {}", "3".repeat(64)),
+            target,
+            "kind other".to_string(),
+            format!("suppressed {}", u32::MAX),
+            format!("seq {}", u64::MAX),
+            format!("captured {}", i64::MAX),
+            format!("type {}", "t".repeat(71)),
+            format!("process {} /opt/{}", u32::MAX, "e".repeat(200)),
+            format!(
+                "integrity violation sha256 {}, recorded {}",
+                "1".repeat(64),
+                "2".repeat(64)
+            ),
+        ];
+        let fitted = fitted(&memos);
+        assert_eq!(fitted.len(), 1);
+        assert!(fitted[0].starts_with("memos sha256 "), "{:?}", fitted);
+    }
+}
@@ -0,0 +1,136 @@
+//! Circuit breaker of the RPC node.
+//!
+//! Once too many of the recent calls failed the breaker opens: nothing is sent for
+//! a cool-down period, the events wait in the sink's queue instead of failing one
+//! after another. Then a single lightweight call probes the node, and the breaker
+//! closes if it answered, or stays open for another cool-down.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_FAILURE_RATE: u8 = 50;
+pub const DEFAULT_COOL_DOWN: Duration = Duration::from_secs(30);
+// The failure rate is taken over the last calls, not less than the minimum
+const WINDOW: usize = 20;
+const MIN_CALLS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Closed,
+    Open {
+        retry_in: Duration,
+    },
+    /// The cool-down is over, the node has to be probed.
+    Probe,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    // Percent of the failed calls
+    failure_rate: u8,
+    cool_down: Duration,
+    // Outcomes of the last calls, true if succeeded
+    calls: VecDeque<bool>,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_rate: u8, cool_down: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_rate,
+            cool_down,
+            calls: VecDeque::with_capacity(WINDOW),
+            opened_at: None,
+        }
+    }
+
+    pub fn state(&self, now: Instant) -> State {
+        match self.opened_at {
+            None => State::Closed,
+            Some(at) => match self
+                .cool_down
+                .checked_sub(now.duration_since(at))
+            {
+                Some(retry_in) if !retry_in.is_zero() => State::Open { retry_in },
+                _ => State::Probe,
+            },
+        }
+    }
+
+    /// Records the outcome of a call, opens the breaker if the failure rate is reached.
+    /// Returns whether it has just opened.
+    pub fn record(&mut self, succeeded: bool, now: Instant) -> bool {
+        if self.opened_at.is_some() {
+            return false;
+        }
+        if self.calls.len() == WINDOW {
+            self.calls.pop_front();
+        }
+        self.calls
+            .push_back(succeeded);
+
+        let failed = self
+            .calls
+            .iter()
+            .filter(|succeeded| !**succeeded)
+            .count();
+        if self.calls.len() < MIN_CALLS
+            || failed * 100 < self.failure_rate as usize * self.calls.len()
+        {
+            return false;
+        }
+        self.opened_at = Some(now);
+        true
+    }
+
+    /// Records the outcome of the probe, closes the breaker if it succeeded.
+    pub fn probed(&mut self, succeeded: bool, now: Instant) {
+        match succeeded {
+            true => {
+                self.opened_at = None;
+                self.calls.clear();
+            }
+            false => self.opened_at = Some(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_on_failure_rate_until_probed() {
+        let mut breaker = CircuitBreaker::new(50, Duration::from_secs(30));
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            assert!(!breaker.record(true, start));
+        }
+        for _ in 0..3 {
+            assert!(!breaker.record(false, start));
+        }
+        assert_eq!(breaker.state(start), State::Closed);
+        // 4 of 8 failed
+        assert!(breaker.record(false, start));
+        assert_eq!(
+            breaker.state(start + Duration::from_secs(10)),
+            State::Open {
+                retry_in: Duration::from_secs(20)
+            }
+        );
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(breaker.state(later), State::Probe);
+        breaker.probed(false, later);
+        assert!(matches!(breaker.state(later), State::Open { .. }));
+
+        let recovered = later + Duration::from_secs(30);
+        breaker.probed(true, recovered);
+        assert_eq!(breaker.state(recovered), State::Closed);
+        // Judged on the calls after the recovery only, not on too few of them
+        for _ in 0..4 {
+            assert!(!breaker.record(false, recovered));
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Hard limit of the lamports spent on the transactions in a period, e.g. a day.
+//!
+//! Once the budget is exhausted the events are not submitted but journaled, until
+//! the next period. The switch is recorded on-chain with the budget's last transaction.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct Budget {
+    limit: u64,
+    period: Duration,
+    period_start: Instant,
+    spent: u64,
+}
+
+impl Budget {
+    pub fn new(limit: u64, period: Duration) -> Budget {
+        Budget {
+            limit,
+            period,
+            period_start: Instant::now(),
+            spent: 0,
+        }
+    }
+
+    /// Whether `fee` can be spent now and still leave `reserve` in the budget.
+    /// Starts a new period once the current one is over.
+    pub fn allows(&mut self, fee: u64, reserve: u64, now: Instant) -> bool {
+        if now.duration_since(self.period_start) >= self.period {
+            self.period_start = now;
+            self.spent = 0;
+        }
+        self.spent + fee + reserve <= self.limit
+    }
+
+    pub fn spend(&mut self, fee: u64) {
+        self.spent += fee;
+    }
+
+    pub fn spent(&self) -> u64 {
+        self.spent
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Time until the next period, when the budget is renewed.
+    pub fn renewed_in(&self, now: Instant) -> Duration {
+        self.period
+            .saturating_sub(now.duration_since(self.period_start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_renewed_every_period() {
+        let mut budget = Budget::new(20_000, Duration::from_secs(3600));
+        let start = budget.period_start;
+
+        // The last 5000 are kept for recording the switch
+        for _ in 0..3 {
+            assert!(budget.allows(5_000, 5_000, start));
+            budget.spend(5_000);
+        }
+        assert!(!budget.allows(5_000, 5_000, start));
+        assert!(budget.allows(5_000, 0, start));
+        assert_eq!(
+            budget.renewed_in(start + Duration::from_secs(600)),
+            Duration::from_secs(3000)
+        );
+
+        assert!(budget.allows(5_000, 5_000, start + Duration::from_secs(3600)));
+        assert_eq!(budget.spent(), 0);
+    }
+}
@@ -0,0 +1,145 @@
+//! Forecasts when the vault runs out of space.
+//!
+//! The program reallocates the vault for every new file, paid by the vault's own
+//! lamports: once they no longer cover the rent of the larger account, the events
+//! of new files fail. A transfer to the vault's address makes room again.
+
+use solana_sdk::{rent::Rent, system_instruction::MAX_PERMITTED_DATA_LENGTH};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+// Growth is measured over the last samples only, the rate of new files changes
+const MAX_SAMPLES: usize = 144;
+
+/// Space of the vault: what's used and what its lamports pay the rent for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultUsage {
+    pub size: u64,
+    pub lamports: u64,
+    pub files: usize,
+    /// The largest size the vault may grow to, rent exempt and within the account limit.
+    pub capacity: u64,
+    rent: Rent,
+}
+
+impl VaultUsage {
+    pub fn new(size: u64, lamports: u64, files: usize, rent: Rent) -> VaultUsage {
+        VaultUsage {
+            size,
+            lamports,
+            files,
+            capacity: max_size(lamports, &rent),
+            rent,
+        }
+    }
+
+    pub fn bytes_per_file(&self) -> Option<f64> {
+        (self.files > 0).then(|| self.size as f64 / self.files as f64)
+    }
+
+    /// Number of new files which still fit, at the current size per file.
+    pub fn files_left(&self) -> Option<u64> {
+        let bytes_per_file = self.bytes_per_file()?;
+        Some(
+            (self
+                .capacity
+                .saturating_sub(self.size) as f64
+                / bytes_per_file) as u64,
+        )
+    }
+
+    /// Lamports to transfer to the vault to make room for `files` more files.
+    pub fn top_up(&self, files: u64) -> Option<u64> {
+        let bytes_per_file = self.bytes_per_file()?;
+        let size = self.size + (files as f64 * bytes_per_file).ceil() as u64;
+        let required = self
+            .rent
+            .minimum_balance(size.min(MAX_PERMITTED_DATA_LENGTH) as usize);
+        Some(required.saturating_sub(self.lamports))
+    }
+}
+
+impl Display for VaultUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files, {} of {} bytes used ({:.1}%)",
+            self.files,
+            self.size,
+            self.capacity,
+            self.size as f64 * 100.0 / self.capacity.max(1) as f64
+        )
+    }
+}
+
+// Inverse of Rent::minimum_balance
+fn max_size(lamports: u64, rent: &Rent) -> u64 {
+    let per_byte = rent.lamports_per_byte_year as f64 * rent.exemption_threshold;
+    let size = (lamports as f64 / per_byte) as u64;
+    size.saturating_sub(solana_sdk::rent::ACCOUNT_STORAGE_OVERHEAD)
+        .min(MAX_PERMITTED_DATA_LENGTH)
+}
+
+/// Observes the vault's size over time and predicts when it's full.
+#[derive(Default)]
+pub struct Forecaster {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Forecaster {
+    pub fn record(&mut self, now: Instant, size: u64) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back((now, size));
+    }
+
+    /// Bytes per second the vault grew by, `None` until there are two samples.
+    pub fn growth(&self) -> Option<f64> {
+        let (first_ts, first_size) = self.samples.front()?;
+        let (last_ts, last_size) = self.samples.back()?;
+        let elapsed = last_ts
+            .duration_since(*first_ts)
+            .as_secs_f64();
+        (elapsed > 0.0).then(|| last_size.saturating_sub(*first_size) as f64 / elapsed)
+    }
+
+    /// Time until the vault is full at the observed growth, `None` if it doesn't grow.
+    pub fn full_in(&self, usage: &VaultUsage) -> Option<Duration> {
+        let growth = self
+            .growth()
+            .filter(|g| *g > 0.0)?;
+        let left = usage
+            .capacity
+            .saturating_sub(usage.size);
+        Some(Duration::from_secs_f64(left as f64 / growth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forecast_at_observed_growth() {
+        let rent = Rent::default();
+        let usage = VaultUsage::new(1000, rent.minimum_balance(2000), 10, rent.clone());
+        assert_eq!(usage.capacity, 2000);
+        assert_eq!(usage.files_left(), Some(10));
+        assert_eq!(usage.top_up(10), Some(0));
+        assert_eq!(
+            usage.top_up(20),
+            Some(rent.minimum_balance(3000) - rent.minimum_balance(2000))
+        );
+
+        let mut forecaster = Forecaster::default();
+        let start = Instant::now();
+        forecaster.record(start, 900);
+        assert_eq!(forecaster.full_in(&usage), None);
+        forecaster.record(start + Duration::from_secs(100), 1000);
+        // 1 byte per second, 1000 bytes left
+        assert_eq!(forecaster.full_in(&usage), Some(Duration::from_secs(1000)));
+    }
+}
@@ -0,0 +1,106 @@
+//! Skew between the local clock and the Solana cluster's clock.
+//!
+//! `solana_ts_received_at` is taken from the Clock sysvar, not from the system clock,
+//! so the file's own timestamps and the event's time only agree while the two clocks
+//! do. The cluster's time is an estimate voted by the validators, a few seconds
+//! of skew are normal.
+
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_SKEW_THRESHOLD_SECS: u64 = 30;
+// Checked also while there are no events to submit
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct ClockSkew {
+    threshold: i64,
+    // Alerts only when the skew crosses the threshold, not on every measurement
+    exceeded: Arc<AtomicBool>,
+}
+
+impl ClockSkew {
+    pub fn new(threshold_secs: u64) -> ClockSkew {
+        ClockSkew {
+            threshold: threshold_secs
+                .try_into()
+                .unwrap_or(i64::MAX),
+            exceeded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns the skew in seconds, the local time minus the Solana time,
+    /// alerts when it gets over the threshold.
+    pub fn measure(&self, solana_ts: i64) -> i64 {
+        let local_ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let skew = local_ts - solana_ts;
+        self.check(skew);
+        skew
+    }
+
+    fn check(&self, skew: i64) {
+        let exceeded = skew.abs() > self.threshold;
+        if self
+            .exceeded
+            .swap(exceeded, Ordering::Relaxed)
+            == exceeded
+        {
+            return;
+        }
+        if exceeded {
+            println!(
+                "ALERT: the local clock is {:+}s off the Solana clock, more than {}s",
+                skew, self.threshold
+            );
+        } else {
+            println!(
+                "The local clock is back within {}s of the Solana clock ({:+}s)",
+                self.threshold, skew
+            );
+        }
+    }
+
+    /// Measures the skew periodically, runs until the process exits.
+    pub async fn monitor(self, rpc_client: Arc<RpcClient>) {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match super::solana_unix_timestamp(&rpc_client).await {
+                Ok(ts) => {
+                    self.measure(ts);
+                }
+                Err(e) => println!("Failed to read the Solana clock: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_on_crossing_threshold() {
+        let clock = ClockSkew::new(30);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let skew = clock.measure(now - 100);
+        assert!((100..=101).contains(&skew));
+        assert!(clock
+            .exceeded
+            .load(Ordering::Relaxed));
+
+        let skew = clock.measure(now + 10);
+        assert!((-10..=-9).contains(&skew));
+        assert!(!clock
+            .exceeded
+            .load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,245 @@
+//! Tracks the sent transactions until they are confirmed, so that the submitter
+//! doesn't have to wait for a confirmation before sending the next transaction.
+//! The confirmed ones are logged with their explorer link, and POSTed to the webhook if set.
+
+use super::explorer::Explorer;
+use crate::error::Error;
+use crate::receipt::Receipts;
+use crate::sink::completeness::SharedCompleteness;
+use crate::sink::latency::SharedLatencies;
+use futures_util::future::join_all;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::signature::Signature;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Not confirmed by then, the transaction has expired together with its blockhash
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(90);
+
+struct Pending {
+    signature: Signature,
+    // What the transaction was about, used in the logs
+    description: String,
+    sent_at: Instant,
+    receipt: Option<Receipt>,
+    // Sequence number of the event, fed back once confirmed
+    seq: Option<u64>,
+}
+
+/// Where to write the receipt of a confirmed transaction.
+pub struct Receipt {
+    pub path: String,
+    pub receipts: Receipts,
+}
+
+pub struct ConfirmationTracker {
+    rpc_client: Arc<RpcClient>,
+    explorer: Explorer,
+    webhook_url: Option<String>,
+    latencies: Option<SharedLatencies>,
+    completeness: Option<SharedCompleteness>,
+    rx: mpsc::UnboundedReceiver<Pending>,
+    pending: Vec<Pending>,
+}
+
+/// Handle of a running [ConfirmationTracker].
+pub struct ConfirmationHandle {
+    tx: mpsc::UnboundedSender<Pending>,
+    task: JoinHandle<()>,
+}
+
+impl ConfirmationHandle {
+    pub fn track(&self, signature: Signature, description: String) {
+        self.track_with_receipt(signature, description, None, None);
+    }
+
+    /// Writes the receipt to the file once the transaction is confirmed,
+    /// and feeds back the event's sequence number.
+    pub fn track_with_receipt(
+        &self,
+        signature: Signature,
+        description: String,
+        receipt: Option<Receipt>,
+        seq: Option<u64>,
+    ) {
+        let _ = self.tx.send(Pending {
+            signature,
+            description,
+            sent_at: Instant::now(),
+            receipt,
+            seq,
+        });
+    }
+
+    /// Waits until all the pending transactions are confirmed or expired.
+    pub async fn close(self) -> Result<(), crate::error::Error> {
+        drop(self.tx);
+        Ok(self.task.await?)
+    }
+}
+
+impl ConfirmationTracker {
+    /// Starts the tracker task. It keeps running until the returned handle
+    /// is closed and all the pending transactions are confirmed or expired.
+    pub fn spawn(
+        rpc_client: Arc<RpcClient>,
+        explorer: Explorer,
+        webhook_url: Option<String>,
+        latencies: Option<SharedLatencies>,
+        completeness: Option<SharedCompleteness>,
+    ) -> ConfirmationHandle {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let tracker = ConfirmationTracker {
+            rpc_client,
+            explorer,
+            webhook_url,
+            latencies,
+            completeness,
+            rx,
+            pending: Vec::new(),
+        };
+        ConfirmationHandle {
+            tx,
+            task: tokio::spawn(tracker.run()),
+        }
+    }
+
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        let mut closed = false;
+        while !closed || !self.pending.is_empty() {
+            tokio::select! {
+                sent = self.rx.recv(), if !closed => match sent {
+                    Some(pending) => self.pending.push(pending),
+                    None => closed = true,
+                },
+                _ = ticker.tick() => self.poll().await,
+            }
+        }
+    }
+
+    // Checks all the pending transactions concurrently
+    async fn poll(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let statuses = join_all(self.pending.iter().map(|p| {
+            self.rpc_client
+                .get_signature_status(&p.signature)
+        }))
+        .await;
+
+        let pending = std::mem::take(&mut self.pending);
+        for (p, status) in pending
+            .into_iter()
+            .zip(statuses)
+        {
+            match status {
+                Ok(Some(Ok(()))) => {
+                    let url = self
+                        .explorer
+                        .transaction(&p.signature);
+                    println!("Confirmed {}: {} {}", p.description, p.signature, url);
+                    let slot = match p.receipt.is_some() || self.latencies.is_some() {
+                        true => self.slot(&p.signature).await,
+                        false => None,
+                    };
+                    if let Some(latencies) = self.latencies.as_ref() {
+                        let mut latencies = latencies.lock().unwrap();
+                        latencies
+                            .submit_to_confirm
+                            .record(p.sent_at.elapsed());
+                        if let Some(slot) = slot {
+                            let now = SystemTime::now()
+                                .duration_since(UNIX_EPOCH)
+                                .map_or(0, |d| d.as_secs());
+                            latencies.last_confirmed = Some((slot, now));
+                        }
+                    }
+                    if let (Some(completeness), Some(seq)) = (self.completeness.as_ref(), p.seq) {
+                        completeness
+                            .lock()
+                            .unwrap()
+                            .confirmed(seq);
+                    }
+                    if let (Some(receipt), Some(slot)) = (p.receipt.as_ref(), slot) {
+                        self.write_receipt(&p.signature, receipt, slot);
+                    }
+                    if let Err(e) = self.notify(&p, url).await {
+                        println!("Failed to notify the webhook of {}: {}", p.description, e);
+                    }
+                }
+                Ok(Some(Err(e))) => {
+                    println!(
+                        "Transaction of {} failed: {}, {} {}",
+                        p.description,
+                        e,
+                        p.signature,
+                        self.explorer
+                            .transaction(&p.signature)
+                    )
+                }
+                _ if p.sent_at.elapsed() > CONFIRMATION_TIMEOUT => println!(
+                    "Transaction of {} not confirmed within {:?}: {}",
+                    p.description, CONFIRMATION_TIMEOUT, p.signature
+                ),
+                // Not processed yet, or the node didn't answer this time
+                _ => self.pending.push(p),
+            }
+        }
+    }
+
+    async fn notify(&self, p: &Pending, explorer_url: String) -> Result<(), Error> {
+        let url = match self.webhook_url.as_ref() {
+            Some(url) => url.clone(),
+            None => return Ok(()),
+        };
+        let body = serde_json::json!({
+            "transaction": "Confirmed",
+            "description": p.description,
+            "signature": p.signature.to_string(),
+            "explorer_url": explorer_url,
+        });
+        // ureq is blocking, keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(body)
+                .map(|_| ())
+                .map_err(Error::from)
+        })
+        .await?
+    }
+
+    async fn slot(&self, signature: &Signature) -> Option<u64> {
+        match self
+            .rpc_client
+            .get_signature_statuses(&[*signature])
+            .await
+        {
+            Ok(response) => response
+                .value
+                .first()
+                .and_then(|status| {
+                    status
+                        .as_ref()
+                        .map(|s| s.slot)
+                }),
+            Err(e) => {
+                println!("Failed to get the slot of {}: {}", signature, e);
+                None
+            }
+        }
+    }
+
+    fn write_receipt(&self, signature: &Signature, receipt: &Receipt, slot: u64) {
+        if let Err(e) = receipt
+            .receipts
+            .write(&receipt.path, &signature.to_string(), slot)
+        {
+            println!("Failed to write the receipt of {}: {}", receipt.path, e);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Links to the Solana explorer, on the cluster the RPC node belongs to.
+
+use solana_sdk::pubkey::Pubkey;
+use std::fmt::Display;
+
+const EXPLORER_URL: &str = "https://explorer.solana.com";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explorer {
+    // Query selecting the cluster, empty for mainnet
+    cluster: String,
+}
+
+impl Explorer {
+    /// The public clusters are recognized by their RPC URL,
+    /// any other node (e.g. a local validator) is opened as a custom cluster.
+    pub fn new(rpc_url: &str) -> Explorer {
+        let cluster = if rpc_url.contains("mainnet") {
+            String::new()
+        } else if rpc_url.contains("devnet") {
+            "?cluster=devnet".to_string()
+        } else if rpc_url.contains("testnet") {
+            "?cluster=testnet".to_string()
+        } else {
+            format!("?cluster=custom&customUrl={}", url_encode(rpc_url))
+        };
+        Explorer { cluster }
+    }
+
+    pub fn transaction(&self, signature: &impl Display) -> String {
+        format!("{}/tx/{}{}", EXPLORER_URL, signature, self.cluster)
+    }
+
+    pub fn address(&self, address: &Pubkey) -> String {
+        format!("{}/address/{}{}", EXPLORER_URL, address, self.cluster)
+    }
+}
+
+// Percent-encodes everything but the unreserved characters
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_of_rpc_url() {
+        let signature = "5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW";
+        assert_eq!(
+            Explorer::new("https://api.mainnet-beta.solana.com").transaction(&signature),
+            format!("https://explorer.solana.com/tx/{}", signature)
+        );
+        assert_eq!(
+            Explorer::new("https://api.devnet.solana.com").transaction(&signature),
+            format!(
+                "https://explorer.solana.com/tx/{}?cluster=devnet",
+                signature
+            )
+        );
+        assert_eq!(
+            Explorer::new("http://127.0.0.1:8899").address(&Pubkey::default()),
+            "https://explorer.solana.com/address/11111111111111111111111111111111?cluster=custom&customUrl=http%3A%2F%2F127.0.0.1%3A8899"
+        );
+    }
+}
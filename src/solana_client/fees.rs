@@ -0,0 +1,102 @@
+//! Priority fees by event type, e.g. the deletions outbid the other transactions
+//! when the cluster is congested while the routine writes pay the base fee only.
+//!
+//! The price is set per transaction: one carrying several events pays the highest
+//! price of its events, so a batched deletion is never held back by the writes.
+
+use crate::event::{Event, EventType};
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+
+// Compute units requested by every instruction without an explicit limit,
+// the priority fee is paid for all of them
+const UNITS_PER_INSTRUCTION: u64 = 200_000;
+const MAX_UNITS: u64 = 1_400_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
+
+/// Compute-unit price of each event type in micro-lamports, none by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PriorityFees {
+    // Indexed like EventType::ALL
+    prices: [u64; EventType::COUNT],
+}
+
+impl PriorityFees {
+    pub fn with(mut self, event_type: EventType, price: u64) -> PriorityFees {
+        self.prices[index(&event_type)] = price;
+        self
+    }
+
+    pub fn get(&self, event_type: &EventType) -> u64 {
+        self.prices[index(event_type)]
+    }
+
+    /// Whether some of the events pay a priority fee, their transactions
+    /// get the [Self::instruction] then.
+    pub fn any(&self, events: &[Event]) -> bool {
+        self.price(events) > 0
+    }
+
+    /// Instruction setting the price of the transaction carrying the events.
+    pub fn instruction(&self, events: &[Event]) -> Option<Instruction> {
+        match self.price(events) {
+            0 => None,
+            price => Some(ComputeBudgetInstruction::set_compute_unit_price(price)),
+        }
+    }
+
+    /// Lamports paid on top of the base fee for the transaction carrying the events
+    /// in its `instructions`, the compute budget instruction excluded.
+    pub fn lamports(&self, events: &[Event], instructions: usize) -> u64 {
+        let units = std::cmp::min(instructions as u64 * UNITS_PER_INSTRUCTION, MAX_UNITS);
+        (self.price(events) as u128 * units as u128).div_ceil(MICRO_LAMPORTS_PER_LAMPORT as u128)
+            as u64
+    }
+
+    fn price(&self, events: &[Event]) -> u64 {
+        events
+            .iter()
+            .map(|event| self.get(&event.event_type))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn index(event_type: &EventType) -> usize {
+    EventType::ALL
+        .iter()
+        .position(|t| t.same_type(event_type))
+        .expect("All the event types are listed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highest_price_of_the_transaction() {
+        let fees = PriorityFees::default()
+            .with(EventType::Deleted, 50_000)
+            .with(EventType::AttributeChanged, 20_000);
+        let event = |event_type| Event::new("a.txt", event_type);
+
+        let written = [event(EventType::Written)];
+        assert!(!fees.any(&written));
+        assert_eq!(fees.instruction(&written), None);
+        assert_eq!(fees.lamports(&written, 2), 0);
+
+        let batch = [
+            event(EventType::Written),
+            event(EventType::Deleted),
+            event(EventType::AttributeChanged),
+        ];
+        assert_eq!(
+            fees.instruction(&batch),
+            Some(ComputeBudgetInstruction::set_compute_unit_price(50_000))
+        );
+        // 400k units at 0.05 lamports
+        assert_eq!(fees.lamports(&batch, 2), 20_000);
+        // At most 1.4M units are requested
+        assert_eq!(fees.lamports(&batch, 10), 70_000);
+    }
+}
@@ -0,0 +1,90 @@
+//! Wallet keypair encrypted at rest, so the raw id.json never sits unencrypted
+//! on the monitored host. The keypair file is encrypted with an age passphrase:
+//! `age --passphrase --armor -o id.json.age id.json`.
+//!
+//! The passphrase is read from the systemd credential `wallet-passphrase`, e.g.
+//! `LoadCredentialEncrypted=wallet-passphrase:/etc/credstore.encrypted/wallet-passphrase`,
+//! or prompted for on the terminal. The plain keypair files are read as they are.
+
+use crate::error::Error;
+use age::secrecy::SecretString;
+use solana_sdk::signer::keypair::{read_keypair, Keypair};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Name of the systemd credential holding the passphrase.
+pub const PASSPHRASE_CREDENTIAL: &str = "wallet-passphrase";
+
+// The binary and the armored age formats
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1";
+const AGE_ARMOR: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Reads the keypair file, decrypted with the passphrase if it's encrypted.
+pub fn read(path: &str) -> Result<Keypair, Error> {
+    let data = Zeroizing::new(std::fs::read(path)?);
+    if !is_encrypted(&data) {
+        return read_keypair(&mut data.as_slice()).map_err(|e| Error::Config(e.to_string()));
+    }
+    decrypt(&data, || passphrase(path))
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_HEADER) || data.starts_with(AGE_ARMOR)
+}
+
+fn decrypt(
+    data: &[u8],
+    passphrase: impl FnOnce() -> Result<SecretString, Error>,
+) -> Result<Keypair, Error> {
+    let identity = age::scrypt::Identity::new(passphrase()?);
+    let json = Zeroizing::new(
+        age::decrypt(&identity, data)
+            .map_err(|e| Error::Config(format!("Failed to decrypt the keypair: {}", e)))?,
+    );
+    read_keypair(&mut json.as_slice()).map_err(|e| Error::Config(e.to_string()))
+}
+
+// The systemd credential if the service has it, otherwise prompted for
+fn passphrase(path: &str) -> Result<SecretString, Error> {
+    if let Some(dir) = std::env::var_os("CREDENTIALS_DIRECTORY") {
+        let credential = Path::new(&dir).join(PASSPHRASE_CREDENTIAL);
+        if credential.exists() {
+            let passphrase = Zeroizing::new(std::fs::read_to_string(&credential)?);
+            return Ok(SecretString::from(
+                passphrase
+                    .trim_end_matches('\n')
+                    .to_string(),
+            ));
+        }
+    }
+    rpassword::prompt_password(format!("Passphrase of the wallet keypair {}: ", path))
+        .map(SecretString::from)
+        .map_err(|e| {
+            Error::Config(format!(
+                "Failed to read the passphrase of the encrypted keypair: {}",
+                e
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signer::Signer;
+
+    #[test]
+    fn test_encrypted_keypair_decrypted() {
+        let keypair = Keypair::new();
+        let json = serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap();
+        let mut recipient = age::scrypt::Recipient::new(SecretString::from("hunter2".to_string()));
+        // Fast enough for a test
+        recipient.set_work_factor(10);
+        let encrypted = age::encrypt(&recipient, json.as_bytes()).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(json.as_bytes()));
+
+        let decrypted = decrypt(&encrypted, || Ok(SecretString::from("hunter2".to_string())));
+        assert_eq!(decrypted.unwrap().pubkey(), keypair.pubkey());
+        assert!(decrypt(&encrypted, || Ok(SecretString::from("hunter3".to_string()))).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use super::{derive_vault, event_instruction, SCHEMA_VERSION};
+use crate::error::Error;
+use crate::event::{Event, PathDictionary};
+use crate::sink::EventSink;
+use async_trait::async_trait;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use std::sync::{Arc, Mutex};
+
+/// Builds the same instructions as [super::SolanaClient] but, instead of sending
+/// them to a validator, keeps them in memory so that the tests can inspect them.
+pub struct MockSubmitter {
+    program: Pubkey,
+    payer: Pubkey,
+    vault: Pubkey,
+    paths: PathDictionary,
+    schema_version: u16,
+    submitted: Arc<Mutex<Vec<Instruction>>>,
+}
+
+impl MockSubmitter {
+    pub fn new(program: Pubkey, payer: Pubkey) -> MockSubmitter {
+        let (vault, _) = derive_vault(&program, &payer);
+        MockSubmitter {
+            program,
+            payer,
+            vault,
+            paths: PathDictionary::default(),
+            schema_version: SCHEMA_VERSION,
+            submitted: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Compresses the paths as if the vault was initialized with `prefixes`.
+    pub fn with_path_prefixes(mut self, prefixes: Vec<String>) -> MockSubmitter {
+        self.paths = PathDictionary::new(prefixes);
+        self
+    }
+
+    /// Builds the instructions of the schema `version`, see [super::SolanaClient::with_schema_version].
+    pub fn with_schema_version(mut self, version: u16) -> MockSubmitter {
+        self.schema_version = version;
+        self
+    }
+
+    /// Handle to the submitted instructions, still valid after the sink is moved.
+    pub fn submitted(&self) -> Arc<Mutex<Vec<Instruction>>> {
+        self.submitted.clone()
+    }
+}
+
+#[async_trait]
+impl EventSink for MockSubmitter {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn on_event(&mut self, event: &Event) -> Result<(), Error> {
+        let instruction = event_instruction(
+            &self.program,
+            &self.payer,
+            &self.vault,
+            &self.paths,
+            self.schema_version,
+            event.clone(),
+        )?;
+        self.submitted
+            .lock()
+            .unwrap()
+            .push(instruction);
+        Ok(())
+    }
+}
+
+// Hermetic tests of the whole path from a file operation to the instruction
+// bytes which would be sent to the program, no validator needed.
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::dir_watcher::DirWatcher;
+    use crate::event::{EventType, EventV3, Timestamp};
+    use crate::solana_client::instruction::EventTrackerInstruction;
+    use borsh::BorshDeserialize;
+    use solana_sdk::instruction::AccountMeta;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    struct Harness {
+        dir: tempfile::TempDir,
+        rx: mpsc::Receiver<Event>,
+        submitter: MockSubmitter,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let dir = tempfile::tempdir().unwrap();
+            let dirs = vec![dir
+                .path()
+                .to_str()
+                .unwrap()
+                .to_string()];
+            let event_types = vec![
+                EventType::Created,
+                EventType::Deleted,
+                EventType::MovedFrom,
+                EventType::MovedTo,
+                EventType::Written,
+            ];
+            let watcher = DirWatcher::new(&dirs, event_types).unwrap();
+
+            let (tx, rx) = mpsc::channel(64);
+            // The watcher never returns, the task ends together with the test runtime
+            tokio::spawn(async move { watcher.run(tx).await });
+
+            Harness {
+                dir,
+                rx,
+                submitter: MockSubmitter::new(Pubkey::new_unique(), Pubkey::new_unique()),
+            }
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir
+                .path()
+                .join(name)
+                .to_str()
+                .unwrap()
+                .to_string()
+        }
+
+        // Waits for `count` events and submits them.
+        async fn submit_next(&mut self, count: usize) {
+            for _ in 0..count {
+                let event = tokio::time::timeout(EVENT_TIMEOUT, self.rx.recv())
+                    .await
+                    .ok()
+                    .flatten()
+                    .expect("file event not reported");
+                self.submitter
+                    .on_event(&event)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    // AddEventV3 tag, the borsh encoded path and event type
+    fn instruction_header(path: &str, event_type: EventType) -> Vec<u8> {
+        let mut bytes = vec![9];
+        bytes.extend((path.len() as u32).to_le_bytes());
+        bytes.extend(path.as_bytes());
+        bytes.extend(borsh::to_vec(&event_type).unwrap());
+        bytes
+    }
+
+    // The whole event of the instruction starting with the header
+    fn decoded(data: &[u8], header: &[u8]) -> EventV3 {
+        assert!(data.starts_with(header));
+        EventV3::try_from_slice(&data[1..]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_instruction_accounts() {
+        let program = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let mut submitter = MockSubmitter::new(program, payer);
+        let submitted = submitter.submitted();
+
+        let event = Event::new("/tmp/a.txt", EventType::Deleted);
+        submitter
+            .on_event(&event)
+            .await
+            .unwrap();
+
+        let submitted = submitted.lock().unwrap();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(submitted[0].program_id, program);
+        assert_eq!(
+            submitted[0].accounts,
+            vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(derive_vault(&program, &payer).0, false),
+            ]
+        );
+        assert_eq!(
+            EventTrackerInstruction::unpack(&submitted[0].data).unwrap(),
+            EventTrackerInstruction::AddEventV3(
+                crate::solana_client::instruction::AddEventV3InstructionData {
+                    event: EventV3::try_from(&event).unwrap()
+                }
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_path() {
+        let mut submitter = MockSubmitter::new(Pubkey::new_unique(), Pubkey::new_unique())
+            .with_path_prefixes(vec!["/srv/data/".to_string()]);
+        let submitted = submitter.submitted();
+
+        let mut event = Event::new("/srv/data/reports/a.csv", EventType::Written);
+        submitter
+            .on_event(&event)
+            .await
+            .unwrap();
+        // Outside of the prefixes, sent as it is
+        event.file_path = "/tmp/a.csv".to_string();
+        submitter
+            .on_event(&event)
+            .await
+            .unwrap();
+
+        let submitted = submitted.lock().unwrap();
+        match EventTrackerInstruction::unpack(&submitted[0].data).unwrap() {
+            EventTrackerInstruction::AddCompressedEventV3(data) => {
+                assert_eq!(
+                    (
+                        data.event.prefix,
+                        data.event
+                            .path_suffix
+                            .as_str()
+                    ),
+                    (0, "reports/a.csv")
+                )
+            }
+            other => panic!("Unexpected instruction: {:?}", other),
+        }
+        assert!(matches!(
+            EventTrackerInstruction::unpack(&submitted[1].data).unwrap(),
+            EventTrackerInstruction::AddEventV3(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_operations_to_instruction_bytes() {
+        let mut harness = Harness::new();
+        let submitted = harness.submitter.submitted();
+        let a = harness.path("a.txt");
+        let b = harness.path("b.txt");
+
+        // Every operation is awaited, so that the watcher reads the metadata
+        // of the file before it's changed by the next one
+        fs::write(&a, "hello").unwrap();
+        harness.submit_next(2).await;
+        fs::rename(&a, &b).unwrap();
+        harness.submit_next(2).await;
+        let mode = fs::metadata(&b)
+            .unwrap()
+            .permissions()
+            .mode();
+        fs::remove_file(&b).unwrap();
+        harness.submit_next(1).await;
+
+        let submitted = submitted.lock().unwrap();
+        let data: Vec<&[u8]> = submitted
+            .iter()
+            .map(|i| i.data.as_slice())
+            .collect();
+        assert_eq!(data.len(), 5);
+
+        // The file could still be empty when its metadata was read
+        let created = decoded(data[0], &instruction_header(&a, EventType::Created));
+        assert_eq!(
+            created
+                .file_info
+                .unwrap()
+                .mode,
+            mode
+        );
+
+        let written = decoded(data[1], &instruction_header(&a, EventType::Written));
+        let info = written.file_info.unwrap();
+        assert_eq!((info.size, info.mode), (5, mode));
+
+        // The old path doesn't exist anymore, so no metadata, and solana_ts_received_at
+        // is left to 0 seconds without nanoseconds
+        let moved_from = decoded(data[2], &instruction_header(&a, EventType::MovedFrom));
+        let unset = Timestamp {
+            secs: 0,
+            nanos: None,
+        };
+        assert_eq!(
+            (moved_from.solana_ts_received_at, moved_from.file_info),
+            (unset, None)
+        );
+
+        let moved_to = decoded(data[3], &instruction_header(&b, EventType::MovedTo));
+        let info = moved_to.file_info.unwrap();
+        assert_eq!((info.size, info.mode), (5, mode));
+
+        let deleted = decoded(data[4], &instruction_header(&b, EventType::Deleted));
+        assert_eq!(
+            (deleted.solana_ts_received_at, deleted.file_info),
+            (unset, None)
+        );
+    }
+}
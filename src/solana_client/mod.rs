@@ -1,129 +1,1231 @@
-pub mod instruction;
+pub mod accounts;
+pub mod arweave;
+pub mod batch;
+pub mod breaker;
+pub mod budget;
+pub mod capacity;
+pub mod clock;
+pub mod confirmation;
+pub mod explorer;
+pub mod fees;
+pub mod keypair;
+pub mod overflow;
+pub mod reader;
+pub mod retry;
+pub mod router;
+pub mod rpc_debug;
+pub mod wallets;
+pub use file_event_tracker::instruction;
+#[cfg(test)]
+pub mod mock;
 
+use async_trait::async_trait;
+use file_event_tracker::error::TrackerError;
+use file_event_tracker::processor::{find_vault_address, AccountData, SCHEMA_VERSION};
+use num_traits::FromPrimitive;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_request::RpcError,
+};
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
+    address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
+    clock::Clock,
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{AccountMeta, Instruction, InstructionError},
     pubkey::Pubkey,
-    signature::Keypair,
+    rent::Rent,
+    signature::{Keypair, Signature},
     signer::Signer,
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError, VersionedTransaction},
 };
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::event::Event;
+use crate::anchor::{Anchored, ChainAnchor};
+use crate::backfill::Baseline;
+use crate::config::SolanaConfig;
+use crate::container::Containers;
+use crate::event::{
+    CompressedEventV2, Event, EventType, EventV2, EventV3, EventsDropped, FileKind, PathDictionary,
+    TimestampOutOfRange,
+};
+use crate::git::{RepoState, Repositories};
+use crate::integrity::ContentHashes;
+use crate::outbox::dead_letter::DeadLetters;
+use crate::receipt::Receipts;
+use crate::sink::completeness::SharedCompleteness;
+use crate::sink::latency::SharedLatencies;
 
 const VAULT_ACCOUNT_SIZE: u64 = 1024;
 const PDA_SEED_PREFIX: &[u8] = b"vault";
+// Enough for a few thousands of transactions at the base fee
+const FEE_RESERVE_LAMPORTS: u64 = 10_000_000;
+// Paid for each signature, the wallet's and the pool wallet's paying the fee, if any
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+// How often the vault's size is checked
+const CAPACITY_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+// The vault filling up sooner than this is warned about
+const CAPACITY_WARNING: Duration = Duration::from_secs(7 * 24 * 3600);
+// How often the last vault's size is checked, it overflows to the next vault when full
+const OVERFLOW_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// SPL Memo program, records the memo in the transaction
+const MEMO_PROGRAM: Pubkey = solana_sdk::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+// Longer extended attributes are recorded by their hash, the event and its other memos
+// have to fit in the transaction too
+const MAX_XATTRS_MEMO_LEN: usize = 256;
 
 pub struct SolanaClient {
     program: Pubkey,
     wallet: Keypair,
+    // Pay the events' transactions in turn instead of the wallet
+    wallet_pool: Option<wallets::WalletPool>,
     pda: Option<Pubkey>,
-    url: String,
-    rpc_client: solana_client::rpc_client::RpcClient,
+    // Measures and compiles the events' transactions
+    packer: batch::Packer,
+    // Creates the vault with the first baseline transaction, see init_account_with_baseline
+    deferred_init: Option<Vec<Instruction>>,
+    // The vaults following the first one once it's full, known once initialized
+    vaults: overflow::VaultChain,
+    overflow_checked: Option<Instant>,
+    rpc_client: Arc<RpcClient>,
+    // Proposed when the vault is created, replaced by the vault's one once initialized
+    paths: PathDictionary,
+    // Schema of the sent events, registered with the watcher
+    schema_version: u16,
+    // Watched git repositories, their state is sent with the events in them
+    git: Repositories,
+    // Watched directories inside containers, the events in them carry the container's id
+    containers: Containers,
+    // Uploads the changed files' content, its id is sent with the event
+    arweave: Option<arweave::Arweave>,
+    // Writes the confirmed transactions to the files' attributes
+    receipts: Option<Receipts>,
+    // Hashes of the files' content recorded on chain, the opened files are verified
+    integrity: Option<ContentHashes>,
+    // Compares the Solana time of the events with the local time
+    clock: clock::ClockSkew,
+    // Compute-unit price of the events' transactions
+    fees: fees::PriorityFees,
+    // Holds the events back while the RPC node keeps failing
+    breaker: breaker::CircuitBreaker,
+    // Retries the transient failures, the events given up on are journaled
+    retry: Option<(retry::RetryPolicy, DeadLetters)>,
+    // Limits the spending, the events over it are journaled
+    budget: Option<(budget::Budget, DeadLetters)>,
+    over_budget: bool,
+    explorer: explorer::Explorer,
+    // Sent transactions waiting for confirmation, taken on shutdown
+    confirmations: Option<confirmation::ConfirmationHandle>,
+    webhook_url: Option<String>,
+    latencies: Option<SharedLatencies>,
+    completeness: Option<SharedCompleteness>,
+    // Set to record the completeness gaps on chain
+    gaps_on_chain: bool,
 }
 
 impl SolanaClient {
-    pub fn new(url: &str, program: Pubkey, wallet: Keypair) -> SolanaClient {
+    /// Has to be called within the tokio runtime, it starts the confirmation tracker.
+    pub fn new(
+        url: &str,
+        commitment: CommitmentConfig,
+        program: Pubkey,
+        wallet: Keypair,
+    ) -> SolanaClient {
+        let rpc_client = Arc::new(RpcClient::new_with_commitment(url.to_string(), commitment));
+        let explorer = explorer::Explorer::new(url);
+        let packer = batch::Packer::new(wallet.pubkey());
         SolanaClient {
             program: program,
             wallet: wallet,
-            url: url.to_string(),
-            rpc_client: solana_client::rpc_client::RpcClient::new(&url),
+            wallet_pool: None,
+            confirmations: Some(confirmation::ConfirmationTracker::spawn(
+                rpc_client.clone(),
+                explorer.clone(),
+                None,
+                None,
+                None,
+            )),
+            webhook_url: None,
+            latencies: None,
+            completeness: None,
+            gaps_on_chain: false,
+            explorer,
+            rpc_client,
             pda: None,
+            packer,
+            deferred_init: None,
+            vaults: overflow::VaultChain::default(),
+            overflow_checked: None,
+            paths: PathDictionary::default(),
+            schema_version: SCHEMA_VERSION,
+            git: Repositories::default(),
+            containers: Containers::default(),
+            arweave: None,
+            receipts: None,
+            integrity: None,
+            clock: clock::ClockSkew::new(clock::DEFAULT_SKEW_THRESHOLD_SECS),
+            fees: fees::PriorityFees::default(),
+            breaker: breaker::CircuitBreaker::new(
+                breaker::DEFAULT_FAILURE_RATE,
+                breaker::DEFAULT_COOL_DOWN,
+            ),
+            retry: None,
+            budget: None,
+            over_budget: false,
+        }
+    }
+
+    /// Also starts monitoring the clock skew.
+    pub fn from_config(config: &SolanaConfig, wallet: Keypair) -> SolanaClient {
+        let client = SolanaClient::new(&config.rpc_url, config.commitment, config.program, wallet);
+        let client = match config.rpc_debug {
+            true => client.with_rpc_debug(),
+            false => client,
+        };
+        let client = client
+            .with_path_prefixes(config.path_prefixes.clone())
+            .with_schema_version(config.schema_version)
+            .with_clock_skew_threshold(config.clock_skew_threshold)
+            .with_circuit_breaker(config.breaker_failure_rate, config.breaker_cool_down)
+            .with_retries(
+                retry::RetryPolicy::new(config.retry_budget),
+                DeadLetters::new(&config.journal_path),
+            )
+            .with_priority_fees(config.priority_fees.clone());
+        tokio::spawn(
+            client
+                .clock
+                .clone()
+                .monitor(client.rpc_client.clone()),
+        );
+        let client = match config.webhook_url.as_ref() {
+            Some(url) => client.with_webhook(url),
+            None => client,
+        };
+        let client = match config.budget_lamports {
+            Some(limit) => client.with_budget(
+                budget::Budget::new(limit, config.budget_period),
+                DeadLetters::new(&config.journal_path),
+            ),
+            None => client,
+        };
+        match config
+            .arweave_bundler
+            .as_ref()
+        {
+            Some(url) => client.with_arweave(arweave::Arweave::new(url, config.arweave_max_size)),
+            None => client,
+        }
+    }
+
+    /// The events' transactions are paid by the wallets of the `pool` in turn, see [wallets].
+    /// Call it before loading the lookup tables.
+    pub fn with_wallet_pool(mut self, pool: wallets::WalletPool) -> SolanaClient {
+        // Measured with the second signature
+        if let Some(payer) = pool.pubkeys().first() {
+            self.packer = batch::Packer::new(*payer);
+        }
+        self.wallet_pool = Some(pool);
+        self
+    }
+
+    /// Logs every RPC request and response, see [rpc_debug].
+    /// Replaces the RPC client, call it before anything else.
+    pub fn with_rpc_debug(mut self) -> SolanaClient {
+        let sender = rpc_debug::LoggingSender::new(&self.rpc_client.url());
+        let config = RpcClientConfig::with_commitment(self.rpc_client.commitment());
+        self.rpc_client = Arc::new(RpcClient::new_sender(sender, config));
+        self.confirmations = Some(self.spawn_confirmations());
+        self
+    }
+
+    /// Once the `budget` of the period is spent, the events are appended to the `journal`
+    /// instead of being submitted, until the next period.
+    pub fn with_budget(mut self, budget: budget::Budget, journal: DeadLetters) -> SolanaClient {
+        self.budget = Some((budget, journal));
+        self
+    }
+
+    /// Alerts when the local clock is more than `secs` seconds off the Solana clock.
+    pub fn with_clock_skew_threshold(mut self, secs: u64) -> SolanaClient {
+        self.clock = clock::ClockSkew::new(secs);
+        self
+    }
+
+    /// Stops sending for `cool_down` once `failure_rate` percent of the recent calls failed.
+    pub fn with_circuit_breaker(mut self, failure_rate: u8, cool_down: Duration) -> SolanaClient {
+        self.breaker = breaker::CircuitBreaker::new(failure_rate, cool_down);
+        self
+    }
+
+    /// Sends the events' transactions again after the transient failures, see [retry].
+    /// The events which failed permanently or past the policy's budget are appended
+    /// to the `dead_letters`.
+    pub fn with_retries(
+        mut self,
+        policy: retry::RetryPolicy,
+        dead_letters: DeadLetters,
+    ) -> SolanaClient {
+        self.retry = Some((policy, dead_letters));
+        self
+    }
+
+    /// The transactions pay the highest priority fee of their events.
+    pub fn with_priority_fees(mut self, fees: fees::PriorityFees) -> SolanaClient {
+        self.fees = fees;
+        self
+    }
+
+    /// POSTs the confirmed transactions to the webhook, with their explorer links.
+    /// Replaces the confirmation tracker, call it before sending anything.
+    pub fn with_webhook(mut self, url: &str) -> SolanaClient {
+        self.webhook_url = Some(url.to_string());
+        self.confirmations = Some(self.spawn_confirmations());
+        self
+    }
+
+    /// Records the latency from the submission of the transactions to their confirmation.
+    /// Replaces the confirmation tracker, call it before sending anything.
+    pub fn with_latencies(mut self, latencies: SharedLatencies) -> SolanaClient {
+        self.latencies = Some(latencies);
+        self.confirmations = Some(self.spawn_confirmations());
+        self
+    }
+
+    /// Feeds back the sequence numbers of the confirmed events, with `on_chain` records
+    /// the reported gaps in a memo. Replaces the confirmation tracker, call it before
+    /// sending anything.
+    pub fn with_completeness(
+        mut self,
+        completeness: SharedCompleteness,
+        on_chain: bool,
+    ) -> SolanaClient {
+        self.completeness = Some(completeness);
+        self.gaps_on_chain = on_chain;
+        self.confirmations = Some(self.spawn_confirmations());
+        self
+    }
+
+    fn spawn_confirmations(&self) -> confirmation::ConfirmationHandle {
+        confirmation::ConfirmationTracker::spawn(
+            self.rpc_client.clone(),
+            self.explorer.clone(),
+            self.webhook_url.clone(),
+            self.latencies.clone(),
+            self.completeness.clone(),
+        )
+    }
+
+    // The sequence number fed back once the event is confirmed, none if not numbered
+    fn seq(&self, event: &Event) -> Option<u64> {
+        (self.completeness.is_some() && event.sequence > 0).then_some(event.sequence)
+    }
+
+    // Records the gaps reported since the last memo, logged if it fails
+    async fn record_gaps(&mut self) {
+        let Some(completeness) = self.completeness.clone() else {
+            return;
+        };
+        if !self.gaps_on_chain {
+            return;
         }
+        let Some((memo, gaps)) = completeness
+            .lock()
+            .unwrap()
+            .memo()
+        else {
+            return;
+        };
+        match self
+            .send(&[memo_instruction(&memo)])
+            .await
+        {
+            Ok(signature) => {
+                completeness
+                    .lock()
+                    .unwrap()
+                    .recorded(gaps);
+                println!(
+                    "Recorded the {}: {}",
+                    memo,
+                    self.explorer
+                        .transaction(&signature)
+                );
+            }
+            Err(e) => println!("Failed to record the {}: {}", memo, e),
+        }
+    }
+
+    /// Links to the transactions and accounts on the RPC node's cluster.
+    pub fn explorer(&self) -> &explorer::Explorer {
+        &self.explorer
+    }
+
+    /// Path prefixes stored in the vault if it's created by [Self::init_account],
+    /// the events under them are sent without repeating the prefix.
+    pub fn with_path_prefixes(mut self, mut prefixes: Vec<String>) -> SolanaClient {
+        // The rest is sent with the full paths
+        prefixes.truncate(PathDictionary::MAX_PREFIXES);
+        self.paths = PathDictionary::new(prefixes);
+        self
+    }
+
+    /// Sends the events in the schema `version`, 1 for the programs deployed before
+    /// the schema 2. The vaults created from now on are registered with it.
+    pub fn with_schema_version(mut self, version: u16) -> SolanaClient {
+        self.schema_version = version;
+        self
+    }
+
+    /// The content of the changed files is uploaded to Arweave before the event is sent,
+    /// the event's transaction carries a memo with the Arweave transaction id.
+    pub fn with_arweave(mut self, arweave: arweave::Arweave) -> SolanaClient {
+        self.arweave = Some(arweave);
+        self
+    }
+
+    /// Once confirmed, the signature and the slot of the event's transaction are written
+    /// to the file, see [crate::receipt].
+    pub fn with_receipts(mut self, receipts: Receipts) -> SolanaClient {
+        self.receipts = Some(receipts);
+        self
+    }
+
+    /// The events of the written files carry a memo with the hash of their content,
+    /// the opened files are hashed again and compared with it, see [crate::integrity].
+    pub fn with_integrity(mut self, hashes: ContentHashes) -> SolanaClient {
+        self.integrity = Some(hashes);
+        self
+    }
+
+    /// The events in the repositories are sent together with a memo of the
+    /// repository's state, read when the event is submitted.
+    pub fn with_repositories(mut self, git: Repositories) -> SolanaClient {
+        self.git = git;
+        self
+    }
+
+    /// The events inside the containers are sent together with a memo of the container's id.
+    pub fn with_containers(mut self, containers: Containers) -> SolanaClient {
+        self.containers = containers;
+        self
     }
 
-    pub fn process_events(
+    // The event's instruction followed by its memos, fitting in a packet
+    async fn event_instructions(
         &self,
-        rx: std::sync::mpsc::Receiver<Event>,
-    ) -> Result<(), crate::error::Error> {
+        event: &Event,
+        skew: Option<i64>,
+    ) -> Result<Vec<Instruction>, crate::error::Error> {
+        let repo_state = self
+            .repo_state(&event.file_path)
+            .await?;
+        let content_id = self
+            .upload_content(event)
+            .await;
+        let vault = self
+            .vaults
+            .vault_of(&event.file_path)
+            .expect("Initialized with the first vault");
+        let instruction = event_instruction(
+            &self.program,
+            &self.wallet.pubkey(),
+            &vault,
+            &self.paths,
+            self.schema_version,
+            event.clone(),
+        )?;
+        let mut memos = Vec::new();
+        if let Some(state) = repo_state {
+            println!("Repository state: {}", state);
+            memos.push(format!("git {}", state));
+        }
+        if let Some(id) = self
+            .containers
+            .container_id(&event.file_path)
+        {
+            memos.push(format!("container {}", id));
+        }
+        if let Some(id) = content_id {
+            memos.push(format!("arweave {}", id));
+        }
+        if let Some(skew) = skew {
+            memos.push(format!("clock skew {:+}s", skew));
+        }
+        if let Some(memo) = owner_memo(event).filter(|_| self.schema_version < 3) {
+            memos.push(memo);
+        }
+        if let Some(memo) = xattrs_memo(event) {
+            memos.push(memo);
+        }
+        if let Some(memo) = inode_memo(event) {
+            memos.push(memo);
+        }
+        if let Some(memo) = diff_memo(event) {
+            memos.push(memo);
+        }
+        // In the events' layout from the schema 3
+        if let Some(target) = event
+            .file_info
+            .as_ref()
+            .and_then(|info| info.link_target.as_ref())
+            .filter(|_| self.schema_version < 3)
+        {
+            memos.push(format!("symlink {}", target));
+        }
+        // The files are the most of them
+        if matches!(event.kind, FileKind::Dir | FileKind::Other) && self.schema_version < 3 {
+            memos.push(format!("kind {}", event.kind));
+        }
+        if event.suppressed_count > 0 && self.schema_version < 3 {
+            memos.push(format!("suppressed {}", event.suppressed_count));
+        }
+        if event.sequence > 0 && self.schema_version < 3 {
+            memos.push(format!("seq {}", event.sequence));
+        }
+        if event.captured_at > 0 && self.schema_version < 3 {
+            memos.push(format!("captured {}", event.captured_at));
+        }
+        if let Some(file_type) = event
+            .file_info
+            .as_ref()
+            .and_then(|info| info.file_type.as_ref())
+            .filter(|_| self.schema_version < 3)
+        {
+            memos.push(format!("type {}", file_type));
+        }
+        if let Some(process) = event
+            .process
+            .as_ref()
+            .filter(|_| self.schema_version < 3)
+        {
+            memos.push(format!("process {} {}", process.pid, process.exe));
+        }
+        if let Some(memo) = self.content_memo(event).await {
+            memos.push(memo);
+        }
+        // Measured with the price of a priority fee, any transaction of the batch may set it
+        let prefix = [ComputeBudgetInstruction::set_compute_unit_price(0)];
+        Ok(self
+            .packer
+            .fit(&prefix, instruction, memos))
+    }
+
+    // Records the hash of the written files' content, verifies the opened files against it
+    async fn content_memo(&self, event: &Event) -> Option<String> {
+        let hashes = self.integrity.as_ref()?;
+        match &event.event_type {
+            EventType::Deleted | EventType::MovedFrom => {
+                hashes.forget(&event.file_path);
+                return None;
+            }
+            EventType::AttributeChanged | EventType::ReadClosed | EventType::Accessed => {
+                return None
+            }
+            EventType::Opened => {
+                // Our own hashing, or modified since, e.g. by the process which opened it,
+                // its written event follows
+                if !hashes.is_recorded(&event.file_path)
+                    || hashes.hashed_recently(&event.file_path)
+                    || modified_since(event)
+                {
+                    return None;
+                }
+            }
+            EventType::Renamed { from, .. } => {
+                hashes.forget(from);
+            }
+            EventType::Created | EventType::MovedTo | EventType::Written => (),
+        }
+        // The content is the target's, recorded with its own events if it's watched
+        if event
+            .file_info
+            .as_ref()
+            .is_some_and(|info| info.is_symlink)
+        {
+            return None;
+        }
+
+        let path = event.file_path.clone();
+        let hasher = hashes.clone();
+        let hashed = match event.event_type {
+            EventType::Opened => None,
+            // Hashed by the pipeline already
+            _ => event.sha256.clone(),
+        };
+        let hashed = match hashed {
+            Some(sha256) => Ok(sha256),
+            // Reads the whole file, keep it off the async workers
+            None => tokio::task::spawn_blocking(move || hasher.hash(&path))
+                .await
+                .map_err(crate::error::Error::from)
+                .and_then(|hashed| hashed.map_err(crate::error::Error::from)),
+        };
+        let sha256 = match hashed {
+            Ok(sha256) => sha256,
+            // Gone already, its deleted event follows
+            Err(crate::error::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return None
+            }
+            Err(e) => {
+                println!("Failed to hash {}: {}", event.file_path, e);
+                return None;
+            }
+        };
+        if event.event_type != EventType::Opened {
+            let memo = format!("sha256 {}", sha256);
+            hashes.record(&event.file_path, sha256);
+            return Some(memo);
+        }
+
+        let violation = hashes.verify(&event.file_path, sha256)?;
+        println!("ALERT: {}", violation);
+        if let Some(url) = self.webhook_url.as_ref() {
+            if let Err(e) = violation.notify(url).await {
+                println!("Failed to notify the webhook: {}", e);
+            }
+        }
+        Some(violation.memo())
+    }
+
+    // Saved once the hashes are sent, logged if it fails
+    async fn save_hashes(&self) {
+        let hashes = match self.integrity.as_ref() {
+            Some(hashes) => hashes.clone(),
+            None => return,
+        };
+        let path = hashes.path().to_string();
+        // Written whole, keep it off the async workers
+        let saved = tokio::task::spawn_blocking(move || hashes.save())
+            .await
+            .map_err(crate::error::Error::from)
+            .and_then(|saved| saved);
+        if let Err(e) = saved {
+            println!("Failed to save the content hashes to {}: {}", path, e);
+        }
+    }
+
+    // Sends the transaction of the events, within the budget and unless the breaker is open
+    async fn send_events(
+        &mut self,
+        events: &[Event],
+        instructions: &[Instruction],
+    ) -> Result<String, crate::error::Error> {
+        let fee = self.signature_fees()
+            + self
+                .fees
+                .lamports(events, instructions.len());
+        if !self
+            .within_budget(fee)
+            .await?
+        {
+            let (_, journal) = self
+                .budget
+                .as_ref()
+                .expect("Only a budget can be exhausted");
+            journal
+                .append(events.to_vec(), "over budget")
+                .await?;
+            return Err(crate::error::Error::OverBudget {
+                journal: journal.path().to_string(),
+            });
+        }
+        self.wait_for_rpc().await;
+
+        let instructions: Vec<_> = self
+            .fees
+            .instruction(events)
+            .into_iter()
+            .chain(instructions.iter().cloned())
+            .collect();
+        let mut retries = self
+            .retry
+            .as_ref()
+            .map(|(policy, _)| policy.start(Instant::now()));
+        let result = loop {
+            println!("Sending to RPC client");
+            let result = self.send(&instructions).await;
+            // Only the node's failures count, not e.g. the program rejecting the event
+            let failed = result
+                .as_ref()
+                .is_err_and(retry::is_retryable);
+            if self
+                .breaker
+                .record(!failed, Instant::now())
+            {
+                println!("ALERT: RPC node keeps failing, holding the events back");
+            }
+            let delay = match (&result, retries.as_mut()) {
+                (Err(e), Some(retries)) => retries.next(e, Instant::now()),
+                _ => None,
+            };
+            let (Some(delay), Err(e)) = (delay, &result) else {
+                break result;
+            };
+            println!(
+                "Failed to send {} event(s): {}, retrying in {:?}",
+                events.len(),
+                e,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            self.wait_for_rpc().await;
+        };
+        let signature = match result {
+            Ok(signature) => signature,
+            Err(e) => {
+                self.give_up(events, &e).await;
+                return Err(e);
+            }
+        };
+        if let Some((budget, _)) = self.budget.as_mut() {
+            budget.spend(fee);
+        }
+        self.save_hashes().await;
+
+        // Confirmed in the background, the next events don't wait for it
+        if let Some(confirmations) = self.confirmations.as_ref() {
+            for event in events {
+                confirmations.track_with_receipt(
+                    signature,
+                    event.to_string(),
+                    self.receipt(event),
+                    self.seq(event),
+                );
+            }
+        }
+        Ok(signature.to_string())
+    }
+
+    /// Builds the transactions recording the existing files in the vault, each file's
+    /// event followed by the memo with its hash, as many files in a transaction as fit.
+    /// The first one creates the vault if its initialization is deferred.
+    pub async fn baseline_transactions(
+        &self,
+        records: Vec<Baseline>,
+    ) -> Result<Vec<Vec<Instruction>>, crate::error::Error> {
         if self.pda.is_none() {
-            return Err(crate::error::Error::new(
-                "PDA has to be initialized for this call",
+            return Err(crate::error::Error::Config(
+                "PDA has to be initialized for this call".to_string(),
             ));
         }
+        // The whole inventory is taken at once
+        let ts = self
+            .get_solana_unix_timestamp()
+            .await
+            .map_or(-1, |ts| ts as i128);
 
-        for event in rx {
-            println!("Consumer received an event: {}", event);
+        let mut groups = Vec::with_capacity(records.len() + 1);
+        if let Some(init) = self.deferred_init.as_ref() {
+            groups.push(init.clone());
+        }
+        for record in records {
+            let memo = memo_instruction(&record.memo());
+            if let Some(hashes) = self.integrity.as_ref() {
+                hashes.record(
+                    &record.event.file_path,
+                    crate::integrity::hex(&record.sha256),
+                );
+            }
+            let mut event = record.event;
+            event.solana_ts_received_at = ts;
+            let vault = self
+                .vaults
+                .vault_of(&event.file_path)
+                .expect("Initialized with the first vault");
+            let instruction = event_instruction(
+                &self.program,
+                &self.wallet.pubkey(),
+                &vault,
+                &self.paths,
+                self.schema_version,
+                event,
+            )?;
+            groups.push(vec![instruction, memo]);
+        }
+        Ok(self.packer.pack(groups))
+    }
+
+    /// Sends the instructions in a single transaction, confirmed in the background.
+    pub async fn send_batch(
+        &mut self,
+        instructions: &[Instruction],
+        description: String,
+    ) -> Result<String, crate::error::Error> {
+        if self
+            .deferred_init
+            .as_ref()
+            .is_some_and(|init| instructions.starts_with(init))
+        {
+            // The next transactions need the vault
+            let signature = self
+                .send_and_confirm(instructions)
+                .await?;
+            self.deferred_init = None;
+            println!(
+                "PDA created, transaction signature: {} {}",
+                signature,
+                self.explorer
+                    .transaction(&signature)
+            );
+            self.save_hashes().await;
+            return Ok(signature.to_string());
+        }
+        let signature = self
+            .send(instructions)
+            .await?;
+        self.save_hashes().await;
+        if let Some(confirmations) = self.confirmations.as_ref() {
+            confirmations.track(signature, description);
+        }
+        Ok(signature.to_string())
+    }
+
+    async fn send_and_confirm(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Signature, crate::error::Error> {
+        let (transaction, fee_payer) = self
+            .sign(instructions)
+            .await?;
+        let signature = self
+            .rpc_client
+            .send_and_confirm_transaction(&transaction)
+            .await
+            .map_err(rpc_error)?;
+        self.paid(fee_payer, &transaction);
+        Ok(signature)
+    }
+
+    async fn send(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<Signature, crate::error::Error> {
+        let (transaction, fee_payer) = self
+            .sign(instructions)
+            .await?;
+        let signature = self
+            .rpc_client
+            .send_transaction(&transaction)
+            .await
+            .map_err(rpc_error)?;
+        self.paid(fee_payer, &transaction);
+        Ok(signature)
+    }
+
+    // The transaction paid by the next pool wallet, if any is funded, and the wallet paying
+    async fn sign(
+        &mut self,
+        instructions: &[Instruction],
+    ) -> Result<(VersionedTransaction, Option<Pubkey>), crate::error::Error> {
+        let blockhash = self
+            .rpc_client
+            .get_latest_blockhash()
+            .await
+            .map_err(rpc_error)?;
 
-            if let Err(err) = self.process_event(event) {
-                println!("Failed to process the event: {}", err);
+        if self
+            .wallet_pool
+            .as_ref()
+            .is_some_and(|pool| pool.check_due(Instant::now()))
+        {
+            self.check_wallet_pool().await;
+        }
+        match self
+            .wallet_pool
+            .as_mut()
+            .and_then(|pool| pool.next_payer())
+        {
+            Some(fee_payer) => Ok((
+                self.packer
+                    .sign_paid(instructions, fee_payer, &self.wallet, blockhash)?,
+                Some(fee_payer.pubkey()),
+            )),
+            None => Ok((
+                self.packer
+                    .sign(instructions, &self.wallet, blockhash)?,
+                None,
+            )),
+        }
+    }
+
+    // Deducts the base fee of the sent transaction from the pool wallet paying it, the
+    // priority fee is accounted for once the balances are read again
+    fn paid(&mut self, fee_payer: Option<Pubkey>, transaction: &VersionedTransaction) {
+        let (Some(pool), Some(fee_payer)) = (self.wallet_pool.as_mut(), fee_payer) else {
+            return;
+        };
+        let signatures = transaction
+            .message
+            .header()
+            .num_required_signatures;
+        pool.spend(&fee_payer, u64::from(signatures) * LAMPORTS_PER_SIGNATURE);
+    }
+
+    // The signature fees of the events' transactions
+    fn signature_fees(&self) -> u64 {
+        match self
+            .wallet_pool
+            .as_ref()
+            .is_some_and(|pool| pool.funded())
+        {
+            true => 2 * LAMPORTS_PER_SIGNATURE,
+            false => LAMPORTS_PER_SIGNATURE,
+        }
+    }
+
+    /// Reads the balances of the pool wallets, the depleted ones leave the rotation
+    /// and the funded ones rejoin it. The wallets failing to be read are kept as they are.
+    pub async fn check_wallet_pool(&mut self) {
+        let Some(pool) = self.wallet_pool.as_mut() else {
+            return;
+        };
+        pool.checked(Instant::now());
+        for payer in pool.pubkeys() {
+            match self
+                .rpc_client
+                .get_balance(&payer)
+                .await
+            {
+                Ok(balance) => pool.set_balance(&payer, balance),
+                Err(e) => println!(
+                    "Failed to read the balance of the pool wallet {}: {}",
+                    payer, e
+                ),
             }
         }
-        Ok(())
+        if !pool.funded() {
+            println!("ALERT: all the pool wallets are depleted, the wallet pays the fees");
+        }
     }
 
-    fn process_event(&self, mut event: Event) -> Result<(), crate::error::Error> {
-        // todo: add cache to call not more often than every second
-        let ts = self.get_solana_unix_timestamp();
-        if let Ok(ts_ok) = ts {
-            event.solana_ts_received_at = ts_ok as i128;
-        } else {
-            event.solana_ts_received_at = -1;
+    // The deleted files have nowhere to write to, and a receipt of an attribute change
+    // would be another attribute change
+    fn receipt(&self, event: &Event) -> Option<confirmation::Receipt> {
+        let receipts = self.receipts.as_ref()?;
+        match event.event_type {
+            EventType::Deleted | EventType::MovedFrom | EventType::AttributeChanged => None,
+            _ => Some(confirmation::Receipt {
+                path: event.file_path.clone(),
+                receipts: receipts.clone(),
+            }),
         }
+    }
 
-        // accounts needed by the transaction
-        let accounts = [
-            AccountMeta::new(self.wallet.pubkey(), true),
-            AccountMeta::new(self.pda.unwrap(), false),
-            // no CPIs, no more accounts needed
-        ]
-        .to_vec();
+    // Checks the budget for the `fee` of the events' transaction, keeping enough for a transaction
+    // recording the switch to journaling on-chain, which is sent once it's exhausted
+    async fn within_budget(&mut self, fee: u64) -> Result<bool, crate::error::Error> {
+        let now = Instant::now();
+        let memo_fee = self.signature_fees();
+        let (budget, _) = match self.budget.as_mut() {
+            Some(budget) => budget,
+            None => return Ok(true),
+        };
+        if budget.allows(fee, memo_fee, now) {
+            if self.over_budget {
+                println!("Spend budget renewed, submitting the events again");
+                self.over_budget = false;
+            }
+            return Ok(true);
+        }
+        if self.over_budget {
+            return Ok(false);
+        }
+
+        self.over_budget = true;
+        let memo = format!(
+            "budget exhausted: {} of {} lamports spent, journaling the events for {}s",
+            budget.spent(),
+            budget.limit(),
+            budget
+                .renewed_in(now)
+                .as_secs()
+        );
+        println!("ALERT: {}", memo);
+        budget.spend(memo_fee);
+        let signature = self
+            .send(&[memo_instruction(&memo)])
+            .await?;
+        println!(
+            "Switch to journaling recorded: {} {}",
+            signature,
+            self.explorer
+                .transaction(&signature)
+        );
+        Ok(false)
+    }
 
-        //// prepare instruction
+    // Journals the events of the transaction which failed for good
+    async fn give_up(&self, events: &[Event], err: &crate::error::Error) {
+        let Some((_, dead_letters)) = self.retry.as_ref() else {
+            return;
+        };
+        let reason = match retry::is_retryable(err) {
+            true => format!("retries exhausted: {}", err),
+            false => format!("failed: {}", err),
+        };
+        match dead_letters
+            .append(events.to_vec(), &reason)
+            .await
+        {
+            Ok(()) => println!(
+                "{} event(s) given up on, journaled to {}",
+                events.len(),
+                dead_letters.path()
+            ),
+            Err(e) => println!("Failed to journal the failed events: {}", e),
+        }
+    }
+
+    // Waits while the circuit breaker is open, probing the node after every cool-down
+    async fn wait_for_rpc(&mut self) {
+        loop {
+            match self
+                .breaker
+                .state(Instant::now())
+            {
+                breaker::State::Closed => return,
+                breaker::State::Open { retry_in } => tokio::time::sleep(retry_in).await,
+                breaker::State::Probe => {
+                    let probe = self
+                        .rpc_client
+                        .get_slot()
+                        .await;
+                    if let Err(e) = probe.as_ref() {
+                        println!("RPC node still failing: {}", e);
+                    } else {
+                        println!("RPC node recovered, sending the events again");
+                    }
+                    self.breaker
+                        .probed(probe.is_ok(), Instant::now());
+                }
+            }
+        }
+    }
+
+    async fn repo_state(&self, path: &str) -> Result<Option<RepoState>, crate::error::Error> {
+        if self.git.is_empty() {
+            return Ok(None);
+        }
+        // Runs git
+        let git = self.git.clone();
+        let path = path.to_string();
+        Ok(tokio::task::spawn_blocking(move || git.state(&path)).await?)
+    }
+
+    // The event is sent even if its content can't be uploaded
+    async fn upload_content(&self, event: &Event) -> Option<String> {
+        let arweave = self
+            .arweave
+            .as_ref()
+            .filter(|a| a.wants(event))?;
+        match arweave
+            .upload(&self.wallet, &event.file_path)
+            .await
+        {
+            Ok(id) => {
+                println!("Content of {} uploaded to Arweave: {}", event.file_path, id);
+                Some(id)
+            }
+            Err(e) => {
+                println!("Failed to upload the content of {}: {}", event.file_path, e);
+                None
+            }
+        }
+    }
 
-        let instr_data =
-            instruction::EventTrackerInstruction::AddEvent(instruction::AddEventInstructionData {
-                event: event,
+    /// Checks that the RPC node is reachable.
+    /// Sends the events' transactions as v0 transactions looking the accounts up in the
+    /// address lookup tables, e.g. the program's, the vaults' and the memo program's.
+    pub async fn load_lookup_tables(
+        &mut self,
+        addresses: &[Pubkey],
+    ) -> Result<(), crate::error::Error> {
+        let mut tables = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let account = self
+                .rpc_client
+                .get_account(address)
+                .await
+                .map_err(rpc_error)?;
+            let table = AddressLookupTable::deserialize(&account.data).map_err(|e| {
+                crate::error::Error::Config(format!(
+                    "Invalid address lookup table {}: {}",
+                    address, e
+                ))
+            })?;
+            println!(
+                "Looking {} accounts up in the table {}",
+                table.addresses.len(),
+                address
+            );
+            tables.push(AddressLookupTableAccount {
+                key: *address,
+                addresses: table.addresses.to_vec(),
+            });
+        }
+        let payer = self
+            .wallet_pool
+            .as_ref()
+            .and_then(|pool| {
+                pool.pubkeys()
+                    .first()
+                    .copied()
             })
-            .pack()?;
+            .unwrap_or(self.wallet.pubkey());
+        self.packer = batch::Packer::new(payer).with_lookup_tables(tables);
+        Ok(())
+    }
 
-        let instruction = Instruction::new_with_bytes(self.program, &instr_data, accounts);
+    pub async fn check_connection(&self) -> Result<(), crate::error::Error> {
+        self.rpc_client
+            .get_latest_blockhash()
+            .await
+            .map(|_| ())
+            .map_err(rpc_error)
+    }
+
+    pub async fn wallet_balance(&self) -> Result<u64, crate::error::Error> {
+        self.rpc_client
+            .get_balance(&self.wallet.pubkey())
+            .await
+            .map_err(rpc_error)
+    }
+
+    /// Lamports the wallet needs to start tracking: the vault's rent
+    /// if the vault doesn't exist yet and a reserve for transaction fees.
+    pub async fn required_balance(&self) -> Result<u64, crate::error::Error> {
+        let (pda_pubkey, _) = self.derive_pda();
+        let vault_balance = self
+            .rpc_client
+            .get_balance(&pda_pubkey)
+            .await
+            .map_err(rpc_error)?;
+        if vault_balance > 0 {
+            return Ok(FEE_RESERVE_LAMPORTS);
+        }
+        Ok(self.vault_rent().await? + FEE_RESERVE_LAMPORTS)
+    }
+
+    async fn vault_rent(&self) -> Result<u64, crate::error::Error> {
+        let vault_account_size = usize::try_from(VAULT_ACCOUNT_SIZE)
+            .map_err(|e| crate::error::Error::Config(e.to_string()))?;
+        self.rpc_client
+            .get_minimum_balance_for_rent_exemption(vault_account_size)
+            .await
+            .map_err(rpc_error)
+    }
+
+    fn derive_pda(&self) -> (Pubkey, u8) {
+        derive_vault(&self.program, &self.wallet.pubkey())
+    }
+
+    pub async fn init_account(&mut self) -> Result<(), crate::error::Error> {
+        let (pda_pubkey, instructions) = self
+            .initialize_instructions()
+            .await?;
 
         let blockhash = self
             .rpc_client
             .get_latest_blockhash()
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
+            .await
+            .map_err(rpc_error)?;
 
         let transaction = Transaction::new_signed_with_payer(
-            &[instruction],
+            &instructions,
             Some(&self.wallet.pubkey()),
             &[&self.wallet],
             blockhash,
         );
 
-        println!("Sending to RPC client");
-        let client_signature = self
+        let signature = self
             .rpc_client
             .send_and_confirm_transaction(&transaction)
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
-        println!("Client signature: {}", client_signature.to_string());
+            .await;
+        if let Err(e) = signature {
+            // If the account already exists, instead of TransactionError::AccountInUse
+            // the retuned error is custom program error: 0x0.
+            // Therefore we will just parse the error string searching for
+            // `already in use` term instead of checking the error type.
+            if e.to_string()
+                .contains("already in use")
+            {
+                println!("Wallet's PDA already exists");
+                let vault = self
+                    .read_vault(&pda_pubkey)
+                    .await?;
+                self.vaults = self
+                    .follow_vaults(pda_pubkey)
+                    .await?;
+                self.check_watcher(&pda_pubkey, &vault)
+                    .await?;
+                // The events can only refer to the prefixes stored in the vault
+                self.paths = vault.path_dictionary;
+                self.pda = Some(pda_pubkey);
+                return Ok(());
+            } else {
+                return Err(rpc_error(e));
+            }
+        }
 
+        let signature = signature.unwrap();
+        println!(
+            "PDA created, transaction signature: {} {}",
+            signature,
+            self.explorer
+                .transaction(&signature)
+        );
+
+        self.vaults = overflow::VaultChain::new(pda_pubkey);
+        self.pda = Some(pda_pubkey);
         Ok(())
     }
 
-    pub fn init_account(&mut self) -> Result<(), crate::error::Error> {
-        // Get the amount of lamports needed to pay for the vault's rent
-        let vault_account_size = usize::try_from(VAULT_ACCOUNT_SIZE)
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
-        let lamports = self
+    /// [Self::init_account] deferred to the first of the [Self::baseline_transactions],
+    /// sparing a new vault a round trip: the vault is created by the transaction recording
+    /// the first files, confirmed before the next one is sent. An existing vault is
+    /// initialized right away.
+    pub async fn init_account_with_baseline(&mut self) -> Result<(), crate::error::Error> {
+        let (pda_pubkey, _) = self.derive_pda();
+        let vault_balance = self
             .rpc_client
-            .get_minimum_balance_for_rent_exemption(vault_account_size)
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
+            .get_balance(&pda_pubkey)
+            .await
+            .map_err(rpc_error)?;
+        if vault_balance > 0 {
+            return self.init_account().await;
+        }
+        let (_, instructions) = self
+            .initialize_instructions()
+            .await?;
+        self.deferred_init = Some(instructions);
+        self.vaults = overflow::VaultChain::new(pda_pubkey);
+        self.pda = Some(pda_pubkey);
+        Ok(())
+    }
+
+    // Initialize and RegisterWatcher of the wallet's vault
+    async fn initialize_instructions(
+        &self,
+    ) -> Result<(Pubkey, Vec<Instruction>), crate::error::Error> {
+        // Get the amount of lamports needed to pay for the vault's rent
+        let lamports = self.vault_rent().await?;
 
-        let wallet_pubkey = self.wallet.pubkey();
-        // Derive the PDA from the payer account, a string representing the unique
-        // purpose of the account ("vault"), and the address of our on-chain program.
-        let seeds = &[PDA_SEED_PREFIX, wallet_pubkey.as_ref()];
-        let (pda_pubkey, pda_bump_seed) = Pubkey::find_program_address(seeds, &self.program);
+        let (pda_pubkey, pda_bump_seed) = self.derive_pda();
 
         // The on-chain program's instruction data, imported from that program's crate.
         let instr_data = instruction::EventTrackerInstruction::Initialize(
             instruction::InitializeInstructionData {
                 lamports,
                 pda_bump_seed,
+                path_prefixes: self.paths.prefixes().to_vec(),
             },
         )
-        .pack()?;
+        .pack()
+        .map_err(crate::error::Error::Serialization)?;
 
         // The accounts required by both our on-chain program and the system program's
         // `create_account` instruction, including the vault's address.
@@ -135,85 +1237,735 @@ impl SolanaClient {
 
         // Create the instruction by serializing our instruction data via borsh
         let instruction = Instruction::new_with_bytes(self.program.clone(), &instr_data, accounts);
+        // The vault documents its watcher from the start
+        let register = self.register_watcher_instruction(&[pda_pubkey])?;
+        Ok((pda_pubkey, vec![instruction, register]))
+    }
+
+    // Registers the watcher in the first vault and the overflow vaults linked from it
+    fn register_watcher_instruction(
+        &self,
+        vaults: &[Pubkey],
+    ) -> Result<Instruction, crate::error::Error> {
+        let instr_data = instruction::EventTrackerInstruction::RegisterWatcher(
+            instruction::RegisterWatcherInstructionData {
+                hostname: hostname(),
+                signing_key: self.wallet.pubkey(),
+                schema_version: self.schema_version,
+            },
+        )
+        .pack()
+        .map_err(crate::error::Error::Serialization)?;
+        let accounts = std::iter::once(AccountMeta::new(self.wallet.pubkey(), true))
+            .chain(
+                vaults
+                    .iter()
+                    .map(|vault| AccountMeta::new(*vault, false)),
+            )
+            .collect();
+        Ok(Instruction::new_with_bytes(
+            self.program,
+            &instr_data,
+            accounts,
+        ))
+    }
+
+    // Registers this watcher in the vault created before the registration, and in its
+    // overflow vaults followed before, fails if the vault accepts another watcher's events only
+    async fn check_watcher(
+        &self,
+        vault: &Pubkey,
+        data: &AccountData,
+    ) -> Result<(), crate::error::Error> {
+        match data.watcher.as_ref() {
+            Some(watcher) if watcher.signing_key != self.wallet.pubkey() => {
+                Err(crate::error::Error::Config(format!(
+                    "The vault {} is fed by the watcher {} registered with the key {}, not by this wallet",
+                    vault, watcher.hostname, watcher.signing_key
+                )))
+            }
+            Some(watcher) => {
+                if watcher.hostname != hostname() {
+                    println!(
+                        "The vault's watcher is registered on {}, the same wallet is used here",
+                        watcher.hostname
+                    );
+                }
+                Ok(())
+            }
+            None => {
+                let instruction = self.register_watcher_instruction(self.vaults.vaults())?;
+                let blockhash = self
+                    .rpc_client
+                    .get_latest_blockhash()
+                    .await
+                    .map_err(rpc_error)?;
+                let transaction = Transaction::new_signed_with_payer(
+                    &[instruction],
+                    Some(&self.wallet.pubkey()),
+                    &[&self.wallet],
+                    blockhash,
+                );
+                let signature = self
+                    .rpc_client
+                    .send_and_confirm_transaction(&transaction)
+                    .await
+                    .map_err(rpc_error)?;
+                println!(
+                    "Watcher registered in the vault {}: {}",
+                    vault,
+                    self.explorer
+                        .transaction(&signature)
+                );
+                Ok(())
+            }
+        }
+    }
+
+    // The vaults linked from the first one, the last one gets the new files
+    async fn follow_vaults(
+        &self,
+        first: Pubkey,
+    ) -> Result<overflow::VaultChain, crate::error::Error> {
+        let mut vaults = overflow::VaultChain::new(first);
+        let mut vault = self
+            .read_vault(&first)
+            .await?;
+        while let Some(next) = vault.next_vault {
+            vaults.push(next, &vault);
+            vault = self.read_vault(&next).await?;
+        }
+        if let Some(last) = vaults
+            .last()
+            .filter(|last| *last != first)
+        {
+            println!(
+                "The new files go to the overflow vault {} of epoch {}",
+                last,
+                vaults.next_epoch() - 1
+            );
+        }
+        Ok(vaults)
+    }
+
+    // Creates the next vault once the last one gets close to the maximum account size,
+    // checked at most once in OVERFLOW_CHECK_INTERVAL
+    async fn check_overflow(&mut self) -> Result<(), crate::error::Error> {
+        if self
+            .overflow_checked
+            .is_some_and(|at| at.elapsed() < OVERFLOW_CHECK_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.overflow_checked = Some(Instant::now());
+        let last = self
+            .vaults
+            .last()
+            .expect("Initialized with the first vault");
+        let size = self
+            .rpc_client
+            .get_account_data(&last)
+            .await
+            .map_err(rpc_error)?
+            .len() as u64;
+        if size < overflow::OVERFLOW_SIZE {
+            return Ok(());
+        }
+        self.init_overflow(last, size)
+            .await
+    }
+
+    async fn init_overflow(&mut self, last: Pubkey, size: u64) -> Result<(), crate::error::Error> {
+        let lamports = self.vault_rent().await?;
+        let epoch = self.vaults.next_epoch();
+        let (pda_pubkey, pda_bump_seed) =
+            find_vault_address(&self.program, &self.wallet.pubkey(), epoch);
+
+        let instr_data = instruction::EventTrackerInstruction::InitializeOverflow(
+            instruction::InitializeOverflowInstructionData {
+                lamports,
+                pda_bump_seed,
+                epoch,
+                path_prefixes: self.paths.prefixes().to_vec(),
+            },
+        )
+        .pack()
+        .map_err(crate::error::Error::Serialization)?;
+        let accounts = vec![
+            AccountMeta::new(self.wallet.pubkey(), true),
+            AccountMeta::new(last, false),
+            AccountMeta::new(pda_pubkey, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+        ];
+        let instruction = Instruction::new_with_bytes(self.program, &instr_data, accounts);
 
         let blockhash = self
             .rpc_client
             .get_latest_blockhash()
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
-
+            .await
+            .map_err(rpc_error)?;
         let transaction = Transaction::new_signed_with_payer(
             &[instruction],
             Some(&self.wallet.pubkey()),
             &[&self.wallet],
             blockhash,
         );
-
-        let signature = self
+        match self
             .rpc_client
-            .send_and_confirm_transaction(&transaction);
-        if let Err(e) = signature {
-            // If the account already exists, instead of TransactionError::AccountInUse
-            // the retuned error is custom program error: 0x0.
-            // Therefore we will just parse the error string searching for
-            // `already in use` term instead of checking the error type.
-            if e.to_string()
-                .contains("already in use")
-            {
-                println!("Wallet's PDA already exists");
-                self.pda = Some(pda_pubkey);
-                return Ok(());
-            } else {
-                return Err(crate::error::Error::new(&e.to_string()));
+            .send_and_confirm_transaction(&transaction)
+            .await
+        {
+            Ok(signature) => println!(
+                "Vault {} is full ({} bytes), the new files go to the overflow vault {} of epoch {}: {}",
+                last,
+                size,
+                pda_pubkey,
+                epoch,
+                self.explorer
+                    .transaction(&signature)
+            ),
+            // Created by another tracker of the same wallet meanwhile, e.g. the standby
+            Err(e) if e.to_string().contains("already in use") => {
+                println!("Overflow vault {} already exists", pda_pubkey)
             }
+            Err(e) => return Err(rpc_error(e)),
         }
 
-        println!("PDA created, transaction signature: {}", signature.unwrap());
-
-        self.pda = Some(pda_pubkey);
+        let first = self
+            .pda
+            .expect("Initialized with the first vault");
+        self.vaults = self
+            .follow_vaults(first)
+            .await?;
         Ok(())
     }
 
-    fn get_solana_unix_timestamp(&self) -> Result<i64, crate::error::Error> {
-        let sysvar_clock_address = "SysvarC1ock11111111111111111111111111111111";
+    /// Reads how much of its space the last vault uses, `None` if it's not created yet.
+    pub async fn vault_usage(
+        &self,
+    ) -> Result<Option<(Pubkey, capacity::VaultUsage)>, crate::error::Error> {
+        let (vault, _) = self.derive_pda();
+        read_vault_usage(&self.rpc_client, &vault).await
+    }
 
-        let recv_body = self
-            .get_account_info(&sysvar_clock_address)
-            .map_err(|e| crate::error::Error::new(&e.to_string()))?;
+    /// The last event of every file in the vaults, empty if they're not created yet.
+    pub async fn vault_events(
+        &self,
+    ) -> Result<std::collections::HashMap<String, Event>, crate::error::Error> {
+        read_vault_events(&self.rpc_client, &self.derive_pda().0).await
+    }
 
-        let res = recv_body
-            .get("result")
-            .and_then(|res| res.get("value"))
-            .and_then(|res| res.get("data"))
-            .and_then(|res| res.get("parsed"))
-            .and_then(|res| res.get("info"))
-            .and_then(|res| res.get("unixTimestamp"));
+    /// Address of the wallet's vault.
+    pub fn vault(&self) -> Pubkey {
+        self.derive_pda().0
+    }
 
-        res.map(|r| r.as_i64().unwrap())
-            .ok_or(crate::error::Error::new(
-                "unixTimestamp is missing in the response, incorrectly parsed?",
-            ))
+    /// Checks the vault's size periodically and warns, with the recommended top up,
+    /// when it's going to be full within a week at the observed growth.
+    pub fn monitor_capacity(&self) {
+        let rpc_client = self.rpc_client.clone();
+        let (first, _) = self.derive_pda();
+        tokio::spawn(async move {
+            let mut forecaster = capacity::Forecaster::default();
+            let mut forecasted = None;
+            let mut ticker = tokio::time::interval(CAPACITY_CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let (vault, usage) = match read_vault_usage(&rpc_client, &first).await {
+                    Ok(Some(usage)) => usage,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        println!("Failed to read the vault's size: {}", e);
+                        continue;
+                    }
+                };
+                // The growth of the full vault says nothing about its overflow vault
+                if forecasted != Some(vault) {
+                    forecaster = capacity::Forecaster::default();
+                    forecasted = Some(vault);
+                }
+                forecaster.record(Instant::now(), usage.size);
+                let full_in = match forecaster.full_in(&usage) {
+                    Some(full_in) if full_in < CAPACITY_WARNING => full_in,
+                    _ => continue,
+                };
+                println!(
+                    "ALERT: vault {} is full in {}h at the current growth, {}",
+                    vault,
+                    full_in.as_secs() / 3600,
+                    usage
+                );
+                if let Some(lamports) = usage.top_up(usage.files as u64) {
+                    println!(
+                        "Transfer {} lamports to {} to make room for {} more files",
+                        lamports, vault, usage.files
+                    );
+                }
+            }
+        });
     }
 
-    fn get_account_info(
+    /// Reads the vaults in batches, `None` for the ones not created yet.
+    pub async fn read_vaults(
         &self,
-        account_key: &str,
-    ) -> Result<std::collections::HashMap<String, serde_json::Value>, ureq::Error> {
-        let req_body = ureq::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-            "method": "getAccountInfo",
-            "params": [
-                account_key,
-                {
-                    "encoding": "jsonParsed",
-                },
-            ],
-        });
+        vaults: &[Pubkey],
+    ) -> Result<Vec<Option<AccountData>>, crate::error::Error> {
+        accounts::read_accounts(&self.rpc_client, vaults, |_, account| {
+            AccountData::unpack(&account.data).map_err(crate::error::Error::Serialization)
+        })
+        .await
+    }
 
-        // https://solana.com/docs/rpc/http/getaccountinfo
-        ureq::post(&self.url)
-            .send_json(&req_body)?
-            .into_json()
-            .map_err(|e| e.into())
+    async fn read_vault(&self, vault: &Pubkey) -> Result<AccountData, crate::error::Error> {
+        let data = self
+            .rpc_client
+            .get_account_data(vault)
+            .await
+            .map_err(rpc_error)?;
+        AccountData::unpack(&data).map_err(crate::error::Error::Serialization)
+    }
+
+    async fn get_solana_unix_timestamp(&self) -> Result<i64, crate::error::Error> {
+        solana_unix_timestamp(&self.rpc_client).await
+    }
+}
+
+// The host name registered in the vault
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// Reads the usage of the last vault linked from the `first` one,
+/// its capacity computed with the cluster's rent.
+async fn read_vault_usage(
+    rpc_client: &RpcClient,
+    first: &Pubkey,
+) -> Result<Option<(Pubkey, capacity::VaultUsage)>, crate::error::Error> {
+    let mut address = *first;
+    loop {
+        let accounts = rpc_client
+            .get_multiple_accounts(&[address, solana_sdk::sysvar::rent::ID])
+            .await
+            .map_err(rpc_error)?;
+        let rent = accounts
+            .get(1)
+            .and_then(|account| account.as_ref())
+            .and_then(solana_sdk::account::from_account::<Rent, _>)
+            .unwrap_or_default();
+        let vault = match accounts
+            .into_iter()
+            .next()
+            .flatten()
+        {
+            Some(vault) => vault,
+            None => return Ok(None),
+        };
+        let data = AccountData::unpack(&vault.data).map_err(crate::error::Error::Serialization)?;
+        if let Some(next) = data.next_vault {
+            address = next;
+            continue;
+        }
+        let usage = capacity::VaultUsage::new(
+            vault.data.len() as u64,
+            vault.lamports,
+            data.last_file_events.len(),
+            rent,
+        );
+        return Ok(Some((address, usage)));
+    }
+}
+
+// The last event of every file in the vault and the overflow vaults linked from it
+async fn read_vault_events(
+    rpc_client: &RpcClient,
+    first: &Pubkey,
+) -> Result<std::collections::HashMap<String, Event>, crate::error::Error> {
+    let mut events = std::collections::HashMap::new();
+    let mut vault = Some(*first);
+    // Following the links of the full vaults to the overflow ones
+    while let Some(address) = vault {
+        let accounts = rpc_client
+            .get_multiple_accounts(&[address])
+            .await
+            .map_err(rpc_error)?;
+        let Some(account) = accounts
+            .into_iter()
+            .next()
+            .flatten()
+        else {
+            break;
+        };
+        let data =
+            AccountData::unpack(&account.data).map_err(crate::error::Error::Serialization)?;
+        events.extend(data.last_file_events);
+        vault = data.next_vault;
+    }
+    Ok(events)
+}
+
+/// Reads the cluster's time from the Clock sysvar.
+async fn solana_unix_timestamp(rpc_client: &RpcClient) -> Result<i64, crate::error::Error> {
+    let account = rpc_client
+        .get_account(&solana_sdk::sysvar::clock::ID)
+        .await
+        .map_err(rpc_error)?;
+
+    solana_sdk::account::from_account::<Clock, _>(&account)
+        .map(|clock| clock.unix_timestamp)
+        .ok_or(crate::error::Error::Rpc {
+            source: "Clock sysvar account can't be decoded".into(),
+            retryable: false,
+        })
+}
+
+/// Derives the vault PDA of the `payer` wallet.
+pub fn derive_vault(program: &Pubkey, payer: &Pubkey) -> (Pubkey, u8) {
+    // Derive the PDA from the payer account, a string representing the unique
+    // purpose of the account ("vault"), and the address of our on-chain program.
+    let seeds = &[PDA_SEED_PREFIX, payer.as_ref()];
+    Pubkey::find_program_address(seeds, program)
+}
+
+/// Builds the instruction storing `event` in the `vault` of the `payer` wallet,
+/// in the schema `schema_version`.
+pub fn add_event_instruction(
+    program: &Pubkey,
+    payer: &Pubkey,
+    vault: &Pubkey,
+    schema_version: u16,
+    event: Event,
+) -> Result<Instruction, crate::error::Error> {
+    // accounts needed by the transaction
+    let accounts = [
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*vault, false),
+        // no CPIs, no more accounts needed
+    ]
+    .to_vec();
+
+    let instr_data = match schema_version {
+        1 => instruction::EventTrackerInstruction::AddEvent(instruction::AddEventInstructionData {
+            event: event,
+        }),
+        2 => instruction::EventTrackerInstruction::AddEventV2(
+            instruction::AddEventV2InstructionData {
+                event: EventV2::try_from(&event).map_err(schema_error)?,
+            },
+        ),
+        _ => instruction::EventTrackerInstruction::AddEventV3(
+            instruction::AddEventV3InstructionData {
+                event: EventV3::try_from(&event).map_err(schema_error)?,
+            },
+        ),
+    }
+    .pack()
+    .map_err(crate::error::Error::Serialization)?;
+
+    Ok(Instruction::new_with_bytes(*program, &instr_data, accounts))
+}
+
+/// Builds the instruction storing `event` in the `vault`, with the path compressed
+/// if it starts with one of the prefixes stored in the vault, `paths`.
+pub fn event_instruction(
+    program: &Pubkey,
+    payer: &Pubkey,
+    vault: &Pubkey,
+    paths: &PathDictionary,
+    schema_version: u16,
+    event: Event,
+) -> Result<Instruction, crate::error::Error> {
+    let compressed = match paths.compress(&event) {
+        Some(compressed) => compressed,
+        None => return add_event_instruction(program, payer, vault, schema_version, event),
+    };
+
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*vault, false),
+    ];
+    let instr_data = match schema_version {
+        1 => instruction::EventTrackerInstruction::AddCompressedEvent(
+            instruction::AddCompressedEventInstructionData { event: compressed },
+        ),
+        2 => instruction::EventTrackerInstruction::AddCompressedEventV2(
+            instruction::AddCompressedEventV2InstructionData {
+                event: CompressedEventV2::try_from(&compressed).map_err(schema_error)?,
+            },
+        ),
+        _ => instruction::EventTrackerInstruction::AddCompressedEventV3(
+            instruction::AddCompressedEventV3InstructionData {
+                event: paths
+                    .compress_v3(&EventV3::try_from(&event).map_err(schema_error)?)
+                    .expect("Compressed with the same prefixes"),
+            },
+        ),
+    }
+    .pack()
+    .map_err(crate::error::Error::Serialization)?;
+
+    Ok(Instruction::new_with_bytes(*program, &instr_data, accounts))
+}
+
+/// Builds the instruction recording in the `vault` that the watcher dropped events.
+pub fn events_dropped_instruction(
+    program: &Pubkey,
+    payer: &Pubkey,
+    vault: &Pubkey,
+    dropped: EventsDropped,
+) -> Result<Instruction, crate::error::Error> {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*vault, false),
+    ];
+    let instr_data = instruction::EventTrackerInstruction::AddEventsDropped(
+        instruction::AddEventsDroppedInstructionData { dropped },
+    )
+    .pack()
+    .map_err(crate::error::Error::Serialization)?;
+
+    Ok(Instruction::new_with_bytes(*program, &instr_data, accounts))
+}
+
+// The event doesn't fit in the schema 2 or 3, the vault wouldn't store it either
+fn schema_error(e: TimestampOutOfRange) -> crate::error::Error {
+    crate::error::Error::serialization(&e.to_string())
+}
+
+/// Base fees of the transactions signed only by the wallet.
+pub fn transaction_fees(transactions: &[Vec<Instruction>]) -> u64 {
+    transactions.len() as u64 * LAMPORTS_PER_SIGNATURE
+}
+
+// Whether the file's size or modification time changed since its event was captured
+fn modified_since(event: &Event) -> bool {
+    let captured = match event.file_info.as_ref() {
+        Some(captured) => captured,
+        None => return false,
+    };
+    crate::dir_watcher::read_file_info(&event.file_path).is_some_and(|current| {
+        (current.size, current.modify_ts) != (captured.size, captured.modify_ts)
+    })
+}
+
+// Who owns the file after its attributes changed, e.g. by a chown. Recorded in a memo,
+// the owner isn't part of the events' layout
+fn owner_memo(event: &Event) -> Option<String> {
+    if event.event_type != EventType::AttributeChanged {
+        return None;
+    }
+    let info = event.file_info.as_ref()?;
+    let name = |name: &Option<String>| {
+        name.as_ref()
+            .map(|name| format!(" ({})", name))
+            .unwrap_or_default()
+    };
+    Some(format!(
+        "owner uid {}{} gid {}{}",
+        info.uid?,
+        name(&info.user),
+        info.gid?,
+        name(&info.group)
+    ))
+}
+
+// The extended attributes captured with the attribute change, their hash if they
+// don't fit in a memo of a single transaction
+fn xattrs_memo(event: &Event) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    let xattrs = &event
+        .file_info
+        .as_ref()?
+        .xattrs;
+    if xattrs.is_empty() {
+        return None;
+    }
+    let listed: Vec<String> = xattrs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let memo = format!("xattrs {}", listed.join(" "));
+    if memo.len() <= MAX_XATTRS_MEMO_LEN {
+        return Some(memo);
+    }
+    let sha256: [u8; 32] = Sha256::digest(memo.as_bytes()).into();
+    Some(format!("xattrs sha256 {}", crate::integrity::hex(&sha256)))
+}
+
+// The file's device and inode number if it has hard links, the same in the events of
+// all its names
+fn inode_memo(event: &Event) -> Option<String> {
+    let info = event.file_info.as_ref()?;
+    if info.nlink? < 2 {
+        return None;
+    }
+    Some(format!("inode {}:{}", info.device?, info.inode?))
+}
+
+// The hash of the written text file's diff, the diff itself may have secrets, e.g. of
+// a config file, and stays off chain
+fn diff_memo(event: &Event) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    let diff = event.diff.as_ref()?;
+    let sha256: [u8; 32] = Sha256::digest(diff.as_bytes()).into();
+    Some(format!("diff sha256 {}", crate::integrity::hex(&sha256)))
+}
+
+/// Builds the instruction recording `memo` in the transaction, no accounts needed.
+pub fn memo_instruction(memo: &str) -> Instruction {
+    Instruction::new_with_bytes(MEMO_PROGRAM, memo.as_bytes(), Vec::new())
+}
+
+// JSON RPC error code of a node which is behind the cluster
+const NODE_UNHEALTHY_ERROR_CODE: i64 = -32005;
+
+/// Converts the RPC client's error, telling apart the errors of our program
+/// and the ones which are worth retrying.
+fn rpc_error(err: ClientError) -> crate::error::Error {
+    if let Some(TransactionError::InstructionError(_, InstructionError::Custom(code))) =
+        err.get_transaction_error()
+    {
+        if let Some(tracker_error) = TrackerError::from_u32(code) {
+            return crate::error::Error::Program(tracker_error);
+        }
+    }
+
+    let retryable = match err.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == NODE_UNHEALTHY_ERROR_CODE
+        }
+        ClientErrorKind::TransactionError(TransactionError::BlockhashNotFound) => true,
+        _ => false,
+    };
+    crate::error::Error::Rpc {
+        source: Box::new(err),
+        retryable,
+    }
+}
+
+#[async_trait]
+impl ChainAnchor for SolanaClient {
+    fn chain(&self) -> &str {
+        "solana"
+    }
+
+    async fn anchor(&mut self, event: &Event) -> Result<String, crate::error::Error> {
+        self.anchor_batch(vec![event.clone()])
+            .await
+            .pop()
+            .expect("A single event is anchored or failed")
+            .transaction
+    }
+
+    /// Packs the events with their memos in as few transactions as fit in a packet.
+    async fn anchor_batch(&mut self, events: Vec<Event>) -> Vec<Anchored> {
+        if events.is_empty() {
+            return Vec::new();
+        }
+        if self.pda.is_none() {
+            return vec![Anchored {
+                events,
+                transaction: Err(crate::error::Error::Config(
+                    "PDA has to be initialized for this call".to_string(),
+                )),
+            }];
+        }
+        if let Err(e) = self.check_overflow().await {
+            println!("Failed to check the vault's size: {}", e);
+        }
+        self.record_gaps().await;
+
+        // The events sent together are timestamped together
+        let ts = self
+            .get_solana_unix_timestamp()
+            .await
+            .ok();
+        let skew = ts.map(|ts| self.clock.measure(ts));
+
+        let mut anchored = Vec::new();
+        let mut prepared = Vec::with_capacity(events.len());
+        for mut event in events {
+            println!("Consumer received an event: {}", event);
+            event.solana_ts_received_at = ts.map_or(-1, |ts| ts as i128);
+            match self
+                .event_instructions(&event, skew)
+                .await
+            {
+                Ok(instructions) => prepared.push((event, instructions)),
+                Err(e) => anchored.push(Anchored {
+                    events: vec![event],
+                    transaction: Err(e),
+                }),
+            }
+        }
+
+        let groups: Vec<_> = prepared
+            .iter()
+            .map(|(_, instructions)| instructions.clone())
+            .collect();
+        // Every transaction of the batch is split as if it paid a priority fee
+        let all: Vec<_> = prepared
+            .iter()
+            .map(|(event, _)| event.clone())
+            .collect();
+        let prefix: Vec<_> = self
+            .fees
+            .instruction(&all)
+            .into_iter()
+            .collect();
+        let mut prepared = prepared.into_iter();
+        for count in self
+            .packer
+            .split(&prefix, &groups)
+        {
+            let (events, groups): (Vec<_>, Vec<_>) = prepared
+                .by_ref()
+                .take(count)
+                .unzip();
+            let transaction = self
+                .send_events(&events, &groups.concat())
+                .await;
+            anchored.push(Anchored {
+                events,
+                transaction,
+            });
+        }
+        anchored
+    }
+
+    /// Records the drops in the last vault, where the new files go.
+    async fn anchor_dropped(
+        &mut self,
+        dropped: &EventsDropped,
+    ) -> Result<String, crate::error::Error> {
+        let vault = self
+            .vaults
+            .last()
+            .ok_or_else(|| {
+                crate::error::Error::Config("PDA has to be initialized for this call".to_string())
+            })?;
+        let instruction = events_dropped_instruction(
+            &self.program,
+            &self.wallet.pubkey(),
+            &vault,
+            dropped.clone(),
+        )?;
+        let signature = self
+            .send(&[instruction])
+            .await?;
+        Ok(signature.to_string())
+    }
+
+    /// Waits for the confirmations of the already sent transactions.
+    async fn shutdown(&mut self) -> Result<(), crate::error::Error> {
+        if let Some(confirmations) = self.confirmations.take() {
+            confirmations.close().await?;
+        }
+        Ok(())
     }
 }
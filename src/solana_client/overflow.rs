@@ -0,0 +1,88 @@
+//! Overflow vaults: an account can't grow over 10 MiB, so once the wallet's vault gets
+//! close to it, the next vault is created with `InitializeOverflow`, at the PDA seeded
+//! with the next epoch, and linked from the full one. The new files go to the last vault,
+//! the files already in a full vault keep being updated there.
+
+use file_event_tracker::processor::AccountData;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// The largest size of an account, the vault can't be reallocated beyond it.
+pub const MAX_VAULT_SIZE: u64 = 10 * 1024 * 1024;
+/// The vault overflows at this size, leaving room for the updates of its files.
+pub const OVERFLOW_SIZE: u64 = MAX_VAULT_SIZE / 10 * 9;
+
+/// The wallet's vaults, oldest first, following the links of the full ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VaultChain {
+    vaults: Vec<Pubkey>,
+    // The files in the full vaults, the others go to the last one
+    full: HashMap<String, Pubkey>,
+}
+
+impl VaultChain {
+    pub fn new(first: Pubkey) -> VaultChain {
+        VaultChain {
+            vaults: vec![first],
+            full: HashMap::new(),
+        }
+    }
+
+    /// Adds the vault the last one links to, the files of the last one stay in it.
+    pub fn push(&mut self, next: Pubkey, last: &AccountData) {
+        let Some(&full) = self.vaults.last() else {
+            return;
+        };
+        for path in last.last_file_events.keys() {
+            self.full
+                .entry(path.clone())
+                .or_insert(full);
+        }
+        self.vaults.push(next);
+    }
+
+    pub fn vaults(&self) -> &[Pubkey] {
+        &self.vaults
+    }
+
+    pub fn last(&self) -> Option<Pubkey> {
+        self.vaults.last().copied()
+    }
+
+    /// Epoch of the next overflow vault, the first vault is of the epoch 0.
+    pub fn next_epoch(&self) -> u32 {
+        self.vaults.len() as u32
+    }
+
+    /// The vault storing the file's events: the full one it's in, or the last one.
+    pub fn vault_of(&self, path: &str) -> Option<Pubkey> {
+        self.full
+            .get(path)
+            .copied()
+            .or(self.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Event, EventType};
+
+    #[test]
+    fn test_new_files_go_to_the_last_vault() {
+        let event = |path: &str| Event::new(path, EventType::Created);
+        let mut full = AccountData::default();
+        full.last_file_events
+            .insert("/srv/a.txt".to_string(), event("/srv/a.txt"));
+
+        let (first, overflow) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut chain = VaultChain::new(first);
+        assert_eq!(chain.vault_of("/srv/a.txt"), Some(first));
+        assert_eq!(chain.next_epoch(), 1);
+
+        chain.push(overflow, &full);
+        assert_eq!(chain.vault_of("/srv/a.txt"), Some(first));
+        assert_eq!(chain.vault_of("/srv/b.txt"), Some(overflow));
+        assert_eq!(chain.next_epoch(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+//! Read-only access to a wallet's vaults by the wallet's public key, for the auditors
+//! who never hold the keypair nor pay for a transaction.
+
+use super::{derive_vault, explorer, read_vault_events, read_vault_usage, rpc_error};
+use crate::config::SolanaConfig;
+use crate::error::Error;
+use crate::event::Event;
+use file_event_history::{Recorded, Transaction};
+use file_event_tracker::processor::{AccountData, WatcherIdentity};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub struct VaultReader {
+    rpc_client: RpcClient,
+    program: Pubkey,
+    vault: Pubkey,
+    explorer: explorer::Explorer,
+}
+
+impl VaultReader {
+    pub fn new(
+        url: &str,
+        commitment: solana_sdk::commitment_config::CommitmentConfig,
+        program: Pubkey,
+        wallet: Pubkey,
+    ) -> VaultReader {
+        VaultReader {
+            rpc_client: RpcClient::new_with_commitment(url.to_string(), commitment),
+            program,
+            vault: derive_vault(&program, &wallet).0,
+            explorer: explorer::Explorer::new(url),
+        }
+    }
+
+    pub fn from_config(config: &SolanaConfig, wallet: Pubkey) -> VaultReader {
+        VaultReader::new(&config.rpc_url, config.commitment, config.program, wallet)
+    }
+
+    /// Address of the wallet's first vault.
+    pub fn vault(&self) -> Pubkey {
+        self.vault
+    }
+
+    pub fn explorer(&self) -> &explorer::Explorer {
+        &self.explorer
+    }
+
+    /// Usage of the vault getting the new files, `None` if it's not created yet.
+    pub async fn vault_usage(
+        &self,
+    ) -> Result<Option<(Pubkey, super::capacity::VaultUsage)>, Error> {
+        read_vault_usage(&self.rpc_client, &self.vault).await
+    }
+
+    /// The last event of every file in the vaults, empty if they're not created yet.
+    pub async fn vault_events(&self) -> Result<HashMap<String, Event>, Error> {
+        read_vault_events(&self.rpc_client, &self.vault).await
+    }
+
+    /// The watcher registered in the vault, if any.
+    pub async fn watcher(&self) -> Result<Option<WatcherIdentity>, Error> {
+        let data = self
+            .rpc_client
+            .get_account_data(&self.vault)
+            .await
+            .map_err(rpc_error)?;
+        AccountData::unpack(&data)
+            .map(|vault| vault.watcher)
+            .map_err(Error::Serialization)
+    }
+
+    /// The events recorded in the vaults by the successful transactions with a block time
+    /// from `since` until `until`, oldest first, each with its transaction.
+    pub async fn vault_records(
+        &self,
+        since: i64,
+        until: Option<i64>,
+    ) -> Result<Vec<Recorded>, Error> {
+        let mut records = Vec::new();
+        let mut vault = Some(self.vault);
+        while let Some(address) = vault {
+            let data = match self
+                .rpc_client
+                .get_multiple_accounts(&[address])
+                .await
+                .map_err(rpc_error)?
+                .into_iter()
+                .next()
+                .flatten()
+            {
+                Some(account) => {
+                    AccountData::unpack(&account.data).map_err(Error::Serialization)?
+                }
+                None => break,
+            };
+            for transaction in self
+                .transactions(&address, since, until)
+                .await?
+            {
+                let events = file_event_history::recorded_events(
+                    &self.program,
+                    &address,
+                    &data.path_dictionary,
+                    &transaction,
+                )
+                .map_err(|e| Error::serialization(&e.to_string()))?;
+                records.extend(
+                    events
+                        .into_iter()
+                        .map(|event| Recorded {
+                            signature: transaction.signature.clone(),
+                            slot: transaction.slot,
+                            block_time: transaction.block_time,
+                            event,
+                        }),
+                );
+            }
+            vault = data.next_vault;
+        }
+        records.sort_by_key(|r| r.slot);
+        Ok(records)
+    }
+
+    // Listed newest first, a page at a time, until the first one before `since`
+    async fn transactions(
+        &self,
+        address: &Pubkey,
+        since: i64,
+        until: Option<i64>,
+    ) -> Result<Vec<Transaction>, Error> {
+        let mut transactions = Vec::new();
+        let mut before = None;
+        loop {
+            let page = self
+                .rpc_client
+                .get_signatures_for_address_with_config(
+                    address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: None,
+                        limit: None,
+                        commitment: Some(self.rpc_client.commitment()),
+                    },
+                )
+                .await
+                .map_err(rpc_error)?;
+            let Some(last) = page.last() else {
+                return Ok(transactions);
+            };
+            before = Signature::from_str(&last.signature).ok();
+            for status in page.iter() {
+                let block_time = status
+                    .block_time
+                    .unwrap_or(i64::MAX);
+                if block_time < since {
+                    return Ok(transactions);
+                }
+                if status.err.is_some() || until.is_some_and(|until| block_time > until) {
+                    continue;
+                }
+                transactions.push(
+                    self.transaction(&status.signature)
+                        .await?,
+                );
+            }
+            if before.is_none() {
+                return Ok(transactions);
+            }
+        }
+    }
+
+    async fn transaction(&self, signature: &str) -> Result<Transaction, Error> {
+        let undecodable = |reason: &str| Error::Rpc {
+            source: format!("Transaction {} {}", signature, reason).into(),
+            retryable: false,
+        };
+        let parsed =
+            Signature::from_str(signature).map_err(|_| undecodable("has an invalid signature"))?;
+        let fetched = self
+            .rpc_client
+            .get_transaction_with_config(
+                &parsed,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(self.rpc_client.commitment()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+            .map_err(rpc_error)?;
+        let decoded = fetched
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| undecodable("can't be decoded"))?;
+        Ok(Transaction {
+            signature: signature.to_string(),
+            slot: fetched.slot,
+            block_time: fetched.block_time,
+            message: decoded.message,
+        })
+    }
+}
@@ -0,0 +1,115 @@
+//! Retries of the events' transactions failing for a transient reason.
+//!
+//! The errors marked as retryable, e.g. an expired blockhash, a node behind the cluster or
+//! a rate-limited request, are retried after a jittered exponential backoff, until the retry
+//! budget is spent. The permanent ones, e.g. the program rejecting the event, aren't.
+
+use crate::error::Error;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_BUDGET: Duration = Duration::from_secs(30);
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    // Longest time spent retrying a transaction, zero for no retries
+    budget: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(budget: Duration) -> RetryPolicy {
+        RetryPolicy { budget }
+    }
+
+    /// Retries of a transaction first sent at `started`.
+    pub fn start(&self, started: Instant) -> Retries {
+        Retries {
+            budget: self.budget,
+            started,
+            delay: FIRST_DELAY,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Retries {
+    budget: Duration,
+    started: Instant,
+    // Upper bound of the next delay, doubled after every retry
+    delay: Duration,
+}
+
+impl Retries {
+    /// How long to wait before sending again after the `err`, none if it's permanent
+    /// or the retry would end past the budget.
+    pub fn next(&mut self, err: &Error, now: Instant) -> Option<Duration> {
+        if !is_retryable(err) {
+            return None;
+        }
+        let delay = jittered(self.delay);
+        if now.duration_since(self.started) + delay > self.budget {
+            return None;
+        }
+        self.delay = std::cmp::min(self.delay * 2, MAX_DELAY);
+        Some(delay)
+    }
+}
+
+pub fn is_retryable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::Rpc {
+            retryable: true,
+            ..
+        }
+    )
+}
+
+// Between half of the delay and all of it, so the clients failing together don't retry together
+fn jittered(delay: Duration) -> Duration {
+    // Keyed randomly for every hasher
+    let random = RandomState::new()
+        .build_hasher()
+        .finish();
+    delay.mul_f64(0.5 + 0.5 * (random as f64 / u64::MAX as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(retryable: bool) -> Error {
+        Error::Rpc {
+            source: "node is behind".into(),
+            retryable,
+        }
+    }
+
+    #[test]
+    fn test_transient_errors_retried_within_budget() {
+        let start = Instant::now();
+        let mut retries = RetryPolicy::new(Duration::from_secs(30)).start(start);
+        let mut elapsed = Duration::ZERO;
+        let mut delays = Vec::new();
+        while let Some(delay) = retries.next(&rpc_error(true), start + elapsed) {
+            delays.push(delay);
+            elapsed += delay;
+        }
+        assert!(elapsed <= Duration::from_secs(30));
+        for (i, delay) in delays.iter().enumerate() {
+            let bound = std::cmp::min(FIRST_DELAY * 2u32.pow(i as u32), MAX_DELAY);
+            assert!(*delay >= bound / 2 && *delay <= bound, "{:?}", delays);
+        }
+        assert!(delays.len() >= 4, "{:?}", delays);
+
+        // Permanent, or no budget
+        let mut retries = RetryPolicy::new(Duration::from_secs(30)).start(start);
+        assert_eq!(retries.next(&rpc_error(false), start), None);
+        assert_eq!(retries.next(&Error::Config("bad".to_string()), start), None);
+        let mut retries = RetryPolicy::new(Duration::ZERO).start(start);
+        assert_eq!(retries.next(&rpc_error(true), start), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Per-directory program routing, e.g. the events of a staging directory go to
+//! a staging program and the rest to the production one, within one tracker.
+//!
+//! Every program has its own [SolanaClient], so its own vault and path dictionary.
+
+use super::SolanaClient;
+use crate::anchor::{Anchored, ChainAnchor};
+use crate::config::{ProgramRoute, SolanaConfig};
+use crate::error::Error;
+use crate::event::{Event, EventsDropped};
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+pub struct ProgramRouter {
+    config: SolanaConfig,
+    default: SolanaClient,
+    routed: Vec<(Pubkey, SolanaClient)>,
+}
+
+impl ProgramRouter {
+    /// The events outside of the routed directories go to the `default` client.
+    pub fn new(config: &SolanaConfig, default: SolanaClient) -> ProgramRouter {
+        ProgramRouter {
+            config: config.clone(),
+            default,
+            routed: Vec::new(),
+        }
+    }
+
+    /// Adds the client of one of the config's routes.
+    pub fn with_route(mut self, route: &ProgramRoute, client: SolanaClient) -> ProgramRouter {
+        self.routed
+            .push((route.program, client));
+        self
+    }
+
+    fn client(&mut self, path: &str) -> &mut SolanaClient {
+        match self.route(path) {
+            Some(i) => &mut self.routed[i].1,
+            None => &mut self.default,
+        }
+    }
+
+    // Index of the routed client of the path, none for the default one
+    fn route(&self, path: &str) -> Option<usize> {
+        let program = self
+            .config
+            .route(path)?
+            .program;
+        self.routed
+            .iter()
+            .position(|(p, _)| *p == program)
+    }
+}
+
+#[async_trait]
+impl ChainAnchor for ProgramRouter {
+    fn chain(&self) -> &str {
+        "solana"
+    }
+
+    async fn anchor(&mut self, event: &Event) -> Result<String, Error> {
+        self.client(&event.file_path)
+            .anchor(event)
+            .await
+    }
+
+    /// Every program's events are batched separately.
+    async fn anchor_batch(&mut self, events: Vec<Event>) -> Vec<Anchored> {
+        let mut default = Vec::new();
+        let mut routed = vec![Vec::new(); self.routed.len()];
+        for event in events {
+            match self.route(&event.file_path) {
+                Some(i) => routed[i].push(event),
+                None => default.push(event),
+            }
+        }
+        let mut anchored = self
+            .default
+            .anchor_batch(default)
+            .await;
+        for ((_, client), events) in self
+            .routed
+            .iter_mut()
+            .zip(routed)
+        {
+            anchored.extend(
+                client
+                    .anchor_batch(events)
+                    .await,
+            );
+        }
+        anchored
+    }
+
+    /// Recorded by the default program, the drops aren't of any directory.
+    async fn anchor_dropped(&mut self, dropped: &EventsDropped) -> Result<String, Error> {
+        self.default
+            .anchor_dropped(dropped)
+            .await
+    }
+
+    /// Waits for the pending transactions of all the programs.
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        let mut result = self.default.shutdown().await;
+        for (_, client) in self.routed.iter_mut() {
+            result = result.and(client.shutdown().await);
+        }
+        result
+    }
+}
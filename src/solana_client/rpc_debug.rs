@@ -0,0 +1,141 @@
+//! Debug logging of the RPC traffic, to troubleshoot a flaky provider: every request
+//! is logged with its method, its duration, and its params and response truncated.
+//! The signatures, keys and blockhashes in them are partially redacted.
+
+use async_trait::async_trait;
+use solana_client::client_error::Result;
+use solana_client::http_sender::HttpSender;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::time::Instant;
+
+// Longer payloads are cut, e.g. the serialized transactions
+const MAX_PAYLOAD: usize = 200;
+// Base58 strings this long are signatures, keys or hashes, only their ends are kept
+const MIN_REDACTED: usize = 32;
+const KEPT: usize = 4;
+
+/// Sends the requests over HTTP like the default sender, and logs them.
+pub struct LoggingSender {
+    http: HttpSender,
+}
+
+impl LoggingSender {
+    pub fn new(url: &str) -> LoggingSender {
+        LoggingSender {
+            http: HttpSender::new(url),
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for LoggingSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let logged_params = payload(&params);
+        let started = Instant::now();
+        let result = self
+            .http
+            .send(request, params)
+            .await;
+        let elapsed = started.elapsed();
+        match &result {
+            Ok(response) => println!(
+                "RPC {} {:?} {} -> {}",
+                request,
+                elapsed,
+                logged_params,
+                payload(response)
+            ),
+            Err(e) => println!(
+                "RPC {} {:?} {} -> failed: {}",
+                request,
+                elapsed,
+                logged_params,
+                truncate(&redact(&e.to_string()))
+            ),
+        }
+        result
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.http
+            .get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.http.url()
+    }
+}
+
+fn payload(value: &serde_json::Value) -> String {
+    truncate(&redact(&value.to_string()))
+}
+
+fn truncate(text: &str) -> String {
+    match text
+        .char_indices()
+        .nth(MAX_PAYLOAD)
+    {
+        Some((end, _)) => format!("{}... ({} bytes)", &text[..end], text.len()),
+        None => text.to_string(),
+    }
+}
+
+/// Keeps only the first and last characters of the long base58 strings,
+/// enough to tell them apart in the logs.
+pub fn redact(text: &str) -> String {
+    let is_base58 = |c: char| c.is_ascii_alphanumeric() && !matches!(c, '0' | 'O' | 'I' | 'l');
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c| !is_base58(c))
+            .unwrap_or(rest.len());
+        // ASCII only, sliced at any byte
+        let (token, tail) = rest.split_at(end);
+        if token.len() >= MIN_REDACTED {
+            redacted.push_str(&token[..KEPT]);
+            redacted.push_str("..");
+            redacted.push_str(&token[token.len() - KEPT..]);
+        } else {
+            redacted.push_str(token);
+        }
+        let mut tail = tail.chars();
+        if let Some(separator) = tail.next() {
+            redacted.push(separator);
+        }
+        rest = tail.as_str();
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_and_signatures_redacted() {
+        let params = serde_json::json!([
+            "BtzKw3sZRdNd8DqToNSd8KRLVU9jYemcEJEgHWupKDjd",
+            {"commitment": "confirmed", "minContextSlot": 284739112}
+        ]);
+        assert_eq!(
+            payload(&params),
+            r#"["BtzK..KDjd",{"commitment":"confirmed","minContextSlot":284739112}]"#
+        );
+        assert_eq!(
+            redact("Transaction 5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLYp5xHdgrXWB2WQ86XhqKT4Y2rZw8Lf1k3BDYC5EosB failed"),
+            "Transaction 5VER..EosB failed"
+        );
+
+        // The serialized transactions are cut
+        assert_eq!(
+            truncate(&"x ".repeat(150)).len(),
+            MAX_PAYLOAD + "... (300 bytes)".len()
+        );
+    }
+}
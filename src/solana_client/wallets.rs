@@ -0,0 +1,177 @@
+//! Pool of funded wallets paying the events' transactions in turn, so the transactions
+//! in flight don't all wait for the locks of one fee payer's account. The tracker's
+//! wallet still signs every event as the vault's watcher, the pool wallets only pay.
+//!
+//! The balances are estimated from the fees paid and read from the RPC node every
+//! [BALANCE_CHECK_INTERVAL]. A wallet below the minimum balance leaves the rotation
+//! until it's funded again, once none is left the tracker's wallet pays.
+
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use std::time::{Duration, Instant};
+
+/// How often the pool wallets' balances are read.
+pub const BALANCE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+struct PoolWallet {
+    keypair: Keypair,
+    // Lamports, the last read balance less the fees paid since
+    balance: u64,
+}
+
+pub struct WalletPool {
+    wallets: Vec<PoolWallet>,
+    // Below it a wallet is depleted
+    min_balance: u64,
+    // The wallet paying the next transaction
+    next: usize,
+    checked: Option<Instant>,
+}
+
+impl WalletPool {
+    /// The balances are unknown until [Self::set_balance], the wallets are used meanwhile.
+    pub fn new(keypairs: Vec<Keypair>, min_balance: u64) -> WalletPool {
+        WalletPool {
+            wallets: keypairs
+                .into_iter()
+                .map(|keypair| PoolWallet {
+                    keypair,
+                    balance: u64::MAX,
+                })
+                .collect(),
+            min_balance,
+            next: 0,
+            checked: None,
+        }
+    }
+
+    pub fn pubkeys(&self) -> Vec<Pubkey> {
+        self.wallets
+            .iter()
+            .map(|wallet| wallet.keypair.pubkey())
+            .collect()
+    }
+
+    /// The next funded wallet, round-robin. `None` if all of them are depleted.
+    pub fn next_payer(&mut self) -> Option<&Keypair> {
+        let count = self.wallets.len();
+        let found = (0..count)
+            .map(|i| (self.next + i) % count)
+            .find(|&i| self.wallets[i].balance >= self.min_balance)?;
+        self.next = (found + 1) % count;
+        Some(&self.wallets[found].keypair)
+    }
+
+    /// Whether at least one wallet is funded.
+    pub fn funded(&self) -> bool {
+        self.wallets
+            .iter()
+            .any(|wallet| wallet.balance >= self.min_balance)
+    }
+
+    /// Deducts the `fee` paid by the `payer`, which leaves the rotation once depleted.
+    pub fn spend(&mut self, payer: &Pubkey, fee: u64) {
+        let min_balance = self.min_balance;
+        let Some(wallet) = self.wallet(payer) else {
+            return;
+        };
+        let was_funded = wallet.balance >= min_balance;
+        wallet.balance = wallet
+            .balance
+            .saturating_sub(fee);
+        if was_funded && wallet.balance < min_balance {
+            println!(
+                "ALERT: pool wallet {} is depleted, {} lamports left, removed from the rotation",
+                payer, wallet.balance
+            );
+        }
+    }
+
+    /// Sets the balance read from the RPC node, a funded wallet rejoins the rotation.
+    pub fn set_balance(&mut self, payer: &Pubkey, balance: u64) {
+        let min_balance = self.min_balance;
+        let Some(wallet) = self.wallet(payer) else {
+            return;
+        };
+        match (wallet.balance >= min_balance, balance >= min_balance) {
+            (true, false) => println!(
+                "ALERT: pool wallet {} is depleted, {} lamports left, removed from the rotation",
+                payer, balance
+            ),
+            (false, true) => println!(
+                "Pool wallet {} is funded with {} lamports, back in the rotation",
+                payer, balance
+            ),
+            _ => {}
+        }
+        wallet.balance = balance;
+    }
+
+    /// Whether the balances are to be read again.
+    pub fn check_due(&self, now: Instant) -> bool {
+        self.checked
+            .is_none_or(|checked| now.duration_since(checked) >= BALANCE_CHECK_INTERVAL)
+    }
+
+    pub fn checked(&mut self, now: Instant) {
+        self.checked = Some(now);
+    }
+
+    fn wallet(&mut self, payer: &Pubkey) -> Option<&mut PoolWallet> {
+        self.wallets
+            .iter_mut()
+            .find(|wallet| wallet.keypair.pubkey() == *payer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depleted_wallets_skipped() {
+        let mut pool = WalletPool::new(
+            (0..3)
+                .map(|_| Keypair::new())
+                .collect(),
+            10_000,
+        );
+        let keys = pool.pubkeys();
+        let next = |pool: &mut WalletPool| {
+            pool.next_payer()
+                .map(|keypair| keypair.pubkey())
+        };
+        // Round-robin
+        let paid: Vec<_> = (0..4)
+            .map(|_| next(&mut pool))
+            .collect();
+        assert_eq!(
+            paid,
+            vec![Some(keys[0]), Some(keys[1]), Some(keys[2]), Some(keys[0])]
+        );
+
+        pool.set_balance(&keys[1], 5_000);
+        pool.set_balance(&keys[2], 15_000);
+        pool.spend(&keys[2], 5_000);
+        assert_eq!(next(&mut pool), Some(keys[2]));
+        assert_eq!(next(&mut pool), Some(keys[0]));
+        pool.spend(&keys[2], 5_000);
+        assert_eq!(next(&mut pool), Some(keys[0]));
+
+        pool.set_balance(&keys[0], 0);
+        assert!(!pool.funded());
+        assert_eq!(next(&mut pool), None);
+        // Funded again
+        pool.set_balance(&keys[1], 1_000_000);
+        assert_eq!(next(&mut pool), Some(keys[1]));
+        assert_eq!(next(&mut pool), Some(keys[1]));
+
+        let now = Instant::now();
+        assert!(pool.check_due(now));
+        pool.checked(now);
+        assert!(!pool.check_due(now + Duration::from_secs(10)));
+        assert!(pool.check_due(now + BALANCE_CHECK_INTERVAL));
+    }
+}
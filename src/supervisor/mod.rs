@@ -0,0 +1,250 @@
+//! Keeps the long-running components (watcher, forwarder, sinks, servers) alive.
+//!
+//! A component which panics, fails or returns is started again after a delay,
+//! doubled with every restart and reset once it ran long enough. The channels
+//! between the components outlive them, see [SharedReceiver], so a restarted
+//! component carries on with the events queued for the failed one.
+//! Once the [Shutdown] is requested, the components which return aren't started again.
+
+use crate::error::Error;
+use futures_util::FutureExt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch, Mutex};
+
+const RESTART_DELAY_MIN: Duration = Duration::from_secs(1);
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(60);
+// Running for this long, the component is considered healthy again
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+
+/// Runs the component returned by `start` for the whole life of the process,
+/// starting it again whenever it panics, fails or returns.
+pub async fn supervise<F, Fut>(name: &str, start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    supervise_until(name, &Shutdown::new(), start).await
+}
+
+/// [supervise] until the `shutdown` is requested, then returns once the component does.
+/// The component is not stopped, it watches the `shutdown` itself or ends with its input.
+pub async fn supervise_until<F, Fut>(name: &str, shutdown: &Shutdown, mut start: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let mut backoff = Backoff::default();
+    loop {
+        let started = Instant::now();
+        match AssertUnwindSafe(start())
+            .catch_unwind()
+            .await
+        {
+            Ok(Ok(())) => println!("The {} stopped", name),
+            Ok(Err(e)) => println!("The {} failed: {}", name, e),
+            // The panic message is printed by the panic hook
+            Err(_) => println!("The {} panicked", name),
+        }
+        if shutdown.is_requested() {
+            return;
+        }
+        let delay = backoff.next(started.elapsed());
+        println!("Restarting the {} in {:?}", name, delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            _ = shutdown.requested() => return,
+        }
+    }
+}
+
+/// Asks the components to stop for good, e.g. on SIGTERM, shared by all of them.
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    /// Requested once SIGINT or SIGTERM is received.
+    #[cfg(unix)]
+    pub fn on_signals() -> Result<Shutdown, Error> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let shutdown = Shutdown::new();
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let requested = shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = interrupt.recv() => (),
+                _ = terminate.recv() => (),
+            }
+            println!("Shutting down, the queued events are sent first");
+            requested.request();
+        });
+        Ok(shutdown)
+    }
+
+    /// Requested once Ctrl-C is pressed.
+    #[cfg(not(unix))]
+    pub fn on_signals() -> Result<Shutdown, Error> {
+        let shutdown = Shutdown::new();
+        let requested = shutdown.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c()
+                .await
+                .is_ok()
+            {
+                println!("Shutting down, the queued events are sent first");
+                requested.request();
+            }
+        });
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Resolves once the shutdown is requested, at once if it already was.
+    pub async fn requested(&self) {
+        let mut rx = self.tx.subscribe();
+        // The sender lives as long as self
+        let _ = rx
+            .wait_for(|requested| *requested)
+            .await;
+    }
+}
+
+#[derive(Debug)]
+struct Backoff {
+    delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            delay: RESTART_DELAY_MIN,
+        }
+    }
+}
+
+impl Backoff {
+    // Delay of the restart of a component which ran for `ran_for`
+    fn next(&mut self, ran_for: Duration) -> Duration {
+        if ran_for >= STABLE_AFTER {
+            self.delay = RESTART_DELAY_MIN;
+        }
+        let delay = self.delay;
+        self.delay = std::cmp::min(self.delay * 2, RESTART_DELAY_MAX);
+        delay
+    }
+}
+
+/// Receiving end of a channel used by the successive runs of a component.
+#[derive(Clone)]
+pub struct SharedReceiver<T> {
+    rx: Arc<Mutex<mpsc::Receiver<T>>>,
+}
+
+impl<T: Send + 'static> SharedReceiver<T> {
+    pub fn new(rx: mpsc::Receiver<T>) -> SharedReceiver<T> {
+        SharedReceiver {
+            rx: Arc::new(Mutex::new(rx)),
+        }
+    }
+
+    /// Receiver of a single run. Relays the items until it's dropped, then the next
+    /// lease takes over. Only the item relayed but not received yet is lost.
+    pub fn lease(&self) -> mpsc::Receiver<T> {
+        // A single item at a time, a full shared channel still holds its senders back
+        let (tx, rx) = mpsc::channel(1);
+        let shared = self.rx.clone();
+        tokio::spawn(async move {
+            let mut shared = shared.lock().await;
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = tx.closed() => break,
+                    item = shared.recv() => {
+                        let Some(item) = item else { break };
+                        if tx.send(item).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restarted_component_resumes() {
+        let mut backoff = Backoff::default();
+        let quick = Duration::from_secs(1);
+        let delays: Vec<_> = (0..8)
+            .map(|_| backoff.next(quick).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(backoff.next(STABLE_AFTER), RESTART_DELAY_MIN);
+
+        let (tx, rx) = mpsc::channel(8);
+        let shared = SharedReceiver::new(rx);
+        for i in 0..3 {
+            tx.send(i).await.unwrap();
+        }
+        let mut first = shared.lease();
+        assert_eq!(first.recv().await, Some(0));
+        drop(first);
+
+        // The next run gets the rest, at most the already relayed ones are lost
+        let mut second = shared.lease();
+        tx.send(3).await.unwrap();
+        let mut received = Vec::new();
+        while received.last() != Some(&3) {
+            received.push(second.recv().await.unwrap());
+        }
+        assert!(received == vec![1, 2, 3] || received == vec![2, 3] || received == vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_not_restarted_after_shutdown() {
+        let shutdown = Shutdown::new();
+        let mut runs = 0;
+        supervise_until("component", &shutdown, || {
+            runs += 1;
+            shutdown.request();
+            async { Ok::<(), Error>(()) }
+        })
+        .await;
+        assert_eq!(runs, 1);
+
+        // Already requested
+        tokio::time::timeout(Duration::from_secs(1), shutdown.requested())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+//! Transport of the events from the watcher to the sinks.
+//!
+//! In the all-in-one mode the watcher and its pipeline pass the events to the sinks
+//! over the configured transport: a tokio channel by default, a std or crossbeam
+//! channel, or the Unix socket of the split-process mode. Over the socket the
+//! in-process deployment takes the same path as separate watcher and submitter
+//! processes, e.g. to try it out before splitting them.
+//!
+//! The selected transport is the queue between the watcher and the sinks, bounded
+//! by its capacity, the tokio channels bridging it to them hold one event each.
+
+use crate::error::Error;
+use crate::event::Event;
+use crate::ipc;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+/// Events queued between the watcher and the sinks without a configured `channel_capacity`.
+/// Once the queue is full the watcher waits for room.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transport {
+    Tokio,
+    Std,
+    Crossbeam,
+    /// The split-process mode's socket, at the path.
+    #[cfg(unix)]
+    Unix(String),
+}
+
+impl Transport {
+    /// Parses the configured name, the Unix socket is created at `socket_path`.
+    pub fn parse(name: &str, socket_path: &str) -> Result<Transport, Error> {
+        match name {
+            "tokio" => Ok(Transport::Tokio),
+            "std" => Ok(Transport::Std),
+            "crossbeam" => Ok(Transport::Crossbeam),
+            #[cfg(unix)]
+            "unix" => Ok(Transport::Unix(socket_path.to_string())),
+            #[cfg(not(unix))]
+            "unix" => Err(Error::Config(format!(
+                "The socket {} of the unix transport needs Unix domain sockets",
+                socket_path
+            ))),
+            other => Err(Error::Config(format!(
+                "Unknown transport {}, expected `tokio`, `std`, `crossbeam` or `unix`",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Tokio => "tokio",
+            Transport::Std => "std",
+            Transport::Crossbeam => "crossbeam",
+            #[cfg(unix)]
+            Transport::Unix(_) => "unix",
+        }
+    }
+
+    /// Opens both ends of the transport, queuing up to `capacity` events.
+    pub fn open(&self, capacity: usize) -> (Box<dyn EventSender>, Box<dyn EventReceiver>) {
+        match self {
+            Transport::Tokio => {
+                let (tx, rx) = mpsc::channel(capacity);
+                (Box::new(tx), Box::new(rx))
+            }
+            Transport::Std => {
+                let (tx, rx) = std::sync::mpsc::sync_channel(capacity);
+                (Box::new(tx), Box::new(Blocking(Some(StdReceiver(rx)))))
+            }
+            Transport::Crossbeam => {
+                let (tx, rx) = crossbeam_channel::bounded(capacity);
+                (Box::new(tx), Box::new(Blocking(Some(rx))))
+            }
+            #[cfg(unix)]
+            Transport::Unix(path) => (
+                Box::new(ipc::SocketSender::new(path)),
+                Box::new(SocketReceiver::bind(path, capacity)),
+            ),
+        }
+    }
+}
+
+/// Sending end of a transport, used by the watcher's side.
+#[async_trait]
+pub trait EventSender: Send {
+    async fn send(&mut self, event: Event) -> Result<(), Error>;
+}
+
+/// Receiving end of a transport, used by the sinks' side.
+#[async_trait]
+pub trait EventReceiver: Send {
+    /// Returns `None` once all the senders are gone.
+    async fn recv(&mut self) -> Result<Option<Event>, Error>;
+}
+
+/// The channel between the watcher and the sinks, carried over the `transport`, holding
+/// up to `capacity` events. The watcher's sends wait for room once the sinks fall behind.
+/// The tokio channel is returned as is, the other transports are bridged to it.
+pub fn channel(
+    transport: &Transport,
+    capacity: usize,
+) -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
+    if *transport == Transport::Tokio {
+        return mpsc::channel(capacity);
+    }
+    println!(
+        "Passing the events over the {} transport",
+        transport.as_str()
+    );
+    // The transport holds the queued events, the bridges only the one being passed on
+    let (tx, mut watcher_rx) = mpsc::channel(1);
+    let (mut sender, mut receiver) = transport.open(capacity);
+    let (sinks_tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        while let Some(event) = watcher_rx.recv().await {
+            if let Err(e) = sender.send(event).await {
+                println!("Failed to send an event over the transport: {}", e);
+                break;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(Some(event)) => {
+                    if sinks_tx
+                        .send(event)
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Failed to receive an event over the transport: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    (tx, rx)
+}
+
+#[async_trait]
+impl EventSender for mpsc::Sender<Event> {
+    async fn send(&mut self, event: Event) -> Result<(), Error> {
+        mpsc::Sender::send(self, event)
+            .await
+            .map_err(|_| Error::Disconnected)
+    }
+}
+
+#[async_trait]
+impl EventReceiver for mpsc::Receiver<Event> {
+    async fn recv(&mut self) -> Result<Option<Event>, Error> {
+        Ok(mpsc::Receiver::recv(self).await)
+    }
+}
+
+// Waits for room on a blocking thread once the channel is full
+#[async_trait]
+impl EventSender for std::sync::mpsc::SyncSender<Event> {
+    async fn send(&mut self, event: Event) -> Result<(), Error> {
+        use std::sync::mpsc::TrySendError;
+        match self.try_send(event) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(event)) => {
+                let tx = self.clone();
+                tokio::task::spawn_blocking(move || tx.send(event))
+                    .await?
+                    .map_err(|_| Error::Disconnected)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(Error::Disconnected),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSender for crossbeam_channel::Sender<Event> {
+    async fn send(&mut self, event: Event) -> Result<(), Error> {
+        use crossbeam_channel::TrySendError;
+        match self.try_send(event) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(event)) => {
+                let tx = self.clone();
+                tokio::task::spawn_blocking(move || tx.send(event))
+                    .await?
+                    .map_err(|_| Error::Disconnected)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(Error::Disconnected),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: ipc::Connector> EventSender for ipc::FrameSender<C> {
+    async fn send(&mut self, event: Event) -> Result<(), Error> {
+        ipc::FrameSender::send(self, &event).await
+    }
+}
+
+/// Receiver waiting for the events on a blocking thread.
+trait BlockingRecv: Send + 'static {
+    fn recv_blocking(&self) -> Option<Event>;
+}
+
+// std's Receiver is Send but not Sync, it's moved to the blocking thread and back
+struct StdReceiver(std::sync::mpsc::Receiver<Event>);
+
+impl BlockingRecv for StdReceiver {
+    fn recv_blocking(&self) -> Option<Event> {
+        self.0.recv().ok()
+    }
+}
+
+impl BlockingRecv for crossbeam_channel::Receiver<Event> {
+    fn recv_blocking(&self) -> Option<Event> {
+        crossbeam_channel::Receiver::recv(self).ok()
+    }
+}
+
+struct Blocking<R>(Option<R>);
+
+#[async_trait]
+impl<R: BlockingRecv> EventReceiver for Blocking<R> {
+    async fn recv(&mut self) -> Result<Option<Event>, Error> {
+        let receiver = self
+            .0
+            .take()
+            .ok_or(Error::Disconnected)?;
+        // recv blocks, keep it off the async workers
+        let (receiver, event) = tokio::task::spawn_blocking(move || {
+            let event = receiver.recv_blocking();
+            (receiver, event)
+        })
+        .await?;
+        self.0 = Some(receiver);
+        Ok(event)
+    }
+}
+
+/// Receiving end of the Unix socket, serves the watcher connections in the background.
+#[cfg(unix)]
+struct SocketReceiver {
+    rx: mpsc::Receiver<Event>,
+    server: tokio::task::JoinHandle<Result<(), Error>>,
+}
+
+#[cfg(unix)]
+impl SocketReceiver {
+    fn bind(path: &str, capacity: usize) -> SocketReceiver {
+        let (tx, rx) = mpsc::channel(capacity);
+        let path = path.to_string();
+        SocketReceiver {
+            rx,
+            server: tokio::spawn(async move { ipc::serve(&path, tx).await }),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl EventReceiver for SocketReceiver {
+    async fn recv(&mut self) -> Result<Option<Event>, Error> {
+        match self.rx.recv().await {
+            Some(event) => Ok(Some(event)),
+            // The server returns only if it failed
+            None => (&mut self.server)
+                .await?
+                .map(|_| None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    #[tokio::test]
+    async fn test_events_delivered_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir
+            .path()
+            .join("tracker.sock")
+            .to_string_lossy()
+            .to_string();
+        let event = |name: &str| Event::new(name, EventType::Written);
+
+        let mut transports = vec![Transport::Tokio, Transport::Std, Transport::Crossbeam];
+        #[cfg(unix)]
+        transports.push(Transport::Unix(socket.clone()));
+        for transport in transports {
+            let (tx, mut rx) = channel(&transport, 4);
+            for name in ["a", "b", "c"] {
+                tx.send(event(name))
+                    .await
+                    .unwrap();
+            }
+            for name in ["a", "b", "c"] {
+                assert_eq!(rx.recv().await, Some(event(name)), "{:?}", transport);
+            }
+        }
+        assert!(Transport::parse("udp", &socket).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_holds_the_sender_back() {
+        let event = |name: &str| Event::new(name, EventType::Written);
+
+        for transport in [Transport::Std, Transport::Crossbeam] {
+            let (mut tx, mut rx) = transport.open(1);
+            tx.send(event("a"))
+                .await
+                .unwrap();
+            // Sent once there's room, after the timeout
+            let full =
+                tokio::time::timeout(std::time::Duration::from_millis(50), tx.send(event("b")))
+                    .await;
+            assert!(full.is_err(), "{:?}", transport);
+            assert_eq!(rx.recv().await.unwrap(), Some(event("a")));
+            assert_eq!(rx.recv().await.unwrap(), Some(event("b")));
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Verifies the watched files against the last events recorded in the vault, read-only.
+//!
+//! An auditor runs it with the wallet's public key only: every file in the watched
+//! directories is compared with its last event on chain, its size and modification
+//! time with the event's file info, and the files recorded but gone are reported too.
+//! Given a time range, the report carries the [proof::Evidence] of the events recorded in it.
+
+pub mod proof;
+
+use crate::dir_watcher::read_file_info;
+use crate::event::{Event, EventType, FileInfo};
+use crate::sink::webhook::event_to_json;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// Same size and modification time as recorded.
+    Matches,
+    /// Changed since its last recorded event.
+    Modified,
+    /// Recorded as existing, not found.
+    Missing,
+    /// Not in the vault, or recorded as deleted or moved away.
+    Unrecorded,
+    /// Recorded without its file info, nothing to compare with.
+    Unverified,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Matches => "matches",
+            Status::Modified => "modified",
+            Status::Missing => "missing",
+            Status::Unrecorded => "unrecorded",
+            Status::Unverified => "unverified",
+        }
+    }
+}
+
+/// A file, its status and what it's compared with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub path: String,
+    pub status: Status,
+    pub recorded: Option<Event>,
+    pub local: Option<FileInfo>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub vault: String,
+    /// Unix time of the verification.
+    pub verified_at: u64,
+    pub entries: Vec<Entry>,
+    pub evidence: Option<proof::Evidence>,
+}
+
+impl Report {
+    pub fn count(&self, status: Status) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.status == status)
+            .count()
+    }
+
+    /// Whether every file matches its record, or can't be compared.
+    pub fn is_clean(&self) -> bool {
+        self.entries
+            .iter()
+            .all(|e| matches!(e.status, Status::Matches | Status::Unverified))
+    }
+
+    pub fn with_evidence(mut self, evidence: proof::Evidence) -> Report {
+        self.evidence = Some(evidence);
+        self
+    }
+
+    /// The report as JSON, the events in the webhooks' format.
+    pub fn to_json(&self) -> serde_json::Value {
+        let entries: Vec<serde_json::Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "path": entry.path,
+                    "status": entry.status.as_str(),
+                    "recorded": entry.recorded.as_ref().map(event_to_json),
+                    "local": entry.local.as_ref().map(|info| serde_json::json!({
+                        "size": info.size,
+                        "mode": info.mode,
+                        "modify_ts": info.modify_ts.map(|t| t as i64),
+                    })),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "vault": self.vault,
+            "verified_at": self.verified_at,
+            "summary": self.to_string(),
+            "entries": entries,
+            "evidence": self.evidence.as_ref().map(proof::Evidence::to_json),
+        })
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} files: {} matching, {} modified, {} missing, {} unrecorded, {} unverified",
+            self.entries.len(),
+            self.count(Status::Matches),
+            self.count(Status::Modified),
+            self.count(Status::Missing),
+            self.count(Status::Unrecorded),
+            self.count(Status::Unverified)
+        )
+    }
+}
+
+/// Compares the regular files in the directories, and in their subdirectories if
+/// `recursive`, with the `recorded` events of the vault. The entries are sorted by path.
+pub fn verify(
+    dirs: &[String],
+    recursive: bool,
+    recorded: &HashMap<String, Event>,
+    vault: &str,
+) -> Report {
+    let mut paths = BTreeSet::new();
+    for dir in dirs {
+        walk(Path::new(dir), recursive, &mut paths);
+    }
+    // The recorded files gone since
+    paths.extend(
+        recorded
+            .keys()
+            .filter(|path| {
+                dirs.iter()
+                    .any(|dir| crate::snapshot::is_under(path, dir))
+            })
+            .cloned(),
+    );
+
+    let entries = paths
+        .into_iter()
+        .map(|path| {
+            let recorded = recorded.get(&path).cloned();
+            let local = read_file_info(&path);
+            Entry {
+                status: status(recorded.as_ref(), local.as_ref()),
+                path,
+                recorded,
+                local,
+            }
+        })
+        .collect();
+    Report {
+        vault: vault.to_string(),
+        verified_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        entries,
+        evidence: None,
+    }
+}
+
+fn status(recorded: Option<&Event>, local: Option<&FileInfo>) -> Status {
+    let recorded =
+        recorded.filter(|e| !matches!(e.event_type, EventType::Deleted | EventType::MovedFrom));
+    match (recorded, local) {
+        (None, _) => Status::Unrecorded,
+        (Some(_), None) => Status::Missing,
+        (Some(event), Some(local)) => match event.file_info.as_ref() {
+            None => Status::Unverified,
+            Some(info) if info.size == local.size && info.modify_ts == local.modify_ts => {
+                Status::Matches
+            }
+            Some(_) => Status::Modified,
+        },
+    }
+}
+
+// The symlinks are not followed, as in the backfill
+fn walk(dir: &Path, recursive: bool, paths: &mut BTreeSet<String>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Failed to read the directory {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() && recursive {
+            walk(&entry.path(), recursive, paths);
+        } else if file_type.is_file() {
+            paths.insert(
+                entry
+                    .path()
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files_compared_with_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir
+            .path()
+            .to_string_lossy()
+            .into_owned();
+        let path = |name: &str| format!("{}/{}", root, name);
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        for name in ["same", "changed", "new", "deleted", "sub/nested"] {
+            std::fs::write(path(name), name).unwrap();
+        }
+        let record = |name: &str, event_type: EventType| {
+            let event = Event {
+                file_info: read_file_info(&path(name)),
+                ..Event::new(path(name), event_type)
+            };
+            (path(name), event)
+        };
+        let mut recorded: HashMap<String, Event> = [
+            record("same", EventType::Written),
+            record("changed", EventType::Created),
+            record("deleted", EventType::Deleted),
+        ]
+        .into_iter()
+        .collect();
+        recorded.insert(path("gone"), Event::new(path("gone"), EventType::Written));
+        // Outside the watched directories
+        recorded.insert("/elsewhere".to_string(), recorded[&path("same")].clone());
+        std::fs::write(path("changed"), "changed again").unwrap();
+
+        let report = verify(std::slice::from_ref(&root), false, &recorded, "vault");
+        let statuses: Vec<(&str, Status)> = report
+            .entries
+            .iter()
+            .map(|e| (&e.path[root.len() + 1..], e.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("changed", Status::Modified),
+                ("deleted", Status::Unrecorded),
+                ("gone", Status::Missing),
+                ("new", Status::Unrecorded),
+                ("same", Status::Matches),
+            ]
+        );
+        assert!(!report.is_clean());
+        assert_eq!(
+            report.to_string(),
+            "5 files: 1 matching, 1 modified, 1 missing, 2 unrecorded, 0 unverified"
+        );
+
+        let json = report.to_json();
+        assert_eq!(json["entries"][1]["status"], "unrecorded");
+        assert_eq!(json["entries"][4]["recorded"]["event_type"], "Written");
+
+        let report = verify(std::slice::from_ref(&root), true, &recorded, "vault");
+        assert_eq!(report.count(Status::Unrecorded), 3);
+    }
+}
@@ -0,0 +1,152 @@
+//! Retention proofs of the events recorded in a time range, for an evidence bundle a third
+//! party re-verifies against any RPC node: fetching the transaction by its signature,
+//! decoding the event from the program's instruction and hashing it again.
+
+use crate::event::Event;
+use crate::sink::webhook::event_to_json;
+use file_event_history::Recorded;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// An event with the transaction which recorded it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proof {
+    pub event: Event,
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// SHA-256 of the Borsh-serialized event, as decoded from the transaction.
+    pub sha256: String,
+    /// Set for the last proven event of a file, whether the vault still holds it.
+    pub in_vault: Option<bool>,
+}
+
+/// The proofs of the exported events and what's needed to check them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evidence {
+    pub program: String,
+    /// Unix time range of the transactions' block time.
+    pub since: i64,
+    pub until: Option<i64>,
+    pub proofs: Vec<Proof>,
+}
+
+impl Evidence {
+    pub fn to_json(&self) -> serde_json::Value {
+        let proofs: Vec<serde_json::Value> = self
+            .proofs
+            .iter()
+            .map(|proof| {
+                serde_json::json!({
+                    "signature": proof.signature,
+                    "slot": proof.slot,
+                    "block_time": proof.block_time,
+                    "sha256": proof.sha256,
+                    "in_vault": proof.in_vault,
+                    "event": event_to_json(&proof.event),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "program": self.program,
+            "since": self.since,
+            "until": self.until,
+            "proofs": proofs,
+        })
+    }
+}
+
+/// SHA-256 of the event as the program serializes it, hex encoded.
+pub fn event_sha256(event: &Event) -> String {
+    let data = borsh::to_vec(event).expect("Serializable");
+    crate::integrity::hex(&Sha256::digest(&data).into())
+}
+
+/// Proofs of the `records` of the files in the directories, oldest first. The hash of the
+/// last one of each file is checked against the event the vault holds for it.
+pub fn proofs(
+    records: Vec<Recorded>,
+    dirs: &[String],
+    vault_events: &HashMap<String, Event>,
+) -> Vec<Proof> {
+    let mut proofs: Vec<Proof> = records
+        .into_iter()
+        .filter(|r| {
+            dirs.iter()
+                .any(|dir| crate::snapshot::is_under(&r.event.file_path, dir))
+        })
+        .map(|r| Proof {
+            sha256: event_sha256(&r.event),
+            event: r.event,
+            signature: r.signature,
+            slot: r.slot,
+            block_time: r.block_time,
+            in_vault: None,
+        })
+        .collect();
+
+    let mut last: HashMap<&str, usize> = HashMap::new();
+    for (i, proof) in proofs.iter().enumerate() {
+        last.insert(&proof.event.file_path, i);
+    }
+    let checked: Vec<(usize, bool)> = last
+        .into_iter()
+        .map(|(path, i)| {
+            let held = vault_events
+                .get(path)
+                .is_some_and(|event| event_sha256(event) == proofs[i].sha256);
+            (i, held)
+        })
+        .collect();
+    for (i, held) in checked {
+        proofs[i].in_vault = Some(held);
+    }
+    proofs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    fn recorded(path: &str, event_type: EventType, slot: u64) -> Recorded {
+        Recorded {
+            signature: format!("sig{}", slot),
+            slot,
+            block_time: Some(1_700_000_000 + slot as i64),
+            event: Event {
+                solana_ts_received_at: slot as i128,
+                ..Event::new(path, event_type)
+            },
+        }
+    }
+
+    #[test]
+    fn test_last_events_checked_against_vault() {
+        let records = vec![
+            recorded("/srv/data/a.txt", EventType::Created, 1),
+            recorded("/srv/data/b.txt", EventType::Created, 2),
+            recorded("/srv/data/a.txt", EventType::Written, 3),
+            recorded("/srv/other/c.txt", EventType::Created, 4),
+        ];
+        let mut vault_events = HashMap::new();
+        vault_events.insert("/srv/data/a.txt".to_string(), records[2].event.clone());
+        // Changed after the range
+        let mut later = records[1].event.clone();
+        later.event_type = EventType::Deleted;
+        vault_events.insert("/srv/data/b.txt".to_string(), later);
+
+        let proofs = proofs(records.clone(), &["/srv/data".to_string()], &vault_events);
+        let checked: Vec<(&str, Option<bool>)> = proofs
+            .iter()
+            .map(|p| (p.signature.as_str(), p.in_vault))
+            .collect();
+        assert_eq!(
+            checked,
+            vec![("sig1", None), ("sig2", Some(false)), ("sig3", Some(true))]
+        );
+        assert_eq!(proofs[2].sha256, event_sha256(&records[2].event));
+        assert_ne!(proofs[0].sha256, proofs[2].sha256);
+        assert_eq!(proofs[2].sha256.len(), 64);
+    }
+}
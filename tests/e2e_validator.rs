@@ -0,0 +1,272 @@
+//! End-to-end test against a local validator: watches a temporary directory,
+//! submits the events to the deployed program and checks the vault's content.
+//!
+//! Needs `solana-test-validator` in PATH and the program built with `cargo build-sbf`.
+//! Built only with the `e2e` feature:
+//!
+//! ```sh
+//! (cd solana_program && cargo build-sbf)
+//! cargo test --features e2e --test e2e_validator
+//! ```
+//!
+//! `TRACKER_E2E_PROGRAM_SO` overrides the path of the program's shared object,
+//! `TRACKER_E2E_RPC_PORT` the validator's RPC port (default 18899). Watches with inotify,
+//! so it's built only on Linux.
+#![cfg(target_os = "linux")]
+
+use blkchn_file_tracker::anchor::AnchorSink;
+use blkchn_file_tracker::config::SolanaConfig;
+use blkchn_file_tracker::dir_watcher::DirWatcher;
+use blkchn_file_tracker::event::EventType;
+use blkchn_file_tracker::sink::FanOut;
+use blkchn_file_tracker::solana_client::{derive_vault, SolanaClient};
+use file_event_tracker::processor::AccountData;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+    signature::Keypair, signer::Signer,
+};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_RPC_PORT: u16 = 18899;
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+// Longer than a slot, so that the transactions of consecutive
+// file operations land in order
+const OPERATION_DELAY: Duration = Duration::from_secs(1);
+
+/// Validator process, killed when the test ends, whatever the outcome.
+struct Validator {
+    process: Child,
+    url: String,
+    // Removed after the validator is gone
+    _ledger: tempfile::TempDir,
+}
+
+impl Validator {
+    fn start(program: &Pubkey, program_so: &PathBuf) -> Validator {
+        let ledger = tempfile::tempdir().unwrap();
+        let rpc_port = env::var("TRACKER_E2E_RPC_PORT")
+            .map(|p| {
+                p.parse()
+                    .expect("TRACKER_E2E_RPC_PORT is not a port")
+            })
+            .unwrap_or(DEFAULT_RPC_PORT);
+
+        // The program is deployed at genesis, under the given address
+        let process = Command::new("solana-test-validator")
+            .arg("--reset")
+            .arg("--quiet")
+            .arg("--ledger")
+            .arg(ledger.path())
+            .arg("--rpc-port")
+            .arg(rpc_port.to_string())
+            .arg("--faucet-port")
+            .arg((rpc_port + 1001).to_string())
+            .arg("--bpf-program")
+            .arg(program.to_string())
+            .arg(program_so)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start solana-test-validator, is it in PATH?");
+
+        Validator {
+            process,
+            url: format!("http://127.0.0.1:{}", rpc_port),
+            _ledger: ledger,
+        }
+    }
+
+    async fn wait_ready(&self, rpc_client: &RpcClient) {
+        let started = Instant::now();
+        while rpc_client
+            .get_latest_blockhash()
+            .await
+            .is_err()
+        {
+            assert!(
+                started.elapsed() < STARTUP_TIMEOUT,
+                "Validator not ready within {:?}",
+                STARTUP_TIMEOUT
+            );
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+impl Drop for Validator {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn program_so() -> PathBuf {
+    let path = env::var("TRACKER_E2E_PROGRAM_SO")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("solana_program/target/deploy/file_event_tracker.so")
+        });
+    assert!(
+        path.exists(),
+        "Program not found at {:?}, run `cargo build-sbf` in solana_program/ first",
+        path
+    );
+    path
+}
+
+async fn fund(rpc_client: &RpcClient, wallet: &Keypair) {
+    let signature = rpc_client
+        .request_airdrop(&wallet.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .await
+        .unwrap();
+    let started = Instant::now();
+    while !rpc_client
+        .confirm_transaction(&signature)
+        .await
+        .unwrap()
+    {
+        assert!(started.elapsed() < STARTUP_TIMEOUT, "Airdrop not confirmed");
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_file_operations_stored_in_vault() {
+    let program = Keypair::new().pubkey();
+    let validator = Validator::start(&program, &program_so());
+    let commitment = CommitmentConfig::confirmed();
+    let rpc_client = RpcClient::new_with_commitment(validator.url.clone(), commitment);
+    validator
+        .wait_ready(&rpc_client)
+        .await;
+
+    let wallet = Keypair::new();
+    fund(&rpc_client, &wallet).await;
+    let (vault, _) = derive_vault(&program, &wallet.pubkey());
+
+    let dir = tempfile::tempdir().unwrap();
+    let dir_path = dir
+        .path()
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // The events are sent with the paths relative to the watched directory
+    let config = SolanaConfig {
+        rpc_url: validator.url.clone(),
+        commitment,
+        rpc_debug: false,
+        program,
+        wallet_keypair: String::new(), // the wallet is passed directly
+        schema_version: file_event_tracker::processor::SCHEMA_VERSION,
+        wallet_pubkey: None,
+        path_prefixes: vec![format!("{}/", dir_path)],
+        arweave_bundler: None,
+        arweave_max_size: 0,
+        clock_skew_threshold: 30,
+        budget_lamports: None,
+        budget_period: std::time::Duration::from_secs(24 * 3600),
+        breaker_failure_rate: 50,
+        breaker_cool_down: std::time::Duration::from_secs(30),
+        retry_budget: std::time::Duration::from_secs(30),
+        batch_size: 1,
+        batch_max_latency: std::time::Duration::from_secs(10),
+        lookup_tables: Vec::new(),
+        priority_fees: Default::default(),
+        journal_path: String::new(),
+        routes: Vec::new(),
+        webhook_url: None,
+        completeness_report: None,
+        completeness_on_chain: false,
+    };
+    let mut submitter = SolanaClient::from_config(&config, wallet);
+    submitter
+        .init_account()
+        .await
+        .unwrap();
+    let watcher = DirWatcher::new(
+        &[dir_path.clone()],
+        vec![
+            EventType::Created,
+            EventType::Written,
+            EventType::MovedFrom,
+            EventType::MovedTo,
+            EventType::Deleted,
+        ],
+    )
+    .unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+    let watcher_task = tokio::spawn(async move { watcher.run(tx).await });
+    let sinks_task = tokio::spawn(
+        FanOut::new()
+            .with_sink(Box::new(AnchorSink::new(submitter)))
+            .run(rx),
+    );
+
+    let path = |name: &str| format!("{}/{}", dir_path, name);
+    fs::write(path("kept.txt"), b"kept").unwrap();
+    tokio::time::sleep(OPERATION_DELAY).await;
+    fs::write(path("removed.txt"), b"removed").unwrap();
+    tokio::time::sleep(OPERATION_DELAY).await;
+    fs::rename(path("kept.txt"), path("renamed.txt")).unwrap();
+    tokio::time::sleep(OPERATION_DELAY).await;
+    fs::remove_file(path("removed.txt")).unwrap();
+    tokio::time::sleep(OPERATION_DELAY).await;
+
+    // Stopping the watcher closes the channel, the submitter then
+    // waits for the confirmations of all the sent transactions
+    watcher_task.abort();
+    sinks_task
+        .await
+        .unwrap()
+        .unwrap();
+
+    let data = rpc_client
+        .get_account_data(&vault)
+        .await
+        .unwrap();
+    let account_data = AccountData::unpack(&data).unwrap();
+    assert_eq!(
+        account_data
+            .path_dictionary
+            .prefixes(),
+        config
+            .path_prefixes
+            .as_slice()
+    );
+
+    let last_event_type = |name: &str| {
+        account_data
+            .last_file_events
+            .get(&path(name))
+            .map(|e| e.event_type.clone())
+    };
+    assert_eq!(last_event_type("kept.txt"), Some(EventType::MovedFrom));
+    assert_eq!(last_event_type("renamed.txt"), Some(EventType::MovedTo));
+    assert_eq!(last_event_type("removed.txt"), Some(EventType::Deleted));
+    assert_eq!(
+        account_data
+            .last_file_events
+            .len(),
+        3
+    );
+
+    // Only the file which still exists has its file info
+    let renamed = &account_data.last_file_events[&path("renamed.txt")];
+    assert_eq!(
+        renamed
+            .file_info
+            .as_ref()
+            .map(|fi| fi.size),
+        Some(4)
+    );
+    assert!(account_data.last_file_events[&path("removed.txt")]
+        .file_info
+        .is_none());
+}